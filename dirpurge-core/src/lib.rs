@@ -0,0 +1,127 @@
+//! Rule evaluation for dirpurge, independent of the filesystem: the CLI
+//! gathers metadata about each candidate and these rules decide what matches.
+
+use std::time::SystemTime;
+
+// Which directories a scan matches and the bounds they must fall within
+#[derive(Debug, Clone, Default)]
+pub struct Rules {
+    // Names matched by substring
+    pub target: Vec<String>,
+    // Paths containing any of these are never matched
+    pub exclude: Vec<String>,
+    pub min_size: Option<u64>,
+    pub max_size: Option<u64>,
+    pub min_items: Option<usize>,
+    pub max_items: Option<usize>,
+    pub min_age: Option<i64>,
+    pub max_age: Option<i64>,
+    // Absolute cutoffs for the candidate's modification time
+    pub modified_before: Option<SystemTime>,
+    pub modified_after: Option<SystemTime>,
+    pub min_atime_age: Option<i64>,
+    pub owner_uid: Option<u32>,
+    pub repo_inactive_days: Option<i64>,
+}
+
+// What the rules need to know about a candidate. Values are asked for
+// cheapest first and only when a rule needs them, so implementations can
+// compute them on demand.
+pub trait Candidate {
+    fn owner(&self) -> Option<u32>;
+    fn modified(&self) -> Option<SystemTime>;
+    // Whole days since `modified`
+    fn age_days(&self) -> Option<i64>;
+    fn accessed_days_ago(&self) -> Option<i64>;
+    // Days since the enclosing repository's last commit, None outside one
+    fn last_commit_days_ago(&self) -> Option<i64>;
+    fn item_count(&self) -> usize;
+    fn size_bytes(&self) -> u64;
+    // Whether size_bytes is extrapolated
+    fn estimated(&self) -> bool {
+        false
+    }
+}
+
+impl Rules {
+    // Whether a directory is a target and not excluded
+    pub fn matches_name(&self, name: &str, path: &str) -> bool {
+        !self.is_excluded(path) && self.target.iter().any(|t| name.contains(t.as_str()))
+    }
+
+    pub fn is_excluded(&self, path: &str) -> bool {
+        self.exclude.iter().any(|ex| path.contains(ex.as_str()))
+    }
+
+    // The same rules with every age, size, count and owner bound lifted
+    pub fn without_filters(&self) -> Rules {
+        Rules {
+            target: self.target.clone(),
+            exclude: self.exclude.clone(),
+            ..Rules::default()
+        }
+    }
+
+    // Apply the bounds to a candidate, giving the reason it was rejected
+    pub fn evaluate(&self, c: &impl Candidate) -> Result<(), String> {
+        if let Some(uid) = self.owner_uid && c.owner() != Some(uid) {
+            return Err(format!("not owned by uid {}", uid));
+        }
+
+        let age = c.age_days();
+        let age_label = age.map_or("unknown".to_string(), |a| format!("{} days", a));
+        if let Some(min) = self.min_age && age.is_none_or(|age| age < min) {
+            return Err(format!("age {} below --min-age {}", age_label, min));
+        }
+        if let Some(max) = self.max_age && age.is_none_or(|age| age > max) {
+            return Err(format!("age {} above --max-age {}", age_label, max));
+        }
+        if self.modified_before.is_some_and(|cutoff| c.modified().is_none_or(|t| t >= cutoff)) {
+            return Err("not modified before --modified-before".to_string());
+        }
+        if self.modified_after.is_some_and(|cutoff| c.modified().is_none_or(|t| t <= cutoff)) {
+            return Err("not modified after --modified-after".to_string());
+        }
+        if let Some(min) = self.min_atime_age && c.accessed_days_ago().is_none_or(|age| age < min) {
+            return Err(format!("accessed within --min-atime-age {} days", min));
+        }
+        if let Some(min) = self.repo_inactive_days {
+            match c.last_commit_days_ago() {
+                None => return Err("not inside a git repository with commits".to_string()),
+                Some(days) if days < min => return Err(format!("last commit {} days ago, within --repo-inactive-days {}", days, min)),
+                Some(_) => {}
+            }
+        }
+
+        // Counted before sizing so item filters can skip the more expensive walk
+        if self.min_items.is_some() || self.max_items.is_some() {
+            let items = c.item_count();
+            if let Some(min) = self.min_items && items < min {
+                return Err(format!("{} items below --min-items {}", items, min));
+            }
+            if let Some(max) = self.max_items && items > max {
+                return Err(format!("{} items above --max-items {}", items, max));
+            }
+        }
+
+        if self.min_size.is_some() || self.max_size.is_some() {
+            let size = c.size_bytes();
+            if self.min_size.is_some_and(|min| size < min) {
+                return Err(format!("size {} below --min-size", format_mb(size, c.estimated())));
+            }
+            if self.max_size.is_some_and(|max| size > max) {
+                return Err(format!("size {} above --max-size", format_mb(size, c.estimated())));
+            }
+        }
+        Ok(())
+    }
+}
+
+pub fn format_mb(bytes: u64, estimated: bool) -> String {
+    let mb = bytes as f64 / 1024.0 / 1024.0;
+    if estimated {
+        format!("~{:.2} MB", mb)
+    } else {
+        format!("{:.2} MB", mb)
+    }
+}
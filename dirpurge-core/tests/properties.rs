@@ -0,0 +1,290 @@
+// Property tests over synthetic directory trees. Each property runs against
+// many trees from a seeded generator, so failures are reproducible from the
+// printed seed.
+
+use dirpurge_core::{Candidate, Rules};
+use std::{cell::Cell, time::{Duration, SystemTime, UNIX_EPOCH}};
+
+const CASES: u64 = 256;
+const NAMES: &[&str] = &["node_modules", "target", "build", ".venv", "venv", "src", "lib", "dist", "my_target", "docs"];
+const TARGETS: &[&str] = &["node_modules", "target", "venv", "build"];
+
+// xorshift64*, enough randomness for test inputs without a dependency
+struct Gen(u64);
+
+impl Gen {
+    fn new(seed: u64) -> Self {
+        Gen(seed.wrapping_mul(0x9E37_79B9_7F4A_7C15) | 1)
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    fn below(&mut self, n: u64) -> u64 {
+        self.next() % n
+    }
+
+    fn maybe<T>(&mut self, value: impl FnOnce(&mut Self) -> T) -> Option<T> {
+        if self.below(3) == 0 { Some(value(self)) } else { None }
+    }
+
+    fn pick<'a>(&mut self, items: &[&'a str]) -> &'a str {
+        items[self.below(items.len() as u64) as usize]
+    }
+}
+
+// One synthetic directory: its metadata plus counters of expensive lookups
+#[derive(Debug, Clone)]
+struct Node {
+    name: String,
+    path: String,
+    owner: Option<u32>,
+    modified: Option<SystemTime>,
+    accessed_days_ago: Option<i64>,
+    last_commit_days_ago: Option<i64>,
+    items: usize,
+    size: u64,
+    item_lookups: Cell<usize>,
+    size_lookups: Cell<usize>,
+}
+
+impl Candidate for Node {
+    fn owner(&self) -> Option<u32> {
+        self.owner
+    }
+
+    fn modified(&self) -> Option<SystemTime> {
+        self.modified
+    }
+
+    fn age_days(&self) -> Option<i64> {
+        self.modified.map(age_of)
+    }
+
+    fn accessed_days_ago(&self) -> Option<i64> {
+        self.accessed_days_ago
+    }
+
+    fn last_commit_days_ago(&self) -> Option<i64> {
+        self.last_commit_days_ago
+    }
+
+    fn item_count(&self) -> usize {
+        self.item_lookups.set(self.item_lookups.get() + 1);
+        self.items
+    }
+
+    fn size_bytes(&self) -> u64 {
+        self.size_lookups.set(self.size_lookups.get() + 1);
+        self.size
+    }
+}
+
+// Ages are measured against a fixed clock so days ago are exact
+const NOW: u64 = 1_800_000_000;
+
+fn age_of(time: SystemTime) -> i64 {
+    (NOW as i64 - time.duration_since(UNIX_EPOCH).unwrap().as_secs() as i64).max(0) / 86400
+}
+
+fn day(days_ago: u64) -> SystemTime {
+    UNIX_EPOCH + Duration::from_secs(NOW - days_ago * 86400)
+}
+
+// A random tree, flattened to its directories
+fn tree(g: &mut Gen) -> Vec<Node> {
+    let mut nodes = Vec::new();
+    let mut stack = vec![("root".to_string(), 0)];
+    while let Some((parent, depth)) = stack.pop() {
+        for _ in 0..g.below(4) {
+            let name = g.pick(NAMES).to_string();
+            let path = format!("{}/{}", parent, name);
+            nodes.push(Node {
+                name,
+                path: path.clone(),
+                owner: g.maybe(|g| g.below(3) as u32),
+                modified: (g.below(4) != 0).then(|| day(g.below(400))),
+                accessed_days_ago: g.maybe(|g| g.below(400) as i64),
+                last_commit_days_ago: g.maybe(|g| g.below(400) as i64),
+                items: g.below(5000) as usize,
+                size: g.below(1 << 30),
+                item_lookups: Cell::new(0),
+                size_lookups: Cell::new(0),
+            });
+            if depth < 4 && nodes.len() < 200 {
+                stack.push((path, depth + 1));
+            }
+        }
+    }
+    nodes
+}
+
+fn rules(g: &mut Gen) -> Rules {
+    let min_age = g.maybe(|g| g.below(200) as i64);
+    let min_size = g.maybe(|g| g.below(1 << 29));
+    Rules {
+        target: (0..1 + g.below(3)).map(|_| g.pick(TARGETS).to_string()).collect(),
+        exclude: g.maybe(|g| vec![g.pick(NAMES).to_string()]).unwrap_or_default(),
+        min_size,
+        max_size: g.maybe(|g| min_size.unwrap_or(0) + g.below(1 << 29)),
+        min_items: g.maybe(|g| g.below(2500) as usize),
+        max_items: g.maybe(|g| 2500 + g.below(2500) as usize),
+        min_age,
+        max_age: g.maybe(|g| min_age.unwrap_or(0) + g.below(200) as i64),
+        modified_before: g.maybe(|g| day(g.below(400))),
+        modified_after: g.maybe(|g| day(g.below(400))),
+        min_atime_age: g.maybe(|g| g.below(400) as i64),
+        owner_uid: g.maybe(|g| g.below(3) as u32),
+        repo_inactive_days: g.maybe(|g| g.below(400) as i64),
+    }
+}
+
+// A directory with every piece of metadata known, for the boundary cases
+fn node(age: u64, items: usize, size: u64) -> Node {
+    Node {
+        name: "node_modules".to_string(),
+        path: "root/node_modules".to_string(),
+        owner: Some(1),
+        modified: Some(day(age)),
+        accessed_days_ago: Some(age as i64),
+        last_commit_days_ago: Some(age as i64),
+        items,
+        size,
+        item_lookups: Cell::new(0),
+        size_lookups: Cell::new(0),
+    }
+}
+
+// The same rules with every bound loosened by a random amount or lifted
+fn widened(g: &mut Gen, r: &Rules) -> Rules {
+    let lower = |g: &mut Gen, min: Option<i64>| min.and_then(|min| (g.below(4) != 0).then(|| min - g.below(50) as i64));
+    Rules {
+        min_size: r.min_size.and_then(|min| (g.below(4) != 0).then(|| min.saturating_sub(g.below(1 << 28)))),
+        max_size: r.max_size.and_then(|max| (g.below(4) != 0).then(|| max + g.below(1 << 28))),
+        min_items: r.min_items.and_then(|min| (g.below(4) != 0).then(|| min.saturating_sub(g.below(500) as usize))),
+        max_items: r.max_items.and_then(|max| (g.below(4) != 0).then(|| max + g.below(500) as usize)),
+        min_age: lower(g, r.min_age),
+        max_age: r.max_age.and_then(|max| (g.below(4) != 0).then(|| max + g.below(50) as i64)),
+        modified_before: r.modified_before.and_then(|t| (g.below(4) != 0).then(|| t + Duration::from_secs(g.below(1 << 22)))),
+        modified_after: r.modified_after.and_then(|t| (g.below(4) != 0).then(|| t - Duration::from_secs(g.below(1 << 22)))),
+        min_atime_age: lower(g, r.min_atime_age),
+        owner_uid: r.owner_uid.filter(|_| g.below(4) != 0),
+        repo_inactive_days: lower(g, r.repo_inactive_days),
+        ..r.clone()
+    }
+}
+
+fn for_each_case(property: impl Fn(&mut Gen, u64)) {
+    for seed in 0..CASES {
+        property(&mut Gen::new(seed), seed);
+    }
+}
+
+#[test]
+fn each_bound_keeps_its_edge() {
+    let accepts = |r: Rules, n: Node| r.evaluate(&n).is_ok();
+    let n = || node(30, 100, 1000);
+
+    // Minimums and maximums of ages, counts and sizes include the bound itself
+    assert!(accepts(Rules { min_age: Some(30), ..Rules::default() }, n()));
+    assert!(!accepts(Rules { min_age: Some(31), ..Rules::default() }, n()));
+    assert!(accepts(Rules { max_age: Some(30), ..Rules::default() }, n()));
+    assert!(!accepts(Rules { max_age: Some(29), ..Rules::default() }, n()));
+    assert!(accepts(Rules { min_atime_age: Some(30), ..Rules::default() }, n()));
+    assert!(!accepts(Rules { min_atime_age: Some(31), ..Rules::default() }, n()));
+    assert!(accepts(Rules { repo_inactive_days: Some(30), ..Rules::default() }, n()));
+    assert!(!accepts(Rules { repo_inactive_days: Some(31), ..Rules::default() }, n()));
+    assert!(accepts(Rules { min_items: Some(100), max_items: Some(100), ..Rules::default() }, n()));
+    assert!(!accepts(Rules { min_items: Some(101), ..Rules::default() }, n()));
+    assert!(!accepts(Rules { max_items: Some(99), ..Rules::default() }, n()));
+    assert!(accepts(Rules { min_size: Some(1000), max_size: Some(1000), ..Rules::default() }, n()));
+    assert!(!accepts(Rules { min_size: Some(1001), ..Rules::default() }, n()));
+    assert!(!accepts(Rules { max_size: Some(999), ..Rules::default() }, n()));
+
+    // Date cutoffs leave out a directory modified at the cutoff itself
+    let at = day(30);
+    let second = Duration::from_secs(1);
+    assert!(!accepts(Rules { modified_before: Some(at), ..Rules::default() }, n()));
+    assert!(accepts(Rules { modified_before: Some(at + second), ..Rules::default() }, n()));
+    assert!(!accepts(Rules { modified_after: Some(at), ..Rules::default() }, n()));
+    assert!(accepts(Rules { modified_after: Some(at - second), ..Rules::default() }, n()));
+
+    assert!(accepts(Rules { owner_uid: Some(1), ..Rules::default() }, n()));
+    assert!(!accepts(Rules { owner_uid: Some(2), ..Rules::default() }, n()));
+
+    // Metadata that couldn't be read fails any bound that needs it
+    let unknown = || Node { owner: None, modified: None, accessed_days_ago: None, last_commit_days_ago: None, ..n() };
+    assert!(accepts(Rules::default(), unknown()));
+    assert!(!accepts(Rules { owner_uid: Some(1), ..Rules::default() }, unknown()));
+    assert!(!accepts(Rules { min_age: Some(0), ..Rules::default() }, unknown()));
+    assert!(!accepts(Rules { max_age: Some(1000), ..Rules::default() }, unknown()));
+    assert!(!accepts(Rules { modified_before: Some(day(0)), ..Rules::default() }, unknown()));
+    assert!(!accepts(Rules { modified_after: Some(UNIX_EPOCH), ..Rules::default() }, unknown()));
+    assert!(!accepts(Rules { min_atime_age: Some(0), ..Rules::default() }, unknown()));
+    assert!(!accepts(Rules { repo_inactive_days: Some(0), ..Rules::default() }, unknown()));
+}
+
+#[test]
+fn widening_a_bound_never_rejects_more() {
+    for_each_case(|g, seed| {
+        let tight = rules(g);
+        let loose = widened(g, &tight);
+        for n in tree(g) {
+            if tight.evaluate(&n).is_ok() {
+                assert!(loose.evaluate(&n).is_ok(), "seed {}: {:?} with {:?}, widened to {:?}", seed, n, tight, loose);
+            }
+        }
+    });
+}
+
+#[test]
+fn without_filters_accepts_every_candidate() {
+    for_each_case(|g, seed| {
+        let r = rules(g).without_filters();
+        for n in tree(g) {
+            assert!(r.evaluate(&n).is_ok(), "seed {}: {:?}", seed, n);
+        }
+    });
+}
+
+#[test]
+fn excluded_paths_never_match_and_targets_always_do() {
+    for_each_case(|g, seed| {
+        let r = rules(g);
+        for n in tree(g) {
+            let excluded = r.exclude.iter().any(|ex| n.path.contains(ex.as_str()));
+            let named = r.target.iter().any(|t| n.name.contains(t.as_str()));
+            assert_eq!(r.matches_name(&n.name, &n.path), named && !excluded, "seed {}: {}", seed, n.path);
+        }
+    });
+}
+
+#[test]
+fn expensive_metadata_is_only_read_when_needed() {
+    for_each_case(|g, seed| {
+        let r = rules(g);
+        for n in tree(g) {
+            let verdict = r.evaluate(&n);
+            let sized = n.size_lookups.get() > 0;
+            let counted = n.item_lookups.get() > 0;
+            if r.min_size.is_none() && r.max_size.is_none() {
+                assert!(!sized, "seed {}: sized without a size bound", seed);
+            }
+            if r.min_items.is_none() && r.max_items.is_none() {
+                assert!(!counted, "seed {}: counted without an item bound", seed);
+            }
+            // A rejection by a cheaper check stops before the walk of the directory
+            if let Err(reason) = verdict
+                && !reason.contains("items")
+                && !reason.contains("size")
+            {
+                assert!(!sized && !counted, "seed {}: walked after rejecting: {}", seed, reason);
+            }
+            assert!(n.size_lookups.get() <= 1 && n.item_lookups.get() <= 1, "seed {}: looked up twice", seed);
+        }
+    });
+}
@@ -0,0 +1,20 @@
+#![no_main]
+#![allow(dead_code, unused_imports)]
+
+// Config files get shared and downloaded, so any input must either load or be
+// refused with an error, and whatever loads must survive `--save-config`
+include!("../../src/main.rs");
+
+libfuzzer_sys::fuzz_target!(|data: &[u8]| {
+    let Ok(content) = std::str::from_utf8(data) else {
+        return;
+    };
+    let Ok(config) = parse_config(content) else {
+        return;
+    };
+    for markers in config.manifests.iter().flat_map(|m| m.values()) {
+        assert!(markers.iter().all(|m| is_marker_name(m)), "marker path slipped through: {:?}", markers);
+    }
+    let saved = serde_json::to_string(&config).expect("a loaded config serializes");
+    parse_config(&saved).expect("a saved config loads again");
+});
@@ -0,0 +1,16 @@
+#![no_main]
+#![allow(dead_code, unused_imports)]
+
+// A --manifest pair is either a target and a plain marker file name or an error
+include!("../../src/main.rs");
+
+libfuzzer_sys::fuzz_target!(|data: &[u8]| {
+    let Ok(pair) = std::str::from_utf8(data) else {
+        return;
+    };
+    if let Ok((target, marker)) = parse_manifest(pair) {
+        assert!(!target.is_empty());
+        assert!(is_marker_name(&marker));
+        assert_eq!(format!("{}={}", target, marker), pair);
+    }
+});
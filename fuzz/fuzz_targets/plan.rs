@@ -0,0 +1,24 @@
+#![no_main]
+#![allow(dead_code, unused_imports)]
+
+// Plans can be edited by hand between `--save-plan` and `apply`; whatever
+// parses must go through the approval check and option setup without panicking
+include!("../../src/main.rs");
+
+libfuzzer_sys::fuzz_target!(|data: &[u8]| {
+    let Ok(content) = std::str::from_utf8(data) else {
+        return;
+    };
+    let Ok(plan) = plan::parse_plan(content) else {
+        return;
+    };
+    plan.total_size_bytes();
+    let policy = plan::ApprovalPolicy {
+        required_signatures: 1,
+        threshold_mb: Some(0.0),
+        ..Default::default()
+    };
+    // No keys are configured, so no signature can be valid
+    assert!(plan::check_approvals(&plan, &policy).is_err());
+    let _ = plan.delete_options(true, false);
+});
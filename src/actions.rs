@@ -0,0 +1,80 @@
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, fmt, path::Path, str::FromStr};
+
+// What to do with the directories matched by one target, overriding the
+// global --use-trash/--backup/--archive choice
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(try_from = "String", into = "String")]
+pub enum TargetAction {
+    Delete,
+    Trash,
+    BackupDelete,
+    ArchiveDelete,
+    // Listed in scans and exports, never deleted
+    ReportOnly,
+}
+
+impl TargetAction {
+    // (use_trash, backup, archive) for the deletion step
+    pub fn flags(self) -> (bool, bool, bool) {
+        match self {
+            TargetAction::Trash => (true, false, false),
+            TargetAction::BackupDelete => (false, true, false),
+            TargetAction::ArchiveDelete => (false, false, true),
+            TargetAction::Delete | TargetAction::ReportOnly => (false, false, false),
+        }
+    }
+}
+
+impl FromStr for TargetAction {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.trim() {
+            "delete" => Ok(TargetAction::Delete),
+            "trash" => Ok(TargetAction::Trash),
+            "backup" | "backup+delete" => Ok(TargetAction::BackupDelete),
+            "archive" | "archive+delete" => Ok(TargetAction::ArchiveDelete),
+            "report-only" => Ok(TargetAction::ReportOnly),
+            _ => Err(format!("invalid action '{}', expected delete, trash, backup+delete, archive+delete or report-only", value)),
+        }
+    }
+}
+
+impl TryFrom<String> for TargetAction {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl fmt::Display for TargetAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            TargetAction::Delete => "delete",
+            TargetAction::Trash => "trash",
+            TargetAction::BackupDelete => "backup+delete",
+            TargetAction::ArchiveDelete => "archive+delete",
+            TargetAction::ReportOnly => "report-only",
+        })
+    }
+}
+
+impl From<TargetAction> for String {
+    fn from(action: TargetAction) -> Self {
+        action.to_string()
+    }
+}
+
+// The configured action for a matched directory: an exact name match wins,
+// then the longest target contained in the name, as targets match by substring
+pub fn action_for(path: &str, actions: &HashMap<String, TargetAction>) -> Option<TargetAction> {
+    let name = Path::new(path).file_name()?.to_string_lossy();
+    actions.get(name.as_ref()).copied().or_else(|| {
+        actions.iter()
+            .filter(|(target, _)| name.contains(target.as_str()))
+            .max_by_key(|(target, _)| (target.len(), std::cmp::Reverse(target.as_str())))
+            .map(|(_, action)| *action)
+    })
+}
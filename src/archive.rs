@@ -0,0 +1,803 @@
+// Zip, tar.gz and tar.zst archive support, compiled in with the `archive` feature
+#[cfg(feature = "archive")]
+pub use enabled::*;
+
+#[cfg(not(feature = "archive"))]
+pub use disabled::*;
+
+use serde::{Deserialize, Serialize};
+use std::{ops::RangeInclusive, path::Path};
+
+use crate::CROSS;
+
+// How --archive backups are packed
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum ArchiveFormat {
+    #[default]
+    #[serde(rename = "zip")]
+    #[value(name = "zip")]
+    Zip,
+    // Keeps Unix permissions, owners and symlinks
+    #[serde(rename = "tar.gz")]
+    #[value(name = "tar.gz")]
+    TarGz,
+    // The same tar, compressed with zstd: many times faster on large trees
+    #[serde(rename = "tar.zst")]
+    #[value(name = "tar.zst")]
+    TarZst,
+}
+
+impl ArchiveFormat {
+    const ALL: [ArchiveFormat; 3] = [ArchiveFormat::Zip, ArchiveFormat::TarGz, ArchiveFormat::TarZst];
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            ArchiveFormat::Zip => "zip",
+            ArchiveFormat::TarGz => "tar.gz",
+            ArchiveFormat::TarZst => "tar.zst",
+        }
+    }
+
+    #[cfg(feature = "archive")]
+    pub fn is_tar(self) -> bool {
+        self != ArchiveFormat::Zip
+    }
+
+    // Compression levels the format's encoder accepts
+    pub fn levels(self) -> RangeInclusive<i32> {
+        match self {
+            ArchiveFormat::Zip | ArchiveFormat::TarGz => 0..=9,
+            ArchiveFormat::TarZst => 1..=22,
+        }
+    }
+
+    pub fn check_level(self, level: i32) -> Result<(), String> {
+        let levels = self.levels();
+        if levels.contains(&level) {
+            return Ok(());
+        }
+        Err(format!("{} --compression-level {} is out of range for {} ({}-{})", CROSS, level, self.extension(), levels.start(), levels.end()))
+    }
+
+    // The format of an archive, from its file name; split archives go by
+    // their first volume, the rest being no archive on their own
+    pub fn of(path: &Path) -> Option<Self> {
+        #[cfg(feature = "archive")]
+        let path = match crate::volumes::split(path) {
+            Some((archive, 1)) => archive,
+            Some(_) => return None,
+            None => path.to_path_buf(),
+        };
+        let name = path.file_name()?.to_string_lossy().into_owned();
+        Self::ALL.into_iter().find(|format| name.ends_with(&format!(".{}", format.extension())))
+    }
+}
+
+#[cfg(feature = "archive")]
+mod enabled {
+    use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+    use indicatif::{ProgressBar, ProgressStyle};
+    use std::{fs, io::{self, Read}, path::{Component, Path, PathBuf}, sync::{atomic::{AtomicU64, Ordering}, Mutex}};
+    use walkdir::{DirEntry, WalkDir};
+    use log::{debug, warn};
+
+    use super::ArchiveFormat;
+    use crate::{backend::BackupOptions, backup::BackupExclude, manifest::{self, Hashing, Manifest, ManifestFile}, naming, prefetch, progress, restore, tarball, throttle::{RateLimiter, Throttled}, volumes, CROSS};
+
+    // zstd's own default, a good trade of speed for size
+    const ZSTD_DEFAULT_LEVEL: i32 = 3;
+
+    // Partials from runs that were killed before they could clean up. Only the
+    // first archive written to a directory looks, later ones would find the
+    // partials of archives still being written alongside them.
+    fn remove_stale_partials(backup_path: &Path) {
+        static CLEANED: Mutex<Vec<PathBuf>> = Mutex::new(Vec::new());
+        let Ok(mut cleaned) = CLEANED.lock() else {
+            return;
+        };
+        if cleaned.iter().any(|dir| dir == backup_path) {
+            return;
+        }
+        cleaned.push(backup_path.to_path_buf());
+        let Ok(entries) = fs::read_dir(backup_path) else {
+            return;
+        };
+        for entry in entries.filter_map(|e| e.ok()) {
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+            // Volumes are partial under their own names
+            let partial = name.strip_suffix(volumes::PART_SUFFIX)
+                .map(|archive| volumes::split(Path::new(archive)).map_or_else(|| PathBuf::from(archive), |(archive, _)| archive))
+                .is_some_and(|archive| ArchiveFormat::of(&archive).is_some());
+            if partial {
+                warn!("Removing partial archive left by an interrupted run: {}", entry.path().display());
+                let _ = fs::remove_file(entry.path());
+            }
+        }
+    }
+
+    // `<name>_<timestamp>.zip` (or .tar.gz), or as the template names it,
+    // numbered when archives of directories with the same name finish within
+    // a second. Creating the partial of the first volume claims the name.
+    fn claim_archive_path(backup_path: &Path, stem: &Path, format: ArchiveFormat, split: Option<u64>) -> io::Result<(PathBuf, fs::File)> {
+        let mut attempt = 1;
+        loop {
+            let archive_path = backup_path.join(naming::numbered(stem, attempt, Some(format.extension())));
+            if !archive_path.exists() && !volumes::volume(&archive_path, 1).exists() {
+                match fs::OpenOptions::new().read(true).write(true).create_new(true).open(volumes::Writer::first_part(&archive_path, split)) {
+                    Ok(file) => return Ok((archive_path, file)),
+                    Err(e) if e.kind() != io::ErrorKind::AlreadyExists => return Err(e),
+                    Err(_) => {}
+                }
+            }
+            attempt += 1;
+        }
+    }
+
+    // Byte progress of one archive, with the file being added and the compression so far
+    fn archive_progress(total_bytes: u64) -> ProgressBar {
+        let pb = progress().add(ProgressBar::new(total_bytes));
+        pb.set_style(
+            ProgressStyle::default_bar()
+                .template("  📦 [{bar:30.green/white}] {bytes}/{total_bytes} {bytes_per_sec} {wide_msg}")
+                .unwrap()
+        );
+        pb
+    }
+
+    // The files of one archive, opened in order with the next ones hinted to the
+    // OS, and checksummed into the manifest as they are read
+    struct Sources<'a> {
+        paths: Vec<&'a Path>,
+        opened: usize,
+        hinted: usize,
+        readahead: bool,
+        manifest: Manifest,
+    }
+
+    impl Sources<'_> {
+        fn open(&mut self, path: &Path) -> Result<Hashing<fs::File>, String> {
+            if self.readahead {
+                prefetch::hint_ahead(&self.paths, self.opened, &mut self.hinted);
+                self.opened += 1;
+            }
+            prefetch::open_sequential(path)
+                .map(Hashing::new)
+                .map_err(|e| format!("{} Failed to open file for archiving: {}", CROSS, e))
+        }
+
+        // Note a file once all of it has gone into the archive
+        fn archived(&mut self, name: &str, metadata: &fs::Metadata, read: Hashing<fs::File>) {
+            let (size, sha256) = read.finish();
+            self.manifest.files.push(ManifestFile { path: name.to_string(), size, mtime: mtime(metadata), sha256 });
+        }
+    }
+
+    fn mtime(metadata: &fs::Metadata) -> u64 {
+        metadata.modified().ok()
+            .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+            .map_or(0, |d| d.as_secs())
+    }
+
+    fn relative_name(root: &Path, path: &Path) -> String {
+        path.strip_prefix(root)
+            .unwrap_or(path)
+            .to_string_lossy()
+            .replace('\\', "/")
+    }
+
+    // What `path` is stored as: relative to `root`, under `prefix` if there
+    // is one, in which case `root` itself is stored as `prefix`
+    fn entry_name(root: &Path, path: &Path, prefix: Option<&str>) -> String {
+        let relative = relative_name(root, path);
+        match prefix {
+            Some(prefix) if relative.is_empty() => prefix.to_string(),
+            Some(prefix) => format!("{}/{}", prefix, relative),
+            None => relative,
+        }
+    }
+
+    // Every file of the archive at `path`, one per volume when split
+    pub fn files(path: &Path) -> Vec<PathBuf> {
+        volumes::all(path)
+    }
+
+    // Packed as `opts` ask; the manifest goes in last and, if asked, beside the
+    // archive as `<archive>.manifest.json`. Returns the archive, or its first
+    // volume when split.
+    pub fn create(path: &str, backup_dir: &str, opts: &BackupOptions) -> Result<String, String> {
+        let (format, level) = (opts.archive_format, opts.compression_level);
+        let dir_path = Path::new(path);
+        let backup_path = Path::new(backup_dir);
+
+        let name = dir_path.file_name()
+            .ok_or_else(|| format!("{} Invalid directory name", CROSS))?
+            .to_string_lossy();
+        let now = chrono::Local::now();
+        let stem = match &opts.name_template {
+            Some(template) => template.render(dir_path, &now),
+            None => PathBuf::from(format!("{}_{}", name, now.format("%Y%m%d_%H%M%S"))),
+        };
+        // Templates may keep archives in directories of their own
+        let backup_path = &backup_path.join(stem.parent().unwrap_or(Path::new("")));
+        let stem = Path::new(stem.file_name().expect("templates name a file"));
+        fs::create_dir_all(backup_path)
+            .map_err(|e| format!("{} Failed to create backup directory: {}", CROSS, e))?;
+
+        remove_stale_partials(backup_path);
+
+        // Written under temporary names and renamed once complete and synced
+        let (archive_path, first) = claim_archive_path(backup_path, stem, format, opts.split_size)
+            .map_err(|e| format!("{} Failed to create archive file: {}", CROSS, e))?;
+        let out = volumes::Writer::new(&archive_path, opts.split_size, first);
+        let written = out.written();
+
+        let mut limiter = RateLimiter::new(opts.upload_limit);
+        let out = Throttled::new(out, &mut limiter);
+
+        let (entries, pb) = walk(dir_path, &opts.exclude);
+        let sources = sources(dir_path, &entries, opts)?;
+        let (out, manifest) = match format {
+            ArchiveFormat::Zip => write_zip(dir_path, &entries, out, sources, level, &pb, &written)?,
+            _ => {
+                let (out, manifest) = compress_tar(dir_path, &entries, out, sources, opts, &pb, Some(&written))?;
+                (out.into_inner(), manifest)
+            }
+        };
+        pb.finish_and_clear();
+        let archive_path = out.commit()
+            .map_err(|e| format!("{} Failed to move archive into place: {}", CROSS, e))?;
+
+        if opts.archive_manifest {
+            let beside = PathBuf::from(format!("{}.manifest.json", archive_path.display()));
+            fs::write(&beside, manifest.to_json().map_err(write_failed)?)
+                .map_err(|e| format!("{} Failed to write manifest {}: {}", CROSS, beside.display(), e))?;
+        }
+
+        Ok(archive_path.to_string_lossy().to_string())
+    }
+
+    // Everything under `dir_path` but what `exclude` leaves out, and a progress
+    // bar sized to its files
+    fn walk(dir_path: &Path, exclude: &BackupExclude) -> (Vec<DirEntry>, ProgressBar) {
+        let entries: Vec<_> = WalkDir::new(dir_path).into_iter()
+            .filter_entry(|e| !exclude.matches(dir_path, e.path()))
+            .filter_map(|e| e.ok())
+            .collect();
+        let total_bytes = entries.iter()
+            .filter(|e| e.file_type().is_file())
+            .filter_map(|e| e.metadata().ok())
+            .map(|m| m.len())
+            .sum();
+        (entries, archive_progress(total_bytes))
+    }
+
+    fn sources<'a>(dir_path: &Path, entries: &'a [DirEntry], opts: &BackupOptions) -> Result<Sources<'a>, String> {
+        let mut manifest = Manifest::new(&restore::absolute(dir_path)?.to_string_lossy());
+        if opts.top_dir {
+            manifest.prefix = dir_path.file_name().map(|name| name.to_string_lossy().into_owned());
+        }
+        Ok(Sources {
+            paths: entries.iter().filter(|e| e.file_type().is_file()).map(|e| e.path()).collect(),
+            opened: 0,
+            hinted: 0,
+            readahead: opts.readahead,
+            manifest,
+        })
+    }
+
+    // A tar of `root` through the compressor `opts` ask for, which is finished
+    // before `out` is handed back
+    fn compress_tar<W: io::Write>(root: &Path, entries: &[DirEntry], out: W, sources: Sources, opts: &BackupOptions, pb: &ProgressBar, written: Option<&AtomicU64>) -> Result<(W, Manifest), String> {
+        let level = opts.compression_level;
+        match opts.archive_format {
+            ArchiveFormat::TarGz => {
+                let level = level.map_or(Compression::default(), |level| Compression::new(level as u32));
+                let (tar, manifest) = write_tar(root, entries, GzEncoder::new(out, level), sources, pb, written)?;
+                Ok((tar.finish().map_err(write_failed)?, manifest))
+            }
+            ArchiveFormat::TarZst => {
+                let mut zst = zstd::Encoder::new(out, level.unwrap_or(ZSTD_DEFAULT_LEVEL)).map_err(write_failed)?;
+                // So a corrupted frame is caught when it is read back
+                zst.include_checksum(true).map_err(write_failed)?;
+                let (tar, manifest) = write_tar(root, entries, zst, sources, pb, written)?;
+                Ok((tar.finish().map_err(write_failed)?, manifest))
+            }
+            ArchiveFormat::Zip => Err(format!("{} Zip archives can't be streamed: use --archive-format tar.gz or tar.zst", CROSS)),
+        }
+    }
+
+    // `path` as a compressed tar written straight to `out`, for destinations
+    // that take a stream rather than a file
+    #[cfg(feature = "s3")]
+    pub fn stream<W: io::Write>(path: &str, out: W, opts: &BackupOptions) -> Result<(W, Manifest), String> {
+        let dir_path = Path::new(path);
+        let (entries, pb) = walk(dir_path, &opts.exclude);
+        let sources = sources(dir_path, &entries, opts)?;
+        let streamed = compress_tar(dir_path, &entries, out, sources, opts, &pb, None);
+        pb.finish_and_clear();
+        streamed
+    }
+
+    fn write_zip(root: &Path, entries: &[DirEntry], out: Throttled<volumes::Writer>, mut sources: Sources, level: Option<i32>, pb: &ProgressBar, written: &AtomicU64) -> Result<(volumes::Writer, Manifest), String> {
+        let mut zip = zip::ZipWriter::new(out);
+
+        let options = zip::write::FileOptions::default()
+            .compression_method(zip::CompressionMethod::Deflated)
+            .compression_level(level)
+            .unix_permissions(0o644);
+
+        // Entries are named as in tars; the root only has one under a prefix
+        let prefix = sources.manifest.prefix.clone();
+        for entry in entries.iter().filter(|e| e.depth() > 0 || prefix.is_some()) {
+            let path = entry.path();
+            let name = entry_name(root, path, prefix.as_deref());
+
+            if path.is_file() {
+                debug!("Adding to archive: {}", name);
+                pb.set_message(format!("{}{}", name, compression_note(pb.position(), Some(written))));
+                let metadata = entry.metadata()
+                    .map_err(|e| format!("{} Failed to read {}: {}", CROSS, path.display(), e))?;
+                // Nothing to gain from deflating nothing, and a compressor to set up per entry
+                let options = if metadata.len() == 0 { options.compression_method(zip::CompressionMethod::Stored) } else { options };
+                let options = options.unix_permissions(permissions(&metadata, &tarball::Kind::File));
+                zip.start_file(name.as_str(), options.large_file(needs_zip64(metadata.len())))
+                    .map_err(|e| format!("{} Failed to add file to archive: {}", CROSS, e))?;
+
+                let mut f = sources.open(path)?;
+                io::copy(&mut pb.wrap_read(&mut f), &mut zip)
+                    .map_err(|e| format!("{} Failed to write file to archive: {}", CROSS, e))?;
+                sources.archived(&relative_name(root, path), &metadata, f);
+            } else if path.is_dir() {
+                let metadata = entry.metadata()
+                    .map_err(|e| format!("{} Failed to read {}: {}", CROSS, path.display(), e))?;
+                zip.add_directory(name.as_str(), options.unix_permissions(permissions(&metadata, &tarball::Kind::Dir)))
+                    .map_err(|e| format!("{} Failed to add directory to archive: {}", CROSS, e))?;
+            }
+        }
+
+        let manifest = sources.manifest;
+        zip.start_file(manifest::NAME, options)
+            .map_err(|e| format!("{} Failed to add file to archive: {}", CROSS, e))?;
+        io::Write::write_all(&mut zip, &manifest.to_json().map_err(write_failed)?).map_err(write_failed)?;
+
+        let file = zip.finish()
+            .map_err(|e| format!("{} Failed to finalize archive: {}", CROSS, e))?
+            .into_inner();
+        Ok((file, manifest))
+    }
+
+    // Whether a file of `len` bytes may come to 4 GiB or more once deflated:
+    // those need Zip64 sizes in their local header, which has to be written
+    // before the data. Incompressible data grows by far less than 1/256.
+    // More than 65,535 entries and offsets past 4 GiB get Zip64 records in the
+    // central directory without asking.
+    fn needs_zip64(len: u64) -> bool {
+        len + len / 256 + 1024 > u32::MAX as u64
+    }
+
+    fn write_failed(e: io::Error) -> String {
+        format!("{} Failed to write file to archive: {}", CROSS, e)
+    }
+
+    // Entries are named relative to the archived directory, under its own
+    // name with --archive-top-dir. Symlinks are stored as links and modes,
+    // owners and times are kept. Returns the compressor, for the caller to finish.
+    fn write_tar<W: io::Write>(root: &Path, entries: &[DirEntry], out: W, mut sources: Sources, pb: &ProgressBar, written: Option<&AtomicU64>) -> Result<(W, Manifest), String> {
+        let mut tar = tarball::Writer::new(out);
+        let failed = write_failed;
+
+        let prefix = sources.manifest.prefix.clone();
+        for entry in entries.iter().filter(|e| e.depth() > 0 || prefix.is_some()) {
+            let path = entry.path();
+            let name = entry_name(root, path, prefix.as_deref());
+            let metadata = entry.metadata()
+                .map_err(|e| format!("{} Failed to read {}: {}", CROSS, path.display(), e))?;
+            let kind = if entry.file_type().is_symlink() {
+                let target = fs::read_link(path)
+                    .map_err(|e| format!("{} Failed to read link {}: {}", CROSS, path.display(), e))?;
+                tarball::Kind::Symlink(target.to_string_lossy().replace('\\', "/"))
+            } else if entry.file_type().is_dir() {
+                tarball::Kind::Dir
+            } else if entry.file_type().is_file() {
+                tarball::Kind::File
+            } else {
+                debug!("Not archiving special file {}", path.display());
+                continue;
+            };
+            let header = tarball::Entry {
+                name: name.clone(),
+                size: metadata.len(),
+                mode: permissions(&metadata, &kind),
+                mtime: mtime(&metadata),
+                uid: owner(&metadata).0,
+                gid: owner(&metadata).1,
+                kind,
+            };
+
+            if header.kind == tarball::Kind::File {
+                debug!("Adding to archive: {}", name);
+                pb.set_message(format!("{}{}", name, compression_note(pb.position(), written)));
+                let mut f = sources.open(path)?;
+                tar.append(&header, Some(&mut pb.wrap_read(&mut f))).map_err(failed)?;
+                sources.archived(&relative_name(root, path), &metadata, f);
+            } else {
+                tar.append(&header, None).map_err(failed)?;
+            }
+        }
+
+        let manifest = sources.manifest;
+        let json = manifest.to_json().map_err(failed)?;
+        let header = tarball::Entry {
+            name: manifest::NAME.to_string(),
+            kind: tarball::Kind::File,
+            size: json.len() as u64,
+            mode: 0o644,
+            mtime: std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).map_or(0, |d| d.as_secs()),
+            uid: 0,
+            gid: 0,
+        };
+        tar.append(&header, Some(&mut json.as_slice())).map_err(failed)?;
+        Ok((tar.finish().map_err(failed)?, manifest))
+    }
+
+    #[cfg(unix)]
+    fn permissions(metadata: &fs::Metadata, _kind: &tarball::Kind) -> u32 {
+        use std::os::unix::fs::PermissionsExt;
+        metadata.permissions().mode()
+    }
+
+    #[cfg(not(unix))]
+    fn permissions(metadata: &fs::Metadata, kind: &tarball::Kind) -> u32 {
+        match kind {
+            tarball::Kind::File if metadata.permissions().readonly() => 0o444,
+            tarball::Kind::File => 0o644,
+            _ => 0o755,
+        }
+    }
+
+    #[cfg(unix)]
+    fn owner(metadata: &fs::Metadata) -> (u64, u64) {
+        use std::os::unix::fs::MetadataExt;
+        (metadata.uid() as u64, metadata.gid() as u64)
+    }
+
+    #[cfg(not(unix))]
+    fn owner(_metadata: &fs::Metadata) -> (u64, u64) {
+        (0, 0)
+    }
+
+    // Compressed size so far relative to the bytes read, from what reached the
+    // archive's volumes; nothing for streams
+    fn compression_note(read: u64, written: Option<&AtomicU64>) -> String {
+        let Some(written) = written.filter(|_| read > 0) else {
+            return String::new();
+        };
+        format!(" (ratio {:.0}%)", written.load(Ordering::Relaxed) as f64 * 100.0 / read as f64)
+    }
+
+    fn open_tar(path: &Path) -> io::Result<Box<dyn Read>> {
+        let file = io::BufReader::new(volumes::Reader::open(path)?);
+        Ok(match ArchiveFormat::of(path) {
+            Some(ArchiveFormat::TarZst) => Box::new(zstd::Decoder::with_buffer(file)?),
+            _ => Box::new(GzDecoder::new(file)),
+        })
+    }
+
+    fn is_tar(path: &Path) -> bool {
+        ArchiveFormat::of(path).is_some_and(ArchiveFormat::is_tar)
+    }
+
+    // Names relative to the archived directory, whether or not they were
+    // stored under its name
+    pub fn list_entries(path: &Path) -> Result<Vec<String>, String> {
+        let (names, json) = if is_tar(path) {
+            let (mut names, mut json) = (Vec::new(), None);
+            tarball::each_entry(open_tar(path).map_err(|e| e.to_string())?, |entry, data| {
+                if entry.name == manifest::NAME {
+                    json = Some(read_all(data)?);
+                } else {
+                    names.push(entry.name.clone());
+                }
+                Ok(())
+            })
+            .map_err(|e| e.to_string())?;
+            (names, json)
+        } else {
+            let file = volumes::Reader::open(path).map_err(|e| e.to_string())?;
+            let mut archive = zip::ZipArchive::new(file).map_err(|e| e.to_string())?;
+            let names = archive.file_names().filter(|name| *name != manifest::NAME).map(String::from).collect();
+            (names, zip_manifest(&mut archive, path)?)
+        };
+        let prefix = json.map(|json| parse_manifest(&json, path)).transpose()?.and_then(|manifest| manifest.prefix);
+        Ok(names.into_iter().filter_map(|name| unprefixed(prefix.as_deref(), &name).map(String::from)).collect())
+    }
+
+    // The directory an archive was made from, as its manifest records it;
+    // None for archives without one
+    pub fn source(path: &Path) -> Result<Option<String>, String> {
+        let json = if is_tar(path) {
+            let mut json = None;
+            tarball::each_entry(open_tar(path).map_err(|e| e.to_string())?, |entry, data| {
+                if entry.name == manifest::NAME {
+                    json = Some(read_all(data)?);
+                }
+                Ok(())
+            })
+            .map_err(|e| format!("{} Failed to read archive {}: {}", CROSS, path.display(), e))?;
+            json
+        } else {
+            let file = volumes::Reader::open(path)
+                .map_err(|e| format!("{} Failed to open archive: {}", CROSS, e))?;
+            let mut archive = zip::ZipArchive::new(file)
+                .map_err(|e| format!("{} Failed to read archive: {}", CROSS, e))?;
+            zip_manifest(&mut archive, path)?
+        };
+        json.map(|json| parse_manifest(&json, path).map(|manifest| manifest.source)).transpose()
+    }
+
+    fn read_all(data: &mut dyn Read) -> io::Result<Vec<u8>> {
+        let mut read = Vec::new();
+        data.read_to_end(&mut read)?;
+        Ok(read)
+    }
+
+    // Zips can be read out of order, so the manifest is had without the rest
+    fn zip_manifest(archive: &mut zip::ZipArchive<volumes::Reader>, path: &Path) -> Result<Option<Vec<u8>>, String> {
+        let Ok(mut entry) = archive.by_name(manifest::NAME) else {
+            return Ok(None);
+        };
+        read_all(&mut entry)
+            .map(Some)
+            .map_err(|e| format!("{} Failed to read the manifest of {}: {}", CROSS, path.display(), e))
+    }
+
+    fn parse_manifest(json: &[u8], path: &Path) -> Result<Manifest, String> {
+        serde_json::from_slice(json).map_err(|e| format!("{} The manifest of {} is damaged: {}", CROSS, path.display(), e))
+    }
+
+    // `name` without the prefix it was stored under; None for the prefix itself
+    fn unprefixed<'a>(prefix: Option<&str>, name: &'a str) -> Option<&'a str> {
+        let Some(prefix) = prefix else {
+            return Some(name);
+        };
+        match name.strip_prefix(prefix) {
+            Some("" | "/") => None,
+            Some(rest) if rest.starts_with('/') => Some(&rest[1..]),
+            _ => Some(name),
+        }
+    }
+
+    // An entry's path under `dest`, None for names that would land outside it
+    fn inside(dest: &Path, name: &str) -> Option<PathBuf> {
+        let relative = Path::new(name);
+        let plain = relative.components().all(|c| matches!(c, Component::Normal(_)));
+        (plain && !name.is_empty()).then(|| dest.join(relative))
+    }
+
+    // Unpack an archive into `dest`, keeping file modes where they were
+    // recorded. Entries that would land outside `dest` are refused. What was
+    // stored under the directory's name goes straight into `dest` too.
+    pub fn extract(archive_path: &Path, dest: &Path) -> Result<usize, String> {
+        let (files, json) = if is_tar(archive_path) { extract_tar(archive_path, dest)? } else { extract_zip(archive_path, dest)? };
+        if let Some(prefix) = json.map(|json| parse_manifest(&json, archive_path)).transpose()?.and_then(|manifest| manifest.prefix) {
+            lift(dest, &prefix).map_err(|e| format!("{} Failed to move {} up into {}: {}", CROSS, prefix, dest.display(), e))?;
+        }
+        debug!("Extracted {} files from {} into {}", files, archive_path.display(), dest.display());
+        Ok(files)
+    }
+
+    // `dest/<prefix>` becomes `dest`, by way of a name beside it
+    fn lift(dest: &Path, prefix: &str) -> io::Result<()> {
+        let mut parts = Path::new(prefix).components();
+        if !matches!((parts.next(), parts.next()), (Some(Component::Normal(_)), None)) {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "the manifest's prefix isn't a directory name"));
+        }
+        let staged = dest.with_file_name(format!(".{}.dirpurge-unpacking", prefix));
+        fs::rename(dest.join(prefix), &staged)?;
+        fs::remove_dir(dest)?;
+        fs::rename(&staged, dest)
+    }
+
+    // The files unpacked, and the manifest
+    fn extract_zip(archive_path: &Path, dest: &Path) -> Result<(usize, Option<Vec<u8>>), String> {
+        let file = volumes::Reader::open(archive_path)
+            .map_err(|e| format!("{} Failed to open archive: {}", CROSS, e))?;
+        let mut archive = zip::ZipArchive::new(file)
+            .map_err(|e| format!("{} Failed to read archive: {}", CROSS, e))?;
+        let json = zip_manifest(&mut archive, archive_path)?;
+        fs::create_dir_all(dest)
+            .map_err(|e| format!("{} Failed to create {}: {}", CROSS, dest.display(), e))?;
+
+        let mut files = 0;
+        for i in 0..archive.len() {
+            let mut entry = archive.by_index(i)
+                .map_err(|e| format!("{} Failed to read archive entry: {}", CROSS, e))?;
+            if entry.name() == manifest::NAME {
+                continue;
+            }
+            let relative = entry.enclosed_name()
+                .filter(|name| !name.as_os_str().is_empty())
+                .map(Path::to_path_buf)
+                .ok_or_else(|| format!("{} Refusing archive entry '{}': not a path inside the archive", CROSS, entry.name()))?;
+            let target = dest.join(relative);
+            if entry.is_dir() {
+                fs::create_dir_all(&target)
+                    .map_err(|e| format!("{} Failed to create {}: {}", CROSS, target.display(), e))?;
+                continue;
+            }
+            if let Some(parent) = target.parent() {
+                fs::create_dir_all(parent)
+                    .map_err(|e| format!("{} Failed to create {}: {}", CROSS, parent.display(), e))?;
+            }
+            let mut out = fs::File::create_new(&target)
+                .map_err(|e| format!("{} Failed to create {}: {}", CROSS, target.display(), e))?;
+            io::copy(&mut entry, &mut out)
+                .map_err(|e| format!("{} Failed to extract {}: {}", CROSS, target.display(), e))?;
+            #[cfg(unix)]
+            if let Some(mode) = entry.unix_mode() {
+                use std::os::unix::fs::PermissionsExt;
+                fs::set_permissions(&target, fs::Permissions::from_mode(mode))
+                    .map_err(|e| format!("{} Failed to set permissions on {}: {}", CROSS, target.display(), e))?;
+            }
+            files += 1;
+        }
+        Ok((files, json))
+    }
+
+    // Links are recreated as links. Nothing is written through one, so a link
+    // stored ahead of a file, or of a file by its own name, can't redirect it
+    // outside `dest`. Modes are kept, and owners too when run as root.
+    fn extract_tar(archive_path: &Path, dest: &Path) -> Result<(usize, Option<Vec<u8>>), String> {
+        let input = open_tar(archive_path)
+            .map_err(|e| format!("{} Failed to open archive: {}", CROSS, e))?;
+        fs::create_dir_all(dest)
+            .map_err(|e| format!("{} Failed to create {}: {}", CROSS, dest.display(), e))?;
+
+        let (mut files, mut json) = (0, None);
+        let mut refused = None;
+        // Set once everything is in, so a read-only directory can still be filled
+        let mut dir_modes = Vec::new();
+        let extracted = tarball::each_entry(input, |entry, data| {
+            if entry.name == manifest::NAME {
+                json = Some(read_all(data)?);
+                return Ok(());
+            }
+            let Some(target) = inside(dest, &entry.name) else {
+                refused = Some(format!("{} Refusing archive entry '{}': not a path inside the archive", CROSS, entry.name));
+                return Err(io::Error::other("refused entry"));
+            };
+            let through_link = target.strip_prefix(dest).ok()
+                .is_some_and(|relative| relative.ancestors().any(|a| !a.as_os_str().is_empty() && dest.join(a).is_symlink()));
+            if through_link {
+                refused = Some(format!("{} Refusing archive entry '{}': it would be written through a link", CROSS, entry.name));
+                return Err(io::Error::other("refused entry"));
+            }
+            if let Some(parent) = target.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            match &entry.kind {
+                tarball::Kind::Dir => fs::create_dir_all(&target)?,
+                tarball::Kind::File => {
+                    io::copy(data, &mut fs::File::create_new(&target)?)?;
+                    let modified = std::time::UNIX_EPOCH + std::time::Duration::from_secs(entry.mtime);
+                    fs::File::options().write(true).open(&target)?.set_modified(modified)?;
+                    files += 1;
+                }
+                tarball::Kind::Symlink(link) => symlink(link, &target)?,
+            }
+            set_owner(&target, entry.uid, entry.gid)?;
+            match entry.kind {
+                tarball::Kind::Dir => dir_modes.push((target, entry.mode)),
+                tarball::Kind::File => set_mode(&target, entry.mode)?,
+                tarball::Kind::Symlink(_) => {}
+            }
+            Ok(())
+        });
+        if let Some(refused) = refused {
+            return Err(refused);
+        }
+        // Deepest first, so no directory is closed off before its children
+        let extracted = extracted.and_then(|_| dir_modes.iter().rev().try_for_each(|(dir, mode)| set_mode(dir, *mode)));
+        extracted.map_err(|e| format!("{} Failed to extract {}: {}", CROSS, archive_path.display(), e))?;
+        Ok((files, json))
+    }
+
+    #[cfg(unix)]
+    fn set_mode(path: &Path, mode: u32) -> io::Result<()> {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(path, fs::Permissions::from_mode(mode & 0o7777))
+    }
+
+    #[cfg(not(unix))]
+    fn set_mode(_path: &Path, _mode: u32) -> io::Result<()> {
+        Ok(())
+    }
+
+    // Only root can give files away; anyone else keeps what they extract
+    #[cfg(unix)]
+    fn set_owner(path: &Path, uid: u64, gid: u64) -> io::Result<()> {
+        if unsafe { libc::geteuid() } != 0 {
+            return Ok(());
+        }
+        std::os::unix::fs::lchown(path, Some(uid as u32), Some(gid as u32))
+    }
+
+    #[cfg(not(unix))]
+    fn set_owner(_path: &Path, _uid: u64, _gid: u64) -> io::Result<()> {
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    fn symlink(link: &str, target: &Path) -> io::Result<()> {
+        std::os::unix::fs::symlink(link, target)
+    }
+
+    #[cfg(not(unix))]
+    fn symlink(link: &str, target: &Path) -> io::Result<()> {
+        warn!("Not restoring link {} -> {}: links need Unix", target.display(), link);
+        Ok(())
+    }
+
+    // Call `visit` with the name and contents of every file in an archive
+    pub fn each_file(path: &Path, mut visit: impl FnMut(&str, &mut dyn Read) -> io::Result<()>) -> Result<(), String> {
+        if is_tar(path) {
+            let mut input = open_tar(path)
+                .map_err(|e| format!("{} Failed to open archive: {}", CROSS, e))?;
+            return tarball::each_entry(&mut input, |entry, data| match entry.kind {
+                tarball::Kind::File => visit(&entry.name, data),
+                _ => Ok(()),
+            })
+            // Read on to the end, where the compressor's checksum is checked
+            .and_then(|_| io::copy(&mut input, &mut io::sink()))
+            .map(|_| ())
+            .map_err(|e| format!("{} Failed to read archive: {}", CROSS, e));
+        }
+        let file = volumes::Reader::open(path)
+            .map_err(|e| format!("{} Failed to open archive: {}", CROSS, e))?;
+        let mut archive = zip::ZipArchive::new(file)
+            .map_err(|e| format!("{} Failed to read archive: {}", CROSS, e))?;
+        for i in 0..archive.len() {
+            let mut entry = archive.by_index(i)
+                .map_err(|e| format!("{} Failed to read archive entry: {}", CROSS, e))?;
+            if entry.is_file() {
+                let name = entry.name().to_string();
+                visit(&name, &mut entry)
+                    .map_err(|e| format!("{} Failed to read {} from archive: {}", CROSS, name, e))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(not(feature = "archive"))]
+mod disabled {
+    use std::{io::{self, Read}, path::{Path, PathBuf}};
+
+    use crate::{backend::BackupOptions, feature_disabled};
+
+    // Only builds with archive support split archives
+    pub fn files(path: &Path) -> Vec<PathBuf> {
+        vec![path.to_path_buf()]
+    }
+
+    pub fn create(_path: &str, _backup_dir: &str, _opts: &BackupOptions) -> Result<String, String> {
+        Err(feature_disabled("archive"))
+    }
+
+    pub fn list_entries(_path: &Path) -> Result<Vec<String>, String> {
+        Err(feature_disabled("archive"))
+    }
+
+    pub fn extract(_archive_path: &Path, _dest: &Path) -> Result<usize, String> {
+        Err(feature_disabled("archive"))
+    }
+
+    pub fn source(_path: &Path) -> Result<Option<String>, String> {
+        Err(feature_disabled("archive"))
+    }
+
+    pub fn each_file(_path: &Path, _visit: impl FnMut(&str, &mut dyn Read) -> io::Result<()>) -> Result<(), String> {
+        Err(feature_disabled("archive"))
+    }
+}
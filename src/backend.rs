@@ -0,0 +1,100 @@
+use std::path::PathBuf;
+
+use crate::{archive::{self, ArchiveFormat}, backup, naming, preserve::Preserve, CROSS};
+
+// What to store for each directory, shared by every backend
+// Builds without archive support only read some of these
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(not(feature = "archive"), allow(dead_code))]
+pub struct BackupOptions {
+    pub archive: bool,
+    pub archive_format: ArchiveFormat,
+    pub compression_level: Option<i32>,
+    // Also write each archive's manifest beside it
+    pub archive_manifest: bool,
+    // Store archive entries under the directory's own name
+    pub top_dir: bool,
+    // Bytes per archive volume; archives are one file if unset
+    pub split_size: Option<u64>,
+    // Where in the destination backups go; `<name>_<timestamp>` if unset
+    pub name_template: Option<naming::NameTemplate>,
+    // Bytes per second written to the destination
+    pub upload_limit: Option<u64>,
+    // S3 storage class to upload the archive in; the bucket's default if unset
+    #[cfg(feature = "s3")]
+    pub storage_class: Option<String>,
+    // Hint the OS to read files ahead of copying them
+    pub readahead: bool,
+    // Copies an interrupted run left unfinished, to carry on with
+    pub partial: Vec<String>,
+    // Link files unchanged since the last copy instead of copying them again
+    pub incremental: bool,
+    // Left out of copies and archives
+    pub exclude: backup::BackupExclude,
+    // What plain copies keep of the original's metadata
+    pub preserve: Preserve,
+    // Whether plain copies clone files by reflink
+    pub strategy: backup::BackupStrategy,
+}
+
+// A place backups can be written to, selected by the URI scheme of --backup-dir.
+// Shared by the deletion workers, so it has to be thread-safe.
+pub trait BackupBackend: Send + Sync {
+    // Human-readable destination, used in messages and logs
+    fn describe(&self) -> String;
+
+    // Copy or archive `path` into the destination, returning where it was stored
+    fn store(&self, path: &str, opts: &BackupOptions) -> Result<String, String>;
+
+    // Local path of a stored backup, when it can be read back for verification
+    fn local_copy(&self, stored: &str) -> Option<PathBuf>;
+}
+
+pub struct LocalBackend {
+    root: String,
+}
+
+impl BackupBackend for LocalBackend {
+    fn describe(&self) -> String {
+        self.root.clone()
+    }
+
+    fn store(&self, path: &str, opts: &BackupOptions) -> Result<String, String> {
+        if opts.archive {
+            archive::create(path, &self.root, opts)
+        } else {
+            backup::backup_directory(path, &self.root, opts)
+        }
+    }
+
+    fn local_copy(&self, stored: &str) -> Option<PathBuf> {
+        Some(PathBuf::from(stored))
+    }
+}
+
+// Plain paths and file:// URIs are local directories. Remote backends register
+// their scheme here, each behind its own cargo feature.
+pub fn open_backend(uri: &str) -> Result<Box<dyn BackupBackend>, String> {
+    match uri.split_once("://") {
+        None => Ok(Box::new(LocalBackend { root: uri.to_string() })),
+        Some(("file", path)) => Ok(Box::new(LocalBackend { root: path.to_string() })),
+        #[cfg(feature = "s3")]
+        Some(("s3", location)) => Ok(Box::new(crate::s3::S3Backend::open(location)?)),
+        #[cfg(not(feature = "s3"))]
+        Some(("s3", _)) => Err(crate::feature_disabled("s3")),
+        #[cfg(feature = "sftp")]
+        Some(("sftp", location)) => Ok(Box::new(crate::sftp::SftpBackend::open(location)?)),
+        #[cfg(not(feature = "sftp"))]
+        Some(("sftp", _)) => Err(crate::feature_disabled("sftp")),
+        Some((scheme, _)) => Err(format!("{} Unsupported backup destination '{}': no backend for {}://", CROSS, uri, scheme)),
+    }
+}
+
+// Local directory behind a destination, for commands that read backups back
+pub fn local_root(uri: &str) -> Option<PathBuf> {
+    match uri.split_once("://") {
+        None => Some(PathBuf::from(uri)),
+        Some(("file", path)) => Some(PathBuf::from(path)),
+        Some(_) => None,
+    }
+}
@@ -0,0 +1,561 @@
+use serde::{Deserialize, Serialize};
+use std::{cell::Cell, fmt, fs, io, path::{Path, PathBuf}, str::FromStr};
+use walkdir::WalkDir;
+use log::{debug, warn};
+
+use regex::Regex;
+
+use crate::{archive::{self, ArchiveFormat}, backend::{self, BackupBackend, BackupOptions}, cow, format_mb, gitignore, journal::{self, Checkpoints, Event}, locale, naming, prefetch, preserve::{self, Preserve}, throttle::{RateLimiter, Throttled}, report::{self, Kind}, CROSS};
+
+// How many backup destinations must succeed before a directory may be deleted
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(try_from = "String", into = "String")]
+pub enum BackupPolicy {
+    #[default]
+    All,
+    Any,
+    Quorum(usize),
+}
+
+impl BackupPolicy {
+    pub fn required(&self, destinations: usize) -> usize {
+        match self {
+            BackupPolicy::All => destinations,
+            BackupPolicy::Any => destinations.min(1),
+            BackupPolicy::Quorum(n) => (*n).min(destinations),
+        }
+    }
+}
+
+impl FromStr for BackupPolicy {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.trim() {
+            "all" => Ok(BackupPolicy::All),
+            "any" => Ok(BackupPolicy::Any),
+            other => other.strip_prefix("quorum=")
+                .and_then(|n| n.parse::<usize>().ok())
+                .filter(|n| *n > 0)
+                .map(BackupPolicy::Quorum)
+                .ok_or_else(|| format!("invalid backup policy '{}', expected all, any or quorum=N", value)),
+        }
+    }
+}
+
+impl TryFrom<String> for BackupPolicy {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl fmt::Display for BackupPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BackupPolicy::All => write!(f, "all"),
+            BackupPolicy::Any => write!(f, "any"),
+            BackupPolicy::Quorum(n) => write!(f, "quorum={}", n),
+        }
+    }
+}
+
+impl From<BackupPolicy> for String {
+    fn from(policy: BackupPolicy) -> Self {
+        policy.to_string()
+    }
+}
+
+// How plain copies get their files' data. Reflink clones share it with the
+// original until either changes, so they take no time or space, but only
+// where both are on one filesystem that can (Btrfs, XFS, APFS). `auto` clones
+// where it can and copies the rest, as `cp --reflink=auto` does.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(try_from = "String", into = "String")]
+pub enum BackupStrategy {
+    #[default]
+    Auto,
+    Reflink,
+    Copy,
+}
+
+impl FromStr for BackupStrategy {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.trim() {
+            "auto" => Ok(BackupStrategy::Auto),
+            "reflink" => Ok(BackupStrategy::Reflink),
+            "copy" => Ok(BackupStrategy::Copy),
+            _ => Err(format!("invalid backup strategy '{}', expected reflink, copy or auto", value)),
+        }
+    }
+}
+
+impl TryFrom<String> for BackupStrategy {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl fmt::Display for BackupStrategy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BackupStrategy::Auto => write!(f, "auto"),
+            BackupStrategy::Reflink => write!(f, "reflink"),
+            BackupStrategy::Copy => write!(f, "copy"),
+        }
+    }
+}
+
+impl From<BackupStrategy> for String {
+    fn from(strategy: BackupStrategy) -> Self {
+        strategy.to_string()
+    }
+}
+
+impl BackupStrategy {
+    pub fn is_default(&self) -> bool {
+        *self == BackupStrategy::default()
+    }
+}
+
+// Back up (or archive) one directory to every destination, returning the
+// successful copies once the policy is satisfied. With `verify`, a copy that
+// can't be read back to verify doesn't count.
+pub fn backup_to_destinations(
+    path: &str,
+    destinations: &[Box<dyn BackupBackend>],
+    opts: &BackupOptions,
+    policy: BackupPolicy,
+    verify: Option<impl Fn(&Path) -> Result<(), String>>,
+) -> Result<Vec<String>, String> {
+    let mut copies = Vec::new();
+
+    for destination in destinations {
+        let result = destination.store(path, opts)
+            .and_then(|copy| match (&verify, destination.local_copy(&copy)) {
+                (Some(verify), Some(local)) => verify(&local).map(|_| copy),
+                (Some(_), None) => Err(format!("{} {} can't be read back to verify", CROSS, copy)),
+                (None, _) => Ok(copy),
+            });
+
+        match result {
+            Ok(copy) => copies.push(copy),
+            Err(e) => {
+                warn!("Backup of {} to {} failed: {}", path, destination.describe(), e);
+                report::message(Kind::Warning, format!("Backup to {} failed: {}", destination.describe(), e));
+            }
+        }
+    }
+
+    let required = policy.required(destinations.len());
+    if copies.len() < required {
+        return Err(format!(
+            "{} Backup policy '{}' not met for {}: {} of {} destination(s) succeeded",
+            CROSS, policy, path, copies.len(), destinations.len()
+        ));
+    }
+
+    debug!("Backup policy '{}' met for {} ({}/{})", policy, path, copies.len(), destinations.len());
+    Ok(copies)
+}
+
+// Copy `path` into a new directory under `backup_dir`, or finish one of the
+// `partial` copies an interrupted run left there. Incremental copies link
+// what is unchanged since the last copy of a directory with the same name.
+pub fn backup_directory(path: &str, backup_dir: &str, opts: &BackupOptions) -> Result<String, String> {
+    let dir_path = Path::new(path);
+    let backup_root = Path::new(backup_dir);
+    
+    fs::create_dir_all(backup_root)
+        .map_err(|e| format!("{} Failed to create backup directory: {}", CROSS, e))?;
+    
+    let dir_name = dir_path.file_name()
+        .ok_or_else(|| format!("{} Invalid directory name", CROSS))?;
+        
+    let resumed = opts.partial.iter()
+        .map(PathBuf::from)
+        .find(|copy| copy.starts_with(backup_root) && copy != backup_root && copy.is_dir());
+    let backup_path = match &resumed {
+        Some(copy) => {
+            debug!("Continuing the interrupted copy in {}", copy.display());
+            copy.clone()
+        }
+        None => claim_backup_path(backup_root, dir_path, opts.name_template.as_ref())
+            .map_err(|e| format!("{} Failed to create backup directory: {}", CROSS, e))?,
+    };
+    journal::record(Event::Copying { path: path.to_string(), to: backup_path.to_string_lossy().into_owned() });
+    let previous = opts.incremental.then(|| match &opts.name_template {
+        Some(template) => template.previous(backup_root, dir_path, &backup_path),
+        None => previous_copy(backup_root, &dir_name.to_string_lossy(), &backup_path),
+    }).flatten();
+    let reflink = Reflink::new(opts.strategy);
+    let mode = CopyMode {
+        readahead: opts.readahead,
+        keep_copied: resumed.is_some(),
+        preserve: Preserve { times: opts.preserve.times || opts.incremental, ..opts.preserve },
+        link_dest: previous.as_deref(),
+        exclude: (!opts.exclude.is_empty()).then_some((dir_path, &opts.exclude)),
+        reflink: reflink.as_ref(),
+    };
+    let mut limiter = RateLimiter::new(opts.upload_limit);
+    let mut checkpoints = Checkpoints::new(path);
+    let (mut linked, mut linked_bytes) = (0usize, 0u64);
+    let (mut cloned, mut cloned_bytes) = (0usize, 0u64);
+
+    // Use copy_dir instead of fs::copy for directories
+    copy_dir_recursive(dir_path, &backup_path, &mut limiter, mode, &mut |bytes, copied| {
+        checkpoints.copied(bytes);
+        match copied {
+            Copied::Linked => (linked, linked_bytes) = (linked + 1, linked_bytes + bytes),
+            Copied::Cloned => (cloned, cloned_bytes) = (cloned + 1, cloned_bytes + bytes),
+            Copied::Written => {}
+        }
+    })
+    .map_err(|e| format!("{} Backup failed: {}", CROSS, e))?;
+    if let Some(previous) = &previous {
+        report::detail(Kind::Info, format!(
+            "Linked {} unchanged file(s) ({}) to {}", locale::count(linked), format_mb(linked_bytes, false), previous.display()
+        ));
+    }
+    if cloned > 0 {
+        report::detail(Kind::Info, format!(
+            "Cloned {} file(s) ({}) into {} by reflink", locale::count(cloned), format_mb(cloned_bytes, false), backup_path.display()
+        ));
+    }
+
+    Ok(backup_path.to_string_lossy().to_string())
+}
+
+// A new, empty directory to copy `dir` into: named by the template, or the
+// directory's name, with a timestamp (and a counter) once that is taken.
+// Creating it is what claims it, so backups running alongside each other
+// never share one.
+fn claim_backup_path(root: &Path, dir: &Path, template: Option<&naming::NameTemplate>) -> io::Result<PathBuf> {
+    let now = chrono::Local::now();
+    let timestamp = now.format("%Y%m%d_%H%M%S");
+    let name = dir.file_name().unwrap_or_default().to_string_lossy();
+    let templated = template.map(|template| root.join(template.render(dir, &now)));
+    if let Some(parent) = templated.as_deref().and_then(Path::parent) {
+        fs::create_dir_all(parent)?;
+    }
+    let mut attempt = 0;
+    loop {
+        let candidate = match (&templated, attempt) {
+            (Some(stem), n) => naming::numbered(stem, n + 1, None),
+            (None, 0) => root.join(&*name),
+            (None, 1) => root.join(format!("{}_{}", name, timestamp)),
+            (None, n) => root.join(format!("{}_{}_{}", name, timestamp, n)),
+        };
+        match fs::create_dir(&candidate) {
+            Ok(()) => {
+                if attempt > 0 {
+                    debug!("Backup destination already exists, creating timestamped backup: {}", candidate.display());
+                }
+                return Ok(candidate);
+            }
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => attempt += 1,
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+// The newest earlier copy of `name` in `root`, other than `current`. Copies
+// are `name`, then `name_<timestamp>[_<n>]`, so the suffixes sort by age.
+fn previous_copy(root: &Path, name: &str, current: &Path) -> Option<PathBuf> {
+    let stamp = Regex::new(r"^_(\d{8}_\d{6})(?:_(\d+))?$").expect("valid regex");
+    fs::read_dir(root).ok()?
+        .filter_map(|e| e.ok())
+        .filter(|entry| entry.path() != current && entry.file_type().is_ok_and(|ty| ty.is_dir()))
+        .filter_map(|entry| {
+            let file_name = entry.file_name().to_string_lossy().into_owned();
+            let suffix = file_name.strip_prefix(name)?;
+            if suffix.is_empty() {
+                return Some(((String::new(), 0), entry.path()));
+            }
+            let captures = stamp.captures(suffix)?;
+            let counter = captures.get(2).map_or(1, |n| n.as_str().parse().unwrap_or(1));
+            Some(((captures[1].to_string(), counter), entry.path()))
+        })
+        .max_by(|a, b| a.0.cmp(&b.0))
+        .map(|(_, path)| path)
+}
+
+// What --backup-exclude leaves out of copies and archives: globs matched
+// against paths inside the directory being backed up, at any depth unless
+// they contain a slash. A matched directory is left out whole.
+#[derive(Debug, Clone, Default)]
+pub struct BackupExclude(Vec<Regex>);
+
+impl BackupExclude {
+    pub fn new(patterns: &[String]) -> Result<Self, String> {
+        patterns.iter()
+            .map(|pattern| parse_exclude(pattern))
+            .collect::<Result<_, _>>()
+            .map(BackupExclude)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    // `path` is somewhere under `root`, the directory being backed up
+    pub fn matches(&self, root: &Path, path: &Path) -> bool {
+        let Ok(relative) = path.strip_prefix(root) else {
+            return false;
+        };
+        let relative = relative.to_string_lossy().replace('\\', "/");
+        !relative.is_empty() && self.0.iter().any(|regex| regex.is_match(&relative))
+    }
+}
+
+// A --backup-exclude pattern; a trailing slash is allowed, as in .gitignore
+pub fn parse_exclude(pattern: &str) -> Result<Regex, String> {
+    gitignore::glob_regex(pattern.trim_end_matches('/'))
+        .filter(|_| !pattern.trim_matches('/').is_empty())
+        .ok_or_else(|| format!("{} Invalid backup exclude pattern: {}", CROSS, pattern))
+}
+
+// How copy_dir_recursive copies
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CopyMode<'a> {
+    // Hint each directory's files to the OS a few ahead of the one being copied
+    pub readahead: bool,
+    // Leave files an interrupted copy already finished as they are
+    pub keep_copied: bool,
+    // What copies keep of their source's metadata. Incremental backups
+    // compare modification times, so they keep those whatever this says.
+    pub preserve: Preserve,
+    // Hardlink files unchanged since this earlier copy instead of copying them
+    pub link_dest: Option<&'a Path>,
+    // Leave out what these match below the root, the directory being copied
+    pub exclude: Option<(&'a Path, &'a BackupExclude)>,
+    // Clone files rather than copying their data, where the filesystem can
+    pub reflink: Option<&'a Reflink>,
+}
+
+// Reflink cloning for one copy, from --backup-strategy: `reflink` fails the
+// copy where a file can't be cloned, `auto` copies instead and stops trying
+// once the filesystem has refused
+#[derive(Debug)]
+pub struct Reflink {
+    required: bool,
+    refused: Cell<bool>,
+}
+
+impl Reflink {
+    pub fn new(strategy: BackupStrategy) -> Option<Self> {
+        match strategy {
+            BackupStrategy::Copy => None,
+            strategy => Some(Reflink { required: strategy == BackupStrategy::Reflink, refused: Cell::new(false) }),
+        }
+    }
+}
+
+// How a file got into a copy
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Copied {
+    Written,
+    // Hardlinked to an earlier copy
+    Linked,
+    // Reflinked to the original
+    Cloned,
+}
+
+// `on_copied` hears of each file's size, and how it was copied. `dst` itself
+// keeps the time it was made, which --prune-backups goes by.
+pub fn copy_dir_recursive(src: &Path, dst: &Path, limiter: &mut RateLimiter, mode: CopyMode, on_copied: &mut dyn FnMut(u64, Copied)) -> io::Result<()> {
+    copy_tree(src, dst, limiter, mode, on_copied)?;
+    preserve::apply(&fs::metadata(src)?, dst, Preserve { times: false, ..mode.preserve })
+}
+
+fn copy_tree(src: &Path, dst: &Path, limiter: &mut RateLimiter, mode: CopyMode, on_copied: &mut dyn FnMut(u64, Copied)) -> io::Result<()> {
+    if !dst.exists() {
+        fs::create_dir_all(dst)?;
+    }
+
+    let mut entries = fs::read_dir(src)?
+        .map(|entry| entry.and_then(|e| e.file_type().map(|ty| (e.path(), ty))))
+        .collect::<io::Result<Vec<_>>>()?;
+    if let Some((root, exclude)) = mode.exclude {
+        entries.retain(|(path, _)| !exclude.matches(root, path));
+    }
+    let files: Vec<&Path> = entries.iter().filter(|(_, ty)| ty.is_file()).map(|(path, _)| path.as_path()).collect();
+    let (mut copied, mut hinted) = (0, 0);
+
+    for (src_path, ty) in &entries {
+        let (src_path, ty) = (src_path.as_path(), *ty);
+        let dst_path = dst.join(src_path.file_name().unwrap_or_default());
+        if ty.is_file() && mode.readahead {
+            prefetch::hint_ahead(&files, copied, &mut hinted);
+            copied += 1;
+        }
+
+        if ty.is_symlink() && mode.preserve.links {
+            preserve::copy_symlink(src_path, &dst_path)?;
+            preserve::apply_to_link(&fs::symlink_metadata(src_path)?, &dst_path, mode.preserve);
+            continue;
+        }
+        // Otherwise links are copied as what they point to
+        let ty = if ty.is_symlink() {
+            match followed(src, src_path) {
+                Some(ty) => ty,
+                None => continue,
+            }
+        } else {
+            ty
+        };
+
+        let earlier = mode.link_dest.map(|dir| dir.join(src_path.file_name().unwrap_or_default()));
+        if ty.is_dir() {
+            let previous = earlier.as_deref().filter(|earlier| earlier.is_dir());
+            copy_tree(src_path, &dst_path, limiter, CopyMode { link_dest: previous, ..mode }, on_copied)?;
+            preserve::apply(&fs::metadata(src_path)?, &dst_path, mode.preserve)?;
+            continue;
+        }
+        if !ty.is_file() {
+            continue;
+        }
+        if mode.keep_copied && already_copied(src_path, &dst_path) {
+            on_copied(fs::metadata(&dst_path)?.len(), Copied::Written);
+            continue;
+        }
+        // Falls back to copying where links can't be made, e.g. across filesystems
+        if let Some(earlier) = earlier.filter(|earlier| unchanged(src_path, earlier))
+            && fs::hard_link(&earlier, &dst_path).is_ok()
+        {
+            on_copied(fs::metadata(&dst_path)?.len(), Copied::Linked);
+            continue;
+        }
+        if let Some(reflink) = mode.reflink.filter(|reflink| !reflink.refused.get()) {
+            match cow::reflink(src_path, &dst_path) {
+                Ok(()) => {
+                    preserve::apply(&fs::metadata(src_path)?, &dst_path, mode.preserve)?;
+                    on_copied(fs::metadata(&dst_path)?.len(), Copied::Cloned);
+                    continue;
+                }
+                Err(e) if reflink.required => {
+                    return Err(io::Error::new(e.kind(), format!("could not clone {} by reflink: {}", src_path.display(), e)));
+                }
+                Err(e) => {
+                    debug!("Copying instead of cloning into {}: {}", dst.display(), e);
+                    reflink.refused.set(true);
+                }
+            }
+        }
+        // fs::copy takes the mode along; a new file gets the default one
+        let bytes = if limiter.is_limited() || !mode.preserve.mode {
+            let mut reader = prefetch::open_sequential(src_path)?;
+            let mut writer = Throttled::new(fs::File::create(&dst_path)?, limiter);
+            io::copy(&mut reader, &mut writer)?
+        } else {
+            fs::copy(src_path, &dst_path)?
+        };
+        preserve::apply(&fs::metadata(src_path)?, &dst_path, mode.preserve)?;
+        on_copied(bytes, Copied::Written);
+    }
+
+    Ok(())
+}
+
+// What the link `path` in `dir` points to, if it can be copied: not when it
+// is dangling, nor when it leads back up to `dir`, which would copy forever
+fn followed(dir: &Path, path: &Path) -> Option<fs::FileType> {
+    let target = match fs::metadata(path) {
+        Ok(target) => target,
+        Err(e) => {
+            warn!("Skipping dangling symlink {}: {}", path.display(), e);
+            return None;
+        }
+    };
+    if target.is_dir()
+        && let (Ok(dir), Ok(resolved)) = (fs::canonicalize(dir), fs::canonicalize(path))
+        && dir.starts_with(&resolved)
+    {
+        warn!("Skipping symlink {} to a directory it is in", path.display());
+        return None;
+    }
+    Some(target.file_type())
+}
+
+// The copy is whole and was written after the source last changed; a file
+// cut off by the interruption is shorter, or older than its source
+fn already_copied(src: &Path, dst: &Path) -> bool {
+    let (Ok(src), Ok(dst)) = (fs::metadata(src), fs::metadata(dst)) else {
+        return false;
+    };
+    src.len() == dst.len() && matches!((src.modified(), dst.modified()), (Ok(changed), Ok(copied)) if copied >= changed)
+}
+
+// Same size and modification time as in the earlier copy, as rsync judges it
+fn unchanged(src: &Path, earlier: &Path) -> bool {
+    let (Ok(src), Ok(earlier)) = (fs::metadata(src), fs::symlink_metadata(earlier)) else {
+        return false;
+    };
+    earlier.is_file() && src.len() == earlier.len() && matches!((src.modified(), earlier.modified()), (Ok(a), Ok(b)) if a == b)
+}
+
+// A file or directory found inside a backup copy or archive
+pub struct BackupMatch {
+    pub backup: PathBuf,
+    pub entry: String,
+}
+
+// Search every backup copy and archive in the given backup directories, at
+// their top or where the template puts them
+pub fn find_in_backups(pattern: &str, backup_dirs: &[String], template: Option<&naming::NameTemplate>) -> Result<Vec<BackupMatch>, String> {
+    let regex = gitignore::glob_regex(pattern)
+        .ok_or_else(|| format!("{} Invalid pattern: {}", CROSS, pattern))?;
+    let mut found = Vec::new();
+
+    for backup_dir in backup_dirs {
+        let Some(root) = backend::local_root(backup_dir) else {
+            warn!("Searching remote backups is not supported: {}", backup_dir);
+            continue;
+        };
+        let backups = match template {
+            Some(template) => template.stored(&root),
+            None => match fs::read_dir(&root) {
+                Ok(backups) => backups.filter_map(|e| e.ok()).map(|e| e.path()).collect(),
+                Err(_) => {
+                    warn!("Backup directory not readable: {}", backup_dir);
+                    continue;
+                }
+            },
+        };
+
+        for backup in backups {
+            if backup.is_dir() {
+                for entry in WalkDir::new(&backup).min_depth(1).into_iter().filter_map(|e| e.ok()) {
+                    let Ok(relative) = entry.path().strip_prefix(&backup) else {
+                        continue;
+                    };
+                    let relative = relative.to_string_lossy().replace('\\', "/");
+                    if regex.is_match(&relative) {
+                        found.push(BackupMatch { backup: backup.clone(), entry: relative });
+                    }
+                }
+            } else if ArchiveFormat::of(&backup).is_some() {
+                let names = match archive::list_entries(&backup) {
+                    Ok(names) => names,
+                    Err(e) => {
+                        warn!("Skipping unreadable archive {}: {}", backup.display(), e);
+                        continue;
+                    }
+                };
+                for name in names {
+                    let name = name.trim_end_matches('/');
+                    if regex.is_match(name) {
+                        found.push(BackupMatch { backup: backup.clone(), entry: name.to_string() });
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(found)
+}
@@ -0,0 +1,249 @@
+// Removing directories with a huge number of entries in batches rather than
+// one `remove_dir_all`: progress is shown as it goes, Ctrl-C stops it between
+// batches, and entries that fail are retried at the end instead of failing
+// the whole removal.
+use indicatif::{ProgressBar, ProgressStyle};
+use std::{fs, io, path::{Path, PathBuf}, sync::atomic::{AtomicBool, Ordering}, thread, time::{Duration, Instant}};
+use walkdir::WalkDir;
+use log::debug;
+
+#[cfg(all(target_os = "linux", feature = "io-uring"))]
+use crate::uring;
+#[cfg(windows)]
+use crate::windelete;
+use crate::{progress, report, CROSS};
+
+// Directories with more entries than this are removed in batches
+pub const DEFAULT_THRESHOLD: usize = 100_000;
+
+// Batches grow or shrink to take about this long, whatever the storage
+const BATCH_TIME: Duration = Duration::from_millis(250);
+const MIN_BATCH: usize = 256;
+const MAX_BATCH: usize = 65_536;
+// Rounds of retrying the entries that could not be removed on the way
+const RETRIES: u32 = 3;
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+// Whether Ctrl-C was pressed during a batched removal; the run stops
+// handing out directories once it has
+pub fn interrupted() -> bool {
+    INTERRUPTED.load(Ordering::SeqCst)
+}
+
+// The first Ctrl-C asks for a stop at the end of the batch, a second one
+// ends the process as usual
+#[cfg(unix)]
+fn catch_interrupt() {
+    static INSTALLED: std::sync::Once = std::sync::Once::new();
+    extern "C" fn on_interrupt(_: libc::c_int) {
+        if INTERRUPTED.swap(true, Ordering::SeqCst) {
+            unsafe {
+                libc::signal(libc::SIGINT, libc::SIG_DFL);
+                libc::raise(libc::SIGINT);
+            }
+        }
+    }
+    INSTALLED.call_once(|| unsafe {
+        libc::signal(libc::SIGINT, on_interrupt as extern "C" fn(libc::c_int) as libc::sighandler_t);
+    });
+}
+
+#[cfg(not(unix))]
+fn catch_interrupt() {}
+
+fn removal_progress(entries: usize) -> ProgressBar {
+    if !report::animated() {
+        return ProgressBar::hidden();
+    }
+    let pb = progress().add(ProgressBar::new(entries as u64));
+    pb.set_style(
+        ProgressStyle::default_bar()
+            .template("  🗑  [{bar:30.red/white}] {pos}/{len} entries {per_sec} {wide_msg}")
+            .unwrap()
+    );
+    pb
+}
+
+#[cfg(not(windows))]
+fn remove_entry(path: &Path, is_dir: bool) -> io::Result<()> {
+    let removed = if is_dir { fs::remove_dir(path) } else { fs::remove_file(path) };
+    gone(removed)
+}
+
+#[cfg(windows)]
+fn remove_entry(path: &Path, _is_dir: bool) -> io::Result<()> {
+    gone(windelete::delete(path))
+}
+
+// Something else removing an entry first is as good as removing it
+fn gone(removed: io::Result<()>) -> io::Result<()> {
+    match removed {
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        other => other,
+    }
+}
+
+// Removes batches of entries, unlinking files through io_uring where the
+// kernel allows it, or on several threads at once on Windows
+struct Remover {
+    #[cfg(all(target_os = "linux", feature = "io-uring"))]
+    ring: Option<uring::Ring>,
+}
+
+impl Remover {
+    #[cfg(all(target_os = "linux", feature = "io-uring"))]
+    fn new() -> Self {
+        let ring = uring::Ring::new()
+            .inspect(|_| debug!("Unlinking through io_uring"))
+            .inspect_err(|e| debug!("io_uring unavailable, removing entry by entry: {}", e))
+            .ok();
+        Remover { ring }
+    }
+
+    #[cfg(not(all(target_os = "linux", feature = "io-uring")))]
+    fn new() -> Self {
+        Remover {}
+    }
+
+    // The files of `batch` first, then its directories one at a time in walk
+    // order, children first, so each is empty by the time it goes. Results
+    // are in the order of `batch`.
+    fn remove(&mut self, batch: &[(PathBuf, bool)]) -> Vec<io::Result<()>> {
+        let files: Vec<&Path> = batch.iter().filter(|(_, is_dir)| !is_dir).map(|(path, _)| path.as_path()).collect();
+        let mut unlinked = self.unlink_files(&files).into_iter();
+        batch.iter()
+            .map(|(path, is_dir)| if *is_dir { remove_entry(path, true) } else { unlinked.next().expect("a result per file") })
+            .collect()
+    }
+
+    #[cfg(all(target_os = "linux", feature = "io-uring"))]
+    fn unlink_files(&mut self, files: &[&Path]) -> Vec<io::Result<()>> {
+        let Some(ring) = &mut self.ring else {
+            return files.iter().map(|file| remove_entry(file, false)).collect();
+        };
+        match ring.unlink_all(files) {
+            Ok(results) => files.iter().zip(results)
+                .map(|(file, result)| match result {
+                    // The kernel takes rings but not unlinks on them
+                    Err(e) if e.raw_os_error() == Some(libc::EINVAL) => {
+                        if self.ring.take().is_some() {
+                            debug!("io_uring can't unlink here, removing entry by entry");
+                        }
+                        remove_entry(file, false)
+                    }
+                    result => gone(result),
+                })
+                .collect(),
+            Err(e) => {
+                debug!("io_uring failed, removing entry by entry: {}", e);
+                self.ring = None;
+                files.iter().map(|file| remove_entry(file, false)).collect()
+            }
+        }
+    }
+
+    #[cfg(windows)]
+    fn unlink_files(&mut self, files: &[&Path]) -> Vec<io::Result<()>> {
+        windelete::delete_all(files).into_iter().map(gone).collect()
+    }
+
+    #[cfg(not(any(all(target_os = "linux", feature = "io-uring"), windows)))]
+    fn unlink_files(&mut self, files: &[&Path]) -> Vec<io::Result<()>> {
+        files.iter().map(|file| remove_entry(file, false)).collect()
+    }
+}
+
+// Empties `pending`, keeping what could not be removed in `stragglers`.
+// Returns how many entries were removed.
+fn remove_batch(remover: &mut Remover, pending: &mut Vec<(PathBuf, bool)>, stragglers: &mut Vec<(PathBuf, bool)>) -> usize {
+    let results = remover.remove(pending);
+    let mut removed = 0;
+    for ((path, is_dir), result) in pending.drain(..).zip(results) {
+        match result {
+            Ok(()) => removed += 1,
+            Err(e) => {
+                debug!("Retrying later: {}: {}", path.display(), e);
+                stragglers.push((path, is_dir));
+            }
+        }
+    }
+    removed
+}
+
+// Remove `path` and the roughly `entries` entries under it, deepest first.
+// Returns how many were removed.
+pub fn remove_dir(path: &Path, entries: usize) -> Result<usize, String> {
+    catch_interrupt();
+    let pb = removal_progress(entries);
+    pb.set_message(path.display().to_string());
+    let (mut batch, mut removed) = (MIN_BATCH * 4, 0);
+    let mut started = Instant::now();
+    let mut remover = Remover::new();
+    let mut pending: Vec<(PathBuf, bool)> = Vec::with_capacity(batch);
+    // Failed on the way, retried once the rest is gone; children come before
+    // their directories, so they are retried in a workable order
+    let mut stragglers: Vec<(PathBuf, bool)> = Vec::new();
+
+    for entry in WalkDir::new(path).contents_first(true) {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(e) => {
+                debug!("Could not read {}: {}", path.display(), e);
+                if let Some(unreadable) = e.path() {
+                    stragglers.push((unreadable.to_path_buf(), true));
+                }
+                continue;
+            }
+        };
+        // The directory itself goes last, with anything created meanwhile
+        if entry.depth() == 0 {
+            continue;
+        }
+        let is_dir = entry.file_type().is_dir();
+        pending.push((entry.into_path(), is_dir));
+        if pending.len() < batch {
+            continue;
+        }
+        removed += remove_batch(&mut remover, &mut pending, &mut stragglers);
+        let took = started.elapsed();
+        if took < BATCH_TIME / 2 {
+            batch = (batch * 2).min(MAX_BATCH);
+        } else if took > BATCH_TIME * 2 {
+            batch = (batch / 2).max(MIN_BATCH);
+        }
+        pb.set_position(removed as u64);
+        started = Instant::now();
+        if interrupted() {
+            pb.abandon_with_message(format!("interrupted: {}", path.display()));
+            return Err(format!("{} Interrupted after removing {} of {} entries from {}", CROSS, removed, entries, path.display()));
+        }
+    }
+    removed += remove_batch(&mut remover, &mut pending, &mut stragglers);
+    pb.set_position(removed as u64);
+
+    for round in 1..=RETRIES {
+        if stragglers.is_empty() {
+            break;
+        }
+        thread::sleep(Duration::from_millis(100 * round as u64));
+        debug!("Retrying {} entries of {} (round {})", stragglers.len(), path.display(), round);
+        stragglers.retain(|(straggler, is_dir)| {
+            let retried = if *is_dir { fs::remove_dir_all(straggler) } else { fs::remove_file(straggler) };
+            match retried {
+                Ok(()) => {
+                    removed += 1;
+                    false
+                }
+                Err(e) => e.kind() != io::ErrorKind::NotFound,
+            }
+        });
+    }
+    pb.finish_and_clear();
+
+    fs::remove_dir_all(path).map_err(|e| match stragglers.first() {
+        Some((straggler, _)) => format!("{} Deletion failed: {} entries could not be removed, such as {}: {}", CROSS, stragglers.len(), straggler.display(), e),
+        None => format!("{} Deletion failed: {}", CROSS, e),
+    })?;
+    Ok(removed)
+}
@@ -0,0 +1,144 @@
+use std::path::Path;
+
+// How much of a file is stored only for it, leaving out extents it shares with
+// reflink copies, clones or snapshots: what deleting it would actually free.
+// None where the filesystem or platform can't tell.
+#[cfg(target_os = "linux")]
+pub fn unique_size(path: &Path) -> Option<u64> {
+    use std::os::unix::io::AsRawFd;
+
+    // <linux/fiemap.h>
+    #[repr(C)]
+    struct Extent {
+        logical: u64,
+        physical: u64,
+        length: u64,
+        reserved64: [u64; 2],
+        flags: u32,
+        reserved: [u32; 3],
+    }
+
+    #[repr(C)]
+    struct Fiemap {
+        start: u64,
+        length: u64,
+        flags: u32,
+        mapped_extents: u32,
+        extent_count: u32,
+        reserved: u32,
+        extents: [Extent; BATCH],
+    }
+
+    const BATCH: usize = 64;
+    const FS_IOC_FIEMAP: u64 = 0xC020_660B;
+    const FIEMAP_EXTENT_LAST: u32 = 0x1;
+    const FIEMAP_EXTENT_SHARED: u32 = 0x2000;
+
+    let file = std::fs::File::open(path).ok()?;
+    let mut map: Box<Fiemap> = Box::new(unsafe { std::mem::zeroed() });
+    let mut unique = 0u64;
+    let mut start = 0u64;
+    loop {
+        map.start = start;
+        map.length = u64::MAX - start;
+        // Delayed allocations are reported as extents of their own
+        map.flags = 0;
+        map.extent_count = BATCH as u32;
+        map.mapped_extents = 0;
+        if unsafe { libc::ioctl(file.as_raw_fd(), FS_IOC_FIEMAP as _, &mut *map as *mut Fiemap) } != 0 {
+            return None;
+        }
+        let extents = &map.extents[..(map.mapped_extents as usize).min(BATCH)];
+        let Some(last) = extents.last() else {
+            return Some(unique);
+        };
+        unique += extents.iter()
+            .filter(|extent| extent.flags & FIEMAP_EXTENT_SHARED == 0)
+            .map(|extent| extent.length)
+            .sum::<u64>();
+        if last.flags & FIEMAP_EXTENT_LAST != 0 {
+            return Some(unique);
+        }
+        start = last.logical + last.length;
+    }
+}
+
+// APFS keeps the private size of every file, the part not shared with clones
+#[cfg(target_os = "macos")]
+pub fn unique_size(path: &Path) -> Option<u64> {
+    use std::{ffi::CString, os::unix::ffi::OsStrExt};
+
+    #[repr(C, packed(4))]
+    struct PrivateSize {
+        length: u32,
+        size: libc::off_t,
+    }
+
+    let path = CString::new(path.as_os_str().as_bytes()).ok()?;
+    let mut attributes: libc::attrlist = unsafe { std::mem::zeroed() };
+    attributes.bitmapcount = libc::ATTR_BIT_MAP_COUNT;
+    attributes.forkattr = libc::ATTR_CMNEXT_PRIVATESIZE;
+    let mut buffer = PrivateSize { length: 0, size: 0 };
+    let result = unsafe {
+        libc::getattrlist(
+            path.as_ptr(),
+            &mut attributes as *mut libc::attrlist as *mut libc::c_void,
+            &mut buffer as *mut PrivateSize as *mut libc::c_void,
+            std::mem::size_of::<PrivateSize>(),
+            libc::FSOPT_NOFOLLOW | libc::FSOPT_ATTR_CMN_EXTENDED,
+        )
+    };
+    // Filesystems without the attribute return only the length
+    if result != 0 || (buffer.length as usize) < std::mem::size_of::<PrivateSize>() {
+        return None;
+    }
+    u64::try_from(buffer.size).ok()
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+pub fn unique_size(_path: &Path) -> Option<u64> {
+    None
+}
+
+// Make `dst` a clone of the file `src`, sharing its extents until either is
+// written to: FICLONE on Btrfs, XFS and bcachefs. Fails across filesystems
+// and on those that can't share data, leaving nothing at `dst`.
+#[cfg(target_os = "linux")]
+pub fn reflink(src: &Path, dst: &Path) -> std::io::Result<()> {
+    use std::os::unix::io::AsRawFd;
+
+    let source = std::fs::File::open(src)?;
+    let clone = std::fs::File::create(dst)?;
+    if unsafe { libc::ioctl(clone.as_raw_fd(), libc::FICLONE as _, source.as_raw_fd()) } != 0 {
+        let e = std::io::Error::last_os_error();
+        drop(clone);
+        let _ = std::fs::remove_file(dst);
+        return Err(e);
+    }
+    Ok(())
+}
+
+// clonefile(2) on APFS, which takes the mode and times along too
+#[cfg(target_os = "macos")]
+pub fn reflink(src: &Path, dst: &Path) -> std::io::Result<()> {
+    use std::{ffi::CString, os::unix::ffi::OsStrExt};
+
+    unsafe extern "C" {
+        fn clonefile(src: *const libc::c_char, dst: *const libc::c_char, flags: u32) -> libc::c_int;
+    }
+    let invalid = |_| std::io::Error::from(std::io::ErrorKind::InvalidInput);
+    let (source, clone) = (CString::new(src.as_os_str().as_bytes()).map_err(invalid)?, CString::new(dst.as_os_str().as_bytes()).map_err(invalid)?);
+    // clonefile won't replace what an interrupted copy left behind
+    if dst.symlink_metadata().is_ok() {
+        std::fs::remove_file(dst)?;
+    }
+    if unsafe { clonefile(source.as_ptr(), clone.as_ptr(), 0) } != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+pub fn reflink(_src: &Path, _dst: &Path) -> std::io::Result<()> {
+    Err(std::io::Error::new(std::io::ErrorKind::Unsupported, "reflinks are not supported on this platform"))
+}
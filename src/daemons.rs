@@ -0,0 +1,67 @@
+use std::{collections::{HashMap, HashSet}, path::Path, process::Command};
+use log::{debug, info, warn};
+
+use crate::{report::{self, Kind}, tools, DirInfo};
+
+// The command that stops the daemon owning a build directory, run from the
+// project directory: a configured one for the target, else for Gradle's build
+// and .gradle the project's wrapper or Gradle, and for sbt's target a running server
+fn stop_command(dir: &Path, configured: &HashMap<String, String>) -> Option<String> {
+    let name = dir.file_name()?.to_string_lossy();
+    if let Some(command) = configured.get(name.as_ref()) {
+        return Some(command.clone());
+    }
+
+    let project = dir.parent()?;
+    // Daemons are started by the tool they belong to, so one that isn't
+    // installed has none running to stop
+    let installed = |program| tools::available(program) || {
+        debug!("{} not on the PATH, no daemon to stop for {}", program, dir.display());
+        false
+    };
+    let gradle_project = project.join("build.gradle").is_file() || project.join("build.gradle.kts").is_file();
+    match name.as_ref() {
+        "build" | ".gradle" if project.join("gradlew").is_file() => Some("./gradlew --stop".to_string()),
+        "build" | ".gradle" if gradle_project && installed("gradle") => Some("gradle --stop".to_string()),
+        "target" if project.join("build.sbt").is_file() && project.join("project/target/active.json").is_file() && installed("sbt") =>
+            Some("sbt --client shutdown".to_string()),
+        _ => None,
+    }
+}
+
+// Stop build daemons before their caches are purged, once per project
+pub fn stop_daemons(dirs: &[DirInfo], configured: &HashMap<String, String>, dry_run: bool) {
+    let mut stopped = HashSet::new();
+    for dir in dirs {
+        let path = Path::new(&dir.path);
+        let (Some(project), Some(command)) = (path.parent(), stop_command(path, configured)) else {
+            continue;
+        };
+        if !stopped.insert((project.to_path_buf(), command.clone())) {
+            continue;
+        }
+
+        if dry_run {
+            report::message(Kind::DryRun, format!("[Dry Run] Would run `{}` in {}", command, project.display()));
+            continue;
+        }
+
+        info!("Stopping build daemon with `{}` in {}", command, project.display());
+        report::message(Kind::Step, format!("Stopping build daemon: {} ({})", command, project.display()));
+        let mut parts = command.split_whitespace();
+        let Some(program) = parts.next() else {
+            continue;
+        };
+        match Command::new(program).args(parts).current_dir(project).output() {
+            Ok(output) if output.status.success() => debug!("`{}` finished", command),
+            Ok(output) => {
+                warn!("`{}` failed in {}: {}", command, project.display(), String::from_utf8_lossy(&output.stderr).trim());
+                report::message(Kind::Info, format!("Could not stop daemon with `{}` ({})", command, output.status));
+            }
+            Err(e) => {
+                warn!("Failed to run `{}` in {}: {}", command, project.display(), e);
+                report::message(Kind::Info, format!("Could not run `{}`: {}", command, e));
+            }
+        }
+    }
+}
@@ -0,0 +1,65 @@
+// Permanent deletion that never leaves a half-removed directory under its own
+// name, where build tools would take it for a whole node_modules or target:
+// it is renamed to a `.dirpurge-deleting-XXXX` sibling first and removed from
+// there. An interrupted removal leaves only that sibling, which scans pass
+// over and `dirpurge resume` finishes.
+use std::{collections::hash_map::RandomState, fs, hash::BuildHasher, io, path::{Path, PathBuf}};
+use log::{debug, warn};
+
+use crate::report::{self, Kind};
+
+pub const PREFIX: &str = ".dirpurge-deleting-";
+
+// Whether `name` is that of a directory set aside to be removed
+pub fn is_set_aside(name: &str) -> bool {
+    name.starts_with(PREFIX)
+}
+
+// Renames `path` to a new sibling and returns it. Where it can't be renamed,
+// e.g. a program holds it open on Windows, it is left where it is and
+// returned as is, to be removed in place.
+pub fn set_aside(path: &Path) -> PathBuf {
+    let Some(parent) = path.parent() else {
+        return path.to_path_buf();
+    };
+    let random = RandomState::new();
+    for attempt in 0u32..8 {
+        let aside = parent.join(format!("{}{:08x}", PREFIX, random.hash_one(attempt) as u32));
+        // A rename onto an empty directory would replace it
+        if aside.symlink_metadata().is_ok() {
+            continue;
+        }
+        match fs::rename(path, &aside) {
+            Ok(()) => {
+                debug!("Set {} aside as {} to remove it", path.display(), aside.display());
+                return aside;
+            }
+            Err(e) => {
+                debug!("Removing {} in place, it can't be renamed: {}", path.display(), e);
+                break;
+            }
+        }
+    }
+    path.to_path_buf()
+}
+
+// Removes what interrupted removals left set aside in `parent`
+pub fn finish(parent: &Path) {
+    let Ok(entries) = fs::read_dir(parent) else {
+        return;
+    };
+    for entry in entries.filter_map(|e| e.ok()) {
+        if !entry.file_name().to_str().is_some_and(is_set_aside) {
+            continue;
+        }
+        let path = entry.path();
+        match fs::remove_dir_all(&path) {
+            Ok(()) => report::detail(Kind::Deleted, format!("Finished removing {}", path.display())),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => {
+                warn!("Could not finish removing {}: {}", path.display(), e);
+                report::message(Kind::Warning, format!("Could not finish removing {}: {}", path.display(), e));
+            }
+        }
+    }
+}
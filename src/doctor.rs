@@ -0,0 +1,179 @@
+// `dirpurge doctor`: what this machine lets dirpurge do, the first thing to
+// look at when something doesn't work as documented. Problems, which would
+// stop or fail a run, make it exit non-zero; warnings only turn features off.
+use std::{fs, path::{Path, PathBuf}};
+
+use crate::{backend, cow, locale, mounts, naming, reserved, report::{self, Kind}, tools, units, CROSS};
+
+#[derive(Default)]
+struct Checks {
+    problems: usize,
+    warnings: usize,
+}
+
+impl Checks {
+    fn ok(&self, text: impl AsRef<str>) {
+        report::message(Kind::Success, text);
+    }
+
+    fn warn(&mut self, text: impl AsRef<str>) {
+        self.warnings += 1;
+        report::message(Kind::Warning, text);
+    }
+
+    fn problem(&mut self, text: impl AsRef<str>) {
+        self.problems += 1;
+        report::message(Kind::Error, text);
+    }
+}
+
+pub fn run(matches: &clap::ArgMatches) -> Result<(), String> {
+    let mut checks = Checks::default();
+    let path = Path::new(matches.get_one::<String>("path").map_or(".", String::as_str));
+
+    report::message(Kind::Setting, format!("dirpurge {}", env!("CARGO_PKG_VERSION")));
+    let features = [
+        ("trash", cfg!(feature = "trash")),
+        ("archive", cfg!(feature = "archive")),
+        ("s3", cfg!(feature = "s3")),
+        ("sftp", cfg!(feature = "sftp")),
+        ("io-uring", cfg!(feature = "io-uring")),
+    ];
+    let built: Vec<&str> = features.iter().filter(|(_, on)| *on).map(|(name, _)| *name).collect();
+    let left_out: Vec<&str> = features.iter().filter(|(_, on)| !*on).map(|(name, _)| *name).collect();
+    report::items(&[
+        format!("Built with: {}", if built.is_empty() { "no optional features".to_string() } else { built.join(", ") }),
+        format!("Built without: {}", if left_out.is_empty() { "nothing".to_string() } else { left_out.join(", ") }),
+    ]);
+
+    let config = matches.get_one::<String>("config").map(|config_path| check_config(&mut checks, config_path)).unwrap_or_default();
+    check_trash(&mut checks);
+    check_terminal();
+
+    report::message(Kind::Setting, "Backup destinations");
+    let backup_dirs: Vec<String> = matches.get_many::<String>("backup-dir").map(|dirs| dirs.cloned().collect())
+        .or_else(|| config.as_ref().and_then(|config| config.backup_dirs.clone().or_else(|| config.backup_dir.clone().map(|dir| vec![dir]))))
+        .unwrap_or_else(|| vec!["./backups".to_string()]);
+    for backup_dir in &backup_dirs {
+        check_destination(&mut checks, backup_dir, path);
+    }
+
+    report::message(Kind::Setting, format!("Filesystem of {}", path.display()));
+    match path.canonicalize() {
+        Ok(resolved) => checks.ok(format!("{}: {}", resolved.display(), mounts::filesystem(&resolved))),
+        Err(e) => checks.problem(format!("{} can't be read: {}", path.display(), e)),
+    }
+
+    report::message(Kind::Setting, "Optional tools");
+    for tool in tools::TOOLS {
+        match tools::find(tool.program) {
+            Some(found) => checks.ok(format!("{}: {} ({})", tool.program, found.display(), tool.used_for)),
+            None => checks.warn(format!("{}: not found, so no {}", tool.program, tool.used_for)),
+        }
+    }
+
+    match (checks.problems, checks.warnings) {
+        (0, 0) => report::message(Kind::Success, "Ready: everything checked is in order"),
+        (0, warnings) => report::message(Kind::Success, format!("Ready, with {} warning(s): runs turn off what needs what's missing and say so", locale::count(warnings))),
+        (problems, _) => return Err(format!("{} {} problem(s) found, which would stop or fail a run", CROSS, locale::count(problems))),
+    }
+    Ok(())
+}
+
+// The config loads, and the values only checked once a run needs them hold up
+fn check_config(checks: &mut Checks, config_path: &str) -> Option<crate::Config> {
+    report::message(Kind::Setting, format!("Configuration {}", config_path));
+    let config = match crate::load_config(config_path) {
+        Ok(config) => config,
+        Err(e) => {
+            checks.problem(e);
+            return None;
+        }
+    };
+    let mut fine = true;
+    let mut check = |result: Result<(), String>| {
+        if let Err(e) = result {
+            checks.problem(e);
+            fine = false;
+        }
+    };
+    check(config.upload_limit.as_deref().map(units::parse_rate).transpose().map(|_| ()));
+    check(config.archive_split_size.as_deref().map(units::parse_volume_size).transpose().map(|_| ()));
+    check(config.modified_before.as_deref().map(units::parse_date).transpose().map(|_| ()));
+    check(config.modified_after.as_deref().map(units::parse_date).transpose().map(|_| ()));
+    check(config.backup_name_template.as_deref().map(naming::NameTemplate::parse).transpose().map(|_| ()));
+    check(config.compression_level.map(|level| config.archive_format.unwrap_or_default().check_level(level)).unwrap_or(Ok(())));
+    if fine {
+        checks.ok("Loads, and its values are valid");
+    }
+    Some(config)
+}
+
+fn check_trash(checks: &mut Checks) {
+    report::message(Kind::Setting, "Trash");
+    if !cfg!(feature = "trash") {
+        checks.warn("Not built in: --use-trash and trash actions are refused");
+        return;
+    }
+    match reserved::home_trash().first() {
+        Some(trash) if trash.is_dir() => checks.ok(format!("{} (other volumes use one of their own)", trash.display())),
+        Some(trash) => checks.ok(format!("{}, made when first used", trash.display())),
+        None => checks.ok("The system's, through the desktop"),
+    }
+}
+
+fn check_terminal() {
+    report::message(Kind::Setting, "Terminal");
+    let term = console::Term::stdout();
+    let (rows, columns) = term.size();
+    report::items(&[
+        if term.is_term() { format!("Output is a terminal, {} columns by {} rows", columns, rows) } else { "Output is not a terminal: progress bars are hidden".to_string() },
+        format!("Colors: {}", if console::colors_enabled() { "on" } else { "off" }),
+        format!("TERM: {}", std::env::var("TERM").unwrap_or_else(|_| "unset".to_string())),
+    ]);
+}
+
+// There or makeable, writable, and what its filesystem offers copies made of `source`
+fn check_destination(checks: &mut Checks, backup_dir: &str, source: &Path) {
+    let Some(root) = backend::local_root(backup_dir) else {
+        checks.ok(format!("{}: remote, reached once a run backs up", backup_dir));
+        return;
+    };
+    // Made on demand, so what counts is the nearest directory that is there
+    let existing = root.ancestors().find(|dir| dir.is_dir()).map(Path::to_path_buf).unwrap_or_else(|| PathBuf::from("."));
+    let probe = existing.join(format!(".dirpurge-doctor-{}", std::process::id()));
+    if let Err(e) = fs::write(&probe, b"dirpurge") {
+        checks.problem(format!("{}: can't write to {}: {}", backup_dir, existing.display(), e));
+        return;
+    }
+    let made = if existing == root { String::new() } else { format!(" (made under {} when first used)", existing.display()) };
+    let free = mounts::free_space(&existing).map_or(String::new(), |free| format!(", {} free", locale::format_mb(free, false)));
+    checks.ok(format!("{}: writable{}, on {}{}", backup_dir, made, mounts::filesystem(&existing), free));
+
+    let clone = existing.join(format!(".dirpurge-doctor-{}-clone", std::process::id()));
+    let reflinks = cow::reflink(&probe, &clone).is_ok();
+    let _ = fs::remove_file(&clone);
+    let shared = cow::unique_size(&probe).is_some();
+    let _ = fs::remove_file(&probe);
+    let same = same_filesystem(source, &existing);
+    report::items(&[
+        format!("Reflinks: {}", match (reflinks, same) {
+            (true, Some(true)) => "yes, --backup-strategy reflink clones the files".to_string(),
+            (true, Some(false)) => format!("yes, but {} is on another filesystem, so copies from it write every byte", source.display()),
+            (true, None) => "yes, for files on the same filesystem".to_string(),
+            (false, _) => "no, copies write every byte".to_string(),
+        }),
+        format!("Shared data: {}", if shared { "measurable, --reclaimable leaves out what clones and snapshots share" } else { "not measurable here, --reclaimable counts it in full" }),
+    ]);
+}
+
+#[cfg(unix)]
+fn same_filesystem(a: &Path, b: &Path) -> Option<bool> {
+    use std::os::unix::fs::MetadataExt;
+    Some(a.metadata().ok()?.dev() == b.metadata().ok()?.dev())
+}
+
+#[cfg(not(unix))]
+fn same_filesystem(_a: &Path, _b: &Path) -> Option<bool> {
+    None
+}
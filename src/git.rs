@@ -0,0 +1,119 @@
+use std::{collections::HashMap, path::{Path, PathBuf}, process::Command, sync::Mutex, time::{Duration, SystemTime, UNIX_EPOCH}};
+use log::debug;
+
+// What `git` reports for one repository
+struct RepoState {
+    // Paths with uncommitted changes, relative to the repository root
+    changed: Vec<String>,
+    // Commits on local branches that no remote has
+    unpushed: u64,
+}
+
+// Checks repositories for work in progress, running git once per repository
+#[derive(Default)]
+pub struct RepoGuard {
+    repos: HashMap<PathBuf, Result<RepoState, String>>,
+}
+
+impl RepoGuard {
+    // Why the repository around `path` shouldn't lose its build directories
+    // right now, if it has uncommitted or unpushed work; changes inside `path`
+    // itself don't count, since it's about to be deleted anyway
+    pub fn active_work(&mut self, path: &Path) -> Option<String> {
+        let path = path.canonicalize().ok()?;
+        let repo = enclosing_repo(&path)?;
+        let inside = format!("{}/", path.strip_prefix(&repo).ok()?.to_string_lossy().replace('\\', "/"));
+
+        let state = self.repos.entry(repo.clone()).or_insert_with(|| repo_state(&repo));
+        let state = match state {
+            Ok(state) => state,
+            // Without git there's no telling, so err on the side of keeping it
+            Err(e) => return Some(e.clone()),
+        };
+        let changed = state.changed.iter()
+            .filter(|changed| !changed.starts_with(&inside) && *changed != inside.trim_end_matches('/'))
+            .count();
+        match (changed, state.unpushed) {
+            (0, 0) => None,
+            (0, unpushed) => Some(format!("{} has {} unpushed commits", repo.display(), unpushed)),
+            (changed, _) => Some(format!("{} has {} uncommitted changes", repo.display(), changed)),
+        }
+    }
+}
+
+// The repository root above `path`, found by its `.git` directory or file
+fn enclosing_repo(path: &Path) -> Option<PathBuf> {
+    path.ancestors().skip(1).find(|dir| dir.join(".git").exists()).map(Path::to_path_buf)
+}
+
+// Scans hit the same repository once per build directory, so commit times are cached
+static HEAD_TIMES: Mutex<Vec<(PathBuf, Option<SystemTime>)>> = Mutex::new(Vec::new());
+
+// When the HEAD commit of the repository enclosing `path` was made; None
+// outside a repository or before its first commit
+pub fn last_commit_time(path: &Path) -> Option<SystemTime> {
+    let repo = enclosing_repo(&path.canonicalize().ok()?)?;
+    let mut cache = HEAD_TIMES.lock().ok()?;
+    if let Some((_, time)) = cache.iter().find(|(cached, _)| *cached == repo) {
+        return *time;
+    }
+    let time = git(&repo, &["log", "-1", "--format=%ct"])
+        .inspect_err(|e| debug!("{}", e))
+        .ok()
+        .and_then(|out| out.trim().parse::<u64>().ok())
+        .map(|secs| UNIX_EPOCH + Duration::from_secs(secs));
+    debug!("Last commit in {}: {:?}", repo.display(), time);
+    cache.push((repo, time));
+    time
+}
+
+fn repo_state(repo: &Path) -> Result<RepoState, String> {
+    let changed = git(repo, &["status", "--porcelain"])?
+        .lines()
+        .filter_map(|line| line.get(3..))
+        .map(|path| path.trim_matches('"').to_string())
+        .collect();
+
+    // Repositories without remotes have nowhere to push to
+    let unpushed = if git(repo, &["remote"])?.trim().is_empty() {
+        0
+    } else {
+        git(repo, &["rev-list", "--count", "--branches", "--not", "--remotes"])?
+            .trim()
+            .parse()
+            .unwrap_or(0)
+    };
+    debug!("Repository {}: changes {:?}, {} unpushed commits", repo.display(), changed, unpushed);
+    Ok(RepoState { changed, unpushed })
+}
+
+// The exclude files git reads for the repository at `repo` besides its
+// .gitignore files, in precedence order from lowest: core.excludesFile, then
+// info/exclude. Without git to ask, where git looks by default.
+pub fn exclude_files(repo: &Path) -> Vec<PathBuf> {
+    let global = match git(repo, &["config", "--path", "--get", "core.excludesFile"]) {
+        Ok(path) if !path.trim().is_empty() => Some(PathBuf::from(path.trim())),
+        _ => std::env::var_os("XDG_CONFIG_HOME").filter(|dir| !dir.is_empty()).map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".config")))
+            .map(|config| config.join("git/ignore")),
+    };
+    // Worktrees and submodules keep it in a git directory elsewhere
+    let info = git(repo, &["rev-parse", "--git-path", "info/exclude"])
+        .map(|path| repo.join(path.trim()))
+        .unwrap_or_else(|_| repo.join(".git/info/exclude"));
+    global.into_iter().chain([info]).collect()
+}
+
+fn git(repo: &Path, args: &[&str]) -> Result<String, String> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(repo)
+        .args(args)
+        .output()
+        .map_err(|e| format!("could not run git in {}: {}", repo.display(), e))?;
+    if !output.status.success() {
+        return Err(format!("git {} failed in {}: {}", args.join(" "), repo.display(),
+            String::from_utf8_lossy(&output.stderr).trim()));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
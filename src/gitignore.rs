@@ -0,0 +1,238 @@
+use regex::Regex;
+use std::{collections::HashMap, fs, path::{Path, PathBuf}};
+use log::debug;
+
+use crate::git;
+
+// Files whose patterns are honored in every directory of the scan. At the
+// root of a repository git's own exclude files come first, so these
+// override them.
+const IGNORE_FILES: [&str; 2] = [".gitignore", ".ignore"];
+
+#[derive(Debug)]
+struct Rule {
+    regex: Regex,
+    negated: bool,
+    dir_only: bool,
+}
+
+// Lazily loads ignore files per directory and answers whether a path is
+// excluded by the rules of any directory between the scan root and itself
+pub struct IgnoreMatcher {
+    root: PathBuf,
+    rules: HashMap<PathBuf, Vec<Rule>>,
+    // Whether the rules exclude each directory that has been above a path
+    dirs: HashMap<PathBuf, bool>,
+}
+
+impl IgnoreMatcher {
+    pub fn new(root: &Path) -> Self {
+        IgnoreMatcher {
+            root: root.to_path_buf(),
+            rules: HashMap::new(),
+            dirs: HashMap::new(),
+        }
+    }
+
+    pub fn is_ignored(&mut self, path: &Path, is_dir: bool) -> bool {
+        let Ok(relative) = path.strip_prefix(&self.root) else {
+            return false;
+        };
+        if relative.as_os_str().is_empty() {
+            return false;
+        }
+
+        // Git never looks inside an excluded directory, so nothing under one
+        // can be put back by a negated pattern, whether or not the caller
+        // skipped descending into it
+        let components: Vec<_> = relative.components().collect();
+        let mut parent = self.root.clone();
+        let mut ignored = false;
+        for component in &components[..components.len() - 1] {
+            parent.push(component);
+            ignored = match self.dirs.get(&parent) {
+                Some(&excluded) => excluded,
+                None => {
+                    let excluded = self.matches(&parent, true);
+                    self.dirs.insert(parent.clone(), excluded);
+                    excluded
+                }
+            };
+            if ignored {
+                break;
+            }
+        }
+
+        let ignored = ignored || self.matches(path, is_dir);
+        if ignored {
+            debug!("Ignored by ignore file: {}", path.display());
+        }
+        ignored
+    }
+
+    // Whether the rules between the root and `path` exclude it, leaving
+    // aside what they say of its parents
+    fn matches(&mut self, path: &Path, is_dir: bool) -> bool {
+        let Ok(relative) = path.strip_prefix(&self.root) else {
+            return false;
+        };
+        // Walk from the root down to the parent, later rules override earlier ones
+        let mut ignored = false;
+        let mut dir = self.root.clone();
+        for component in relative.components() {
+            let rel = path.strip_prefix(&dir).unwrap_or(path);
+            let rel = rel.to_string_lossy().replace('\\', "/");
+            for rule in self.rules_for(&dir) {
+                if rule.dir_only && !is_dir {
+                    continue;
+                }
+                if rule.regex.is_match(&rel) {
+                    ignored = !rule.negated;
+                }
+            }
+            dir.push(component);
+        }
+        ignored
+    }
+
+    fn rules_for(&mut self, dir: &Path) -> &[Rule] {
+        self.rules
+            .entry(dir.to_path_buf())
+            .or_insert_with(|| {
+                let git = if dir.join(".git").exists() { git::exclude_files(dir) } else { Vec::new() };
+                git.into_iter()
+                    .chain(IGNORE_FILES.iter().map(|name| dir.join(name)))
+                    .filter_map(|file| fs::read_to_string(file).ok())
+                    .flat_map(|content| content.lines().filter_map(parse_line).collect::<Vec<_>>())
+                    .collect()
+            })
+    }
+}
+
+fn parse_line(line: &str) -> Option<Rule> {
+    let line = line.trim_end();
+    if line.is_empty() || line.starts_with('#') {
+        return None;
+    }
+
+    let (negated, pattern) = match line.strip_prefix('!') {
+        Some(rest) => (true, rest),
+        None => (false, line.strip_prefix('\\').unwrap_or(line)),
+    };
+    let (dir_only, pattern) = match pattern.strip_suffix('/') {
+        Some(rest) => (true, rest),
+        None => (false, pattern),
+    };
+    if pattern.is_empty() {
+        return None;
+    }
+
+    // Patterns without an inner slash match at any depth
+    let pattern = match pattern.strip_prefix('/') {
+        Some(anchored) => anchored.to_string(),
+        None if pattern.contains('/') => pattern.to_string(),
+        None => format!("**/{}", pattern),
+    };
+
+    Regex::new(&glob_to_regex(&pattern))
+        .ok()
+        .map(|regex| Rule { regex, negated, dir_only })
+}
+
+// A glob that matches a path at any depth unless it contains a slash
+pub fn glob_regex(pattern: &str) -> Option<Regex> {
+    let pattern = if pattern.contains('/') {
+        pattern.trim_start_matches('/').to_string()
+    } else {
+        format!("**/{}", pattern)
+    };
+    Regex::new(&glob_to_regex(&pattern)).ok()
+}
+
+fn glob_to_regex(pattern: &str) -> String {
+    let segments: Vec<&str> = pattern.split('/').collect();
+    let mut re = String::from("^");
+
+    for (i, segment) in segments.iter().enumerate() {
+        let last = i + 1 == segments.len();
+        if *segment == "**" {
+            re.push_str(if last { ".*" } else { "(?:[^/]+/)*" });
+            continue;
+        }
+        re.push_str(&segment_to_regex(segment));
+        if !last {
+            re.push('/');
+        }
+    }
+
+    re.push('$');
+    re
+}
+
+fn segment_to_regex(segment: &str) -> String {
+    let mut re = String::new();
+    let mut chars = segment.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '*' => re.push_str("[^/]*"),
+            '?' => re.push_str("[^/]"),
+            '[' => {
+                re.push('[');
+                if matches!(chars.peek(), Some('!') | Some('^')) {
+                    chars.next();
+                    re.push('^');
+                }
+                for c in chars.by_ref() {
+                    if c == ']' {
+                        break;
+                    }
+                    if c == '\\' || c == '[' {
+                        re.push('\\');
+                    }
+                    re.push(c);
+                }
+                re.push(']');
+            }
+            '\\' => {
+                if let Some(escaped) = chars.next() {
+                    re.push_str(&regex::escape(&escaped.to_string()));
+                }
+            }
+            _ => re.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+
+    re
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    // Asked directly about paths the scan would never reach, since it doesn't
+    // descend into ignored directories
+    #[test]
+    fn negated_patterns_cant_bring_back_what_an_ignored_parent_holds() {
+        let root = std::env::temp_dir().join(format!("dirpurge-gitignore-{}", std::process::id()));
+        for dir in ["cache/keep", "build/keep", "build/out"] {
+            fs::create_dir_all(root.join(dir)).unwrap();
+            fs::write(root.join(dir).join("a"), b"x").unwrap();
+        }
+        fs::write(root.join(".gitignore"), "cache/\nbuild/*\n!keep/\n").unwrap();
+        let mut matcher = IgnoreMatcher::new(&root);
+
+        // cache/ itself is excluded, so git never sees what is in it
+        assert!(matcher.is_ignored(&root.join("cache"), true));
+        assert!(matcher.is_ignored(&root.join("cache/keep"), true));
+        assert!(matcher.is_ignored(&root.join("cache/keep/a"), false));
+        // Only build/'s entries are, so one of them can be put back
+        assert!(!matcher.is_ignored(&root.join("build"), true));
+        assert!(!matcher.is_ignored(&root.join("build/keep"), true));
+        assert!(!matcher.is_ignored(&root.join("build/keep/a"), false));
+        assert!(matcher.is_ignored(&root.join("build/out"), true));
+        assert!(matcher.is_ignored(&root.join("build/out/a"), false));
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,341 @@
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{fs, io, path::Path};
+
+// Checksum used to verify backups
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum HashAlgo {
+    // Written out below, its chunks hashed on all cores
+    #[default]
+    Blake3,
+    Sha256,
+}
+
+pub fn hash_reader(algo: HashAlgo, reader: &mut (impl io::Read + ?Sized)) -> io::Result<Vec<u8>> {
+    match algo {
+        HashAlgo::Blake3 => Ok(blake3(reader, workers())?.to_vec()),
+        HashAlgo::Sha256 => {
+            let mut hasher = Sha256::new();
+            io::copy(reader, &mut hasher)?;
+            Ok(hasher.finalize().to_vec())
+        }
+    }
+}
+
+pub fn hash_file(algo: HashAlgo, path: &Path) -> io::Result<Vec<u8>> {
+    hash_reader(algo, &mut fs::File::open(path)?)
+}
+
+// How many files, or chunks of one, to hash at once
+pub fn workers() -> usize {
+    std::thread::available_parallelism().map_or(1, |n| n.get())
+}
+
+// Chunks each worker takes from a batch; fewer aren't worth a thread
+const CHUNKS_PER_WORKER: usize = 16;
+
+// Reads a batch of chunks at a time and hashes them on up to `workers`
+// threads. Only the tree above them is built one chunk at a time.
+fn blake3(reader: &mut (impl io::Read + ?Sized), workers: usize) -> io::Result<[u8; 32]> {
+    let mut hasher = Blake3::new();
+    let mut batch = vec![0; workers * CHUNKS_PER_WORKER * CHUNK_LEN];
+    loop {
+        let mut filled = 0;
+        while filled < batch.len() {
+            match reader.read(&mut batch[filled..]) {
+                Ok(0) => break,
+                Ok(n) => filled += n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        // Batches are read whole, so only the last can end partway through a chunk
+        let whole = filled / CHUNK_LEN * CHUNK_LEN;
+        if whole > 0 {
+            hasher.update_chunks(&batch[..whole], workers);
+        }
+        hasher.update(&batch[whole..filled]);
+        if filled < batch.len() {
+            return Ok(hasher.finalize());
+        }
+    }
+}
+
+// BLAKE3, after the reference implementation in the specification
+
+const IV: [u32; 8] = [0x6A09E667, 0xBB67AE85, 0x3C6EF372, 0xA54FF53A, 0x510E527F, 0x9B05688C, 0x1F83D9AB, 0x5BE0CD19];
+const MSG_PERMUTATION: [usize; 16] = [2, 6, 3, 10, 7, 0, 4, 13, 1, 11, 12, 5, 9, 14, 15, 8];
+const BLOCK_LEN: usize = 64;
+const CHUNK_LEN: usize = 1024;
+const CHUNK_START: u32 = 1;
+const CHUNK_END: u32 = 2;
+const PARENT: u32 = 4;
+const ROOT: u32 = 8;
+
+fn g(state: &mut [u32; 16], a: usize, b: usize, c: usize, d: usize, mx: u32, my: u32) {
+    state[a] = state[a].wrapping_add(state[b]).wrapping_add(mx);
+    state[d] = (state[d] ^ state[a]).rotate_right(16);
+    state[c] = state[c].wrapping_add(state[d]);
+    state[b] = (state[b] ^ state[c]).rotate_right(12);
+    state[a] = state[a].wrapping_add(state[b]).wrapping_add(my);
+    state[d] = (state[d] ^ state[a]).rotate_right(8);
+    state[c] = state[c].wrapping_add(state[d]);
+    state[b] = (state[b] ^ state[c]).rotate_right(7);
+}
+
+fn round(state: &mut [u32; 16], m: &[u32; 16]) {
+    g(state, 0, 4, 8, 12, m[0], m[1]);
+    g(state, 1, 5, 9, 13, m[2], m[3]);
+    g(state, 2, 6, 10, 14, m[4], m[5]);
+    g(state, 3, 7, 11, 15, m[6], m[7]);
+    g(state, 0, 5, 10, 15, m[8], m[9]);
+    g(state, 1, 6, 11, 12, m[10], m[11]);
+    g(state, 2, 7, 8, 13, m[12], m[13]);
+    g(state, 3, 4, 9, 14, m[14], m[15]);
+}
+
+fn compress(cv: &[u32; 8], block: &[u32; 16], counter: u64, block_len: u32, flags: u32) -> [u32; 16] {
+    let mut state = [
+        cv[0], cv[1], cv[2], cv[3], cv[4], cv[5], cv[6], cv[7],
+        IV[0], IV[1], IV[2], IV[3],
+        counter as u32, (counter >> 32) as u32, block_len, flags,
+    ];
+    let mut m = *block;
+    for i in 0..7 {
+        round(&mut state, &m);
+        if i < 6 {
+            m = std::array::from_fn(|j| m[MSG_PERMUTATION[j]]);
+        }
+    }
+    for i in 0..8 {
+        state[i] ^= state[i + 8];
+        state[i + 8] ^= cv[i];
+    }
+    state
+}
+
+fn first_8(words: [u32; 16]) -> [u32; 8] {
+    std::array::from_fn(|i| words[i])
+}
+
+fn words(bytes: &[u8; BLOCK_LEN]) -> [u32; 16] {
+    std::array::from_fn(|i| u32::from_le_bytes(bytes[i * 4..i * 4 + 4].try_into().unwrap()))
+}
+
+// A compression not yet run, so the root can be finalized with its own flag
+struct Output {
+    cv: [u32; 8],
+    block: [u32; 16],
+    counter: u64,
+    block_len: u32,
+    flags: u32,
+}
+
+impl Output {
+    fn chaining_value(&self) -> [u32; 8] {
+        first_8(compress(&self.cv, &self.block, self.counter, self.block_len, self.flags))
+    }
+
+    fn root_hash(&self) -> [u8; 32] {
+        let words = compress(&self.cv, &self.block, 0, self.block_len, self.flags | ROOT);
+        let mut hash = [0; 32];
+        for (bytes, word) in hash.chunks_exact_mut(4).zip(words) {
+            bytes.copy_from_slice(&word.to_le_bytes());
+        }
+        hash
+    }
+}
+
+fn parent_output(left: [u32; 8], right: [u32; 8]) -> Output {
+    let block = std::array::from_fn(|i| if i < 8 { left[i] } else { right[i - 8] });
+    Output { cv: IV, block, counter: 0, block_len: BLOCK_LEN as u32, flags: PARENT }
+}
+
+struct ChunkState {
+    cv: [u32; 8],
+    counter: u64,
+    block: [u8; BLOCK_LEN],
+    block_len: usize,
+    blocks_compressed: usize,
+}
+
+impl ChunkState {
+    fn new(counter: u64) -> Self {
+        ChunkState { cv: IV, counter, block: [0; BLOCK_LEN], block_len: 0, blocks_compressed: 0 }
+    }
+
+    fn len(&self) -> usize {
+        BLOCK_LEN * self.blocks_compressed + self.block_len
+    }
+
+    fn start_flag(&self) -> u32 {
+        if self.blocks_compressed == 0 { CHUNK_START } else { 0 }
+    }
+
+    fn update(&mut self, mut input: &[u8]) {
+        while !input.is_empty() {
+            // The last block is held back, it is compressed with CHUNK_END
+            if self.block_len == BLOCK_LEN {
+                self.cv = first_8(compress(&self.cv, &words(&self.block), self.counter, BLOCK_LEN as u32, self.start_flag()));
+                self.blocks_compressed += 1;
+                self.block = [0; BLOCK_LEN];
+                self.block_len = 0;
+            }
+            let take = (BLOCK_LEN - self.block_len).min(input.len());
+            self.block[self.block_len..self.block_len + take].copy_from_slice(&input[..take]);
+            self.block_len += take;
+            input = &input[take..];
+        }
+    }
+
+    fn output(&self) -> Output {
+        Output {
+            cv: self.cv,
+            block: words(&self.block),
+            counter: self.counter,
+            block_len: self.block_len as u32,
+            flags: self.start_flag() | CHUNK_END,
+        }
+    }
+}
+
+struct Blake3 {
+    chunk: ChunkState,
+    // Chaining values of completed subtrees, one per set bit of the chunk count
+    stack: Vec<[u32; 8]>,
+}
+
+impl Blake3 {
+    fn new() -> Self {
+        Blake3 { chunk: ChunkState::new(0), stack: Vec::new() }
+    }
+
+    fn add_chunk(&mut self, mut cv: [u32; 8], mut total_chunks: u64) {
+        while total_chunks & 1 == 0 {
+            let left = self.stack.pop().expect("a completed subtree to merge with");
+            cv = parent_output(left, cv).chaining_value();
+            total_chunks >>= 1;
+        }
+        self.stack.push(cv);
+    }
+
+    // Whole chunks, starting where the last whole chunk ended. The last of
+    // them stays in `chunk`, since the root may have to be made from it.
+    fn update_chunks(&mut self, input: &[u8], workers: usize) {
+        if self.chunk.len() == CHUNK_LEN {
+            let cv = self.chunk.output().chaining_value();
+            let total_chunks = self.chunk.counter + 1;
+            self.add_chunk(cv, total_chunks);
+            self.chunk = ChunkState::new(total_chunks);
+        }
+        let first = self.chunk.counter;
+        let (body, last) = input.split_at(input.len() - CHUNK_LEN);
+        let chunks = body.len() / CHUNK_LEN;
+        let threads = workers.min(chunks / CHUNKS_PER_WORKER).max(1);
+        let share = chunks.div_ceil(threads).max(1) * CHUNK_LEN;
+        let chaining_value = |(i, chunk): (usize, &[u8])| {
+            let mut state = ChunkState::new(first + i as u64);
+            state.update(chunk);
+            state.output().chaining_value()
+        };
+        let cvs: Vec<[u32; 8]> = if threads == 1 {
+            body.chunks(CHUNK_LEN).enumerate().map(chaining_value).collect()
+        } else {
+            std::thread::scope(|scope| {
+                let parts: Vec<_> = body.chunks(share).enumerate()
+                    .map(|(part, body)| scope.spawn(move || {
+                        let offset = part * share / CHUNK_LEN;
+                        body.chunks(CHUNK_LEN).enumerate().map(|(i, chunk)| chaining_value((offset + i, chunk))).collect::<Vec<_>>()
+                    }))
+                    .collect();
+                parts.into_iter().flat_map(|part| part.join().expect("hashing thread panicked")).collect()
+            })
+        };
+        for (i, cv) in cvs.into_iter().enumerate() {
+            self.add_chunk(cv, first + i as u64 + 1);
+        }
+        self.chunk = ChunkState::new(first + chunks as u64);
+        self.chunk.update(last);
+    }
+
+    fn update(&mut self, mut input: &[u8]) {
+        while !input.is_empty() {
+            if self.chunk.len() == CHUNK_LEN {
+                let cv = self.chunk.output().chaining_value();
+                let total_chunks = self.chunk.counter + 1;
+                self.add_chunk(cv, total_chunks);
+                self.chunk = ChunkState::new(total_chunks);
+            }
+            let take = (CHUNK_LEN - self.chunk.len()).min(input.len());
+            self.chunk.update(&input[..take]);
+            input = &input[take..];
+        }
+    }
+
+    fn finalize(&self) -> [u8; 32] {
+        let mut output = self.chunk.output();
+        for left in self.stack.iter().rev() {
+            output = parent_output(*left, output.chaining_value());
+        }
+        output.root_hash()
+    }
+}
+
+// Checked against the official test vectors: input byte i is i % 251, and
+// the hash is the first 32 bytes of the extended output
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    const VECTORS: [(usize, &str); 7] = [
+        (0, "af1349b9f5f9a1a6a0404dea36dcc9499bcb25c9adc112b7cc9a93cae41f3262"),
+        (1, "2d3adedff11b61f14c886e35afa036736dcd87a74d27b5c1510225d0f592e213"),
+        (1023, "10108970eeda3eb932baac1428c7a2163b0e924c9a9e25b35bba72b28f70bd11"),
+        (1024, "42214739f095a406f3fc83deb889744ac00df831c10daa55189b5d121c855af7"),
+        (1025, "d00278ae47eb27b34faecf67b4fe263f82d5412916c1ffd97c8cb7fb814b8444"),
+        (2048, "e776b6028c7cd22a4d0ba182a8bf62205d2ef576467e838ed6f2529b85fba24a"),
+        (102400, "bc3e3d41a1146b069abffad3c0d44860cf664390afce4d9661f7902e7943e085"),
+    ];
+
+    fn input(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i % 251) as u8).collect()
+    }
+
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    // Hands out a few bytes per read, so input arrives split across blocks and chunks
+    struct Trickle<'a>(&'a [u8]);
+
+    impl Read for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let n = buf.len().min(self.0.len()).min(7);
+            buf[..n].copy_from_slice(&self.0[..n]);
+            self.0 = &self.0[n..];
+            Ok(n)
+        }
+    }
+
+    #[test]
+    fn blake3_matches_the_official_test_vectors() {
+        for (len, expected) in VECTORS {
+            let input = input(len);
+            assert_eq!(hex(&hash_reader(HashAlgo::Blake3, &mut input.as_slice()).unwrap()), expected, "{} bytes", len);
+            assert_eq!(hex(&hash_reader(HashAlgo::Blake3, &mut Trickle(&input)).unwrap()), expected, "{} bytes in pieces", len);
+        }
+    }
+
+    // However many threads share the chunks, and however the batches fall
+    #[test]
+    fn blake3_is_the_same_on_any_number_of_threads() {
+        let (len, expected) = VECTORS[6];
+        let input = input(len);
+        for workers in 1..=5 {
+            assert_eq!(hex(&blake3(&mut input.as_slice(), workers).unwrap()), expected, "{} workers", workers);
+            assert_eq!(hex(&blake3(&mut Trickle(&input), workers).unwrap()), expected, "{} workers, in pieces", workers);
+        }
+    }
+}
@@ -0,0 +1,117 @@
+// Progress notes while a long removal run is still going, so a scheduled
+// cleanup that stalls is noticed before the next one is due: once the run has
+// taken --heartbeat seconds, and as often again after that, a line in the
+// report and, with --heartbeat-webhook, a POST with the same numbers.
+use std::{sync::{atomic::{AtomicU64, AtomicUsize, Ordering}, Condvar, Mutex}, thread, time::{Duration, Instant}};
+use log::{info, warn};
+
+use crate::{locale, report::{self, Kind}, webhook, format_mb};
+
+pub struct Heartbeat {
+    every: Duration,
+    webhook: Option<String>,
+    started: Instant,
+    // What the run set out to remove
+    dirs: usize,
+    bytes: u64,
+    // Counted by the workers as directories go or fail
+    done: AtomicUsize,
+    failed: AtomicUsize,
+    freed: AtomicU64,
+    stopped: Mutex<bool>,
+    wake: Condvar,
+}
+
+impl Heartbeat {
+    pub fn new(every: Duration, webhook: Option<String>, dirs: usize, bytes: u64) -> Self {
+        Heartbeat {
+            every,
+            webhook,
+            started: Instant::now(),
+            dirs,
+            bytes,
+            done: AtomicUsize::new(0),
+            failed: AtomicUsize::new(0),
+            freed: AtomicU64::new(0),
+            stopped: Mutex::new(false),
+            wake: Condvar::new(),
+        }
+    }
+
+    // A directory that was removed and freed `bytes`
+    pub fn removed(&self, bytes: u64) {
+        self.done.fetch_add(1, Ordering::Relaxed);
+        self.freed.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    pub fn failed(&self) {
+        self.done.fetch_add(1, Ordering::Relaxed);
+        self.failed.fetch_add(1, Ordering::Relaxed);
+    }
+
+    // Runs `work` with a heartbeat going alongside, when there is one
+    pub fn around<T>(heartbeat: Option<&Heartbeat>, work: impl FnOnce() -> T) -> T {
+        let Some(heartbeat) = heartbeat else {
+            return work();
+        };
+        thread::scope(|scope| {
+            scope.spawn(|| heartbeat.beat());
+            let result = work();
+            *heartbeat.stopped.lock().unwrap_or_else(|poisoned| poisoned.into_inner()) = true;
+            heartbeat.wake.notify_all();
+            result
+        })
+    }
+
+    fn beat(&self) {
+        let mut stopped = self.stopped.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        loop {
+            let (guard, timeout) = self.wake.wait_timeout_while(stopped, self.every, |stopped| !*stopped)
+                .unwrap_or_else(|poisoned| poisoned.into_inner());
+            stopped = guard;
+            if !timeout.timed_out() {
+                return;
+            }
+            self.report();
+        }
+    }
+
+    fn report(&self) {
+        let (done, failed, freed) = (self.done.load(Ordering::Relaxed), self.failed.load(Ordering::Relaxed), self.freed.load(Ordering::Relaxed));
+        let elapsed = self.started.elapsed().as_secs();
+        // By bytes, so one huge directory left doesn't read as nearly done
+        let percent = if self.bytes == 0 { 100.0 * done as f64 / self.dirs.max(1) as f64 } else { 100.0 * freed as f64 / self.bytes as f64 };
+        let failures = if failed > 0 { format!(", {} failed", locale::count(failed)) } else { String::new() };
+        let text = format!(
+            "Still running after {}: {}% done, {} of {} directories, {} freed{}",
+            running_for(elapsed), locale::number(percent.min(100.0), 0), locale::count(done), locale::count(self.dirs), format_mb(freed, false), failures
+        );
+        info!("{}", text);
+        report::message(Kind::Info, text);
+        if let Some(url) = &self.webhook {
+            let body = serde_json::json!({
+                "event": "heartbeat",
+                "elapsed_seconds": elapsed,
+                "percent_done": percent.min(100.0),
+                "directories_done": done,
+                "directories": self.dirs,
+                "failed": failed,
+                "freed_bytes": freed,
+                "total_bytes": self.bytes,
+            });
+            // The run carries on whether anyone heard it or not
+            if let Err(e) = webhook::post(url, &body) {
+                warn!("Heartbeat webhook failed: {}", e);
+            }
+        }
+    }
+}
+
+// "1h 05m", "12m 30s" or "45s"
+fn running_for(secs: u64) -> String {
+    match (secs / 3600, secs / 60 % 60, secs % 60) {
+        (0, 0, s) => format!("{}s", s),
+        (0, m, s) => format!("{}m {:02}s", m, s),
+        (h, m, _) => format!("{}h {:02}m", h, m),
+    }
+}
@@ -0,0 +1,232 @@
+use serde::{Deserialize, Serialize};
+use std::{collections::{BTreeMap, HashMap}, fs, sync::Mutex, time::Duration};
+use log::{info, warn};
+
+use crate::{locale, report::{self, Kind}, webhook, DirInfo, CROSS, format_mb};
+
+// Stamped as `v`; files from before the stamp are version 1
+const FORMAT: u32 = 3;
+// Only the most recent removals are kept, so the file stays small
+const MAX_SAMPLES: usize = 5000;
+
+// Sizes of tracked directories as of the previous run, and how long recent
+// removals took
+#[derive(Serialize, Deserialize, Debug)]
+struct History {
+    #[serde(rename = "v")]
+    version: u32,
+    directories: HashMap<String, Recorded>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    actions: Vec<ActionSample>,
+}
+
+impl Default for History {
+    fn default() -> Self {
+        History { version: FORMAT, directories: HashMap::new(), actions: Vec::new() }
+    }
+}
+
+// One directory removed, for `dirpurge stats actions`
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct ActionSample {
+    // As journaled: delete, trash, backup+delete, ...
+    action: String,
+    filesystem: String,
+    size_bytes: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    entries: Option<usize>,
+    seconds: f64,
+    recorded_at: String,
+}
+
+// Parse a history file of this or an older format. One from a newer dirpurge
+// is refused rather than rewritten without what it added.
+fn parse(data: &str, history_path: &str) -> Result<History, String> {
+    let mut value: serde_json::Value = serde_json::from_str(data)
+        .map_err(|e| format!("{} Error parsing history {}: {}", CROSS, history_path, e))?;
+    let version = value.get("v").and_then(serde_json::Value::as_u64).map_or(1, |v| v as u32);
+    if version > FORMAT {
+        return Err(format!("{} History {} was written by a newer dirpurge (format {}), upgrade or pass another --history", CROSS, history_path, version));
+    }
+    // Version 2 only added the stamp, 3 the action samples, which default to none
+    if let Some(history) = value.as_object_mut() {
+        history.insert("v".to_string(), FORMAT.into());
+    }
+    serde_json::from_value(value)
+        .map_err(|e| format!("{} Error parsing history {}: {}", CROSS, history_path, e))
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct Recorded {
+    size_bytes: u64,
+    recorded_at: String,
+}
+
+#[derive(Serialize, Debug)]
+struct GrowthAlert<'a> {
+    path: &'a str,
+    previous_bytes: u64,
+    size_bytes: u64,
+    growth_percent: f64,
+    since: &'a str,
+}
+
+// Compare tracked directories against the sizes recorded by the last run,
+// warn about any that grew by more than `alert_percent`, then record the new sizes
+pub fn check_growth(
+    tracked: &[DirInfo],
+    history_path: &str,
+    alert_percent: Option<f64>,
+    webhook: Option<&str>,
+) -> Result<(), String> {
+    let mut history = load(history_path)?;
+
+    if let Some(threshold) = alert_percent {
+        let alerts: Vec<GrowthAlert> = tracked.iter()
+            .filter_map(|dir| {
+                let previous = history.directories.get(&dir.path)?;
+                // Growth from nothing has no meaningful percentage
+                if previous.size_bytes == 0 {
+                    return None;
+                }
+                let growth_percent = (dir.size_bytes as f64 - previous.size_bytes as f64) / previous.size_bytes as f64 * 100.0;
+                (growth_percent > threshold).then_some(GrowthAlert {
+                    path: &dir.path,
+                    previous_bytes: previous.size_bytes,
+                    size_bytes: dir.size_bytes,
+                    growth_percent,
+                    since: &previous.recorded_at,
+                })
+            })
+            .collect();
+
+        for alert in &alerts {
+            warn!("{} grew {:.1}% since {} ({} -> {} bytes)", alert.path, alert.growth_percent, alert.since, alert.previous_bytes, alert.size_bytes);
+            report::message(Kind::Warning, format!(
+                "{} grew {:.1}% since the last run ({} -> {})",
+                alert.path,
+                alert.growth_percent,
+                format_mb(alert.previous_bytes, false),
+                format_mb(alert.size_bytes, false)
+            ));
+        }
+        if let Some(url) = webhook
+            && !alerts.is_empty()
+            && let Err(e) = webhook::post(url, &serde_json::json!({ "event": "growth", "alerts": alerts }))
+        {
+            // A failed notification shouldn't lose this run's sizes
+            warn!("Growth webhook failed: {}", e);
+            report::message(Kind::Info, format!("Could not send growth alerts: {}", e));
+        }
+    }
+
+    let now = chrono::Local::now().to_rfc3339();
+    for dir in tracked {
+        history.directories.insert(dir.path.clone(), Recorded { size_bytes: dir.size_bytes, recorded_at: now.clone() });
+    }
+    save(&history, history_path)?;
+    info!("Recorded {} tracked directories in {}", tracked.len(), history_path);
+    Ok(())
+}
+
+fn load(history_path: &str) -> Result<History, String> {
+    match fs::read_to_string(history_path) {
+        Ok(data) => parse(&data, history_path),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(History::default()),
+        Err(e) => Err(format!("{} Error reading history {}: {}", CROSS, history_path, e)),
+    }
+}
+
+fn save(history: &History, history_path: &str) -> Result<(), String> {
+    let json = serde_json::to_string_pretty(history)
+        .map_err(|e| format!("{} Error serializing history: {}", CROSS, e))?;
+    fs::write(history_path, json)
+        .map_err(|e| format!("{} Error writing history {}: {}", CROSS, history_path, e))
+}
+
+// Removals of this run, kept until `record_actions` writes them out; timed
+// in the deletion workers
+static SAMPLES: Mutex<Vec<ActionSample>> = Mutex::new(Vec::new());
+
+// Note that `dir` was handled with `action` on `filesystem` in `took`
+pub fn time_action(dir: &DirInfo, action: String, filesystem: String, took: Duration) {
+    let sample = ActionSample {
+        action,
+        filesystem,
+        size_bytes: dir.size_bytes,
+        entries: dir.item_count,
+        seconds: took.as_secs_f64(),
+        recorded_at: chrono::Local::now().to_rfc3339(),
+    };
+    if let Ok(mut samples) = SAMPLES.lock() {
+        samples.push(sample);
+    }
+}
+
+// Add this run's timed removals to the history file
+pub fn record_actions(history_path: &str) -> Result<(), String> {
+    let samples: Vec<ActionSample> = SAMPLES.lock().map(|mut samples| samples.drain(..).collect()).unwrap_or_default();
+    if samples.is_empty() {
+        return Ok(());
+    }
+    let mut history = load(history_path)?;
+    history.actions.extend(samples);
+    let excess = history.actions.len().saturating_sub(MAX_SAMPLES);
+    history.actions.drain(..excess);
+    save(&history, history_path)?;
+    info!("Recorded action timings in {}", history_path);
+    Ok(())
+}
+
+// `dirpurge stats actions`: how fast each action went on each filesystem,
+// over every removal the history file holds
+pub fn report_actions(history_path: &str) -> Result<(), String> {
+    let history = match fs::read_to_string(history_path) {
+        Ok(data) => parse(&data, history_path)?,
+        Err(e) => return Err(format!("{} Error reading history {}: {}", CROSS, history_path, e)),
+    };
+    if history.actions.is_empty() {
+        report::message(Kind::Info, format!("No removals recorded in {} yet; runs with --history record them", history_path));
+        return Ok(());
+    }
+
+    #[derive(Default)]
+    struct Totals {
+        dirs: usize,
+        bytes: u64,
+        entries: usize,
+        seconds: f64,
+    }
+    let mut totals: BTreeMap<&str, BTreeMap<&str, Totals>> = BTreeMap::new();
+    for sample in &history.actions {
+        let totals = totals.entry(&sample.filesystem).or_default().entry(&sample.action).or_default();
+        totals.dirs += 1;
+        totals.bytes += sample.size_bytes;
+        totals.entries += sample.entries.unwrap_or(0);
+        totals.seconds += sample.seconds;
+    }
+
+    report::message(Kind::Info, format!("Throughput per action, from {} removal(s) recorded in {}:", locale::count(history.actions.len()), history_path));
+    for (filesystem, actions) in totals {
+        report::message(Kind::Setting, filesystem);
+        // Fastest first
+        let mut actions: Vec<(&str, Totals)> = actions.into_iter().collect();
+        let rate = |totals: &Totals| totals.bytes as f64 / totals.seconds.max(f64::EPSILON);
+        actions.sort_by(|a, b| rate(&b.1).total_cmp(&rate(&a.1)));
+        report::items(&actions.iter()
+            .map(|(action, totals)| {
+                let entries = if totals.entries > 0 {
+                    format!(", {} entries/s", locale::number(totals.entries as f64 / totals.seconds.max(f64::EPSILON), 0))
+                } else {
+                    String::new()
+                };
+                format!(
+                    "{}: {}/s{}, {} dir(s), {} in {}s",
+                    action, format_mb(rate(totals) as u64, false), entries, locale::count(totals.dirs),
+                    format_mb(totals.bytes, false), locale::number(totals.seconds, 1)
+                )
+            })
+            .collect::<Vec<_>>());
+    }
+    Ok(())
+}
@@ -0,0 +1,376 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, HashSet},
+    env,
+    fs::{self, File, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+    sync::{atomic::{AtomicU64, Ordering}, Mutex},
+    time::{Duration, Instant},
+};
+use log::{debug, error, warn};
+
+use crate::{plan::Plan, protected, CROSS};
+
+// What happened, one line of the journal each
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "event", rename_all = "kebab-case")]
+pub enum Event {
+    // A run starting, with everything needed to redo it. Plan paths are
+    // relative to `cwd`.
+    Begin {
+        plan: Box<Plan>,
+        cwd: Option<String>,
+    },
+    // An interrupted run being continued
+    Resume,
+    // Written for every directory before any of them is touched
+    Planned { path: String, size_bytes: u64, action: String },
+    // A plain copy into `to` under way; resuming carries on with it
+    Copying { path: String, to: String },
+    // How far a long copy had got, written every checkpoint interval
+    Checkpoint { path: String, files: u64, bytes: u64 },
+    BackedUp { path: String, copies: Vec<String> },
+    // Removal started, with every copy made and verified
+    Deleting { path: String },
+    Done {
+        path: String,
+        #[serde(default)]
+        trashed: bool,
+        #[serde(default)]
+        copies: Vec<String>,
+    },
+    // Stale files removed from a directory that was kept
+    Thinned { path: String, files: usize, bytes: u64 },
+    Failed { path: String, error: String },
+    Finish,
+    // A removed directory brought back by `restore`
+    Restored { path: String, from: String, to: String },
+}
+
+// Stamped on every entry as `v`. Lines from before the stamp are version 1.
+pub const FORMAT: u32 = 2;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Entry {
+    #[serde(rename = "v")]
+    pub version: u32,
+    pub run: String,
+    pub timestamp: String,
+    #[serde(flatten)]
+    pub event: Event,
+}
+
+struct Journal {
+    file: File,
+    run: String,
+}
+
+// The journal of the current run; nothing is recorded until it is opened
+static JOURNAL: Mutex<Option<Journal>> = Mutex::new(None);
+
+// Seconds between checkpoints of a long copy
+static CHECKPOINT_INTERVAL: AtomicU64 = AtomicU64::new(DEFAULT_CHECKPOINT_INTERVAL);
+pub const DEFAULT_CHECKPOINT_INTERVAL: u64 = 30;
+
+pub fn checkpoint_every(secs: Option<u64>) {
+    CHECKPOINT_INTERVAL.store(secs.unwrap_or(DEFAULT_CHECKPOINT_INTERVAL), Ordering::Relaxed);
+}
+
+// Counts what a copy of `path` has written and records a Checkpoint once
+// the interval has passed since the last one
+pub struct Checkpoints {
+    path: String,
+    last: Instant,
+    files: u64,
+    bytes: u64,
+}
+
+impl Checkpoints {
+    pub fn new(path: &str) -> Self {
+        Checkpoints { path: path.to_string(), last: Instant::now(), files: 0, bytes: 0 }
+    }
+
+    // A file now in the copy, whether copied just now or kept from before
+    pub fn copied(&mut self, bytes: u64) {
+        self.files += 1;
+        self.bytes += bytes;
+        if self.last.elapsed() >= Duration::from_secs(CHECKPOINT_INTERVAL.load(Ordering::Relaxed)) {
+            record(Event::Checkpoint { path: self.path.clone(), files: self.files, bytes: self.bytes });
+            self.last = Instant::now();
+        }
+    }
+}
+
+// Where dirpurge keeps what it needs between runs
+pub fn state_dir() -> Option<PathBuf> {
+    if let Some(state) = env::var_os("XDG_STATE_HOME") {
+        return Some(PathBuf::from(state).join("dirpurge"));
+    }
+    if cfg!(windows) {
+        env::var_os("LOCALAPPDATA").map(|dir| PathBuf::from(dir).join("dirpurge"))
+    } else {
+        protected::home_dir().map(|home| home.join(".local/state/dirpurge"))
+    }
+}
+
+// Where the journal lives unless `journal` is configured
+fn default_path() -> Option<PathBuf> {
+    state_dir().map(|dir| dir.join("journal.jsonl"))
+}
+
+pub fn resolve(configured: Option<&str>) -> Result<PathBuf, String> {
+    configured.map(PathBuf::from)
+        .or_else(default_path)
+        .ok_or_else(|| format!("{} No home directory for the journal, pass --journal FILE", CROSS))
+}
+
+fn open(path: &Path, run: String) -> Result<(), String> {
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        fs::create_dir_all(parent)
+            .map_err(|e| format!("{} Cannot create journal directory {}: {}", CROSS, parent.display(), e))?;
+    }
+    let file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .map_err(|e| format!("{} Cannot open journal {}: {} (pass --journal FILE to write it elsewhere)", CROSS, path.display(), e))?;
+    debug!("Journaling run {} to {}", run, path.display());
+    *JOURNAL.lock().map_err(|_| format!("{} Journal lock poisoned", CROSS))? = Some(Journal { file, run });
+    Ok(())
+}
+
+// Start journaling a new run of `plan`
+pub fn begin(path: &Path, plan: &Plan) -> Result<(), String> {
+    open(path, format!("{}-{}", plan.created_at_ms, std::process::id()))?;
+    let cwd = env::current_dir().ok().map(|dir| dir.to_string_lossy().into_owned());
+    record(Event::Begin { plan: Box::new(plan.clone()), cwd });
+    Ok(())
+}
+
+// Record further events of an earlier run under its own id
+pub fn attach(path: &Path, run: &str) -> Result<(), String> {
+    open(path, run.to_string())
+}
+
+// Continue journaling an interrupted run
+pub fn resume(path: &Path, run: &str) -> Result<(), String> {
+    attach(path, run)?;
+    record(Event::Resume);
+    Ok(())
+}
+
+// Append an event and flush it to disk before returning; a no-op without an
+// open journal, as in dry runs
+pub fn record(event: Event) {
+    let Ok(mut journal) = JOURNAL.lock() else {
+        return;
+    };
+    let Some(journal) = journal.as_mut() else {
+        return;
+    };
+    let entry = Entry {
+        version: FORMAT,
+        run: journal.run.clone(),
+        timestamp: chrono::Local::now().to_rfc3339(),
+        event,
+    };
+    let written = serde_json::to_string(&entry)
+        .map_err(std::io::Error::other)
+        .and_then(|line| writeln!(journal.file, "{}", line))
+        .and_then(|_| journal.file.sync_data());
+    if let Err(e) = written {
+        error!("Failed to write journal entry: {}", e);
+    }
+}
+
+// Every entry in the journal. A torn last line from a crash mid-write is dropped.
+pub fn read(path: &Path) -> Result<Vec<Entry>, String> {
+    let content = fs::read_to_string(path)
+        .map_err(|e| format!("{} Error reading journal {}: {}", CROSS, path.display(), e))?;
+    let lines: Vec<&str> = content.lines().filter(|line| !line.trim().is_empty()).collect();
+    let mut entries = Vec::with_capacity(lines.len());
+    let mut newer = 0;
+    for (i, line) in lines.iter().enumerate() {
+        let parsed = serde_json::from_str::<serde_json::Value>(line)
+            .map_err(|e| e.to_string())
+            .and_then(|value| match version_of(&value) {
+                version if version > FORMAT => Ok(None),
+                version => serde_json::from_value(migrate(value, version)).map(Some).map_err(|e| e.to_string()),
+            });
+        match parsed {
+            Ok(Some(entry)) => entries.push(entry),
+            Ok(None) => newer += 1,
+            Err(e) if i + 1 == lines.len() => warn!("Ignoring incomplete last journal line: {}", e),
+            Err(e) => return Err(format!("{} Error parsing journal {} line {}: {}", CROSS, path.display(), i + 1, e)),
+        }
+    }
+    // Runs of a newer dirpurge can't be undone by this one, the rest still can
+    if newer > 0 {
+        warn!("Skipped {} entries of {} written by a newer dirpurge (format above {}), upgrade to act on them", newer, path.display(), FORMAT);
+    }
+    Ok(entries)
+}
+
+fn version_of(value: &serde_json::Value) -> u32 {
+    value.get("v").and_then(serde_json::Value::as_u64).map_or(1, |v| v as u32)
+}
+
+// Bring an entry written in an older format up to FORMAT, one version at a time
+fn migrate(mut value: serde_json::Value, from: u32) -> serde_json::Value {
+    for version in from..FORMAT {
+        match version {
+            // Version 2 added the stamp. The earliest runs didn't record
+            // where they started, so their paths were taken from wherever
+            // resume or restore ran; that is filled in as their `cwd`.
+            1 => {
+                if value.get("event").and_then(serde_json::Value::as_str) == Some("begin") && value.get("cwd").is_none() {
+                    let cwd = env::current_dir().ok().map(|dir| dir.to_string_lossy().into_owned());
+                    value["cwd"] = cwd.into();
+                }
+            }
+            _ => unreachable!("no migration from journal format {}", version),
+        }
+    }
+    if let Some(entry) = value.as_object_mut() {
+        entry.insert("v".to_string(), FORMAT.into());
+    }
+    value
+}
+
+// A run that began but never finished
+#[derive(Debug)]
+pub struct Interrupted {
+    pub run: String,
+    pub plan: Plan,
+    // Where the run was started, which its relative paths depend on
+    pub cwd: Option<PathBuf>,
+    // Directories already removed
+    pub done: HashSet<String>,
+    // Copies made before the interruption, path -> copies
+    pub backed_up: HashMap<String, Vec<String>>,
+    // Copies cut off part-way, path -> partial copies
+    pub copying: HashMap<String, Vec<String>>,
+    // Files and bytes each of those had at its last checkpoint
+    pub checkpoints: HashMap<String, (u64, u64)>,
+    // Directories whose removal had started
+    pub deleting: HashSet<String>,
+}
+
+// The given run, or the most recent one, if it didn't finish
+pub fn interrupted(entries: &[Entry], run: Option<&str>) -> Option<Interrupted> {
+    let run = match run {
+        Some(run) => run.to_string(),
+        None => entries.iter().rev().find(|e| matches!(e.event, Event::Begin { .. }))?.run.clone(),
+    };
+    let mut found: Option<Interrupted> = None;
+    for entry in entries.iter().filter(|e| e.run == run) {
+        match &entry.event {
+            Event::Begin { plan, cwd } => {
+                found = Some(Interrupted {
+                    run: run.clone(),
+                    plan: (**plan).clone(),
+                    cwd: cwd.as_ref().map(PathBuf::from),
+                    done: HashSet::new(),
+                    backed_up: HashMap::new(),
+                    copying: HashMap::new(),
+                    checkpoints: HashMap::new(),
+                    deleting: HashSet::new(),
+                });
+            }
+            Event::Copying { path, to } => {
+                if let Some(found) = found.as_mut() {
+                    let partial = found.copying.entry(path.clone()).or_default();
+                    if !partial.contains(to) {
+                        partial.push(to.clone());
+                    }
+                }
+            }
+            Event::Checkpoint { path, files, bytes } => {
+                if let Some(found) = found.as_mut() {
+                    found.checkpoints.insert(path.clone(), (*files, *bytes));
+                }
+            }
+            Event::BackedUp { path, copies } => {
+                if let Some(found) = found.as_mut() {
+                    found.copying.remove(path);
+                    found.checkpoints.remove(path);
+                    found.backed_up.insert(path.clone(), copies.clone());
+                }
+            }
+            Event::Deleting { path } => {
+                if let Some(found) = found.as_mut() {
+                    found.deleting.insert(path.clone());
+                }
+            }
+            Event::Done { path, .. } | Event::Thinned { path, .. } => {
+                if let Some(found) = found.as_mut() {
+                    found.done.insert(path.clone());
+                }
+            }
+            Event::Finish => return None,
+            Event::Resume | Event::Planned { .. } | Event::Failed { .. } | Event::Restored { .. } => {}
+        }
+    }
+    found
+}
+
+// A directory a run removed, as the journal recorded it
+#[derive(Debug, Clone)]
+pub struct Removed {
+    pub run: String,
+    // Absolute, resolved against the run's working directory
+    pub path: PathBuf,
+    pub trashed: bool,
+    pub copies: Vec<String>,
+    pub removed_at: String,
+}
+
+// The most recent removal of `path`, optionally only within `run`
+pub fn last_removal(entries: &[Entry], path: &Path, run: Option<&str>) -> Option<Removed> {
+    removals(entries, run).into_iter().rfind(|removed| removed.path == path)
+}
+
+// Every removal recorded, oldest first, optionally only within `run`
+pub fn removals(entries: &[Entry], run: Option<&str>) -> Vec<Removed> {
+    let mut cwds: HashMap<&str, PathBuf> = HashMap::new();
+    let mut found = Vec::new();
+    for entry in entries {
+        match &entry.event {
+            Event::Begin { cwd: Some(cwd), .. } => {
+                cwds.insert(&entry.run, PathBuf::from(cwd));
+            }
+            Event::Done { path: done, trashed, copies } if run.is_none_or(|run| run == entry.run) => {
+                let absolute = match cwds.get(entry.run.as_str()) {
+                    Some(cwd) => normalize(&cwd.join(done)),
+                    None => normalize(Path::new(done)),
+                };
+                found.push(Removed {
+                    run: entry.run.clone(),
+                    path: absolute,
+                    trashed: *trashed,
+                    copies: copies.clone(),
+                    removed_at: entry.timestamp.clone(),
+                });
+            }
+            _ => {}
+        }
+    }
+    found
+}
+
+// `.` and `..` resolved without touching the filesystem, where the path may
+// no longer exist
+pub fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            std::path::Component::CurDir => {}
+            std::path::Component::ParentDir => {
+                normalized.pop();
+            }
+            other => normalized.push(other),
+        }
+    }
+    normalized
+}
@@ -0,0 +1,117 @@
+// Number and date formats for what reports show people. Machine-readable
+// output (JSON, CSV, journals) always keeps plain numbers and RFC 3339 times.
+use std::sync::OnceLock;
+
+use crate::CROSS;
+
+pub struct Locale {
+    pub tag: &'static str,
+    decimal: char,
+    // Between groups of three digits; empty for none
+    group: &'static str,
+    // chrono format for local date and time
+    date: &'static str,
+}
+
+// The "C" locale: what reports showed before they were localized
+const C: Locale = Locale { tag: "C", decimal: '.', group: "", date: "%Y-%m-%d %H:%M:%S" };
+
+const LOCALES: &[Locale] = &[
+    C,
+    Locale { tag: "en-US", decimal: '.', group: ",", date: "%b %-d, %Y %-I:%M %p" },
+    Locale { tag: "en-GB", decimal: '.', group: ",", date: "%-d %b %Y %H:%M" },
+    Locale { tag: "de-DE", decimal: ',', group: ".", date: "%d.%m.%Y %H:%M" },
+    Locale { tag: "de-CH", decimal: '.', group: "’", date: "%d.%m.%Y %H:%M" },
+    Locale { tag: "fr-FR", decimal: ',', group: "\u{202f}", date: "%d/%m/%Y %H:%M" },
+    Locale { tag: "es-ES", decimal: ',', group: ".", date: "%d/%m/%Y %H:%M" },
+    Locale { tag: "it-IT", decimal: ',', group: ".", date: "%d/%m/%Y %H:%M" },
+    Locale { tag: "nl-NL", decimal: ',', group: ".", date: "%d-%m-%Y %H:%M" },
+    Locale { tag: "pt-BR", decimal: ',', group: ".", date: "%d/%m/%Y %H:%M" },
+    Locale { tag: "pl-PL", decimal: ',', group: "\u{a0}", date: "%d.%m.%Y %H:%M" },
+    Locale { tag: "sv-SE", decimal: ',', group: "\u{a0}", date: "%Y-%m-%d %H:%M" },
+    Locale { tag: "ja-JP", decimal: '.', group: ",", date: "%Y/%m/%d %H:%M" },
+    Locale { tag: "zh-CN", decimal: '.', group: ",", date: "%Y/%m/%d %H:%M" },
+];
+
+static LOCALE: OnceLock<&'static Locale> = OnceLock::new();
+
+// `de_DE.UTF-8`, `de-de` and `de` all name de-DE; None if nothing matches
+fn lookup(name: &str) -> Option<&'static Locale> {
+    let name = name.split(['.', '@']).next().unwrap_or_default().replace('_', "-");
+    if name.is_empty() || name.eq_ignore_ascii_case("POSIX") {
+        return Some(&LOCALES[0]);
+    }
+    LOCALES.iter().find(|l| l.tag.eq_ignore_ascii_case(&name)).or_else(|| {
+        let language = name.split('-').next().unwrap_or_default();
+        LOCALES.iter().find(|l| l.tag.split('-').next().is_some_and(|l| l.eq_ignore_ascii_case(language)))
+    })
+}
+
+// The locale the environment asks for, in the order the C library consults it
+fn from_env() -> &'static Locale {
+    ["LC_ALL", "LC_NUMERIC", "LANG"].iter()
+        .filter_map(|var| std::env::var(var).ok())
+        .find(|value| !value.is_empty())
+        .and_then(|value| lookup(&value))
+        .unwrap_or(&LOCALES[0])
+}
+
+// Use `name` for the rest of the run, or the environment's locale when
+// reports are for a person; scripts reading plain or event output get C
+pub fn init(name: Option<&str>, human: bool) -> Result<(), String> {
+    let locale = match name {
+        Some(name) => lookup(name).ok_or_else(|| format!(
+            "{} Unknown report locale '{}', expected one of: {}",
+            CROSS, name, LOCALES.iter().map(|l| l.tag).collect::<Vec<_>>().join(", ")
+        ))?,
+        None if human => from_env(),
+        None => &LOCALES[0],
+    };
+    let _ = LOCALE.set(locale);
+    Ok(())
+}
+
+fn current() -> &'static Locale {
+    LOCALE.get().copied().unwrap_or(&LOCALES[0])
+}
+
+fn grouped(digits: &str, group: &str) -> String {
+    let mut out = String::new();
+    for (i, digit) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            out.push_str(group);
+        }
+        out.push(digit);
+    }
+    out
+}
+
+pub fn number(value: f64, decimals: usize) -> String {
+    let locale = current();
+    let formatted = format!("{:.*}", decimals, value);
+    let (sign, formatted) = formatted.split_at(if formatted.starts_with('-') { 1 } else { 0 });
+    let (whole, fraction) = formatted.split_once('.').unwrap_or((formatted, ""));
+    let mut out = sign.to_string() + &grouped(whole, locale.group);
+    if !fraction.is_empty() {
+        out.push(locale.decimal);
+        out += fraction;
+    }
+    out
+}
+
+pub fn count(n: usize) -> String {
+    grouped(&n.to_string(), current().group)
+}
+
+// Sizes in reports, binary megabytes with two decimals
+pub fn format_mb(bytes: u64, estimated: bool) -> String {
+    let mb = number(bytes as f64 / 1024.0 / 1024.0, 2);
+    if estimated { format!("~{} MB", mb) } else { format!("{} MB", mb) }
+}
+
+// An RFC 3339 time as the locale writes it, in local time; anything else as is
+pub fn date(rfc3339: &str) -> String {
+    chrono::DateTime::parse_from_rfc3339(rfc3339)
+        .map(|time| time.with_timezone(&chrono::Local).format(current().date).to_string())
+        .unwrap_or_else(|_| rfc3339.to_string())
+}
@@ -0,0 +1,58 @@
+use std::{fs, path::{Path, PathBuf}};
+use walkdir::WalkDir;
+use log::debug;
+
+// Find a lock or sentinel file showing another tool is working inside `path`:
+// held `*.lock`/`.cargo-lock` files, LibreOffice `.~lock.*#` files and npm's
+// `.staging` directory, which only exists while an install is running
+pub fn find_active_lock(path: &Path) -> Option<PathBuf> {
+    WalkDir::new(path)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .find(|e| {
+            let name = e.file_name().to_string_lossy();
+            if e.file_type().is_dir() {
+                return name == ".staging"
+                    && e.path().parent().is_some_and(|p| p.ends_with("node_modules"));
+            }
+            if name.starts_with(".~lock.") {
+                return true;
+            }
+            (name.ends_with(".lock") || name == ".cargo-lock")
+                && e.file_type().is_file()
+                && is_held(e.path())
+        })
+        .map(|e| {
+            debug!("Active lock: {}", e.path().display());
+            e.into_path()
+        })
+}
+
+// Cargo, Gradle and friends hold an advisory lock on these while they run
+#[cfg(unix)]
+fn is_held(path: &Path) -> bool {
+    use std::os::unix::io::AsRawFd;
+
+    let Ok(file) = fs::File::open(path) else {
+        return false;
+    };
+    let fd = file.as_raw_fd();
+    if unsafe { libc::flock(fd, libc::LOCK_EX | libc::LOCK_NB) } != 0 {
+        return true;
+    }
+    unsafe { libc::flock(fd, libc::LOCK_UN) };
+    false
+}
+
+// An exclusive open fails while another process has the file open
+#[cfg(windows)]
+fn is_held(path: &Path) -> bool {
+    use std::os::windows::fs::OpenOptionsExt;
+
+    fs::OpenOptions::new().read(true).share_mode(0).open(path).is_err()
+}
+
+#[cfg(not(any(unix, windows)))]
+fn is_held(_path: &Path) -> bool {
+    false
+}
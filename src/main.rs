@@ -1,11 +1,78 @@
 use clap::{Arg, ArgAction, Command};
 use console::{Emoji, Style};
-use indicatif::{ProgressBar, ProgressStyle};
+use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
 use serde::{Deserialize, Serialize};
-use std::{fs, io::{self, Write}, path::Path, time::Duration};
+use std::{cell::{Cell, OnceCell}, collections::{HashMap, HashSet, VecDeque}, fs, io::{self, Write}, path::{Path, PathBuf}, sync::{atomic::{AtomicBool, Ordering}, mpsc, Mutex, OnceLock}, thread, time::{Duration, Instant, SystemTime}};
 use walkdir::WalkDir;
 use log::{debug, error, info};
 
+use actions::TargetAction;
+use dirpurge_core::{Candidate, Rules};
+use locale::format_mb;
+use heartbeat::Heartbeat;
+use journal::Event;
+use report::{Kind, OutputStyle};
+use skipped::SkipReason;
+
+mod actions;
+mod archive;
+#[cfg(feature = "archive")]
+mod manifest;
+#[cfg(feature = "archive")]
+mod tarball;
+mod backend;
+mod backup;
+mod chunked;
+mod cow;
+mod daemons;
+mod deleting;
+mod doctor;
+mod git;
+mod gitignore;
+mod hash;
+mod heartbeat;
+mod history;
+mod journal;
+mod locale;
+mod locks;
+mod mounts;
+mod naming;
+mod pdf;
+mod permissions;
+mod plan;
+mod policy;
+mod prefetch;
+mod preserve;
+mod processes;
+mod protected;
+mod reserved;
+mod prune;
+mod quarantine;
+mod report;
+mod restore;
+#[cfg(feature = "s3")]
+mod s3;
+#[cfg(feature = "sftp")]
+mod sftp;
+mod skipped;
+#[cfg(feature = "s3")]
+mod storage_class;
+mod thin;
+mod throttle;
+mod tools;
+mod trashed;
+mod treemap;
+mod units;
+#[cfg(all(target_os = "linux", feature = "io-uring"))]
+mod uring;
+mod verify;
+#[cfg(feature = "archive")]
+mod volumes;
+mod webhook;
+#[cfg(windows)]
+mod windelete;
+mod yaml;
+
 // Emoji constants
 static WARN: Emoji = Emoji("⚠️ ", "!");
 static TRASH: Emoji = Emoji("🗑 ", "-");
@@ -23,28 +90,120 @@ fn red() -> Style { Style::new().red() }
 fn yellow() -> Style { Style::new().yellow() }
 fn bold() -> Style { Style::new().bold() }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
 struct Config {
     target: Option<Vec<String>>,
     exclude: Option<Vec<String>>,
     depth: Option<usize>,
+    min_depth: Option<usize>,
     min_size: Option<f64>,
+    max_size: Option<f64>,
+    min_items: Option<usize>,
+    max_items: Option<usize>,
     min_age: Option<i64>,
-    follow_symlinks: Option<bool>,
+    max_age: Option<i64>,
+    modified_before: Option<String>,
+    modified_after: Option<String>,
+    min_atime_age: Option<i64>,
+    repo_inactive_days: Option<i64>,
+    keep_latest: Option<usize>,
+    age_source: Option<AgeSource>,
+    owned_by_me: Option<bool>,
+    owner: Option<String>,
+    #[serde(alias = "follow_symlinks")]
+    follow_symlinks_traversal: Option<bool>,
+    count_symlinked_sizes: Option<bool>,
+    one_file_system: Option<bool>,
+    count_hardlinks: Option<bool>,
+    size_mode: Option<SizeMode>,
+    estimate: Option<bool>,
+    reclaimable: Option<bool>,
+    respect_ignore: Option<bool>,
+    prune: Option<bool>,
+    require_manifest: Option<bool>,
+    manifests: Option<HashMap<String, Vec<String>>>,
     delete: Option<bool>,
     yes: Option<bool>,
     dry_run: Option<bool>,
     use_trash: Option<bool>,
+    quarantine: Option<bool>,
+    quarantine_dir: Option<String>,
     backup: Option<bool>,
     archive: Option<bool>,
+    archive_format: Option<archive::ArchiveFormat>,
+    compression_level: Option<i32>,
+    archive_manifest: Option<bool>,
+    archive_top_dir: Option<bool>,
+    // Size of archive volumes, e.g. "2GB"
+    archive_split_size: Option<String>,
+    backup_exclude: Option<Vec<String>>,
+    // Storage class rules for archives uploaded to S3, e.g. "node_modules=GLACIER_IR"
+    s3_storage_class: Option<Vec<String>>,
+    // Where backups go in each destination, e.g. "{project}/{name}-{date}.{ext}"
+    backup_name_template: Option<String>,
+    backup_preserve: Option<preserve::Preserve>,
+    backup_strategy: Option<backup::BackupStrategy>,
     backup_dir: Option<String>,
+    backup_dirs: Option<Vec<String>>,
+    backup_policy: Option<backup::BackupPolicy>,
+    prune_backups: Option<prune::RetentionPolicy>,
+    upload_limit: Option<String>,
+    readahead: Option<bool>,
+    incremental: Option<bool>,
+    verify_sample: Option<f64>,
+    verify_archive: Option<bool>,
+    skip_space_check: Option<bool>,
+    hash_algo: Option<hash::HashAlgo>,
+    jobs: Option<usize>,
+    // Directories with more entries are removed in batches
+    chunk_above: Option<usize>,
+    free: Option<String>,
+    max_dirs: Option<usize>,
+    max_total: Option<String>,
+    purge_files_older_than: Option<i64>,
+    // Path prefix -> jobs, upload limit and sizing for directories under it
+    mounts: Option<HashMap<String, mounts::MountConfig>>,
     interactive: Option<bool>,
     confirm_phrase: Option<String>,
     json: Option<String>,
+    yaml: Option<String>,
+    jsonl: Option<String>,
     csv: Option<String>,
+    pdf: Option<String>,
+    treemap: Option<String>,
+    stream: Option<String>,
+    // Sizes of report-only directories from the previous run
+    history: Option<String>,
+    growth_alert: Option<f64>,
+    growth_webhook: Option<String>,
     log: Option<String>,
     verbose: Option<bool>,
     quiet: Option<bool>,
+    output: Option<OutputStyle>,
+    report_locale: Option<String>,
+    approval: Option<plan::ApprovalPolicy>,
+    sudo_helper: Option<String>,
+    elevate: Option<bool>,
+    ignore_locks: Option<bool>,
+    ignore_processes: Option<bool>,
+    stop_daemons: Option<bool>,
+    // Target name -> command that stops its daemon, run in the project directory
+    daemon_commands: Option<HashMap<String, String>>,
+    // Target name -> what to do with its matches instead of the global action
+    actions: Option<HashMap<String, TargetAction>>,
+    git_safe: Option<bool>,
+    // Paths never deleted or archived, on top of the built-in system paths
+    protected: Option<Vec<String>>,
+    allow_dangerous_root: Option<bool>,
+    // Delete matches that resolve to somewhere outside the search path
+    allow_outside_root: Option<bool>,
+    // Journal of deletions for `resume`, instead of the one in the state directory
+    journal: Option<String>,
+    // Seconds between journal checkpoints of a long copy
+    checkpoint_interval: Option<u64>,
+    // Seconds into a removal run before progress is reported, and between reports
+    heartbeat: Option<u64>,
+    heartbeat_webhook: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -53,14 +212,94 @@ struct DirInfo {
     size_bytes: u64,
     age_days: Option<i64>,
     item_count: Option<usize>,
+    // size_bytes was extrapolated from a sample with --estimate
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    estimated: bool,
+    // Matched by a report-only target: tracked, never deleted
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    report_only: bool,
+    // NTFS junctions and other reparse points inside it, left out of the size
+    #[serde(default, skip_serializing_if = "is_zero")]
+    reparse_points: usize,
+    // What deleting it would free, leaving out data shared with clones and
+    // snapshots, with --reclaimable on filesystems that track it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    reclaimable_bytes: Option<u64>,
+    // Space allocated on disk where that is less than size_bytes, as with
+    // transparent compression (btrfs, ZFS, NTFS) or sparse files
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    disk_bytes: Option<u64>,
+}
+
+fn is_zero(n: &usize) -> bool {
+    *n == 0
+}
+
+impl DirInfo {
+    fn size_label(&self) -> String {
+        let mut label = format_mb(self.size_bytes, self.estimated);
+        if let Some(disk) = self.disk_bytes {
+            label += &format!(", {} on disk", format_mb(disk, false));
+        }
+        if let Some(reclaimable) = self.reclaimable_bytes {
+            label += &format!(", {} reclaimable", format_mb(reclaimable, false));
+        }
+        match self.reparse_points {
+            0 => label,
+            n => format!("{}, excl. {} reparse point{}", label, n, if n == 1 { "" } else { "s" }),
+        }
+    }
+
+    // What removing it takes off the disk, as far as is known
+    fn disk_size(&self) -> u64 {
+        self.disk_bytes.unwrap_or(self.size_bytes)
+    }
+
+    // What removing it frees, counting clones when --reclaimable found out
+    fn freed_size(&self) -> u64 {
+        self.reclaimable_bytes.unwrap_or_else(|| self.disk_size())
+    }
+}
+
+// Total disk usage of `dirs` when it is less than their total size
+fn disk_total(dirs: &[DirInfo]) -> Option<u64> {
+    dirs.iter().any(|d| d.disk_bytes.is_some()).then(|| dirs.iter().map(DirInfo::disk_size).sum())
 }
 
 fn load_config(config_path: &str) -> Result<Config, String> {
     debug!("Loading config from {}", config_path);
     fs::read_to_string(config_path)
         .map_err(|e| format!("{} Error reading config: {}", CROSS, e))
-        .and_then(|content| serde_json::from_str(&content)
-        .map_err(|e| format!("{} Error parsing config: {}", CROSS, e)))
+        .and_then(|content| parse_config(&content))
+}
+
+// Configs get shared, so anything that would make a rule surprising is refused here
+fn parse_config(content: &str) -> Result<Config, String> {
+    let config: Config = serde_json::from_str(content)
+        .map_err(|e| format!("{} Error parsing config: {}", CROSS, e))?;
+    for (target, markers) in config.manifests.iter().flatten() {
+        if target.is_empty() {
+            return Err(format!("{} Error parsing config: manifests entry with an empty target", CROSS));
+        }
+        if let Some(marker) = markers.iter().find(|m| !is_marker_name(m)) {
+            return Err(format!("{} Error parsing config: manifest marker '{}' for {} must be a plain file name", CROSS, marker, target));
+        }
+    }
+    Ok(config)
+}
+
+// Markers are looked up beside a match; a path could point anywhere
+fn is_marker_name(marker: &str) -> bool {
+    !marker.is_empty() && marker != "." && marker != ".." && !marker.contains(['/', '\\'])
+}
+
+// A --manifest TARGET=FILE pair
+fn parse_manifest(pair: &str) -> Result<(String, String), String> {
+    match pair.split_once('=') {
+        Some((target, marker)) if !target.is_empty() && is_marker_name(marker) =>
+            Ok((target.to_string(), marker.to_string())),
+        _ => Err(format!("{} Invalid --manifest '{}', expected TARGET=FILE with a plain file name", CROSS, pair)),
+    }
 }
 
 fn save_config(config: &Config, config_path: &str) -> Result<(), String> {
@@ -71,112 +310,505 @@ fn save_config(config: &Config, config_path: &str) -> Result<(), String> {
         .map_err(|e| format!("{} Error writing config: {}", CROSS, e)))
 }
 
-fn get_directory_size(path: &Path, follow_symlinks: bool) -> u64 {
+// The files of dirpurge's own a run reads and writes, which no target may
+// take with it: the config it was given, its journal and its history
+fn own_state(config: &Config, config_path: Option<&String>) -> Vec<PathBuf> {
+    let mut own: Vec<PathBuf> = config_path.map(PathBuf::from).into_iter().collect();
+    own.extend(journal::resolve(config.journal.as_deref()).ok());
+    own.extend(config.history.as_ref().map(PathBuf::from));
+    own
+}
+
+// Settles what this run can do with the tools found here: what can go
+// without its tool is turned off, with one warning for all of it, and what
+// can't is refused before anything is touched
+fn check_tools(config: &mut Config) -> Result<(), String> {
+    let destinations = config.backup_dirs.clone().or_else(|| config.backup_dir.clone().map(|dir| vec![dir])).unwrap_or_default();
+    let backing_up = config.backup.unwrap_or(false) || config.archive.unwrap_or(false)
+        || config.actions.as_ref().is_some_and(|actions| actions.values().any(|action| matches!(action.flags(), (_, true, _) | (_, _, true))));
+    for destination in destinations.iter().filter(|_| backing_up) {
+        if destination.starts_with("sftp://") && !tools::available("sftp") {
+            return Err(format!("{} sftp:// destinations need the OpenSSH sftp client on the PATH: {}", CROSS, destination));
+        }
+        if destination.starts_with("s3://") && !tools::available("curl") {
+            return Err(format!("{} s3:// destinations need curl on the PATH: {}", CROSS, destination));
+        }
+    }
+    if let Some(program) = config.sudo_helper.as_deref().and_then(|helper| helper.split_whitespace().next())
+        && !tools::available(program)
+    {
+        return Err(format!("{} --sudo-helper {} is not on the PATH", CROSS, program));
+    }
+
+    let mut missing = tools::Missing::default();
+    if config.growth_webhook.is_some() && !missing.check("curl", "--growth-webhook") {
+        config.growth_webhook = None;
+    }
+    if config.heartbeat_webhook.is_some() && !missing.check("curl", "--heartbeat-webhook") {
+        config.heartbeat_webhook = None;
+    }
+    // Without git there's no telling, so these keep what they would have checked
+    if config.git_safe == Some(true) {
+        missing.check("git", "--git-safe, which keeps every directory inside a repository");
+    }
+    if config.repo_inactive_days.is_some() {
+        missing.check("git", "--repo-inactive-days, which matches nothing");
+    }
+    if config.stop_daemons == Some(true)
+        && let Some(commands) = &mut config.daemon_commands
+    {
+        // Commands run from inside the project, like ./gradlew, are looked for there
+        commands.retain(|target, command| {
+            let program = command.split_whitespace().next().unwrap_or_default();
+            program.contains(['/', '\\']) || missing.check(program, &format!("stopping the daemon of {}", target))
+        });
+    }
+    missing.report();
+    Ok(())
+}
+
+// Everything below `path` that sizing looks at. Reparse point directories
+// (junctions pnpm and Yarn link packages with) are never entered, even with
+// --count-symlinked-sizes: they lead to content stored and counted elsewhere,
+// often outside the directory. Each one passed over is counted in `reparse`.
+fn sizing_walk<'a>(path: &Path, opts: &ScanOptions, reparse: &'a Cell<usize>) -> impl Iterator<Item = walkdir::DirEntry> + 'a {
     WalkDir::new(path)
-        .follow_links(follow_symlinks)
+        .follow_links(opts.count_symlinked_sizes)
+        .same_file_system(opts.one_file_system)
         .into_iter()
+        .filter_entry(move |e| {
+            if e.depth() > 0 && is_reparse_dir(e) {
+                debug!("Not sizing through reparse point: {}", e.path().display());
+                reparse.set(reparse.get() + 1);
+                return false;
+            }
+            true
+        })
         .filter_map(|e| e.ok())
+}
+
+#[cfg(windows)]
+fn is_reparse_dir(e: &walkdir::DirEntry) -> bool {
+    use std::os::windows::fs::MetadataExt;
+    use windows_sys::Win32::Storage::FileSystem::{FILE_ATTRIBUTE_DIRECTORY, FILE_ATTRIBUTE_REPARSE_POINT};
+
+    // Files with reparse tags (dedup, cloud placeholders) hold their own data
+    if !e.file_type().is_dir() && !e.path_is_symlink() {
+        return false;
+    }
+    fs::symlink_metadata(e.path()).is_ok_and(|m| {
+        let attributes = m.file_attributes();
+        attributes & FILE_ATTRIBUTE_DIRECTORY != 0 && attributes & FILE_ATTRIBUTE_REPARSE_POINT != 0
+    })
+}
+
+// Only NTFS has reparse points; symlinks elsewhere follow --count-symlinked-sizes
+#[cfg(not(windows))]
+fn is_reparse_dir(_e: &walkdir::DirEntry) -> bool {
+    false
+}
+
+// What a size walk noticed besides the total
+#[derive(Default)]
+struct SizeNotes {
+    // Reparse points passed over
+    reparse_points: Cell<usize>,
+    // Bytes no clone or snapshot shares, with --reclaimable; None if any file
+    // can't tell
+    unique_bytes: Cell<Option<u64>>,
+    // Allocated bytes, kept when apparent sizes overstate them
+    disk_bytes: Cell<Option<u64>>,
+}
+
+fn get_directory_size(path: &Path, opts: &ScanOptions, notes: &SizeNotes) -> u64 {
+    let mut seen_links = HashSet::new();
+    let mut unshared = opts.reclaimable.then_some(0u64);
+    let mut allocated = 0u64;
+    let size = sizing_walk(path, opts, &notes.reparse_points)
         .filter(|e| e.file_type().is_file())
+        .filter_map(|e| e.metadata().ok().map(|m| (e, m)))
+        .filter(|(_, m)| opts.count_hardlinks || hardlink_id(m).is_none_or(|id| seen_links.insert(id)))
+        .fold(0, |acc, (e, m)| {
+            unshared = unshared.and_then(|total| cow::unique_size(e.path()).map(|n| total + n));
+            if opts.size_mode == SizeMode::Apparent {
+                allocated += allocated_size(e.path(), &m);
+            }
+            acc + file_size(e.path(), &m, opts.size_mode)
+        });
+    notes.unique_bytes.set(unshared);
+    // Usually block rounding puts disk usage above the apparent size; below it,
+    // deleting frees less than the size suggests
+    if opts.size_mode == SizeMode::Apparent && allocated < size {
+        notes.disk_bytes.set(Some(allocated));
+    }
+    size
+}
+
+// Only every n-th file is stat'ed when estimating
+const ESTIMATE_STRIDE: usize = 16;
+
+// Stat a sample of the files and extrapolate by the total file count. Listing
+// a directory is much cheaper than stat'ing every entry in it.
+fn estimate_directory_size(path: &Path, opts: &ScanOptions, notes: &SizeNotes) -> u64 {
+    let mut seen_links = HashSet::new();
+    let (mut files, mut sampled, mut sampled_size) = (0u64, 0u64, 0u64);
+    let entries = sizing_walk(path, opts, &notes.reparse_points)
+        .filter(|e| e.file_type().is_file());
+
+    for (i, e) in entries.enumerate() {
+        files += 1;
+        if !i.is_multiple_of(ESTIMATE_STRIDE) {
+            continue;
+        }
+        let Ok(m) = e.metadata() else {
+            continue;
+        };
+        sampled += 1;
+        if opts.count_hardlinks || hardlink_id(&m).is_none_or(|id| seen_links.insert(id)) {
+            sampled_size += file_size(e.path(), &m, opts.size_mode);
+        }
+    }
+
+    if sampled == 0 {
+        return 0;
+    }
+    (sampled_size as f64 * files as f64 / sampled as f64) as u64
+}
+
+// Whether sizes report file length or the space actually allocated on disk
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+enum SizeMode {
+    #[default]
+    Apparent,
+    Disk,
+}
+
+fn file_size(path: &Path, metadata: &fs::Metadata, mode: SizeMode) -> u64 {
+    match mode {
+        SizeMode::Apparent => metadata.len(),
+        SizeMode::Disk => allocated_size(path, metadata),
+    }
+}
+
+#[cfg(unix)]
+fn allocated_size(_path: &Path, metadata: &fs::Metadata) -> u64 {
+    use std::os::unix::fs::MetadataExt;
+    // st_blocks is always in 512-byte units
+    metadata.blocks() * 512
+}
+
+#[cfg(windows)]
+fn allocated_size(path: &Path, metadata: &fs::Metadata) -> u64 {
+    use std::os::windows::ffi::OsStrExt;
+    use windows_sys::Win32::Storage::FileSystem::{GetCompressedFileSizeW, INVALID_FILE_SIZE};
+
+    let wide: Vec<u16> = path.as_os_str().encode_wide().chain(Some(0)).collect();
+    let mut high = 0u32;
+    // Reports the on-disk size of compressed and sparse files
+    let low = unsafe { GetCompressedFileSizeW(wide.as_ptr(), &mut high) };
+    if low == INVALID_FILE_SIZE && io::Error::last_os_error().raw_os_error() != Some(0) {
+        return metadata.len();
+    }
+    ((high as u64) << 32) | low as u64
+}
+
+#[cfg(not(any(unix, windows)))]
+fn allocated_size(_path: &Path, metadata: &fs::Metadata) -> u64 {
+    metadata.len()
+}
+
+// (device, inode) of files with more than one link, so they are sized once
+#[cfg(unix)]
+fn hardlink_id(metadata: &fs::Metadata) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+    (metadata.nlink() > 1).then(|| (metadata.dev(), metadata.ino()))
+}
+
+#[cfg(not(unix))]
+fn hardlink_id(_metadata: &fs::Metadata) -> Option<(u64, u64)> {
+    None
+}
+
+fn count_directory_items(path: &Path, opts: &ScanOptions) -> usize {
+    sizing_walk(path, opts, &Cell::new(0)).count()
+}
+
+// Whole days since `time`; timestamps in the future count as just touched
+fn days_ago(time: SystemTime) -> i64 {
+    time.elapsed().map_or(0, |d| d.as_secs() as i64 / 86400)
+}
+
+// Newest timestamp of anything in the directory, itself included
+fn newest_time(path: &Path, opts: &ScanOptions, time: impl Fn(&fs::Metadata) -> io::Result<SystemTime>) -> Option<SystemTime> {
+    sizing_walk(path, opts, &Cell::new(0))
         .filter_map(|e| e.metadata().ok())
-        .fold(0, |acc, m| acc + m.len())
+        .filter_map(|m| time(&m).ok())
+        .max()
 }
 
-fn count_directory_items(path: &Path, follow_symlinks: bool) -> usize {
-    WalkDir::new(path)
-        .follow_links(follow_symlinks)
-        .into_iter()
-        .filter_map(|e| e.ok())
-        .count()
+// Days since anything in the directory was last read. Entries whose access time
+// the platform doesn't report fall back to their modification time.
+fn directory_accessed_days_ago(path: &Path, opts: &ScanOptions) -> Option<i64> {
+    newest_time(path, opts, |m| m.accessed().or_else(|_| m.modified())).map(days_ago)
+}
+
+#[cfg(unix)]
+fn directory_owner(path: &Path) -> Option<u32> {
+    use std::os::unix::fs::MetadataExt;
+    fs::symlink_metadata(path).ok().map(|m| m.uid())
+}
+
+#[cfg(not(unix))]
+fn directory_owner(_path: &Path) -> Option<u32> {
+    None
+}
+
+// Resolve --owned-by-me / --owner USER (a name or numeric uid) to a uid
+#[cfg(unix)]
+fn resolve_owner(owned_by_me: bool, owner: Option<&str>) -> Result<Option<u32>, String> {
+    if owned_by_me {
+        return Ok(Some(unsafe { libc::geteuid() }));
+    }
+    let Some(owner) = owner else {
+        return Ok(None);
+    };
+    if let Ok(uid) = owner.parse() {
+        return Ok(Some(uid));
+    }
+    let name = std::ffi::CString::new(owner)
+        .map_err(|_| format!("{} Invalid user name: {}", CROSS, owner))?;
+    let entry = unsafe { libc::getpwnam(name.as_ptr()) };
+    if entry.is_null() {
+        return Err(format!("{} Unknown user: {}", CROSS, owner));
+    }
+    Ok(Some(unsafe { (*entry).pw_uid }))
 }
 
-fn directory_modified_days_ago(path: &Path) -> Option<i64> {
-    fs::metadata(path)
-        .ok()?
-        .modified()
-        .ok()?
-        .elapsed()
-        .ok()
-        .map(|d| d.as_secs() as i64 / 86400)
+// Ownership filters need Unix uids
+#[cfg(not(unix))]
+fn resolve_owner(owned_by_me: bool, owner: Option<&str>) -> Result<Option<u32>, String> {
+    if owned_by_me || owner.is_some() {
+        log::warn!("Ownership filters are not supported on this platform, ignoring them");
+        report::message(Kind::Warning, "--owned-by-me/--owner are ignored on this platform");
+    }
+    Ok(None)
+}
+
+// Where a directory's age comes from
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+enum AgeSource {
+    // Newest modification time of anything inside
+    #[default]
+    Content,
+    // The directory's own mtime, which only changes when entries are added or removed
+    DirMtime,
+}
+
+// The modification time a directory is aged by
+fn directory_mtime(path: &Path, opts: &ScanOptions) -> Option<SystemTime> {
+    match opts.age_source {
+        AgeSource::Content => newest_time(path, opts, fs::Metadata::modified),
+        AgeSource::DirMtime => fs::metadata(path).ok()?.modified().ok(),
+    }
 }
 
-fn find_directories(
-    base_path: &str,
-    target: &[String],
-    exclude: &[String],
+// Everything that decides which directories a scan reports
+#[derive(Debug, Clone)]
+struct ScanOptions {
+    // Targets, exclusions and the filters matches must pass
+    rules: Rules,
     depth: Option<usize>,
-    min_size: Option<u64>,
-    min_age: Option<i64>,
-    follow_symlinks: bool,
+    min_depth: Option<usize>,
+    age_source: AgeSource,
+    // Descend through symlinked directories while searching for matches
+    follow_symlinks_traversal: bool,
+    // Include what symlinks point to in sizes and item counts
+    count_symlinked_sizes: bool,
+    // Never cross into other mounted filesystems
+    one_file_system: bool,
+    // Size every link of a hardlinked file instead of counting it once
+    count_hardlinks: bool,
+    size_mode: SizeMode,
+    // Extrapolate sizes from a sample of files
+    estimate: bool,
+    // Also work out how much of each size isn't shared with clones
+    reclaimable: bool,
+    // Sizing overrides for directories under configured mounts
+    mounts: Vec<mounts::Tuning>,
+    respect_ignore: bool,
+    // Don't descend into a directory once it matched
+    prune: bool,
+    // Target name -> marker files, one of which must sit next to a match
+    manifests: Option<HashMap<String, Vec<String>>>,
+    // Per-target actions; report-only targets bypass the filters
+    actions: HashMap<String, TargetAction>,
+    // Spare this many of the most recently modified matches of each parent
+    keep_latest: Option<usize>,
+    // Backup destinations and trash, never descended into
+    reserved: reserved::Reserved,
     verbose: bool,
-) -> Vec<DirInfo> {
+}
+
+// Marker files that identify the project owning common build directories
+fn default_manifests() -> HashMap<String, Vec<String>> {
+    let python = ["pyproject.toml", "requirements.txt", "setup.py", "Pipfile"];
+    [
+        ("node_modules", vec!["package.json"]),
+        ("target", vec!["Cargo.toml"]),
+        ("venv", python.to_vec()),
+        (".venv", python.to_vec()),
+        ("build", vec!["build.gradle", "build.gradle.kts", "CMakeLists.txt", "package.json", "setup.py", "pyproject.toml"]),
+    ]
+    .into_iter()
+    .map(|(target, markers)| (target.to_string(), markers.into_iter().map(String::from).collect()))
+    .collect()
+}
+
+// A match passes if any target it matched has no markers or has one beside it
+fn has_project_manifest(path: &Path, name: &str, target: &[String], manifests: &HashMap<String, Vec<String>>) -> bool {
+    let Some(parent) = path.parent() else {
+        return false;
+    };
+    target.iter()
+        .filter(|t| name.contains(t.as_str()))
+        .any(|t| manifests.get(t)
+            .is_none_or(|markers| markers.iter().any(|m| parent.join(m).exists())))
+}
+
+#[cfg(unix)]
+fn device_id(path: &Path) -> Option<u64> {
+    use std::os::unix::fs::MetadataExt;
+    fs::metadata(path).ok().map(|m| m.dev())
+}
+
+// WalkDir already refuses to descend across volumes elsewhere
+#[cfg(not(unix))]
+fn device_id(_path: &Path) -> Option<u64> {
+    None
+}
+
+fn matches_target(e: &walkdir::DirEntry, opts: &ScanOptions) -> bool {
+    let name = e.file_name().to_string_lossy();
+    let path_str = e.path().to_string_lossy();
+
+    // Skip directory if it's in the exclude list
+    if opts.rules.is_excluded(&path_str) {
+        debug!("Excluding directory: {}", path_str);
+        return false;
+    }
+
+    // Include directory if it's in the target list
+    if !opts.rules.matches_name(&name, &path_str) {
+        return false;
+    }
+
+    // Only match build directories that sit next to their project manifest
+    if let Some(manifests) = &opts.manifests
+        && !has_project_manifest(e.path(), &name, &opts.rules.target, manifests)
+    {
+        debug!("No project manifest next to: {}", path_str);
+        return false;
+    }
+
+    if opts.verbose {
+        debug!("Found matching directory: {}", path_str);
+    }
+    true
+}
+
+// Shared by the deletion bar and the nested per-archive bars so they don't draw over each other
+fn progress() -> &'static MultiProgress {
+    static PROGRESS: OnceLock<MultiProgress> = OnceLock::new();
+    PROGRESS.get_or_init(MultiProgress::new)
+}
+
+// Spinner shown while scanning and sizing in verbose mode
+fn scan_spinner(verbose: bool) -> Option<ProgressBar> {
+    if !verbose || !report::animated() {
+        return None;
+    }
+    let sp = ProgressBar::new_spinner();
+    sp.set_style(
+        ProgressStyle::default_spinner()
+            .tick_strings(&["⠋", "⠙", "⠹", "⠸", "⠼", "⠴", "⠦", "⠧", "⠇", "⠏"])
+            .template("{spinner} Scanning directories... {elapsed_precise}")
+            .unwrap()
+    );
+    sp.enable_steady_tick(Duration::from_millis(100));
+    Some(sp)
+}
+
+fn find_directories(base_path: &str, opts: &ScanOptions, on_found: &mut dyn FnMut(&DirInfo)) -> Vec<DirInfo> {
     let base = Path::new(base_path);
     
     // Create a progress bar for directory scanning if verbose
-    let spinner = if verbose {
-        let sp = ProgressBar::new_spinner();
-        sp.set_style(
-            ProgressStyle::default_spinner()
-                .tick_strings(&["⠋", "⠙", "⠹", "⠸", "⠼", "⠴", "⠦", "⠧", "⠇", "⠏"])
-                .template("{spinner} Scanning directories... {elapsed_precise}")
-                .unwrap()
-        );
-        sp.enable_steady_tick(Duration::from_millis(100));
-        Some(sp)
-    } else {
-        None
-    };
+    let spinner = scan_spinner(opts.verbose);
 
     // Set up the walker with depth if specified
-    let walker = match depth {
+    let walker = match opts.depth {
         Some(d) => WalkDir::new(base).max_depth(d),
         None => WalkDir::new(base)
-    };
+    }
+    .follow_links(opts.follow_symlinks_traversal)
+    .same_file_system(opts.one_file_system);
 
-    let result = walker.into_iter()
-        .filter_map(Result::ok)
-        .filter(|e| e.file_type().is_dir())
-        .filter(|e| {
-            let name = e.file_name().to_string_lossy();
-            let path_str = e.path().to_string_lossy();
-            
-            // Skip directory if it's in the exclude list
-            if exclude.iter().any(|ex| path_str.contains(ex)) {
-                debug!("Excluding directory: {}", path_str);
+    // Directories excluded by .gitignore/.ignore are neither matched nor descended into
+    let mut ignore_matcher = opts.respect_ignore.then(|| gitignore::IgnoreMatcher::new(base));
+    // Nor are backups and trash under the root, full of copies of matches
+    let resolved_base = base.canonicalize().unwrap_or_else(|_| base.to_path_buf());
+    let reserved = opts.reserved.around(&resolved_base);
+
+    let mut entries = walker.into_iter()
+        .filter_entry(|e| {
+            if e.depth() > 0
+                && e.file_type().is_dir()
+                && let Some(what) = reserved.kind_of(&resolved_base.join(e.path().strip_prefix(base).unwrap_or(e.path())))
+            {
+                report::detail(Kind::Info, format!("Not scanning the {}: {}", what, e.path().display()));
                 return false;
             }
-            
-            // Include directory if it's in the target list
-            let matches = target.iter().any(|t| name.contains(t));
-            if matches && verbose {
-                debug!("Found matching directory: {}", path_str);
-            }
-            matches
-        })
-        .filter(|e| {
-            min_age.map_or(true, |min| {
-                directory_modified_days_ago(e.path())
-                    .map_or(false, |age| age >= min)
-            })
-        })
-        .filter_map(|e| {
-            if let Some(spinner) = &spinner {
-                spinner.set_message(format!("Analyzing {}", e.path().display()));
-            }
-            
-            let size = get_directory_size(e.path(), follow_symlinks);
-            let age = directory_modified_days_ago(e.path());
-            let item_count = Some(count_directory_items(e.path(), follow_symlinks));
-            
-            min_size.map_or(Some(size), |min| (size >= min).then_some(size))
-                .map(|size| DirInfo {
-                    path: e.path().to_string_lossy().into_owned(),
-                    size_bytes: size,
-                    age_days: age,
-                    item_count,
-                })
-        })
-        .collect::<Vec<_>>();
-    
+            ignore_matcher.as_mut()
+                .is_none_or(|m| !m.is_ignored(e.path(), e.file_type().is_dir()))
+        });
+
+    // Mount points themselves live on the other filesystem and are never matched
+    let base_device = if opts.one_file_system { device_id(base) } else { None };
+
+    let mut result = Vec::new();
+    while let Some(entry) = entries.next() {
+        let Ok(e) = entry else {
+            continue;
+        };
+        if !e.file_type().is_dir() {
+            continue;
+        }
+        // Not left to walkdir, which never shows filter_entry what is above
+        // its min_depth, so backups and trash there would be scanned
+        if e.depth() < opts.min_depth.unwrap_or(0) {
+            continue;
+        }
+        if base_device.is_some() && device_id(e.path()) != base_device {
+            debug!("Not crossing into other filesystem: {}", e.path().display());
+            continue;
+        }
+        if !matches_target(&e, opts) {
+            continue;
+        }
+
+        // Nested matches (node_modules/foo/node_modules) are part of this one
+        if opts.prune {
+            entries.skip_current_dir();
+        }
+
+        // Size each match right away so it can be reported before the walk ends
+        if let Some(info) = analyze_directory(e.path(), opts, spinner.as_ref()) {
+            on_found(&info);
+            result.push(info);
+        }
+    }
+
     // Finish and clear the spinner
     if let Some(spinner) = spinner {
         spinner.finish_and_clear();
@@ -185,138 +817,402 @@ fn find_directories(
     result
 }
 
-fn archive_directory(path: &str, backup_dir: &str) -> Result<String, String> {
-    let dir_path = Path::new(path);
-    let backup_path = Path::new(backup_dir);
-    
-    fs::create_dir_all(backup_path)
-        .map_err(|e| format!("{} Failed to create backup directory: {}", CROSS, e))?;
-
-    let timestamp = chrono::Local::now().format("%Y%m%d_%H%M%S");
-    let archive_name = format!("{}_{}.zip",
-        dir_path.file_name()
-            .ok_or_else(|| format!("{} Invalid directory name", CROSS))?
-            .to_string_lossy(),
-        timestamp
-    );
-    
-    let archive_path = backup_path.join(&archive_name);
-    let archive_file = fs::File::create(&archive_path)
-        .map_err(|e| format!("{} Failed to create archive file: {}", CROSS, e))?;
-    
-    let mut zip = zip::ZipWriter::new(archive_file);
-    
-    let options = zip::write::FileOptions::default()
-        .compression_method(zip::CompressionMethod::Deflated)
-        .unix_permissions(0o755);
-    
-    let mut buffer = Vec::new();
-    
-    // Walk the directory and add all files to the zip
-    let walker = WalkDir::new(dir_path).into_iter().filter_map(|e| e.ok());
-    
-    for entry in walker {
-        let path = entry.path();
-        let name = path.strip_prefix(Path::new(path))
-            .unwrap_or(path)
-            .to_string_lossy();
-        
-        if path.is_file() {
-            debug!("Adding to archive: {}", name);
-            zip.start_file(name.to_string(), options)
-                .map_err(|e| format!("{} Failed to add file to archive: {}", CROSS, e))?;
-            
-            let mut f = fs::File::open(path)
-                .map_err(|e| format!("{} Failed to open file for archiving: {}", CROSS, e))?;
-            
-            io::copy(&mut f, &mut buffer)
-                .map_err(|e| format!("{} Failed to read file for archiving: {}", CROSS, e))?;
-            
-            zip.write_all(&buffer)
-                .map_err(|e| format!("{} Failed to write file to archive: {}", CROSS, e))?;
-            
-            buffer.clear();
-        } else if !path.as_os_str().is_empty() {
-            // Only create explicit directory entries for non-root directories
-            zip.add_directory(name.to_string(), options)
-                .map_err(|e| format!("{} Failed to add directory to archive: {}", CROSS, e))?;
+// Size a candidate and apply the age and size filters
+fn analyze_directory(path: &Path, opts: &ScanOptions, spinner: Option<&ProgressBar>) -> Option<DirInfo> {
+    if refuse_protected(&path.to_string_lossy()) {
+        return None;
+    }
+
+    // Sized the way its mount is configured to be
+    let tuned;
+    let opts = match mounts::for_path(&opts.mounts, path) {
+        Some(tuning) => {
+            tuned = ScanOptions {
+                estimate: tuning.estimate.unwrap_or(opts.estimate),
+                size_mode: tuning.size_mode.unwrap_or(opts.size_mode),
+                ..opts.clone()
+            };
+            &tuned
         }
+        None => opts,
+    };
+
+    // Inventory targets are tracked whatever their age or size
+    let report_only = actions::action_for(&path.to_string_lossy(), &opts.actions) == Some(TargetAction::ReportOnly);
+    let unfiltered;
+    let rules = if report_only {
+        unfiltered = opts.rules.without_filters();
+        &unfiltered
+    } else {
+        &opts.rules
+    };
+
+    if let Some(spinner) = spinner {
+        spinner.set_message(format!("Analyzing {}", path.display()));
     }
-    
-    zip.finish()
-        .map_err(|e| format!("{} Failed to finalize archive: {}", CROSS, e))?;
-    
-    Ok(archive_path.to_string_lossy().to_string())
+
+    let candidate = FsCandidate::new(path, opts);
+    if let Err(detail) = rules.evaluate(&candidate) {
+        skipped::record(&path.to_string_lossy(), SkipReason::Filtered, detail);
+        return None;
+    }
+
+    Some(DirInfo {
+        path: path.to_string_lossy().into_owned(),
+        size_bytes: candidate.size_bytes(),
+        age_days: candidate.age_days(),
+        item_count: Some(candidate.item_count()),
+        estimated: opts.estimate,
+        report_only,
+        reparse_points: candidate.notes.reparse_points.get(),
+        reclaimable_bytes: candidate.notes.unique_bytes.get(),
+        disk_bytes: candidate.notes.disk_bytes.get(),
+    })
 }
 
-fn backup_directory(path: &str, backup_dir: &str) -> Result<String, String> {
-    let dir_path = Path::new(path);
-    let backup_root = Path::new(backup_dir);
-    
-    fs::create_dir_all(backup_root)
-        .map_err(|e| format!("{} Failed to create backup directory: {}", CROSS, e))?;
-    
-    let dir_name = dir_path.file_name()
-        .ok_or_else(|| format!("{} Invalid directory name", CROSS))?;
-        
-    let backup_path = backup_root.join(dir_name);
-    
-    if backup_path.exists() {
-        let timestamp = chrono::Local::now().format("%Y%m%d_%H%M%S");
-        let new_backup_path = backup_root.join(format!(
-            "{}_{}", 
-            dir_name.to_string_lossy(),
-            timestamp
-        ));
-        
-        debug!("Backup destination already exists, creating timestamped backup: {}", new_backup_path.display());
-        
-        // Use copy_dir instead of fs::copy for directories
-        copy_dir_recursive(dir_path, &new_backup_path)
-            .map_err(|e| format!("{} Backup failed: {}", CROSS, e))?;
-            
-        return Ok(new_backup_path.to_string_lossy().to_string());
+// A directory on disk, each piece of metadata read once and only when asked for
+struct FsCandidate<'a> {
+    path: &'a Path,
+    opts: &'a ScanOptions,
+    modified: OnceCell<Option<SystemTime>>,
+    items: OnceCell<usize>,
+    size: OnceCell<u64>,
+    notes: SizeNotes,
+}
+
+impl<'a> FsCandidate<'a> {
+    fn new(path: &'a Path, opts: &'a ScanOptions) -> Self {
+        FsCandidate { path, opts, modified: OnceCell::new(), items: OnceCell::new(), size: OnceCell::new(), notes: SizeNotes::default() }
     }
-    
-    // Use copy_dir instead of fs::copy for directories
-    copy_dir_recursive(dir_path, &backup_path)
-        .map_err(|e| format!("{} Backup failed: {}", CROSS, e))?;
+}
+
+impl Candidate for FsCandidate<'_> {
+    fn owner(&self) -> Option<u32> {
+        directory_owner(self.path)
+    }
+
+    fn modified(&self) -> Option<SystemTime> {
+        *self.modified.get_or_init(|| directory_mtime(self.path, self.opts))
+    }
+
+    fn age_days(&self) -> Option<i64> {
+        self.modified().map(days_ago)
+    }
+
+    fn accessed_days_ago(&self) -> Option<i64> {
+        directory_accessed_days_ago(self.path, self.opts)
+    }
+
+    fn last_commit_days_ago(&self) -> Option<i64> {
+        git::last_commit_time(self.path).map(days_ago)
+    }
+
+    fn item_count(&self) -> usize {
+        *self.items.get_or_init(|| count_directory_items(self.path, self.opts))
+    }
+
+    fn size_bytes(&self) -> u64 {
+        *self.size.get_or_init(|| if self.opts.estimate {
+            estimate_directory_size(self.path, self.opts, &self.notes)
+        } else {
+            get_directory_size(self.path, self.opts, &self.notes)
+        })
+    }
+
+    fn estimated(&self) -> bool {
+        self.opts.estimate
+    }
+}
 
-    Ok(backup_path.to_string_lossy().to_string())
+// Matches on the deny list are reported loudly instead of silently filtered
+fn refuse_protected(path: &str) -> bool {
+    let Some((protected, what)) = protected::check(Path::new(path)) else {
+        return false;
+    };
+    let detail = format!("{} {}", what, protected.display());
+    error!("Refusing to touch {}: {}", path, detail);
+    report::message(Kind::Error, format!("Refusing to touch {} ({})", path, detail));
+    skipped::record(path, SkipReason::Protected, detail);
+    true
 }
 
-fn copy_dir_recursive(src: &Path, dst: &Path) -> io::Result<()> {
-    if !dst.exists() {
-        fs::create_dir_all(dst)?;
+// A match reached through a symlink can resolve to somewhere outside the tree
+// that was searched, which deleting it would destroy instead
+fn refuse_escape(path: &str, root: Option<&str>) -> bool {
+    let Some(root) = root else {
+        return false;
+    };
+    let Ok(resolved) = fs::canonicalize(path) else {
+        return false;
+    };
+    let root = fs::canonicalize(root).unwrap_or_else(|_| PathBuf::from(root));
+    if resolved.starts_with(&root) {
+        return false;
     }
+    let detail = format!("resolves to {}, outside {}", resolved.display(), root.display());
+    error!("Refusing to delete {}: {}", path, detail);
+    report::message(Kind::Error, format!("Refusing to delete {} ({}), pass --allow-outside-root to allow it", path, detail));
+    skipped::record(path, SkipReason::OutsideRoot, detail);
+    true
+}
 
-    for entry in fs::read_dir(src)? {
-        let entry = entry?;
-        let ty = entry.file_type()?;
-        let src_path = entry.path();
-        let dst_path = dst.join(entry.file_name());
+// Leave the `keep` newest matches under each parent directory out, so
+// versioned outputs like build-1.2 and build-1.3 keep their latest ones
+fn spare_latest(dirs: Vec<DirInfo>, keep: usize, opts: &ScanOptions) -> Vec<DirInfo> {
+    let mut groups: HashMap<PathBuf, Vec<DirInfo>> = HashMap::new();
+    let mut remaining = Vec::new();
+    for dir in dirs {
+        if dir.report_only {
+            remaining.push(dir);
+            continue;
+        }
+        let parent = Path::new(&dir.path).parent().map(Path::to_path_buf).unwrap_or_default();
+        groups.entry(parent).or_default().push(dir);
+    }
+    for (parent, group) in groups {
+        // Small groups are kept whole without dating them
+        let group_len = group.len();
+        let mut dated: Vec<(Option<SystemTime>, DirInfo)> = group.into_iter()
+            .map(|dir| (if keep < group_len { directory_mtime(Path::new(&dir.path), opts) } else { None }, dir))
+            .collect();
+        // Newest first, undatable ones counted as oldest
+        dated.sort_by_key(|(modified, _)| std::cmp::Reverse(*modified));
+        for (index, (_, dir)) in dated.into_iter().enumerate() {
+            if index < keep {
+                skipped::record(&dir.path, SkipReason::KeptLatest, format!("one of the {} newest in {}", keep, parent.display()));
+            } else {
+                remaining.push(dir);
+            }
+        }
+    }
+    remaining
+}
+
+// Replace estimated sizes with exact ones, dropping directories that no longer
+// pass the filters
+fn size_exactly(dirs: Vec<DirInfo>, opts: &ScanOptions) -> Vec<DirInfo> {
+    if !dirs.iter().any(|d| d.estimated) {
+        return dirs;
+    }
+    report::message(Kind::Setting, format!("Computing exact sizes of {} directories", dirs.len()));
+    let mounts = opts.mounts.iter().map(|t| mounts::Tuning { estimate: None, ..t.clone() }).collect();
+    let exact = ScanOptions { estimate: false, mounts, ..opts.clone() };
+    dirs.into_iter()
+        .filter_map(|dir| {
+            let info = analyze_directory(Path::new(&dir.path), &exact, None);
+            if info.is_none() {
+                info!("No longer matches the filters with its exact size: {}", dir.path);
+                report::message(Kind::Info, format!("Skipping (below filters when sized exactly): {}", dir.path));
+            }
+            info
+        })
+        .collect()
+}
+
+// Writes each match as a JSON line the moment it is found
+struct ResultStream {
+    out: Box<dyn Write>,
+}
+
+impl ResultStream {
+    // `-` streams to stdout
+    fn open(dest: &str) -> Result<Self, String> {
+        let out: Box<dyn Write> = if dest == "-" {
+            Box::new(io::stdout())
+        } else {
+            Box::new(fs::File::create(dest)
+                .map_err(|e| format!("{} Error creating stream file {}: {}", CROSS, dest, e))?)
+        };
+        Ok(ResultStream { out })
+    }
 
-        if ty.is_dir() {
-            copy_dir_recursive(&src_path, &dst_path)?;
-        } else if ty.is_file() {
-            fs::copy(&src_path, &dst_path)?;
+    fn emit(&mut self, info: &DirInfo) {
+        let written = serde_json::to_string(info)
+            .map_err(io::Error::other)
+            .and_then(|line| writeln!(self.out, "{}", line))
+            .and_then(|_| self.out.flush());
+        if let Err(e) = written {
+            error!("Failed to stream {}: {}", info.path, e);
         }
     }
+}
 
-    Ok(())
+// Read candidate directories, one per line, from a file or `-` for stdin
+// With `comments`, lines starting with '#' are skipped
+fn read_path_list(source: &str, comments: bool) -> Result<Vec<PathBuf>, String> {
+    let content = if source == "-" {
+        io::read_to_string(io::stdin())
+            .map_err(|e| format!("{} Error reading paths from stdin: {}", CROSS, e))?
+    } else {
+        fs::read_to_string(source)
+            .map_err(|e| format!("{} Error reading paths from {}: {}", CROSS, source, e))?
+    };
+
+    Ok(content.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .filter(|line| !(comments && line.starts_with('#')))
+        .map(PathBuf::from)
+        .collect())
 }
 
-fn delete_directories(
-    dirs: &[DirInfo],
+// Size and filter an explicit list of directories without walking for them
+fn directories_from_list(paths: Vec<PathBuf>, opts: &ScanOptions, on_found: &mut dyn FnMut(&DirInfo)) -> Vec<DirInfo> {
+    let spinner = scan_spinner(opts.verbose);
+
+    let result = paths.into_iter()
+        .filter(|path| {
+            if !path.is_dir() {
+                report::message(Kind::Info, format!("Skipping non-directory: {}", path.display()));
+                return false;
+            }
+            let path_str = path.to_string_lossy();
+            if opts.rules.is_excluded(&path_str) {
+                debug!("Excluding directory: {}", path_str);
+                return false;
+            }
+            true
+        })
+        .filter_map(|path| analyze_directory(&path, opts, spinner.as_ref()))
+        .inspect(|info| on_found(info))
+        .collect();
+
+    if let Some(spinner) = spinner {
+        spinner.finish_and_clear();
+    }
+
+    result
+}
+
+// How matched directories are backed up and removed
+#[derive(Debug, Clone, Default)]
+struct DeleteOptions {
     dry_run: bool,
     verbose: bool,
     use_trash: bool,
+    // Where directories go instead of being deleted, with --quarantine
+    quarantine: Option<PathBuf>,
     backup: bool,
     archive: bool,
-    backup_dir: Option<&str>,
+    archive_format: archive::ArchiveFormat,
+    // The archive format's compression level; its default if unset
+    compression_level: Option<i32>,
+    // Write each archive's manifest beside it as well as into it
+    archive_manifest: bool,
+    // Store archive entries under the directory's own name
+    archive_top_dir: bool,
+    // Bytes per archive volume; archives are one file if unset
+    archive_split_size: Option<u64>,
+    // Globs left out of backups and archives
+    backup_exclude: Vec<String>,
+    s3_storage_class: Vec<String>,
+    backup_name_template: Option<String>,
+    // What plain copies keep of the original's metadata
+    backup_preserve: preserve::Preserve,
+    // Reflink or copy the files of plain copies
+    backup_strategy: backup::BackupStrategy,
+    backup_dirs: Vec<String>,
+    backup_policy: backup::BackupPolicy,
+    // Old backups to remove from the backup directories after the run
+    prune_backups: Option<prune::RetentionPolicy>,
+    // Bytes per second written to backup destinations
+    upload_limit: Option<u64>,
+    // Hint the OS to read files ahead while backing them up
+    readahead: bool,
+    // Hardlink backed-up files unchanged since the previous copy
+    incremental: bool,
     interactive: bool,
-) -> Result<Vec<String>, String> {
-    let pb = ProgressBar::new(dirs.len() as u64);
+    // Percentage of files to hash-compare against the backup before deleting
+    verify_sample: Option<f64>,
+    // Re-read every archive in full and compare it with the source before deleting
+    verify_archive: bool,
+    // Copy even when the destinations look too full for it
+    skip_space_check: bool,
+    hash_algo: hash::HashAlgo,
+    // Command used to remove directories the current user can't (e.g. `sudo`)
+    sudo_helper: Option<String>,
+    // Defer directories holding other tools' lock files instead of deleting under them
+    respect_locks: bool,
+    // Skip directories that running processes have open or work in
+    check_processes: bool,
+    // Stop Gradle/sbt daemons first; target name -> stop command overrides
+    stop_daemons: Option<HashMap<String, String>>,
+    // Per-target overrides of use_trash/backup/archive
+    actions: HashMap<String, TargetAction>,
+    // Skip directories in repositories with uncommitted or unpushed work
+    git_safe: bool,
+    // Matches must still be inside this directory once symlinks are resolved
+    root: Option<String>,
+    // Copies an interrupted run already made, path -> copies; reused on resume
+    backed_up: HashMap<String, Vec<String>>,
+    // Copies it left part-way, path -> partial copies; finished on resume
+    copying: HashMap<String, Vec<String>>,
+    // Directories copied and deleted at the same time
+    jobs: usize,
+    // Entries above which a directory is removed in batches, None for the default
+    chunk_above: Option<usize>,
+    // Stop once this many bytes are freed, largest directories first
+    free_goal: Option<u64>,
+    // Jobs and upload limits for directories under configured mounts
+    mounts: Vec<mounts::Tuning>,
+    // Most directories and bytes one run may remove; the rest is skipped
+    max_dirs: Option<usize>,
+    max_total: Option<u64>,
+    // Keep matched directories, removing only files older than this many days
+    purge_files_older_than: Option<i64>,
+    // Report progress this far into the run and as often again; also POSTed to the webhook
+    heartbeat: Option<Duration>,
+    heartbeat_webhook: Option<String>,
+}
+
+// How long deferred, locked directories get before their retry
+const LOCK_RETRY_DELAY: Duration = Duration::from_secs(2);
+
+fn delete_directories(dirs: &[DirInfo], opts: &DeleteOptions) -> Result<Vec<String>, String> {
+    // Report-only targets are listed but never touched
+    let dirs: Vec<DirInfo> = dirs.iter()
+        .filter(|dir| {
+            let report_only = dir.report_only
+                || actions::action_for(&dir.path, &opts.actions) == Some(TargetAction::ReportOnly);
+            if report_only {
+                skipped::record(&dir.path, SkipReason::ReportOnly, "target is report-only");
+            }
+            !report_only
+        })
+        .cloned()
+        .collect();
+    let dirs = dirs.as_slice();
+
+    // Everything about to happen goes on record before anything is touched
+    let flags_for = |dir: &DirInfo| actions::action_for(&dir.path, &opts.actions)
+        .map_or((opts.use_trash, opts.backup, opts.archive), TargetAction::flags);
+    for dir in dirs {
+        let (use_trash, backup, archive) = flags_for(dir);
+        journal::record(Event::Planned {
+            path: dir.path.clone(),
+            size_bytes: dir.size_bytes,
+            action: action_label(use_trash, backup, archive, opts),
+        });
+    }
+
+    // Resolve every destination up front so a bad URI fails before anything is touched
+    let needs_copies = opts.backup || opts.archive
+        || opts.actions.values().any(|a| matches!(a, TargetAction::BackupDelete | TargetAction::ArchiveDelete));
+    let destinations = if needs_copies {
+        opts.backup_dirs.iter()
+            .map(|uri| backend::open_backend(uri))
+            .collect::<Result<Vec<_>, _>>()?
+    } else {
+        Vec::new()
+    };
+    if needs_copies {
+        check_verifiable(dirs, opts)?;
+    }
+    if needs_copies && !opts.dry_run && !opts.skip_space_check {
+        check_backup_space(dirs, opts)?;
+    }
+
+    let pb = progress().add(ProgressBar::new(dirs.len() as u64));
     pb.set_style(
         ProgressStyle::default_bar()
             .template("{spinner} [{elapsed_precise}] [{bar:40.cyan/blue}] {pos}/{len} ({eta})")
@@ -324,100 +1220,527 @@ fn delete_directories(
             .progress_chars("🟩🟧🟥")
     );
 
-    let mut processed_paths = Vec::new();
-    let mut backup_paths = Vec::new();
+    // Directories another tool is working in go to the back of the queue and get one retry
+    let mut queue: VecDeque<(&DirInfo, bool)> = dirs.iter().map(|dir| (dir, false)).collect();
+    let mut waited = false;
+    // Stopped daemons release their caches before anything checks for running processes
+    if let Some(commands) = &opts.stop_daemons {
+        daemons::stop_daemons(dirs, commands, opts.dry_run);
+    }
+    let running = opts.check_processes.then(processes::ProcessSnapshot::capture);
+    let mut repos = opts.git_safe.then(git::RepoGuard::default);
 
-    for dir in dirs {
+    // The checks and prompts below run here one directory at a time; copying
+    // and deleting is handed to `jobs` workers. Prompts need the terminal to
+    // themselves, so interactive runs use a single one.
+    // Directories under a configured mount get as many workers as it allows,
+    // the rest share --jobs: group i + 1 is the i-th mount, group 0 the rest.
+    let groups: HashMap<&str, usize> = dirs.iter()
+        .map(|dir| (dir.path.as_str(), mounts::index_for(&opts.mounts, Path::new(&dir.path)).map_or(0, |i| i + 1)))
+        .collect();
+    let limits: Vec<usize> = std::iter::once(opts.jobs)
+        .chain(opts.mounts.iter().map(|t| t.jobs.unwrap_or(opts.jobs)))
+        .map(|jobs| if opts.interactive { 1 } else { jobs.max(1) })
+        .collect();
+    let jobs = groups.values().collect::<HashSet<_>>().into_iter().map(|&group| limits[group]).sum::<usize>().max(1);
+    let slots = mounts::Slots::new(limits);
+    let outcome = Mutex::new(Outcome::default());
+    let heartbeat = opts.heartbeat.map(|every| {
+        Heartbeat::new(every, opts.heartbeat_webhook.clone(), dirs.len(), dirs.iter().map(DirInfo::freed_size).sum())
+    });
+    let work = |dir: &DirInfo| {
+        let slot = slots.acquire(groups[dir.path.as_str()]);
+        // Looked up while the directory is still there
+        let filesystem = (!opts.dry_run).then(|| mounts::filesystem(Path::new(&dir.path)));
+        let started = Instant::now();
+        let result = purge_directory(dir, opts, &destinations);
+        drop(slot);
         pb.inc(1);
-        
-        // Interactive mode - ask for confirmation for each directory
-        if interactive && !dry_run {
-            println!("\n{} Directory: {}", INFO, bold().apply_to(&dir.path));
-            println!("   Size: {:.2} MB", dir.size_bytes as f64 / 1024.0 / 1024.0);
-            if let Some(age) = dir.age_days {
-                println!("   Age: {} days", age);
+        if let (Some(filesystem), Ok(_)) = (filesystem, &result) {
+            let (use_trash, backup, archive) = flags_for(dir);
+            history::time_action(dir, action_label(use_trash, backup, archive, opts), filesystem, started.elapsed());
+        }
+        if let Some(heartbeat) = &heartbeat {
+            match &result {
+                Ok(_) => heartbeat.removed(dir.freed_size()),
+                Err(_) => heartbeat.failed(),
+            }
+        }
+        let mut outcome = outcome.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        match result {
+            Ok(copies) => outcome.copies.extend(copies),
+            Err(e) => outcome.errors.push((dir.path.clone(), e)),
+        }
+    };
+
+    // Bytes the directories handed to `work` so far will free, for --free
+    let mut freeing = 0u64;
+    let mut kept: Vec<&DirInfo> = Vec::new();
+    // What has been handed out, against --max-dirs and --max-total
+    let (mut handed_out, mut handed_out_bytes) = (0usize, 0u64);
+    let mut capped = 0usize;
+    let (sender, receiver) = mpsc::sync_channel::<&DirInfo>(jobs);
+    let receiver = Mutex::new(receiver);
+    let dispatched: Result<(), String> = Heartbeat::around(heartbeat.as_ref(), || thread::scope(|scope| {
+        if jobs > 1 {
+            for _ in 0..jobs {
+                scope.spawn(|| loop {
+                    let next = receiver.lock().map_err(|_| ()).and_then(|r| r.recv().map_err(|_| ()));
+                    let Ok(dir) = next else {
+                        break;
+                    };
+                    work(dir);
+                });
             }
-            if let Some(count) = dir.item_count {
-                println!("   Items: {}", count);
+        }
+
+        while let Some((dir, retried)) = queue.pop_front() {
+            // Left for `resume`, with the journal unfinished
+            if chunked::interrupted() {
+                report::message(Kind::Warning, format!("Interrupted, {} directories not started", queue.len() + 1));
+                break;
             }
-            
-            print!("{} Delete this directory? (y/n): ", WARN);
-            io::stdout().flush().map_err(|e| format!("IO error: {}", e))?;
-            
-            let mut input = String::new();
-            io::stdin().read_line(&mut input)
-                .map_err(|e| format!("{} Input error: {}", CROSS, e))?;
-                
-            if !input.trim().eq_ignore_ascii_case("y") {
-                println!("{} Skipping directory", INFO);
+            if let Some(goal) = opts.free_goal
+                && freeing >= goal
+            {
+                skipped::record(&dir.path, SkipReason::GoalMet, format!("{} freed already", format_mb(freeing, false)));
+                kept.push(dir);
+                pb.inc(1);
                 continue;
             }
+            // Plans may come from elsewhere, so the deny list is checked again right before acting
+            if !retried && (refuse_protected(&dir.path) || refuse_escape(&dir.path, opts.root.as_deref())) {
+                pb.inc(1);
+                continue;
+            }
+            if let Some(running) = &running {
+                let users = running.users_of(Path::new(&dir.path));
+                if let Some(first) = users.first() {
+                    let what = if processes::is_build_tool(first) { "Build in progress" } else { "In use" };
+                    skipped::record(&dir.path, SkipReason::InUse, format!("{} by {}", what.to_lowercase(), users.join(", ")));
+                    report::message(Kind::Warning, format!("{}, skipping {} ({})", what, dir.path, users.join(", ")));
+                    pb.inc(1);
+                    continue;
+                }
+            }
+            if let Some(repos) = repos.as_mut()
+                && !retried
+                && let Some(work) = repos.active_work(Path::new(&dir.path))
+            {
+                skipped::record(&dir.path, SkipReason::ActiveRepo, work.clone());
+                report::message(Kind::Warning, format!("Skipping {}: {}", dir.path, work));
+                pb.inc(1);
+                continue;
+            }
+            if opts.respect_locks {
+                if retried && !waited {
+                    thread::sleep(LOCK_RETRY_DELAY);
+                    waited = true;
+                }
+                if let Some(lock) = locks::find_active_lock(Path::new(&dir.path)) {
+                    if !retried {
+                        info!("Deferring {}, locked by {}", dir.path, lock.display());
+                        report::message(Kind::Info, format!("In use, retrying at the end: {} ({})", dir.path, lock.display()));
+                        queue.push_back((dir, true));
+                        continue;
+                    }
+                    skipped::record(&dir.path, SkipReason::Locked, format!("still locked by {}", lock.display()));
+                    report::message(Kind::Warning, format!("Skipping directory still in use: {}", dir.path));
+                    pb.inc(1);
+                    continue;
+                }
+            }
+
+            // Interactive mode - ask for confirmation for each directory
+            if opts.interactive && !opts.dry_run {
+                println!("\n{} Directory: {}", INFO, bold().apply_to(&dir.path));
+                println!("   Size: {}", dir.size_label());
+                if let Some(age) = dir.age_days {
+                    println!("   Age: {} days", age);
+                }
+                if let Some(count) = dir.item_count {
+                    println!("   Items: {}", locale::count(count));
+                }
+
+                print!("{} Delete this directory? (y/n): ", WARN);
+                io::stdout().flush().map_err(|e| format!("IO error: {}", e))?;
+
+                let mut input = String::new();
+                io::stdin().read_line(&mut input)
+                    .map_err(|e| format!("{} Input error: {}", CROSS, e))?;
+
+                if !input.trim().eq_ignore_ascii_case("y") {
+                    skipped::record(&dir.path, SkipReason::UserDeclined, "declined at deletion prompt");
+                    report::message(Kind::Info, "Skipping directory");
+                    pb.inc(1);
+                    continue;
+                }
+            }
+
+            if let Some(cap) = exceeded_cap(opts, handed_out, handed_out_bytes, dir) {
+                skipped::record(&dir.path, SkipReason::Capped, format!("skipped due to cap: {}", cap));
+                report::detail(Kind::Info, format!("Skipping due to cap ({}): {}", cap, dir.path));
+                capped += 1;
+                pb.inc(1);
+                continue;
+            }
+            handed_out += 1;
+            handed_out_bytes += dir.size_bytes;
+            // Counted as it is handed out, so workers don't overshoot the goal
+            freeing += dir.freed_size();
+            if jobs == 1 {
+                work(dir);
+            } else if sender.send(dir).is_err() {
+                break;
+            }
+        }
+        // Lets the workers run dry and exit
+        drop(sender);
+        Ok(())
+    }));
+    dispatched?;
+
+    let outcome = outcome.into_inner().unwrap_or_else(|poisoned| poisoned.into_inner());
+    if let Some(goal) = opts.free_goal {
+        let set_aside = if use_trash_anywhere(opts) { Some("the trash") } else if opts.quarantine.is_some() { Some("quarantine") } else { None };
+        report_goal(goal, freeing, &kept, set_aside);
+    }
+    if capped > 0 {
+        report::message(Kind::Warning, format!("{} directories skipped due to cap", capped));
+    }
+
+    if let Some(policy) = &opts.prune_backups {
+        let template = opts.backup_name_template.as_deref().map(naming::NameTemplate::parse).transpose()?;
+        prune::prune(&opts.backup_dirs, template.as_ref(), policy, &outcome.copies, opts.dry_run);
+    }
+
+    // Left unfinished in the journal, so `resume` retries what failed
+    if !outcome.errors.is_empty() {
+        pb.abandon_with_message(format!("{} {} of {} directories failed", CROSS, outcome.errors.len(), dirs.len()));
+        report_failures(&outcome.errors, opts);
+        return Ok(outcome.copies);
+    }
+
+    journal::record(Event::Finish);
+    pb.finish_with_message(format!("{} {}", 
+        green().apply_to(TICK),
+        green().apply_to("Operation completed successfully!")
+    ));
+    
+    Ok(outcome.copies)
+}
+
+// Everything that failed, once, after everything else was done
+fn report_failures(errors: &[(String, String)], opts: &DeleteOptions) {
+    report::message(Kind::Error, format!("{} directories could not be removed:", errors.len()));
+    report::items(&errors.iter().map(|(path, error)| format!("{}: {}", path, error)).collect::<Vec<_>>());
+    let mut remedies: Vec<&str> = errors.iter().map(|(_, error)| remedy(error, opts)).collect();
+    remedies.sort();
+    remedies.dedup();
+    for remedy in remedies {
+        report::message(Kind::Hint, remedy);
+    }
+    report::message(Kind::Hint, "Run `dirpurge resume` to retry the failed directories");
+}
+
+// What usually gets a failed directory removed, from what went wrong
+fn remedy(error: &str, opts: &DeleteOptions) -> &'static str {
+    let error = error.to_lowercase();
+    if error.contains("permission denied") || error.contains("access is denied") || error.contains("operation not permitted") {
+        if opts.sudo_helper.is_some() {
+            "Permission denied: remove what is left with elevated rights, e.g. `dirpurge apply --elevate` on a saved plan"
+        } else {
+            "Permission denied: rerun with --sudo-helper to remove them with elevated rights"
+        }
+    } else if error.contains("being used by another process") || error.contains("resource busy") || error.contains("text file busy") {
+        "In use: close the programs holding files open in them"
+    } else if error.contains("trash") {
+        "Trash failed: check the trash is available on that drive, or rerun without --use-trash"
+    } else if error.contains("backup") || error.contains("archive") || error.contains("no space left") {
+        "Backup failed: check the backup destination is reachable and has room, or relax --backup-policy"
+    } else {
+        "Check the errors above, then retry"
+    }
+}
+
+// The cap removing `dir` would break, given what the run already removes.
+// Smaller directories further down may still fit under --max-total.
+fn exceeded_cap(opts: &DeleteOptions, dirs: usize, bytes: u64, dir: &DirInfo) -> Option<String> {
+    if let Some(max) = opts.max_dirs
+        && dirs >= max
+    {
+        return Some(format!("--max-dirs {}", max));
+    }
+    if let Some(max) = opts.max_total
+        && bytes.saturating_add(dir.size_bytes) > max
+    {
+        return Some(format!("--max-total {}", format_mb(max, false)));
+    }
+    None
+}
+
+// How a --free run went and what it left in place. Trashed or quarantined
+// directories were only `set_aside` there.
+fn report_goal(goal: u64, freed: u64, kept: &[&DirInfo], set_aside: Option<&str>) {
+    let verb = set_aside.map_or("freed".to_string(), |place| format!("moved to {}", place));
+    if freed < goal {
+        report::message(Kind::Warning, format!("Only ~{} {} of the {} goal, no matches left", format_mb(freed, false), verb, format_mb(goal, false)));
+    } else {
+        report::message(Kind::Info, format!("Goal of {} met with ~{} {}", format_mb(goal, false), format_mb(freed, false), verb));
+    }
+    if !kept.is_empty() {
+        let kept_size: u64 = kept.iter().map(|dir| dir.size_bytes).sum();
+        report::message(Kind::Info, format!("Kept {} directories ({}):", kept.len(), format_mb(kept_size, false)));
+        report::items(&kept.iter().map(|dir| format!("{} ({})", dir.path, dir.size_label())).collect::<Vec<_>>());
+    }
+    match set_aside {
+        Some("quarantine") => report::message(Kind::Hint, "Quarantined directories take up space until `dirpurge quarantine purge` removes them"),
+        Some(_) => report::message(Kind::Hint, "Trashed directories take up space until the trash is emptied"),
+        None => {}
+    }
+}
+
+// Whether any directory goes to the trash rather than being deleted
+fn use_trash_anywhere(opts: &DeleteOptions) -> bool {
+    opts.use_trash || opts.actions.values().any(|action| action.flags().0)
+}
+
+// Copies asked to be verified have to be readable where they are made, or the
+// directories would go on an unchecked copy: s3:// and sftp:// are refused
+// rather than passed over
+fn check_verifiable(dirs: &[DirInfo], opts: &DeleteOptions) -> Result<(), String> {
+    let verifying = dirs.iter().any(|dir| {
+        let (_, backup, archive) = actions::action_for(&dir.path, &opts.actions)
+            .map_or((opts.use_trash, opts.backup, opts.archive), TargetAction::flags);
+        (archive && opts.verify_archive) || ((backup || archive) && opts.verify_sample.is_some())
+    });
+    match opts.backup_dirs.iter().find(|uri| backend::local_root(uri).is_none()) {
+        Some(uri) if verifying => Err(format!(
+            "{} Copies in {} can't be read back to verify; back up to a local directory, or leave out --verify-archive and --verify-sample to go without",
+            CROSS, uri
+        )),
+        _ => Ok(()),
+    }
+}
+
+// Whether each local destination has room for what the run copies there,
+// so a full disk stops it before the first copy rather than halfway through
+// one. Sizes are what was scanned plus a tenth; clones and incremental copies
+// share what they can and aren't counted.
+fn check_backup_space(dirs: &[DirInfo], opts: &DeleteOptions) -> Result<(), String> {
+    let total: u64 = dirs.iter()
+        .filter(|dir| !opts.backed_up.contains_key(&dir.path))
+        .map(|dir| {
+            let (_, backup, archive) = actions::action_for(&dir.path, &opts.actions)
+                .map_or((opts.use_trash, opts.backup, opts.archive), TargetAction::flags);
+            let copied = backup && opts.backup_strategy != backup::BackupStrategy::Reflink && !opts.incremental;
+            if archive || copied { dir.size_bytes } else { 0 }
+        })
+        .sum();
+    if total == 0 {
+        return Ok(());
+    }
+    let needed = total.saturating_add(total / 10);
+    for uri in &opts.backup_dirs {
+        let Some(root) = backend::local_root(uri) else {
+            continue;
+        };
+        // Made on demand, so it is the nearest directory there that counts
+        let existing = root.ancestors().find(|dir| dir.is_dir()).map(Path::to_path_buf).unwrap_or_else(|| PathBuf::from("."));
+        let Some(free) = mounts::free_space(&existing) else {
+            continue;
+        };
+        debug!("{} has {} free, {} needed", uri, free, needed);
+        if free < needed {
+            return Err(format!(
+                "{} Not enough space for backups in {}: about {} needed ({} to copy plus 10%), {} free; free up space, back up elsewhere, or pass --skip-space-check",
+                CROSS, uri, format_mb(needed, false), format_mb(total, false), format_mb(free, false)
+            ));
         }
-        
-        // Handle backup or archive if requested
-        if (backup || archive) && backup_dir.is_some() {
-            let backup_dir = backup_dir.unwrap();
-            let result = if archive {
-                archive_directory(&dir.path, backup_dir)
-            } else {
-                backup_directory(&dir.path, backup_dir)
-            };
-            
-            match result {
-                Ok(path) => {
-                    if verbose {
-                        println!("{} {}", 
-                            DISK,
-                            green().apply_to(format!("{} to: {}", 
-                                if archive { "Archived" } else { "Backed up" }, 
-                                path
-                            ))
-                        );
-                    }
-                    backup_paths.push(path);
-                },
-                Err(e) => {
-                    pb.abandon_with_message(format!("{} Operation failed", CROSS));
-                    return Err(e);
+    }
+    Ok(())
+}
+
+// What the deletion workers did, gathered as each directory finishes
+#[derive(Default)]
+struct Outcome {
+    copies: Vec<String>,
+    // Path and error of each directory that failed
+    errors: Vec<(String, String)>,
+}
+
+// Copy a directory to the backup destinations if its action asks for it, then
+// remove it, journaling each step. Returns the copies made.
+fn purge_directory(dir: &DirInfo, opts: &DeleteOptions, destinations: &[Box<dyn backend::BackupBackend>]) -> Result<Vec<String>, String> {
+    let action = actions::action_for(&dir.path, &opts.actions);
+    let (use_trash, backup, archive) = action
+        .map_or((opts.use_trash, opts.backup, opts.archive), TargetAction::flags);
+
+    // Handle backup or archive if requested
+    let mut copies = Vec::new();
+    if let Some(made) = opts.backed_up.get(&dir.path) {
+        // A copy of a half-deleted directory would be worse than the one already made
+        report::detail(Kind::Info, format!("Reusing copies from the interrupted run: {}", dir.path));
+        copies = made.clone();
+    } else if (backup || archive) && !opts.backup_dirs.is_empty() {
+        let backup_options = backend::BackupOptions {
+            archive,
+            archive_format: opts.archive_format,
+            compression_level: opts.compression_level,
+            archive_manifest: opts.archive_manifest,
+            top_dir: opts.archive_top_dir,
+            split_size: opts.archive_split_size,
+            name_template: opts.backup_name_template.as_deref().map(naming::NameTemplate::parse).transpose()?,
+            upload_limit: mounts::for_path(&opts.mounts, Path::new(&dir.path))
+                .and_then(|tuning| tuning.upload_limit)
+                .or(opts.upload_limit),
+            #[cfg(feature = "s3")]
+            storage_class: storage_class::StorageClasses::new(&opts.s3_storage_class)?.for_dir(&dir.path, dir.size_bytes),
+            readahead: opts.readahead,
+            incremental: opts.incremental,
+            partial: opts.copying.get(&dir.path).cloned().unwrap_or_default(),
+            exclude: backup::BackupExclude::new(&opts.backup_exclude)?,
+            preserve: opts.backup_preserve,
+            strategy: opts.backup_strategy,
+        };
+        // Spot-check each copy before the original is gone
+        let percent = if archive && opts.verify_archive { Some(100.0) } else { opts.verify_sample };
+        let verify = percent.map(|percent| {
+            let backup_options = &backup_options;
+            move |copy: &Path| verify::verify_backup_sample(Path::new(&dir.path), copy, percent, opts.hash_algo, backup_options).map(|_| ())
+        });
+
+        match backup::backup_to_destinations(&dir.path, destinations, &backup_options, opts.backup_policy, verify) {
+            Ok(made) => {
+                for path in &made {
+                    report::detail(Kind::Saved, format!("{} to: {}", 
+                        if archive { "Archived" } else { "Backed up" }, 
+                        path
+                    ));
                 }
+                journal::record(Event::BackedUp { path: dir.path.clone(), copies: made.clone() });
+                copies = made;
+            },
+            Err(e) => {
+                journal::record(Event::Failed { path: dir.path.clone(), error: e.clone() });
+                skipped::record(&dir.path, SkipReason::Error, e.clone());
+                return Err(e);
             }
         }
+    }
 
-        if !dry_run {
-            match handle_deletion(&dir.path, use_trash, verbose) {
-                Ok(_) => processed_paths.push(dir.path.clone()),
-                Err(e) => {
-                    pb.abandon_with_message(format!("{} Operation failed", CROSS));
-                    return Err(e);
+    // Thinning keeps the directory, only its stale files go
+    if let Some(days) = opts.purge_files_older_than {
+        return match thin::thin_directory(Path::new(&dir.path), thin::cutoff(days), use_trash, opts.dry_run) {
+            Ok(thinned) => {
+                let (kind, verb) = match (opts.dry_run, use_trash) {
+                    (true, _) => (Kind::DryRun, "[Dry Run] Would remove"),
+                    (false, true) => (Kind::Trashed, "Trashed"),
+                    (false, false) => (Kind::Deleted, "Removed"),
+                };
+                report::detail(kind, format!("{} {} file(s) older than {} days ({}) from {}, and {} emptied subdirectories",
+                    verb, thinned.files, days, format_mb(thinned.bytes, false), dir.path, thinned.dirs));
+                if !opts.dry_run {
+                    journal::record(Event::Thinned { path: dir.path.clone(), files: thinned.files, bytes: thinned.bytes });
                 }
+                Ok(copies)
+            }
+            Err(e) => {
+                journal::record(Event::Failed { path: dir.path.clone(), error: e.clone() });
+                skipped::record(&dir.path, SkipReason::Error, e.clone());
+                Err(e)
+            }
+        };
+    }
+
+    // Put aside rather than deleted; the trash is a place of its own
+    let quarantine = opts.quarantine.as_ref().filter(|_| !use_trash);
+    if opts.dry_run {
+        report::detail(Kind::DryRun, match action {
+            Some(action) => format!("[Dry Run] Would {}: {}", action, dir.path),
+            None if quarantine.is_some() => format!("[Dry Run] Would quarantine: {}", dir.path),
+            None => format!("[Dry Run] Would delete: {}", dir.path),
+        });
+        return Ok(copies);
+    }
+
+    journal::record(Event::Deleting { path: dir.path.clone() });
+    if let Some(root) = quarantine {
+        return match quarantine::quarantine(Path::new(&dir.path), root, dir.size_bytes) {
+            Ok(moved) => {
+                report::detail(Kind::Trashed, format!("Quarantined: {} in {}", dir.path, moved.display()));
+                // Restored from like any copy, until the quarantine is purged
+                let kept = copies.iter().cloned().chain([moved.to_string_lossy().into_owned()]).collect();
+                journal::record(Event::Done { path: dir.path.clone(), trashed: false, copies: kept });
+                Ok(copies)
             }
-        } else if verbose {
-            println!("{} {}", 
-                yellow().apply_to(WARN),
-                cyan().apply_to(format!("[Dry Run] Would delete: {}", dir.path))
-            );
-            processed_paths.push(dir.path.clone());
+            Err(e) => {
+                journal::record(Event::Failed { path: dir.path.clone(), error: e.clone() });
+                skipped::record(&dir.path, SkipReason::Error, e.clone());
+                Err(e)
+            }
+        };
+    }
+    let deleted = match &opts.sudo_helper {
+        Some(helper) if permissions::needs_elevation(Path::new(&dir.path)) =>
+            permissions::elevated_delete(&dir.path, helper),
+        _ => {
+            let threshold = opts.chunk_above.unwrap_or(chunked::DEFAULT_THRESHOLD);
+            handle_deletion(&dir.path, use_trash, dir.item_count.filter(|&entries| entries > threshold))
+        }
+    };
+    match deleted {
+        Ok(_) => {
+            journal::record(Event::Done { path: dir.path.clone(), trashed: use_trash, copies: copies.clone() });
+            Ok(copies)
+        },
+        Err(e) => {
+            journal::record(Event::Failed { path: dir.path.clone(), error: e.clone() });
+            skipped::record(&dir.path, SkipReason::Error, e.clone());
+            Err(e)
         }
     }
-    
-    pb.finish_with_message(format!("{} {}", 
-        green().apply_to(TICK),
-        green().apply_to("Operation completed successfully!")
-    ));
-    
-    Ok(backup_paths)
 }
 
-fn handle_deletion(path: &str, use_trash: bool, verbose: bool) -> Result<(), String> {
+// How a directory is handled, as written to the journal
+fn action_label(use_trash: bool, backup: bool, archive: bool, opts: &DeleteOptions) -> String {
+    let copy = if archive { "archive+" } else if backup { "backup+" } else { "" };
+    let removal = if use_trash { "trash" } else if opts.quarantine.is_some() { "quarantine" } else { "delete" };
+    let files = opts.purge_files_older_than.map_or(String::new(), |days| format!("-files-older-than-{}d", days));
+    format!("{}{}{}", copy, removal, files)
+}
+
+// Error for options whose subsystem was left out of this build
+#[cfg(not(all(feature = "trash", feature = "archive", feature = "s3")))]
+fn feature_disabled(feature: &str) -> String {
+    format!("{} dirpurge was built without the `{}` feature", CROSS, feature)
+}
+
+// Storage classes only mean something to builds that upload to S3
+#[cfg(feature = "s3")]
+use storage_class::check_rule as check_storage_class;
+
+#[cfg(not(feature = "s3"))]
+fn check_storage_class(_rule: &str) -> Result<String, String> {
+    Err(feature_disabled("s3"))
+}
+
+#[cfg(feature = "trash")]
+fn move_to_trash(path: &str) -> Result<(), String> {
+    trash::delete(path).map_err(|e| e.to_string())
+}
+
+#[cfg(not(feature = "trash"))]
+fn move_to_trash(_path: &str) -> Result<(), String> {
+    Err(feature_disabled("trash"))
+}
+
+// `batched` is the entry count of a directory big enough to remove in batches
+fn handle_deletion(path: &str, use_trash: bool, batched: Option<usize>) -> Result<(), String> {
     if use_trash {
-        match trash::delete(path) {
+        match move_to_trash(path) {
             Ok(_) => {
-                if verbose {
-                    println!("{} {}", 
-                        TRASH,
-                        green().apply_to(format!("Moved to trash: {}", path))
-                    );
-                }
+                report::detail(Kind::Trashed, format!("Moved to trash: {}", path));
                 Ok(())
             },
             Err(e) => {
@@ -426,29 +1749,63 @@ fn handle_deletion(path: &str, use_trash: bool, verbose: bool) -> Result<(), Str
             }
         }
     } else {
-        match fs::remove_dir_all(path) {
+        // Out of the way first, so nothing half-removed is left under its name
+        let aside = deleting::set_aside(Path::new(path));
+        let left_in = |e: String| if aside == Path::new(path) { e } else { format!("{}; what is left is in {}", e, aside.display()) };
+        if let Some(entries) = batched {
+            let removed = chunked::remove_dir(&aside, entries)
+                .map_err(left_in)
+                .inspect_err(|e| error!("Deletion failed for {}: {}", path, e))?;
+            report::detail(Kind::Deleted, format!("Permanently deleted: {} ({} entries, in batches)", path, locale::count(removed)));
+            return Ok(());
+        }
+        match fs::remove_dir_all(&aside) {
             Ok(_) => {
-                if verbose {
-                    println!("{} {}", 
-                        CROSS,
-                        red().apply_to(format!("Permanently deleted: {}", path))
-                    );
-                }
+                report::detail(Kind::Deleted, format!("Permanently deleted: {}", path));
                 Ok(())
             },
             Err(e) => {
                 error!("Deletion failed for {}: {}", path, e);
-                Err(format!("{} Deletion failed: {}", CROSS, e))
+                Err(left_in(format!("{} Deletion failed: {}", CROSS, e)))
             }
         }
     }
 }
 
+// The files a run's results are exported to
+#[derive(Debug, Clone, Default)]
+struct Exports {
+    json: Option<String>,
+    yaml: Option<String>,
+    jsonl: Option<String>,
+    csv: Option<String>,
+    pdf: Option<String>,
+    treemap: Option<String>,
+}
+
+impl Exports {
+    fn of(config: &Config) -> Self {
+        Exports {
+            json: config.json.clone(),
+            yaml: config.yaml.clone(),
+            jsonl: config.jsonl.clone(),
+            csv: config.csv.clone(),
+            pdf: config.pdf.clone(),
+            treemap: config.treemap.clone(),
+        }
+    }
+
+    fn any(&self) -> bool {
+        self.json.is_some() || self.yaml.is_some() || self.jsonl.is_some() || self.csv.is_some() || self.pdf.is_some() || self.treemap.is_some()
+    }
+}
+
 fn export_summary(
     dirs: &[DirInfo], 
-    json_path: Option<&str>, 
-    csv_path: Option<&str>,
+    inventory: &[DirInfo],
+    exports: &Exports,
     backup_paths: &[String],
+    rules: &policy::Policy,
 ) -> Result<(), String> {
     // Create a summary object with more details
     #[derive(Serialize)]
@@ -456,13 +1813,34 @@ fn export_summary(
         directories: Vec<DirInfo>,
         total_size_bytes: u64,
         total_size_mb: f64,
+        // Less than total_size_bytes on compressed filesystems
+        #[serde(skip_serializing_if = "Option::is_none")]
+        total_disk_bytes: Option<u64>,
         count: usize,
         average_size_mb: f64,
         oldest_dir_days: Option<i64>,
         newest_dir_days: Option<i64>,
         backups: Vec<String>,
+        // Report-only matches, tracked but never deleted
+        #[serde(skip_serializing_if = "Vec::is_empty")]
+        inventory: Vec<DirInfo>,
+        // Candidates that were found but not acted on, and why
+        skipped: Vec<skipped::SkippedDir>,
+        // The rules the run was made with
+        rules: policy::Policy,
         timestamp: String,
     }
+
+    // One row per directory, acted on or skipped
+    #[derive(Serialize)]
+    struct CsvRow<'a> {
+        path: &'a str,
+        size_bytes: Option<u64>,
+        age_days: Option<i64>,
+        item_count: Option<usize>,
+        status: &'a str,
+        reason: String,
+    }
     
     let total_size: u64 = dirs.iter().map(|d| d.size_bytes).sum();
     let total_size_mb = total_size as f64 / 1024.0 / 1024.0;
@@ -480,71 +1858,146 @@ fn export_summary(
         directories: dirs.to_vec(),
         total_size_bytes: total_size,
         total_size_mb,
+        total_disk_bytes: disk_total(dirs),
         count: dirs.len(),
         average_size_mb,
         oldest_dir_days,
         newest_dir_days,
         backups: backup_paths.to_vec(),
+        inventory: inventory.to_vec(),
+        skipped: skipped::all(),
+        rules: rules.clone(),
         timestamp: chrono::Local::now().to_rfc3339(),
     };
 
-    if let Some(json_file) = json_path {
+    if let Some(json_file) = exports.json.as_deref() {
         match serde_json::to_string_pretty(&summary) {
             Ok(json) => {
                 if let Err(e) = fs::write(json_file, json) {
                     error!("JSON export error: {}", e);
-                    eprintln!("{} {}", 
-                        CROSS,
-                        red().apply_to(format!("JSON export error: {}", e))
-                    );
+                    report::message(Kind::Error, format!("JSON export error: {}", e));
                 } else {
                     info!("Saved JSON summary to {}", json_file);
-                    println!("{} {}", 
-                        DISK,
-                        green().apply_to(format!("Saved JSON summary to {}", json_file))
-                    );
+                    report::message(Kind::Saved, format!("Saved JSON summary to {}", json_file));
                 }
             }
             Err(e) => {
                 error!("JSON serialization error: {}", e);
-                eprintln!("{} {}", 
-                    CROSS,
-                    red().apply_to(format!("JSON serialization error: {}", e))
-                );
+                report::message(Kind::Error, format!("JSON serialization error: {}", e));
             }
         }
     }
     
-    if let Some(csv_file) = csv_path {
+    if let Some(yaml_file) = exports.yaml.as_deref() {
+        match serde_json::to_value(&summary) {
+            Ok(value) => {
+                if let Err(e) = fs::write(yaml_file, yaml::to_string(&value)) {
+                    error!("YAML export error: {}", e);
+                    report::message(Kind::Error, format!("YAML export error: {}", e));
+                } else {
+                    info!("Saved YAML summary to {}", yaml_file);
+                    report::message(Kind::Saved, format!("Saved YAML summary to {}", yaml_file));
+                }
+            }
+            Err(e) => {
+                error!("YAML serialization error: {}", e);
+                report::message(Kind::Error, format!("YAML serialization error: {}", e));
+            }
+        }
+    }
+
+    // One self-contained line per directory, for log pipelines to take in as
+    // they read rather than as one document
+    if let Some(jsonl_file) = exports.jsonl.as_deref() {
+        let lines = dirs.iter().map(|d| (d, "processed")).chain(inventory.iter().map(|d| (d, "report-only")));
+        let written = fs::File::create(jsonl_file).and_then(|file| {
+            let mut out = io::BufWriter::new(file);
+            for (dir, status) in lines {
+                let mut line = serde_json::to_value(dir).map_err(io::Error::other)?;
+                line["status"] = status.into();
+                line["timestamp"] = summary.timestamp.clone().into();
+                writeln!(out, "{}", line)?;
+            }
+            out.flush()
+        });
+        if let Err(e) = written {
+            error!("JSON Lines export error: {}", e);
+            report::message(Kind::Error, format!("JSON Lines export error: {}", e));
+        } else {
+            info!("Saved JSON Lines to {}", jsonl_file);
+            report::message(Kind::Saved, format!("Saved JSON Lines to {}", jsonl_file));
+        }
+    }
+
+    if let Some(csv_file) = exports.csv.as_deref() {
         match csv::Writer::from_path(csv_file) {
             Ok(mut wtr) => {
-                if let Err(e) = dirs.iter().try_for_each(|d| wtr.serialize(d)) {
+                let processed = dirs.iter().map(|d| CsvRow {
+                    path: &d.path,
+                    size_bytes: Some(d.size_bytes),
+                    age_days: d.age_days,
+                    item_count: d.item_count,
+                    status: "processed",
+                    reason: String::new(),
+                });
+                let tracked = inventory.iter().map(|d| CsvRow {
+                    path: &d.path,
+                    size_bytes: Some(d.size_bytes),
+                    age_days: d.age_days,
+                    item_count: d.item_count,
+                    status: "report-only",
+                    reason: String::new(),
+                });
+                let skipped = summary.skipped.iter().map(|s| CsvRow {
+                    path: &s.path,
+                    size_bytes: None,
+                    age_days: None,
+                    item_count: None,
+                    status: "skipped",
+                    reason: format!("{}: {}", s.reason, s.detail),
+                });
+                if let Err(e) = processed.chain(tracked).chain(skipped).try_for_each(|row| wtr.serialize(row)) {
                     error!("CSV export error: {}", e);
-                    eprintln!("{} {}", 
-                        CROSS,
-                        red().apply_to(format!("CSV export error: {}", e))
-                    );
+                    report::message(Kind::Error, format!("CSV export error: {}", e));
                 } else {
                     info!("Saved CSV summary to {}", csv_file);
-                    println!("{} {}", 
-                        DISK,
-                        green().apply_to(format!("Saved CSV summary to {}", csv_file))
-                    );
+                    report::message(Kind::Saved, format!("Saved CSV summary to {}", csv_file));
                 }
             }
             Err(e) => {
                 error!("CSV creation error: {}", e);
-                eprintln!("{} {}", 
-                    CROSS,
-                    red().apply_to(format!("CSV creation error: {}", e))
-                );
+                report::message(Kind::Error, format!("CSV creation error: {}", e));
             }
         }
     }
+
+    if let Some(pdf_file) = exports.pdf.as_deref() {
+        if let Err(e) = pdf::write(pdf_file, dirs, inventory, &summary.skipped, backup_paths, &summary.timestamp) {
+            error!("PDF export error: {}", e);
+            report::message(Kind::Error, format!("PDF export error: {}", e));
+        } else {
+            info!("Saved PDF report to {}", pdf_file);
+            report::message(Kind::Saved, format!("Saved PDF report to {}", pdf_file));
+        }
+    }
+
+    if let Some(treemap_file) = exports.treemap.as_deref() {
+        export_treemap(treemap_file, dirs, inventory);
+    }
     
     Ok(())
 }
 
+fn export_treemap(treemap_file: &str, dirs: &[DirInfo], inventory: &[DirInfo]) {
+    if let Err(e) = fs::write(treemap_file, treemap::svg(dirs, inventory)) {
+        error!("Treemap export error: {}", e);
+        report::message(Kind::Error, format!("Treemap export error: {}", e));
+    } else {
+        info!("Saved treemap to {}", treemap_file);
+        report::message(Kind::Saved, format!("Saved treemap to {}", treemap_file));
+    }
+}
+
 fn confirm_deletion(phrase: Option<&String>) -> Result<bool, String> {
     let default_phrase = "DELETE".to_string();
     let phrase = phrase.unwrap_or(&default_phrase);
@@ -580,12 +2033,12 @@ fn interactive_select_directories(dirs: &[DirInfo]) -> Vec<DirInfo> {
         }
         
         println!("\n[{}/{}] Directory: {}", i+1, dirs.len(), bold().apply_to(&dir.path));
-        println!("   Size: {:.2} MB", dir.size_bytes as f64 / 1024.0 / 1024.0);
+        println!("   Size: {}", dir.size_label());
         if let Some(age) = dir.age_days {
             println!("   Age: {} days", age);
         }
         if let Some(count) = dir.item_count {
-            println!("   Items: {}", count);
+            println!("   Items: {}", locale::count(count));
         }
         
         print!("Select? (y/n/a/q): ");
@@ -608,43 +2061,633 @@ fn interactive_select_directories(dirs: &[DirInfo]) -> Vec<DirInfo> {
             },
             "q" => {
                 println!("🛑 Selection canceled");
+                for dir in &dirs[i..] {
+                    skipped::record(&dir.path, SkipReason::UserDeclined, "selection canceled");
+                }
                 break;
             },
-            _ => println!("❌ Skipped"),
+            _ => {
+                skipped::record(&dir.path, SkipReason::UserDeclined, "not selected");
+                println!("❌ Skipped");
+            }
+        }
+    }
+    
+    selected
+}
+
+fn setup_logger(log_file: Option<&str>, verbose: bool) -> Result<(), String> {
+    let mut builder = env_logger::Builder::new();
+    
+    // Set log level based on verbose flag
+    builder.filter_level(if verbose { 
+        log::LevelFilter::Debug
+    } else {
+        log::LevelFilter::Info
+    });
+    
+    // Format for standard output
+    builder.format_timestamp(None);
+    builder.format_module_path(false);
+    
+    // Add file logger if specified
+    if let Some(log_path) = log_file {
+        let file = fs::File::create(log_path)
+            .map_err(|e| format!("{} Failed to create log file: {}", CROSS, e))?;
+            
+        builder.target(env_logger::Target::Pipe(Box::new(file)));
+    }
+    
+    builder.init();
+    
+    Ok(())
+}
+
+// Print the token that confirms applying this plan, alone so scripts can capture it
+fn run_plan_token(matches: &clap::ArgMatches) -> Result<(), String> {
+    let plan = plan::load_plan(matches.get_one::<String>("plan").unwrap())?;
+    println!("{}", plan.confirm_token()?);
+    Ok(())
+}
+
+fn run_plan_sign(matches: &clap::ArgMatches) -> Result<(), String> {
+    let plan_path = matches.get_one::<String>("plan").unwrap();
+    let key = plan::read_key(matches.get_one::<String>("key").unwrap())?;
+    let signer = matches.get_one::<String>("signer")
+        .cloned()
+        .or_else(|| std::env::var("USER").ok())
+        .or_else(|| std::env::var("USERNAME").ok())
+        .ok_or_else(|| format!("{} Could not determine signer name, use --signer", CROSS))?;
+
+    let mut plan = plan::load_plan(plan_path)?;
+    plan.sign(&signer, &key)?;
+    plan::save_plan(&plan, plan_path)?;
+
+    info!("{} signed plan {}", signer, plan_path);
+    report::message(Kind::Success, format!(
+        "Signed {} as {} ({} signature(s))", plan_path, signer, plan.signatures.len()
+    ));
+    Ok(())
+}
+
+fn run_backups_find(matches: &clap::ArgMatches) -> Result<(), String> {
+    let pattern = matches.get_one::<String>("pattern").unwrap();
+    let backup_dirs: Vec<String> = matches.get_many::<String>("backup-dir")
+        .map(|dirs| dirs.cloned().collect())
+        .unwrap_or_default();
+
+    let template = matches.get_one::<String>("backup-name-template").map(|template| naming::NameTemplate::parse(template)).transpose()?;
+
+    let found = backup::find_in_backups(pattern, &backup_dirs, template.as_ref())?;
+    if found.is_empty() {
+        report::message(Kind::Info, format!("No backups contain '{}'", pattern));
+        return Ok(());
+    }
+
+    report::message(Kind::Success, format!("{} match(es) for '{}':", found.len(), pattern));
+    report::items(&found.iter().map(|m| format!("{} {}", m.backup.display(), m.entry)).collect::<Vec<_>>());
+    Ok(())
+}
+
+fn run_apply(matches: &clap::ArgMatches) -> Result<(), String> {
+    let plan_path = matches.get_one::<String>("plan").unwrap();
+    let allow_changed = matches.get_flag("allow-changed");
+    let yes = matches.get_flag("yes");
+    let dry_run = matches.get_flag("dry-run");
+    let verbose = matches.get_flag("verbose");
+    report::init(OutputStyle::default(), verbose, false);
+    locale::init(None, true)?;
+
+    let config = matches.get_one::<String>("config")
+        .map(|config_path| load_config(config_path))
+        .transpose()?
+        .unwrap_or_default();
+    protected::init(config.protected.as_deref().unwrap_or_default(), &own_state(&config, matches.get_one::<String>("config")));
+
+    let plan = plan::load_plan(plan_path)?;
+    // A token issued for another plan must not confirm this one
+    let token = matches.get_one::<String>("confirm-token");
+    if let Some(token) = token
+        && *token != plan.confirm_token()?
+    {
+        return Err(format!("{} --confirm-token doesn't match {}; get its token with `dirpurge plan token {}`", CROSS, plan_path, plan_path));
+    }
+    let allow_dangerous_root = matches.get_flag("allow-dangerous-root");
+    let allow_outside_root = matches.get_flag("allow-outside-root");
+    if !dry_run && !allow_dangerous_root
+        && let Some(what) = protected::dangerous_root(Path::new(&plan.base_path))
+    {
+        return Err(format!("{} Plan was made in {}, which is {}; pass --allow-dangerous-root to apply it", CROSS, plan.base_path, what));
+    }
+    report::message(Kind::Setting, format!(
+        "Applying plan {} created {} ({} directories)",
+        plan_path, locale::date(&plan.created_at), plan.directories.len()
+    ));
+
+    plan::check_approvals(&plan, config.approval.as_ref())?;
+
+    let sudo_helper = matches.get_one::<String>("sudo-helper").cloned();
+    let elevate = matches.get_flag("elevate");
+    let (dirs, elevated) = permissions::partition(plan::revalidate(&plan, allow_changed), sudo_helper.as_deref(), elevate);
+    if dirs.is_empty() && elevated.is_empty() {
+        report::message(Kind::Info, "No directories left to apply");
+        return Ok(());
+    }
+
+    let confirmed = if yes || token.is_some() || dry_run {
+        true
+    } else {
+        confirm_deletion(None)?
+    };
+    if !confirmed {
+        report::message(Kind::Info, "Operation canceled");
+        return Ok(());
+    }
+
+    // Hand the whole plan to the elevated run, which re-validates it itself
+    if !elevated.is_empty() && !dry_run {
+        let mut args = Vec::new();
+        if allow_changed {
+            args.push("--allow-changed".to_string());
+        }
+        if verbose {
+            args.push("--verbose".to_string());
+        }
+        if allow_dangerous_root {
+            args.push("--allow-dangerous-root".to_string());
+        }
+        if allow_outside_root {
+            args.push("--allow-outside-root".to_string());
+        }
+        if let Some(config_path) = matches.get_one::<String>("config") {
+            let config_path = fs::canonicalize(config_path)
+                .map_err(|e| format!("{} Cannot resolve config {}: {}", CROSS, config_path, e))?;
+            args.extend(["--config".to_string(), config_path.to_string_lossy().into_owned()]);
+        }
+        if let Some(commands) = plan.stop_daemons.as_ref().filter(|_| !matches.get_flag("skip-daemons")) {
+            daemons::stop_daemons(&[dirs, elevated].concat(), commands, false);
+        }
+        return permissions::apply_elevated(Path::new(plan_path), &plan, &args);
+    }
+
+    let mut delete_options = DeleteOptions { sudo_helper, ..plan.delete_options(dry_run, verbose) };
+    if matches.get_flag("skip-daemons") {
+        delete_options.stop_daemons = None;
+    }
+    if allow_outside_root {
+        delete_options.root = None;
+    }
+    if !dry_run {
+        journal::checkpoint_every(config.checkpoint_interval);
+        journal::begin(&journal::resolve(config.journal.as_deref())?, &plan::Plan::new(&plan.base_path, &dirs, &delete_options))?;
+    }
+    delete_directories(&dirs, &delete_options)?;
+    if !elevated.is_empty() {
+        report::message(Kind::DryRun, format!("[Dry Run] Would apply {} directories in an elevated run", elevated.len()));
+    }
+
+    Ok(())
+}
+
+// Finish a run that was interrupted part-way, from its journal
+fn run_resume(matches: &clap::ArgMatches) -> Result<(), String> {
+    let yes = matches.get_flag("yes");
+    let dry_run = matches.get_flag("dry-run");
+    let verbose = matches.get_flag("verbose");
+    report::init(OutputStyle::default(), verbose, false);
+    locale::init(None, true)?;
+
+    let journal_path = journal::resolve(matches.get_one::<String>("journal").map(String::as_str))?;
+    let entries = journal::read(&journal_path)?;
+    let Some(run) = journal::interrupted(&entries, matches.get_one::<String>("run").map(String::as_str)) else {
+        report::message(Kind::Info, format!("No interrupted run to resume in {}", journal_path.display()));
+        return Ok(());
+    };
+    // The plan's paths are relative to where the run was started
+    if let Some(cwd) = &run.cwd {
+        std::env::set_current_dir(cwd)
+            .map_err(|e| format!("{} Cannot enter {}, where run {} was started: {}", CROSS, cwd.display(), run.run, e))?;
+    }
+
+    let dirs: Vec<DirInfo> = run.plan.directories.iter()
+        .filter(|dir| !run.done.contains(&dir.path))
+        .filter(|dir| {
+            // Gone entirely, the deletion finished but wasn't recorded,
+            // or left only what it had set aside to remove
+            let exists = Path::new(&dir.path).symlink_metadata().is_ok();
+            if !exists {
+                report::detail(Kind::Info, format!("Already gone: {}", dir.path));
+                if run.deleting.contains(&dir.path) && !dry_run {
+                    deleting::finish(Path::new(&dir.path).parent().filter(|parent| !parent.as_os_str().is_empty()).unwrap_or(Path::new(".")));
+                }
+            }
+            exists
+        })
+        .cloned()
+        .collect();
+    let copying = dirs.iter().filter(|dir| run.copying.contains_key(&dir.path)).count();
+    let deleting = dirs.iter().filter(|dir| run.deleting.contains(&dir.path)).count();
+    let part_done = [(copying, "part-copied"), (deleting, "part-removed")].iter()
+        .filter(|(n, _)| *n > 0)
+        .map(|(n, state)| format!(", {} {}", locale::count(*n), state))
+        .collect::<String>();
+    report::message(Kind::Setting, format!(
+        "Resuming run {} from {} ({} of {} directories left{})",
+        run.run, locale::date(&run.plan.created_at), dirs.len(), run.plan.directories.len(), part_done
+    ));
+    if dirs.is_empty() {
+        if !dry_run {
+            journal::resume(&journal_path, &run.run)?;
+            journal::record(Event::Finish);
+        }
+        report::message(Kind::Success, "Nothing left to delete");
+        return Ok(());
+    }
+    report::items(&dirs.iter().map(|dir| dir.path.clone()).collect::<Vec<_>>());
+    for dir in &dirs {
+        if run.deleting.contains(&dir.path) {
+            report::detail(Kind::Info, format!("Finishing the removal of {}", dir.path));
+        } else if let Some(partial) = run.copying.get(&dir.path) {
+            let (files, bytes) = run.checkpoints.get(&dir.path).copied().unwrap_or_default();
+            report::detail(Kind::Info, format!(
+                "Continuing the copy of {} in {} ({} files, {} at the last checkpoint)",
+                dir.path, partial.join(", "), locale::count(files as usize), format_mb(bytes, false)
+            ));
+        }
+    }
+
+    let confirmed = if yes || dry_run {
+        true
+    } else {
+        confirm_deletion(None)?
+    };
+    if !confirmed {
+        report::message(Kind::Info, "Operation canceled");
+        return Ok(());
+    }
+
+    let delete_options = DeleteOptions { backed_up: run.backed_up, copying: run.copying, ..run.plan.delete_options(dry_run, verbose) };
+    if !dry_run {
+        journal::checkpoint_every(matches.get_one::<u64>("checkpoint-interval").copied());
+        journal::resume(&journal_path, &run.run)?;
+    }
+    delete_directories(&dirs, &delete_options)?;
+    Ok(())
+}
+
+// Unpack an archive dirpurge made back where its directory was
+fn run_unarchive(matches: &clap::ArgMatches) -> Result<(), String> {
+    let dry_run = matches.get_flag("dry-run");
+    report::init(OutputStyle::default(), matches.get_flag("verbose"), false);
+    locale::init(None, true)?;
+
+    let archive_path = Path::new(matches.get_one::<String>("archive").unwrap());
+    if archive::ArchiveFormat::of(archive_path).is_none() || !archive_path.is_file() {
+        return Err(format!("{} {} is not a .zip, .tar.gz or .tar.zst archive", CROSS, archive_path.display()));
+    }
+    let dest = match matches.get_one::<String>("to") {
+        Some(to) => restore::absolute(Path::new(to))?,
+        None => archive::source(archive_path)?
+            .map(PathBuf::from)
+            .ok_or_else(|| format!("{} {} doesn't record where it came from, pass --to DIR", CROSS, archive_path.display()))?,
+    };
+    if dest.symlink_metadata().is_ok() {
+        return Err(format!("{} {} already exists, pass --to DIR to unpack elsewhere", CROSS, dest.display()));
+    }
+    if dry_run {
+        report::message(Kind::DryRun, format!("[Dry Run] Would unpack {} into {}", archive_path.display(), dest.display()));
+        return Ok(());
+    }
+
+    // Like a failed restore, a failed unpack isn't left half-done
+    let files = archive::extract(archive_path, &dest).inspect_err(|_| {
+        let _ = fs::remove_dir_all(&dest);
+    })?;
+    info!("Unpacked {} files from {} into {}", files, archive_path.display(), dest.display());
+    report::message(Kind::Success, format!("Unpacked {} files from {} into {}", locale::count(files), archive_path.display(), dest.display()));
+    Ok(())
+}
+
+// Bring back one directory an earlier run removed
+fn run_restore(matches: &clap::ArgMatches) -> Result<(), String> {
+    let dry_run = matches.get_flag("dry-run");
+    report::init(OutputStyle::default(), matches.get_flag("verbose"), false);
+    locale::init(None, true)?;
+
+    let path = restore::absolute(Path::new(matches.get_one::<String>("path").unwrap()))?;
+    let to = matches.get_one::<String>("to").map(|to| restore::absolute(Path::new(to))).transpose()?;
+    let journal_path = journal::resolve(matches.get_one::<String>("journal").map(String::as_str))?;
+    let entries = journal::read(&journal_path)?;
+    let removed = journal::last_removal(&entries, &path, matches.get_one::<String>("run").map(String::as_str))
+        .ok_or_else(|| format!("{} The journal {} has no record of removing {}", CROSS, journal_path.display(), path.display()))?;
+    report::message(Kind::Setting, format!("Restoring {} (removed {} by run {})", removed.path.display(), locale::date(&removed.removed_at), removed.run));
+
+    let dest = to.unwrap_or_else(|| removed.path.clone());
+    let from = restore::restore(&removed, &dest, dry_run)?;
+    if !dry_run {
+        restore::record(&journal_path, &removed, &from, &dest)?;
+        info!("Restored {} from {}", dest.display(), from);
+        report::message(Kind::Success, format!("Restored {} from {}", dest.display(), from));
+    }
+    Ok(())
+}
+
+// `trash list` and `trash restore`, for what runs moved to the trash
+fn run_trash(matches: &clap::ArgMatches) -> Result<(), String> {
+    report::init(OutputStyle::default(), false, false);
+    locale::init(None, true)?;
+    let (command, matches) = matches.subcommand().expect("subcommand required");
+    let journal_path = journal::resolve(matches.get_one::<String>("journal").map(String::as_str))?;
+    match command {
+        "list" => trashed::list(&journal_path),
+        "restore" => {
+            let path = restore::absolute(Path::new(matches.get_one::<String>("path").unwrap()))?;
+            let to = matches.get_one::<String>("to").map(|to| restore::absolute(Path::new(to))).transpose()?;
+            trashed::restore(&journal_path, &path, to.as_deref(), matches.get_flag("dry-run"))
         }
+        _ => unreachable!("subcommand required"),
+    }
+}
+
+// Remove one named directory with the same guardrails as a scan, minus the scan
+fn run_purge_path(matches: &clap::ArgMatches) -> Result<(), String> {
+    let path = matches.get_one::<String>("path").unwrap();
+    let mut config = matches.get_one::<String>("config")
+        .map(|config_path| load_config(config_path))
+        .transpose()?
+        .unwrap_or_default();
+    if matches.get_flag("use-trash") {
+        config.use_trash = Some(true);
+    }
+    if matches.get_flag("backup") {
+        config.backup = Some(true);
+    }
+    if matches.get_flag("archive") {
+        config.archive = Some(true);
+    }
+    if let Some(format) = matches.get_one::<archive::ArchiveFormat>("archive-format") {
+        config.archive_format = Some(*format);
+    }
+    if let Some(level) = matches.get_one::<i32>("compression-level") {
+        config.compression_level = Some(*level);
+    }
+    if matches.get_flag("archive-manifest") {
+        config.archive_manifest = Some(true);
+    }
+    if matches.get_flag("archive-top-dir") {
+        config.archive_top_dir = Some(true);
+    }
+    if let Some(size) = matches.get_one::<String>("archive-split-size") {
+        config.archive_split_size = Some(size.clone());
+    }
+    if let Some(patterns) = matches.get_many::<String>("backup-exclude") {
+        config.backup_exclude = Some(patterns.cloned().collect());
+    }
+    if let Some(rules) = matches.get_many::<String>("s3-storage-class") {
+        config.s3_storage_class = Some(rules.cloned().collect());
+    }
+    if let Some(template) = matches.get_one::<String>("backup-name-template") {
+        config.backup_name_template = Some(template.clone());
+    }
+    if let Some(preserve) = matches.get_one::<preserve::Preserve>("backup-preserve") {
+        config.backup_preserve = Some(*preserve);
+    }
+    if let Some(strategy) = matches.get_one::<backup::BackupStrategy>("backup-strategy") {
+        config.backup_strategy = Some(*strategy);
+    }
+    if let Some(backup_dirs) = matches.get_many::<String>("backup-dir") {
+        let backup_dirs: Vec<String> = backup_dirs.cloned().collect();
+        config.backup_dir = backup_dirs.first().cloned();
+        config.backup_dirs = (backup_dirs.len() > 1).then_some(backup_dirs);
+    }
+    if let Some(verify_sample) = matches.get_one::<f64>("verify-sample") {
+        config.verify_sample = Some(*verify_sample);
+    }
+    if matches.get_flag("verify-archive") {
+        config.verify_archive = Some(true);
+    }
+    if matches.get_flag("skip-space-check") {
+        config.skip_space_check = Some(true);
+    }
+    if let Some(hash_algo) = matches.get_one::<hash::HashAlgo>("hash-algo") {
+        config.hash_algo = Some(*hash_algo);
+    }
+    if let Some(prune_backups) = matches.get_one::<prune::RetentionPolicy>("prune-backups") {
+        config.prune_backups = Some(*prune_backups);
+    }
+    if let Some(entries) = matches.get_one::<usize>("chunk-above") {
+        config.chunk_above = Some(*entries);
+    }
+    if let Some(journal) = matches.get_one::<String>("journal") {
+        config.journal = Some(journal.clone());
+    }
+    if let Some(secs) = matches.get_one::<u64>("checkpoint-interval") {
+        config.checkpoint_interval = Some(*secs);
+    }
+    if let Some(secs) = matches.get_one::<u64>("heartbeat") {
+        config.heartbeat = Some(*secs);
+    }
+    if let Some(url) = matches.get_one::<String>("heartbeat-webhook") {
+        config.heartbeat_webhook = Some(url.clone());
+    }
+    if let Some(json) = matches.get_one::<String>("json") {
+        config.json = Some(json.clone());
+    }
+    if let Some(yaml) = matches.get_one::<String>("yaml") {
+        config.yaml = Some(yaml.clone());
+    }
+    if let Some(jsonl) = matches.get_one::<String>("jsonl") {
+        config.jsonl = Some(jsonl.clone());
+    }
+    if let Some(csv) = matches.get_one::<String>("csv") {
+        config.csv = Some(csv.clone());
+    }
+    if let Some(pdf) = matches.get_one::<String>("pdf") {
+        config.pdf = Some(pdf.clone());
+    }
+    if let Some(treemap) = matches.get_one::<String>("treemap") {
+        config.treemap = Some(treemap.clone());
+    }
+    if let Some(paths) = matches.get_many::<String>("protect") {
+        config.protected.get_or_insert_with(Vec::new).extend(paths.cloned());
     }
-    
-    selected
-}
+    if matches.get_flag("allow-dangerous-root") {
+        config.allow_dangerous_root = Some(true);
+    }
+    let yes = matches.get_flag("yes");
+    let dry_run = matches.get_flag("dry-run");
+    let verbose = matches.get_flag("verbose");
+    report::init(OutputStyle::default(), verbose, false);
+    locale::init(config.report_locale.as_deref(), true)?;
+    protected::init(config.protected.as_deref().unwrap_or_default(), &own_state(&config, matches.get_one::<String>("config")));
 
-fn setup_logger(log_file: Option<&str>, verbose: bool) -> Result<(), String> {
-    let mut builder = env_logger::Builder::new();
-    
-    // Set log level based on verbose flag
-    builder.filter_level(if verbose { 
-        log::LevelFilter::Debug
+    let use_trash = config.use_trash.unwrap_or(false);
+    let archive = config.archive.unwrap_or(false);
+    let archive_format = config.archive_format.unwrap_or_default();
+    if let Some(level) = config.compression_level {
+        archive_format.check_level(level)?;
+    }
+    #[cfg(not(feature = "trash"))]
+    if use_trash {
+        return Err(feature_disabled("trash"));
+    }
+    #[cfg(not(feature = "archive"))]
+    if archive {
+        return Err(feature_disabled("archive"));
+    }
+    #[cfg(feature = "s3")]
+    storage_class::StorageClasses::new(config.s3_storage_class.as_deref().unwrap_or_default())?;
+    #[cfg(not(feature = "s3"))]
+    if config.s3_storage_class.as_ref().is_some_and(|rules| !rules.is_empty()) {
+        return Err(feature_disabled("s3"));
+    }
+    check_tools(&mut config)?;
+
+    let dir = Path::new(path);
+    let metadata = dir.symlink_metadata()
+        .map_err(|e| format!("{} Cannot read {}: {}", CROSS, path, e))?;
+    // Removing a link would leave what it points to, removing through it would surprise
+    if metadata.file_type().is_symlink() {
+        return Err(format!("{} {} is a symlink, name the directory it points to instead", CROSS, path));
+    }
+    if !metadata.is_dir() {
+        return Err(format!("{} {} is not a directory", CROSS, path));
+    }
+    if !dry_run && !config.allow_dangerous_root.unwrap_or(false)
+        && let Some(what) = protected::dangerous_root(dir)
+    {
+        return Err(format!("{} {} is {}, pass --allow-dangerous-root to delete it", CROSS, path, what));
+    }
+
+    // No targets or filters: the directory was chosen by name
+    let scan_options = ScanOptions {
+        rules: Rules::default(),
+        depth: None,
+        min_depth: None,
+        age_source: AgeSource::default(),
+        follow_symlinks_traversal: false,
+        count_symlinked_sizes: false,
+        one_file_system: false,
+        count_hardlinks: false,
+        size_mode: SizeMode::default(),
+        estimate: false,
+        reclaimable: false,
+        mounts: Vec::new(),
+        respect_ignore: false,
+        prune: true,
+        manifests: None,
+        actions: HashMap::new(),
+        keep_latest: None,
+        reserved: reserved::Reserved::default(),
+        verbose,
+    };
+    let Some(info) = analyze_directory(dir, &scan_options, None) else {
+        return Err(format!("{} Refusing to purge {}", CROSS, path));
+    };
+
+    report::message(Kind::Setting, format!("Purging: {}", path));
+    report::detail(Kind::Setting, format!("Mode: {}", if dry_run { "DRY RUN" } else { "DELETE" }));
+    let dirs = vec![info];
+    report::found(&dirs, &[], false);
+
+    let delete_options = DeleteOptions {
+        dry_run,
+        verbose,
+        use_trash,
+        backup: config.backup.unwrap_or(false),
+        archive,
+        archive_format,
+        compression_level: config.compression_level,
+        archive_manifest: config.archive_manifest.unwrap_or(false),
+        archive_top_dir: config.archive_top_dir.unwrap_or(false),
+        archive_split_size: config.archive_split_size.as_deref().map(units::parse_volume_size).transpose()?,
+        backup_exclude: config.backup_exclude.clone().unwrap_or_default(),
+        s3_storage_class: config.s3_storage_class.clone().unwrap_or_default(),
+        backup_name_template: config.backup_name_template.clone(),
+        backup_preserve: config.backup_preserve.unwrap_or_default(),
+        backup_strategy: config.backup_strategy.unwrap_or_default(),
+        backup_dirs: config.backup_dirs.clone()
+            .or_else(|| config.backup_dir.clone().map(|dir| vec![dir]))
+            .unwrap_or_else(|| vec!["./backups".to_string()]),
+        backup_policy: config.backup_policy.unwrap_or_default(),
+        prune_backups: config.prune_backups,
+        upload_limit: config.upload_limit.as_deref().map(units::parse_rate).transpose()?,
+        readahead: config.readahead.unwrap_or(false),
+        incremental: config.incremental.unwrap_or(false),
+        verify_sample: config.verify_sample,
+        verify_archive: config.verify_archive.unwrap_or(false),
+        skip_space_check: config.skip_space_check.unwrap_or(false),
+        hash_algo: config.hash_algo.unwrap_or_default(),
+        respect_locks: !config.ignore_locks.unwrap_or(false),
+        check_processes: !config.ignore_processes.unwrap_or(false),
+        git_safe: config.git_safe.unwrap_or(false),
+        jobs: 1,
+        chunk_above: config.chunk_above,
+        heartbeat: config.heartbeat.map(Duration::from_secs),
+        heartbeat_webhook: config.heartbeat_webhook.clone(),
+        ..DeleteOptions::default()
+    };
+
+    let confirmed = if yes || dry_run {
+        true
     } else {
-        log::LevelFilter::Info
-    });
-    
-    // Format for standard output
-    builder.format_timestamp(None);
-    builder.format_module_path(false);
-    
-    // Add file logger if specified
-    if let Some(log_path) = log_file {
-        let file = fs::File::create(log_path)
-            .map_err(|e| format!("{} Failed to create log file: {}", CROSS, e))?;
-            
-        builder.target(env_logger::Target::Pipe(Box::new(file)));
+        confirm_deletion(config.confirm_phrase.as_ref())?
+    };
+    if !confirmed {
+        report::message(Kind::Info, "Operation canceled");
+        return Ok(());
+    }
+
+    if !dry_run {
+        journal::checkpoint_every(config.checkpoint_interval);
+        journal::begin(&journal::resolve(config.journal.as_deref())?, &plan::Plan::new(path, &dirs, &delete_options))?;
+    }
+    let backup_paths = delete_directories(&dirs, &delete_options)?;
+    let exports = Exports::of(&config);
+    if exports.any() {
+        let rules = policy::effective(path, &scan_options);
+        export_summary(&dirs, &[], &exports, &backup_paths, &rules)?;
     }
-    
-    builder.init();
-    
     Ok(())
 }
 
-fn main() -> Result<(), String> {
+// Write the directories that need elevation to a plan and apply it in an elevated run
+fn apply_elevated_plan(base_path: &str, dirs: &[DirInfo], opts: &DeleteOptions) -> Result<(), String> {
+    if opts.dry_run {
+        report::message(Kind::DryRun, format!("[Dry Run] Would apply {} directories in an elevated run", dirs.len()));
+        return Ok(());
+    }
+
+    if let Some(commands) = &opts.stop_daemons {
+        daemons::stop_daemons(dirs, commands, false);
+    }
+    let plan = plan::Plan::new(base_path, dirs, opts);
+    let (plan_dir, plan_path) = permissions::write_private_plan(&plan)?;
+    let args = if opts.verbose { vec!["--verbose".to_string()] } else { Vec::new() };
+    let result = permissions::apply_elevated(&plan_path, &plan, &args);
+    let _ = fs::remove_dir_all(&plan_dir);
+    result
+}
+
+// Set by --check when there is something to purge
+static ACTION_NEEDED: AtomicBool = AtomicBool::new(false);
+
+// 0 when everything went as asked, 1 when the run stopped on an error, 2 when
+// it finished but some directories couldn't be removed, 4 when --check found
+// directories to purge
+fn main() -> std::process::ExitCode {
+    match run() {
+        Ok(()) if skipped::any_failed() => std::process::ExitCode::from(2),
+        Ok(()) if ACTION_NEEDED.load(Ordering::Relaxed) => std::process::ExitCode::from(4),
+        Ok(()) => std::process::ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Error: {:?}", e);
+            std::process::ExitCode::FAILURE
+        }
+    }
+}
+
+fn run() -> Result<(), String> {
     let matches = Command::new("🧹 dirpurge")
         .version("1.0.0")
         .about("Advanced directory cleanup tool with safety features")
@@ -653,8 +2696,17 @@ fn main() -> Result<(), String> {
         )
         .arg(Arg::new("path")
             .help("📁 Base directory to search")
-            .required(true)
+            .required_unless_present_any(["paths-from", "purge-list"])
             .index(1))
+        .arg(Arg::new("paths-from")
+            .long("paths-from")
+            .help("📥 Process the directories listed in FILE (one per line, '-' for stdin) instead of searching")
+            .value_name("FILE"))
+        .arg(Arg::new("purge-list")
+            .long("purge-list")
+            .help("🧾 Delete the directories listed in FILE (one per line, '#' comments), filters acting as sanity checks")
+            .value_name("FILE")
+            .conflicts_with_all(["paths-from", "path"]))
         .arg(Arg::new("target")
             .short('t')
             .long("target")
@@ -671,18 +2723,126 @@ fn main() -> Result<(), String> {
             .long("depth")
             .help("📏 Maximum search depth (0 = unlimited)")
             .value_parser(clap::value_parser!(usize)))
+        .arg(Arg::new("min-depth")
+            .long("min-depth")
+            .help("📏 Minimum depth of a match below the search path (shallower directories are still searched)")
+            .value_parser(clap::value_parser!(usize)))
         .arg(Arg::new("min-size")
             .long("min-size")
             .help("📦 Minimum directory size in MB to include")
             .value_parser(clap::value_parser!(f64)))
+        .arg(Arg::new("max-size")
+            .long("max-size")
+            .help("📦 Maximum directory size in MB to include")
+            .value_parser(clap::value_parser!(f64)))
+        .arg(Arg::new("min-items")
+            .long("min-items")
+            .help("🧮 Minimum number of entries inside a directory to include")
+            .value_parser(clap::value_parser!(usize)))
+        .arg(Arg::new("max-items")
+            .long("max-items")
+            .help("🧮 Maximum number of entries inside a directory to include")
+            .value_parser(clap::value_parser!(usize)))
         .arg(Arg::new("min-age")
             .long("min-age")
             .help("📅 Minimum age in days to include")
             .value_parser(clap::value_parser!(i64)))
-        .arg(Arg::new("follow-symlinks")
-            .long("follow-symlinks")
+        .arg(Arg::new("max-age")
+            .long("max-age")
+            .help("📅 Maximum age in days to include")
+            .value_parser(clap::value_parser!(i64)))
+        .arg(Arg::new("modified-before")
+            .long("modified-before")
+            .help("📆 Only include directories last modified before this date (YYYY-MM-DD or RFC 3339)")
+            .value_name("DATE")
+            .value_parser(|v: &str| units::parse_date(v).map(|_| v.to_string())))
+        .arg(Arg::new("modified-after")
+            .long("modified-after")
+            .help("📆 Only include directories last modified after this date (YYYY-MM-DD or RFC 3339)")
+            .value_name("DATE")
+            .value_parser(|v: &str| units::parse_date(v).map(|_| v.to_string())))
+        .arg(Arg::new("age-source")
+            .long("age-source")
+            .help("📅 Age directories by their newest content (default) or their own dir-mtime")
+            .value_name("SOURCE")
+            .value_parser(clap::value_parser!(AgeSource)))
+        .arg(Arg::new("min-atime-age")
+            .long("min-atime-age")
+            .help("👀 Only include directories where nothing was accessed for this many days")
+            .value_name("DAYS")
+            .value_parser(clap::value_parser!(i64)))
+        .arg(Arg::new("repo-inactive-days")
+            .long("repo-inactive-days")
+            .help("🌿 Only include directories in git repositories without a commit for this many days")
+            .value_name("DAYS")
+            .value_parser(clap::value_parser!(i64)))
+        .arg(Arg::new("keep-latest")
+            .long("keep-latest")
+            .help("🗃  Always keep the N most recently modified matches in each parent directory")
+            .value_name("N")
+            .value_parser(clap::value_parser!(usize)))
+        .arg(Arg::new("owned-by-me")
+            .long("owned-by-me")
+            .help("👤 Only include directories owned by the current user")
+            .action(ArgAction::SetTrue)
+            .conflicts_with("owner"))
+        .arg(Arg::new("owner")
+            .long("owner")
+            .help("👤 Only include directories owned by this user name or uid")
+            .value_name("USER"))
+        .arg(Arg::new("follow-symlinks-traversal")
+            .long("follow-symlinks-traversal")
+            .visible_alias("follow-symlinks")
             .help("🔗 Follow symbolic links during search")
             .action(ArgAction::SetTrue))
+        .arg(Arg::new("count-symlinked-sizes")
+            .long("count-symlinked-sizes")
+            .help("🔗 Include the targets of symbolic links in directory sizes")
+            .action(ArgAction::SetTrue))
+        .arg(Arg::new("one-file-system")
+            .short('x')
+            .long("one-file-system")
+            .help("🧱 Don't cross filesystem boundaries (mount points)")
+            .action(ArgAction::SetTrue))
+        .arg(Arg::new("count-hardlinks")
+            .long("count-hardlinks")
+            .help("🔗 Count every hardlink to a file towards sizes (default: once)")
+            .action(ArgAction::SetTrue))
+        .arg(Arg::new("size-mode")
+            .long("size-mode")
+            .help("📐 Report apparent file length or allocated disk usage")
+            .value_name("MODE")
+            .value_parser(clap::value_parser!(SizeMode)))
+        .arg(Arg::new("estimate")
+            .long("estimate")
+            .help("🎲 Estimate sizes from a sample of files; exact sizes are still computed before deleting")
+            .action(ArgAction::SetTrue))
+        .arg(Arg::new("reclaimable")
+            .long("reclaimable")
+            .help("🧬 Also report how much deleting each directory frees, leaving out data shared with clones and snapshots (btrfs, XFS, APFS)")
+            .action(ArgAction::SetTrue))
+        .arg(Arg::new("respect-ignore")
+            .long("respect-ignore")
+            .help("🙈 Skip directories excluded by .gitignore/.ignore files and git's exclude files")
+            .action(ArgAction::SetTrue))
+        .arg(Arg::new("no-prune")
+            .long("no-prune")
+            .help("🌿 Keep searching inside matched directories for nested matches")
+            .action(ArgAction::SetTrue))
+        .arg(Arg::new("require-manifest")
+            .long("require-manifest")
+            .help("📜 Only match targets next to their project manifest (e.g. target + Cargo.toml)")
+            .action(ArgAction::SetTrue))
+        .arg(Arg::new("manifest")
+            .long("manifest")
+            .help("📜 Pair a target with a marker file for --require-manifest (multiple allowed)")
+            .value_name("TARGET=FILE")
+            .action(ArgAction::Append))
+        .arg(Arg::new("action")
+            .long("action")
+            .help("🎯 Per-target action: delete, trash, backup+delete, archive+delete or report-only (multiple allowed)")
+            .value_name("TARGET=ACTION")
+            .action(ArgAction::Append))
         .arg(Arg::new("delete")
             .long("delete")
             .help(format!("{} Perform deletion", TRASH))
@@ -697,10 +2857,24 @@ fn main() -> Result<(), String> {
             .long("dry-run")
             .help("🌵 Simulate operations without making changes")
             .action(ArgAction::SetTrue))
+        .arg(Arg::new("check")
+            .long("check")
+            .help("🚦 Only report how many directories match and their total size; exit with 4 if there are any")
+            .action(ArgAction::SetTrue)
+            .conflicts_with_all(["delete", "dry-run", "interactive"]))
         .arg(Arg::new("use-trash")
             .long("use-trash")
             .help("🗑  Move to trash instead of permanent deletion")
             .action(ArgAction::SetTrue))
+        .arg(Arg::new("quarantine")
+            .long("quarantine")
+            .help("⏳ Move to a quarantine directory instead of deleting, for `dirpurge quarantine purge` to remove later")
+            .action(ArgAction::SetTrue)
+            .conflicts_with_all(["use-trash", "purge-files-older-than"]))
+        .arg(Arg::new("quarantine-dir")
+            .long("quarantine-dir")
+            .help("📂 Quarantine directory (default: in dirpurge's state directory)")
+            .value_name("DIR"))
         .arg(Arg::new("backup")
             .short('b')
             .long("backup")
@@ -711,11 +2885,172 @@ fn main() -> Result<(), String> {
             .long("archive")
             .help("📦 Create zip archives before deletion")
             .action(ArgAction::SetTrue))
+        .arg(Arg::new("archive-format")
+            .long("archive-format")
+            .help("📦 Archive format for --archive: zip (default), tar.gz, which keeps permissions and symlinks, or the much faster tar.zst")
+            .value_name("FORMAT")
+            .value_parser(clap::value_parser!(archive::ArchiveFormat)))
+        .arg(Arg::new("compression-level")
+            .long("compression-level")
+            .help("🗜  Compression level for archives: 0-9 for zip and tar.gz, 1-22 for tar.zst")
+            .value_name("LEVEL")
+            .value_parser(clap::value_parser!(i32)))
+        .arg(Arg::new("archive-manifest")
+            .long("archive-manifest")
+            .help("🧾 Write each archive's manifest of files, sizes, times and SHA-256 beside it too")
+            .action(ArgAction::SetTrue))
+        .arg(Arg::new("archive-top-dir")
+            .long("archive-top-dir")
+            .help("📁 Store archive entries under the archived directory's name (node_modules/...)")
+            .action(ArgAction::SetTrue))
+        .arg(Arg::new("archive-split-size")
+            .long("archive-split-size")
+            .help("✂️  Split archives into volumes of this size (e.g. 2GB), named <archive>.001, .002, ...")
+            .value_name("SIZE"))
+        .arg(Arg::new("backup-exclude")
+            .long("backup-exclude")
+            .help("✂️ Leave paths matching this glob out of backups and archives (e.g. '*.o', '.git'); repeatable")
+            .value_name("PATTERN")
+            .action(ArgAction::Append)
+            .value_parser(|v: &str| backup::parse_exclude(v).map(|_| v.to_string())))
+        .arg(Arg::new("s3-storage-class")
+            .long("s3-storage-class")
+            .help("🧊 S3 storage class for uploaded archives: CLASS, TARGET=CLASS or >SIZE=CLASS (e.g. 'node_modules=GLACIER_IR', '>1GB=DEEP_ARCHIVE'); repeatable")
+            .value_name("RULE")
+            .action(ArgAction::Append)
+            .value_parser(check_storage_class))
+        .arg(Arg::new("backup-name-template")
+            .long("backup-name-template")
+            .help("🏷  Where backups go in each destination: {project}, {name}, {date}, {time} and {hash}, then .{ext} (e.g. '{project}/{name}-{date}-{hash}.{ext}')")
+            .value_name("TEMPLATE")
+            .value_parser(|v: &str| naming::NameTemplate::parse(v).map(|_| v.to_string())))
+        .arg(Arg::new("backup-preserve")
+            .long("backup-preserve")
+            .help("🪪 What plain backups keep of each file: all (default), none, or any of mode,owner,times,links")
+            .value_name("ASPECTS")
+            .value_parser(clap::value_parser!(preserve::Preserve)))
+        .arg(Arg::new("backup-strategy")
+            .long("backup-strategy")
+            .help("🧬 Clone files into plain backups by reflink where the filesystem can: auto (default), reflink or copy")
+            .value_name("STRATEGY")
+            .value_parser(clap::value_parser!(backup::BackupStrategy)))
         .arg(Arg::new("backup-dir")
             .long("backup-dir")
-            .help("📂 Directory for backups/archives")
+            .help("📂 Directory for backups/archives (multiple allowed)")
             .value_name("DIR")
+            .action(ArgAction::Append)
             .default_value("./backups"))
+        .arg(Arg::new("backup-policy")
+            .long("backup-policy")
+            .help("🗳  Backup destinations that must succeed: all, any or quorum=N")
+            .value_name("POLICY")
+            .value_parser(clap::value_parser!(backup::BackupPolicy)))
+        .arg(Arg::new("prune-backups")
+            .long("prune-backups")
+            .help("🧹 After the run, remove old backups beyond these limits, e.g. days=30,size=10GB,count=20")
+            .value_name("POLICY")
+            .value_parser(clap::value_parser!(prune::RetentionPolicy)))
+        .arg(Arg::new("upload-limit")
+            .long("upload-limit")
+            .help("🐢 Limit backup/archive write throughput (e.g. 10MB/s)")
+            .value_name("RATE")
+            .value_parser(|v: &str| units::parse_rate(v).map(|_| v.to_string())))
+        .arg(Arg::new("readahead")
+            .long("readahead")
+            .help("📖 Ask the OS to read files ahead of backing them up, faster on spinning disks")
+            .action(ArgAction::SetTrue))
+        .arg(Arg::new("incremental")
+            .long("incremental")
+            .help("🔗 Hardlink files unchanged since the previous backup of a directory instead of copying them")
+            .action(ArgAction::SetTrue))
+        .arg(Arg::new("free")
+            .long("free")
+            .help("🎯 Delete the largest matches only until this much space is freed (e.g. 20GB), keeping the rest")
+            .value_name("AMOUNT")
+            .value_parser(|v: &str| units::parse_size(v).map(|_| v.to_string())))
+        .arg(Arg::new("max-dirs")
+            .long("max-dirs")
+            .help("🧯 Never remove more than this many directories in one run")
+            .value_name("N")
+            .value_parser(clap::value_parser!(usize)))
+        .arg(Arg::new("max-total")
+            .long("max-total")
+            .help("🧯 Never remove more than this much in one run (e.g. 50GB)")
+            .value_name("SIZE")
+            .value_parser(|v: &str| units::parse_size(v).map(|_| v.to_string())))
+        .arg(Arg::new("purge-files-older-than")
+            .long("purge-files-older-than")
+            .help("🧹 Keep matched directories, deleting only files older than this many days and the subdirectories left empty")
+            .value_name("DAYS")
+            .value_parser(clap::value_parser!(i64).range(0..)))
+        .arg(Arg::new("chunk-above")
+            .long("chunk-above")
+            .help("🧱 Remove directories with more entries than this in batches, with progress and a clean stop on Ctrl-C (default: 100000)")
+            .value_name("ENTRIES")
+            .value_parser(clap::value_parser!(usize)))
+        .arg(Arg::new("jobs")
+            .long("jobs")
+            .short('j')
+            .help("⚡ Back up and delete this many directories at once (default: 1)")
+            .value_name("N")
+            .value_parser(clap::value_parser!(u16).range(1..)))
+        .arg(Arg::new("verify-sample")
+            .long("verify-sample")
+            .help("🔬 Hash-check a random sample of backed up files before deletion (e.g. 5%)")
+            .value_name("PERCENT")
+            .value_parser(verify::parse_percent))
+        .arg(Arg::new("verify-archive")
+            .long("verify-archive")
+            .help("🔏 Re-read each archive and check every file against the source before deletion")
+            .action(ArgAction::SetTrue))
+        .arg(Arg::new("skip-space-check")
+            .long("skip-space-check")
+            .help("💽 Back up even when the destinations don't look to have room for it")
+            .action(ArgAction::SetTrue))
+        .arg(Arg::new("hash-algo")
+            .long("hash-algo")
+            .help("🧮 Checksum for backup verification: blake3 (default) or sha256")
+            .value_name("ALGO")
+            .value_parser(clap::value_parser!(hash::HashAlgo)))
+        .arg(Arg::new("sudo-helper")
+            .long("sudo-helper")
+            .help("🔑 Remove directories that need elevated rights through this command (default: sudo)")
+            .value_name("CMD")
+            .num_args(0..=1)
+            .default_missing_value("sudo"))
+        .arg(Arg::new("elevate")
+            .long("elevate")
+            .help("🛡  Apply directories that need elevated rights from a plan file via pkexec (Linux) or UAC (Windows)")
+            .action(ArgAction::SetTrue))
+        .arg(Arg::new("ignore-locks")
+            .long("ignore-locks")
+            .help("🔓 Delete directories even while another tool holds a lock file inside them")
+            .action(ArgAction::SetTrue))
+        .arg(Arg::new("ignore-processes")
+            .long("ignore-processes")
+            .help("🏗  Delete directories even while running processes (e.g. build daemons) use them")
+            .action(ArgAction::SetTrue))
+        .arg(Arg::new("allow-dangerous-root")
+            .long("allow-dangerous-root")
+            .help("☢ Allow deleting when the search path is the filesystem root, a drive root or the home directory")
+            .action(ArgAction::SetTrue))
+        .arg(Arg::new("allow-outside-root")
+            .long("allow-outside-root")
+            .help("🔗 Delete matches even if symlinks resolve them to somewhere outside the search path")
+            .action(ArgAction::SetTrue))
+        .arg(Arg::new("protect")
+            .long("protect")
+            .help("🛡 Never delete or archive this path or anything inside it (multiple allowed)")
+            .value_name("PATH")
+            .action(ArgAction::Append))
+        .arg(Arg::new("git-safe")
+            .long("git-safe")
+            .help("🌿 Skip directories inside git repositories with uncommitted changes or unpushed commits")
+            .action(ArgAction::SetTrue))
+        .arg(Arg::new("stop-daemons")
+            .long("stop-daemons")
+            .help("🐘 Stop Gradle/sbt daemons (e.g. ./gradlew --stop) before purging their build directories")
+            .action(ArgAction::SetTrue))
         .arg(Arg::new("interactive")
             .short('i')
             .long("interactive")
@@ -729,14 +3064,70 @@ fn main() -> Result<(), String> {
             .long("json")
             .help("📄 Export results to JSON file")
             .value_name("FILE"))
+        .arg(Arg::new("yaml")
+            .long("yaml")
+            .help("📄 Export results to YAML file")
+            .value_name("FILE"))
+        .arg(Arg::new("jsonl")
+            .long("jsonl")
+            .help("📜 Export results as JSON Lines, one directory per line")
+            .value_name("FILE"))
+        .arg(Arg::new("stream")
+            .long("stream")
+            .help("📡 Write each match as a JSON line as soon as it is found ('-' for stdout)")
+            .value_name("FILE"))
         .arg(Arg::new("csv")
             .long("csv")
             .help("📊 Export results to CSV file")
             .value_name("FILE"))
+        .arg(Arg::new("pdf")
+            .long("pdf")
+            .help("🧾 Export a PDF report: summary, a chart of the largest directories and every directory")
+            .value_name("FILE"))
+        .arg(Arg::new("treemap")
+            .long("treemap")
+            .help("🗺  Export an SVG treemap of the matches, sized by bytes and grouped by target")
+            .value_name("FILE"))
+        .arg(Arg::new("history")
+            .long("history")
+            .help("📈 Record report-only directory sizes, and how fast each removal went, in this file")
+            .value_name("FILE"))
+        .arg(Arg::new("growth-alert")
+            .long("growth-alert")
+            .help("📈 Warn when a report-only directory grew by more than this percentage since the last run")
+            .value_name("PERCENT")
+            .value_parser(clap::value_parser!(f64)))
+        .arg(Arg::new("growth-webhook")
+            .long("growth-webhook")
+            .help("📈 POST growth alerts as JSON to this URL (needs curl)")
+            .value_name("URL"))
+        .arg(Arg::new("heartbeat")
+            .long("heartbeat")
+            .help("💓 Once a removal run has taken this many seconds, report its progress, and again as often after that")
+            .value_name("SECS")
+            .value_parser(clap::value_parser!(u64).range(1..)))
+        .arg(Arg::new("heartbeat-webhook")
+            .long("heartbeat-webhook")
+            .help("💓 Also POST each --heartbeat report as JSON to this URL (needs curl)")
+            .value_name("URL")
+            .requires("heartbeat"))
         .arg(Arg::new("log")
             .long("log")
             .help("📝 Write log to file")
             .value_name("FILE"))
+        .arg(Arg::new("journal")
+            .long("journal")
+            .help("📓 Journal deletions to this file for `resume` (default: dirpurge/journal.jsonl in the state directory)")
+            .value_name("FILE"))
+        .arg(Arg::new("checkpoint-interval")
+            .long("checkpoint-interval")
+            .help("⏱️  Seconds between journal checkpoints of a long copy, so `resume` carries on from the last one (default: 30)")
+            .value_name("SECS")
+            .value_parser(clap::value_parser!(u64)))
+        .arg(Arg::new("save-plan")
+            .long("save-plan")
+            .help("📋 Save selected directories to a plan file for later `apply`")
+            .value_name("FILE"))
         .arg(Arg::new("config")
             .short('c')
             .long("config")
@@ -756,11 +3147,449 @@ fn main() -> Result<(), String> {
             .long("quiet")
             .help("🔈 Suppress non-essential output")
             .action(ArgAction::SetTrue))
+        .arg(Arg::new("output")
+            .long("output")
+            .help("🖥 How to render console output")
+            .value_name("STYLE")
+            .value_parser(clap::value_parser!(OutputStyle)))
+        .arg(Arg::new("report-locale")
+            .long("report-locale")
+            .help("🌐 Locale for numbers and dates in reports, e.g. de-DE (default: from LC_ALL/LANG)")
+            .value_name("LOCALE"))
+        .subcommand_negates_reqs(true)
+        .args_conflicts_with_subcommands(true)
+        .subcommand(Command::new("apply")
+            .about("📋 Apply a saved plan after re-validating its directories")
+            .arg(Arg::new("plan")
+                .help("📄 Plan file created with --save-plan")
+                .required(true)
+                .index(1))
+            .arg(Arg::new("config")
+                .short('c')
+                .long("config")
+                .help("⚙️  Load the approval policy from a JSON config file")
+                .value_name("FILE"))
+            .arg(Arg::new("allow-changed")
+                .long("allow-changed")
+                .help("⚠️  Apply directories modified after the plan was created")
+                .action(ArgAction::SetTrue))
+            .arg(Arg::new("skip-daemons")
+                .long("skip-daemons")
+                .help("💤 Leave build daemons running even if the plan stops them (as elevated runs do)")
+                .action(ArgAction::SetTrue))
+            .arg(Arg::new("sudo-helper")
+                .long("sudo-helper")
+                .help("🔑 Remove directories that need elevated rights through this command (default: sudo)")
+                .value_name("CMD")
+                .num_args(0..=1)
+                .default_missing_value("sudo"))
+            .arg(Arg::new("elevate")
+                .long("elevate")
+                .help("🛡  Apply directories that need elevated rights from a plan file via pkexec (Linux) or UAC (Windows)")
+                .action(ArgAction::SetTrue))
+            .arg(Arg::new("yes")
+                .short('y')
+                .long("yes")
+                .help("✅ Skip confirmation prompts")
+                .action(ArgAction::SetTrue))
+            .arg(Arg::new("confirm-token")
+                .long("confirm-token")
+                .help("🎟  Confirm with the plan's token from `dirpurge plan token` instead of --yes")
+                .value_name("TOKEN")
+                .env("DIRPURGE_TOKEN")
+                .hide_env_values(true))
+            .arg(Arg::new("dry-run")
+                .short('d')
+                .long("dry-run")
+                .help("🌵 Simulate operations without making changes")
+                .action(ArgAction::SetTrue))
+            .arg(Arg::new("allow-dangerous-root")
+                .long("allow-dangerous-root")
+                .help("☢ Allow applying a plan made in the filesystem root, a drive root or the home directory")
+                .action(ArgAction::SetTrue))
+            .arg(Arg::new("allow-outside-root")
+                .long("allow-outside-root")
+                .help("🔗 Delete planned directories even if symlinks resolve them outside the plan's search path")
+                .action(ArgAction::SetTrue))
+            .arg(Arg::new("verbose")
+                .short('v')
+                .long("verbose")
+                .help("🔊 Enable verbose output")
+                .action(ArgAction::SetTrue)))
+        .subcommand(Command::new("resume")
+            .about("📓 Continue an interrupted run from the deletion journal")
+            .arg(Arg::new("journal")
+                .long("journal")
+                .help("📓 Journal to resume from (default: dirpurge/journal.jsonl in the state directory)")
+                .value_name("FILE"))
+            .arg(Arg::new("checkpoint-interval")
+                .long("checkpoint-interval")
+                .help("⏱️  Seconds between journal checkpoints of a long copy, so `resume` carries on from the last one (default: 30)")
+                .value_name("SECS")
+                .value_parser(clap::value_parser!(u64)))
+            .arg(Arg::new("run")
+                .long("run")
+                .help("🔖 Run to resume (defaults to the most recent one)")
+                .value_name("ID"))
+            .arg(Arg::new("yes")
+                .short('y')
+                .long("yes")
+                .help("✅ Skip confirmation prompts")
+                .action(ArgAction::SetTrue))
+            .arg(Arg::new("dry-run")
+                .short('d')
+                .long("dry-run")
+                .help("🌵 Show what would be resumed without making changes")
+                .action(ArgAction::SetTrue))
+            .arg(Arg::new("verbose")
+                .short('v')
+                .long("verbose")
+                .help("🔊 Enable verbose output")
+                .action(ArgAction::SetTrue)))
+        .subcommand(Command::new("restore")
+            .about("♻️  Restore a directory removed by an earlier run, from its backup, archive or the trash")
+            .arg(Arg::new("path")
+                .help("📁 Directory to bring back, as it was before removal")
+                .required(true)
+                .index(1))
+            .arg(Arg::new("to")
+                .long("to")
+                .help("📂 Restore here instead of the original location")
+                .value_name("DIR"))
+            .arg(Arg::new("journal")
+                .long("journal")
+                .help("📓 Journal to look the removal up in (default: dirpurge/journal.jsonl in the state directory)")
+                .value_name("FILE"))
+            .arg(Arg::new("run")
+                .long("run")
+                .help("🔖 Only consider removals by this run (defaults to the latest removal)")
+                .value_name("ID"))
+            .arg(Arg::new("dry-run")
+                .short('d')
+                .long("dry-run")
+                .help("🌵 Show where the directory would be restored from")
+                .action(ArgAction::SetTrue))
+            .arg(Arg::new("verbose")
+                .short('v')
+                .long("verbose")
+                .help("🔊 Enable verbose output")
+                .action(ArgAction::SetTrue)))
+        .subcommand(Command::new("trash")
+            .about("🗑  List and restore what runs moved to the OS trash")
+            .subcommand_required(true)
+            .subcommand(Command::new("list")
+                .about("📋 Show what dirpurge moved to the trash and is still there")
+                .arg(Arg::new("journal")
+                    .long("journal")
+                    .help("📓 Journal the runs recorded in (default: dirpurge/journal.jsonl in the state directory)")
+                    .value_name("FILE")))
+            .subcommand(Command::new("restore")
+                .about("♻️  Put a directory dirpurge moved to the trash back")
+                .arg(Arg::new("path")
+                    .help("📁 Directory to bring back, as it was before it was trashed")
+                    .required(true)
+                    .index(1))
+                .arg(Arg::new("to")
+                    .long("to")
+                    .help("📂 Restore here instead of the original location")
+                    .value_name("DIR"))
+                .arg(Arg::new("journal")
+                    .long("journal")
+                    .help("📓 Journal the runs recorded in (default: dirpurge/journal.jsonl in the state directory)")
+                    .value_name("FILE"))
+                .arg(Arg::new("dry-run")
+                    .short('d')
+                    .long("dry-run")
+                    .help("🌵 Show what would be restored")
+                    .action(ArgAction::SetTrue))))
+        .subcommand(Command::new("unarchive")
+            .about("📦 Unpack an archive made by dirpurge back where its directory was")
+            .arg(Arg::new("archive")
+                .help("🗜 The .zip, .tar.gz or .tar.zst archive")
+                .required(true)
+                .index(1))
+            .arg(Arg::new("to")
+                .long("to")
+                .help("📂 Unpack here instead of the location recorded in the archive")
+                .value_name("DIR"))
+            .arg(Arg::new("dry-run")
+                .short('d')
+                .long("dry-run")
+                .help("🌵 Show where the archive would be unpacked")
+                .action(ArgAction::SetTrue))
+            .arg(Arg::new("verbose")
+                .short('v')
+                .long("verbose")
+                .help("🔊 Enable verbose output")
+                .action(ArgAction::SetTrue)))
+        .subcommand(Command::new("purge-path")
+            .about("🎯 Purge exactly one directory, skipping discovery but keeping every safety check")
+            .arg(Arg::new("path")
+                .help("📁 Directory to remove")
+                .required(true)
+                .index(1))
+            .arg(Arg::new("config")
+                .short('c')
+                .long("config")
+                .help("⚙️  Load settings from a JSON config file")
+                .value_name("FILE"))
+            .arg(Arg::new("use-trash")
+                .long("use-trash")
+                .help("🗑  Move to trash instead of permanent deletion")
+                .action(ArgAction::SetTrue))
+            .arg(Arg::new("backup")
+                .short('b')
+                .long("backup")
+                .help("💾 Create a backup before deletion")
+                .action(ArgAction::SetTrue))
+            .arg(Arg::new("archive")
+                .short('a')
+                .long("archive")
+                .help("📦 Create an archive before deletion")
+                .action(ArgAction::SetTrue))
+            .arg(Arg::new("archive-format")
+                .long("archive-format")
+                .help("📦 Archive format for --archive: zip (default), tar.gz or tar.zst")
+                .value_name("FORMAT")
+                .value_parser(clap::value_parser!(archive::ArchiveFormat)))
+            .arg(Arg::new("compression-level")
+                .long("compression-level")
+                .help("🗜  Compression level for archives: 0-9 for zip and tar.gz, 1-22 for tar.zst")
+                .value_name("LEVEL")
+                .value_parser(clap::value_parser!(i32)))
+            .arg(Arg::new("archive-manifest")
+                .long("archive-manifest")
+                .help("🧾 Write the archive's manifest of files, sizes, times and SHA-256 beside it too")
+                .action(ArgAction::SetTrue))
+            .arg(Arg::new("archive-top-dir")
+                .long("archive-top-dir")
+                .help("📁 Store archive entries under the directory's name")
+                .action(ArgAction::SetTrue))
+            .arg(Arg::new("archive-split-size")
+                .long("archive-split-size")
+                .help("✂️  Split archives into volumes of this size (e.g. 2GB)")
+                .value_name("SIZE"))
+            .arg(Arg::new("backup-exclude")
+                .long("backup-exclude")
+                .help("✂️ Leave paths matching this glob out of the backup or archive; repeatable")
+                .value_name("PATTERN")
+                .action(ArgAction::Append)
+                .value_parser(|v: &str| backup::parse_exclude(v).map(|_| v.to_string())))
+            .arg(Arg::new("s3-storage-class")
+                .long("s3-storage-class")
+                .help("🧊 S3 storage class for the uploaded archive: CLASS, TARGET=CLASS or >SIZE=CLASS; repeatable")
+                .value_name("RULE")
+                .action(ArgAction::Append)
+                .value_parser(check_storage_class))
+            .arg(Arg::new("backup-name-template")
+                .long("backup-name-template")
+                .help("🏷  Where the backup goes in each destination (e.g. '{project}/{name}-{date}.{ext}')")
+                .value_name("TEMPLATE")
+                .value_parser(|v: &str| naming::NameTemplate::parse(v).map(|_| v.to_string())))
+            .arg(Arg::new("backup-preserve")
+                .long("backup-preserve")
+                .help("🪪 What a plain backup keeps of each file: all (default), none, or any of mode,owner,times,links")
+                .value_name("ASPECTS")
+                .value_parser(clap::value_parser!(preserve::Preserve)))
+            .arg(Arg::new("backup-strategy")
+                .long("backup-strategy")
+                .help("🧬 Clone files into a plain backup by reflink where the filesystem can: auto (default), reflink or copy")
+                .value_name("STRATEGY")
+                .value_parser(clap::value_parser!(backup::BackupStrategy)))
+            .arg(Arg::new("backup-dir")
+                .long("backup-dir")
+                .help("📂 Directory for backups/archives (multiple allowed)")
+                .value_name("DIR")
+                .action(ArgAction::Append))
+            .arg(Arg::new("verify-sample")
+                .long("verify-sample")
+                .help("🔬 Hash-check a random sample of backed up files before deletion (e.g. 5%)")
+                .value_name("PERCENT")
+                .value_parser(verify::parse_percent))
+            .arg(Arg::new("verify-archive")
+                .long("verify-archive")
+                .help("🔏 Re-read the archive and check every file against the source before deletion")
+                .action(ArgAction::SetTrue))
+            .arg(Arg::new("skip-space-check")
+                .long("skip-space-check")
+                .help("💽 Back up even when the destination doesn't look to have room for it")
+                .action(ArgAction::SetTrue))
+            .arg(Arg::new("hash-algo")
+                .long("hash-algo")
+                .help("🧮 Checksum for backup verification: blake3 (default) or sha256")
+                .value_name("ALGO")
+                .value_parser(clap::value_parser!(hash::HashAlgo)))
+            .arg(Arg::new("chunk-above")
+                .long("chunk-above")
+                .help("🧱 Remove the directory in batches if it has more entries than this (default: 100000)")
+                .value_name("ENTRIES")
+                .value_parser(clap::value_parser!(usize)))
+            .arg(Arg::new("prune-backups")
+                .long("prune-backups")
+                .help("🧹 Afterwards, remove old backups beyond these limits, e.g. days=30,size=10GB")
+                .value_name("POLICY")
+                .value_parser(clap::value_parser!(prune::RetentionPolicy)))
+            .arg(Arg::new("journal")
+                .long("journal")
+                .help("📓 Journal the deletion to this file (default: dirpurge/journal.jsonl in the state directory)")
+                .value_name("FILE"))
+            .arg(Arg::new("checkpoint-interval")
+                .long("checkpoint-interval")
+                .help("⏱️  Seconds between journal checkpoints of a long copy, so `resume` carries on from the last one (default: 30)")
+                .value_name("SECS")
+                .value_parser(clap::value_parser!(u64)))
+            .arg(Arg::new("heartbeat")
+                .long("heartbeat")
+                .help("💓 Once the removal has taken this many seconds, report its progress, and again as often after that")
+                .value_name("SECS")
+                .value_parser(clap::value_parser!(u64).range(1..)))
+            .arg(Arg::new("heartbeat-webhook")
+                .long("heartbeat-webhook")
+                .help("💓 Also POST each --heartbeat report as JSON to this URL (needs curl)")
+                .value_name("URL")
+                .requires("heartbeat"))
+            .arg(Arg::new("json")
+                .long("json")
+                .help("📄 Export results to JSON file")
+                .value_name("FILE"))
+            .arg(Arg::new("yaml")
+                .long("yaml")
+                .help("📄 Export results to YAML file")
+                .value_name("FILE"))
+            .arg(Arg::new("jsonl")
+                .long("jsonl")
+                .help("📜 Export results as JSON Lines, one directory per line")
+                .value_name("FILE"))
+            .arg(Arg::new("csv")
+                .long("csv")
+                .help("📊 Export results to CSV file")
+                .value_name("FILE"))
+            .arg(Arg::new("pdf")
+                .long("pdf")
+                .help("🧾 Export a PDF report of the removal")
+                .value_name("FILE"))
+            .arg(Arg::new("treemap")
+                .long("treemap")
+                .help("🗺  Export an SVG treemap of the removal")
+                .value_name("FILE"))
+            .arg(Arg::new("protect")
+                .long("protect")
+                .help("🛡 Never delete or archive this path or anything inside it (multiple allowed)")
+                .value_name("PATH")
+                .action(ArgAction::Append))
+            .arg(Arg::new("allow-dangerous-root")
+                .long("allow-dangerous-root")
+                .help("☢ Allow purging the filesystem root, a drive root or the home directory")
+                .action(ArgAction::SetTrue))
+            .arg(Arg::new("yes")
+                .short('y')
+                .long("yes")
+                .help("✅ Skip confirmation prompts")
+                .action(ArgAction::SetTrue))
+            .arg(Arg::new("dry-run")
+                .short('d')
+                .long("dry-run")
+                .help("🌵 Simulate operations without making changes")
+                .action(ArgAction::SetTrue))
+            .arg(Arg::new("verbose")
+                .short('v')
+                .long("verbose")
+                .help("🔊 Enable verbose output")
+                .action(ArgAction::SetTrue)))
+        .subcommand(Command::new("plan")
+            .about("📋 Manage saved plans")
+            .subcommand_required(true)
+            .subcommand(Command::new("sign")
+                .about("✍️  Add an approval signature to a plan")
+                .arg(Arg::new("plan")
+                    .help("📄 Plan file to sign")
+                    .required(true)
+                    .index(1))
+                .arg(Arg::new("key")
+                    .short('k')
+                    .long("key")
+                    .help("🔑 Signer's secret key file")
+                    .value_name("FILE")
+                    .required(true))
+                .arg(Arg::new("signer")
+                    .long("signer")
+                    .help("👤 Signer name (defaults to the current user)")
+                    .value_name("NAME")))
+            .subcommand(Command::new("token")
+                .about("🎟  Print the token that confirms applying a plan with --confirm-token")
+                .arg(Arg::new("plan")
+                    .help("📄 Plan file")
+                    .required(true)
+                    .index(1))))
+        .subcommand(Command::new("backups")
+            .about("💾 Inspect existing backups and archives")
+            .subcommand_required(true)
+            .subcommand(Command::new("find")
+                .about("🔍 Find which backup contains files matching a pattern")
+                .arg(Arg::new("pattern")
+                    .help("🔎 Glob matched against paths inside backups (e.g. '.env', 'src/**/*.rs')")
+                    .required(true)
+                    .index(1))
+                .arg(Arg::new("backup-dir")
+                    .long("backup-dir")
+                    .help("📂 Backup directories to search (multiple allowed)")
+                    .value_name("DIR")
+                    .action(ArgAction::Append)
+                    .default_value("./backups"))
+                .arg(Arg::new("backup-name-template")
+                    .long("backup-name-template")
+                    .help("🏷  The --backup-name-template the backups were made with")
+                    .value_name("TEMPLATE"))))
+        .subcommand(Command::new("quarantine")
+            .about("⏳ Manage directories put aside with --quarantine")
+            .subcommand_required(true)
+            .subcommand(Command::new("purge")
+                .about("🔥 Permanently remove quarantined directories past their grace period")
+                .arg(Arg::new("older-than")
+                    .long("older-than")
+                    .help("⌛ Remove what was quarantined longer ago than this, e.g. 14d, 12h or 2w")
+                    .value_name("AGE")
+                    .required(true)
+                    .value_parser(units::parse_age))
+                .arg(Arg::new("quarantine-dir")
+                    .long("quarantine-dir")
+                    .help("📂 Quarantine directory the runs used (default: in dirpurge's state directory)")
+                    .value_name("DIR"))
+                .arg(Arg::new("dry-run")
+                    .long("dry-run")
+                    .help("🧪 Show what would be removed without removing it")
+                    .action(ArgAction::SetTrue))))
+        .subcommand(Command::new("doctor")
+            .about("🩺 Check what this machine lets dirpurge do: trash, terminal, config, backup destinations, filesystem and optional tools")
+            .arg(Arg::new("path")
+                .help("📁 Directory whose filesystem to check, as the one you'd purge")
+                .default_value(".")
+                .index(1))
+            .arg(Arg::new("config")
+                .short('c')
+                .long("config")
+                .help("⚙️  Config file to validate, and take backup destinations from")
+                .value_name("FILE"))
+            .arg(Arg::new("backup-dir")
+                .long("backup-dir")
+                .help("📂 Backup destinations to check (multiple allowed)")
+                .value_name("DIR")
+                .action(ArgAction::Append)))
+        .subcommand(Command::new("stats")
+            .about("📊 Summarize what earlier runs recorded in their --history file")
+            .subcommand_required(true)
+            .subcommand(Command::new("actions")
+                .about("⏱  Compare how fast delete, trash, backup and archive went on each filesystem")
+                .arg(Arg::new("history")
+                    .long("history")
+                    .help("📈 History file the runs recorded in")
+                    .value_name("FILE")
+                    .required(true))))
         .after_help(format!(
             "{}\n{}{}",
             yellow().apply_to("💡 Tip: Always run with --dry-run first to test!"),
             cyan().apply_to("\nExamples:\n  "),
-            cyan().apply_to("dirpurge ./project\n  dirpurge ./src -t node_modules --delete\n  dirpurge . --config settings.json\n  dirpurge . -i --use-trash")
+            cyan().apply_to("dirpurge ./project\n  dirpurge ./src -t node_modules --delete\n  dirpurge . --config settings.json\n  dirpurge . -i --use-trash\n  dirpurge . --save-plan plan.json && dirpurge apply plan.json\n  dirpurge purge-path ./old-build --backup")
         ))
         .get_matches();
 
@@ -770,34 +3599,50 @@ fn main() -> Result<(), String> {
         matches.get_flag("verbose")
     )?;
 
-    // Load config file if specified
+    // Subcommands work on plans and backups, not on a fresh scan
+    match matches.subcommand() {
+        Some(("apply", apply_matches)) => return run_apply(apply_matches),
+        Some(("resume", resume_matches)) => return run_resume(resume_matches),
+        Some(("restore", restore_matches)) => return run_restore(restore_matches),
+        Some(("trash", trash_matches)) => return run_trash(trash_matches),
+        Some(("purge-path", purge_matches)) => return run_purge_path(purge_matches),
+        Some(("unarchive", unarchive_matches)) => return run_unarchive(unarchive_matches),
+        Some(("plan", plan_matches)) => match plan_matches.subcommand() {
+            Some(("sign", sign_matches)) => return run_plan_sign(sign_matches),
+            Some(("token", token_matches)) => return run_plan_token(token_matches),
+            _ => unreachable!("subcommand required"),
+        },
+        Some(("backups", backups_matches)) => match backups_matches.subcommand() {
+            Some(("find", find_matches)) => return run_backups_find(find_matches),
+            _ => unreachable!("subcommand required"),
+        },
+        Some(("quarantine", quarantine_matches)) => match quarantine_matches.subcommand() {
+            Some(("purge", purge_matches)) => return quarantine::purge(
+                &quarantine::resolve(purge_matches.get_one::<String>("quarantine-dir").map(String::as_str))?,
+                *purge_matches.get_one::<Duration>("older-than").unwrap(),
+                purge_matches.get_flag("dry-run"),
+            ),
+            _ => unreachable!("subcommand required"),
+        },
+        Some(("doctor", doctor_matches)) => return doctor::run(doctor_matches),
+        Some(("stats", stats_matches)) => match stats_matches.subcommand() {
+            Some(("actions", actions_matches)) => return history::report_actions(actions_matches.get_one::<String>("history").unwrap()),
+            _ => unreachable!("subcommand required"),
+        },
+        _ => {}
+    }
+
+    // Load config file if specified. A broken one stops the run, since it may
+    // hold the protected paths.
     let mut config = matches.get_one::<String>("config")
-        .and_then(|config_path| load_config(config_path).ok())
-        .unwrap_or_else(|| Config {
-            target: None,
-            exclude: None,
-            depth: None,
-            min_size: None,
-            min_age: None,
-            follow_symlinks: None,
-            delete: None,
-            yes: None,
-            dry_run: None,
-            use_trash: None,
-            backup: None,
-            archive: None,
-            backup_dir: None,
-            interactive: None,
-            confirm_phrase: None,
-            json: None,
-            csv: None,
-            log: None,
-            verbose: None,
-            quiet: None,
-        });
+        .map(|config_path| load_config(config_path))
+        .transpose()?
+        .unwrap_or_default();
 
-    // Base path is required
-    let base_path = matches.get_one::<String>("path").unwrap();
+    // Base path is required unless candidates are given explicitly
+    let purge_list = matches.get_one::<String>("purge-list");
+    let paths_from = matches.get_one::<String>("paths-from").or(purge_list);
+    let base_path = matches.get_one::<String>("path").map_or(".", String::as_str);
 
     // Get command line args and override config values
     if let Some(targets) = matches.get_many::<String>("target") {
@@ -809,18 +3654,104 @@ fn main() -> Result<(), String> {
     if let Some(depth) = matches.get_one::<usize>("depth") {
         config.depth = Some(*depth);
     }
+    if let Some(min_depth) = matches.get_one::<usize>("min-depth") {
+        config.min_depth = Some(*min_depth);
+    }
     if let Some(min_size) = matches.get_one::<f64>("min-size") {
         config.min_size = Some(*min_size);
     }
+    if let Some(max_size) = matches.get_one::<f64>("max-size") {
+        config.max_size = Some(*max_size);
+    }
+    if let Some(min_items) = matches.get_one::<usize>("min-items") {
+        config.min_items = Some(*min_items);
+    }
+    if let Some(max_items) = matches.get_one::<usize>("max-items") {
+        config.max_items = Some(*max_items);
+    }
     if let Some(min_age) = matches.get_one::<i64>("min-age") {
         config.min_age = Some(*min_age);
     }
-    if matches.contains_id("follow-symlinks") {
-        config.follow_symlinks = Some(matches.get_flag("follow-symlinks"));
+    if let Some(max_age) = matches.get_one::<i64>("max-age") {
+        config.max_age = Some(*max_age);
+    }
+    if let Some(modified_before) = matches.get_one::<String>("modified-before") {
+        config.modified_before = Some(modified_before.clone());
+    }
+    if let Some(modified_after) = matches.get_one::<String>("modified-after") {
+        config.modified_after = Some(modified_after.clone());
+    }
+    if matches.contains_id("owned-by-me") {
+        config.owned_by_me = Some(matches.get_flag("owned-by-me"));
+    }
+    if let Some(owner) = matches.get_one::<String>("owner") {
+        config.owner = Some(owner.clone());
+    }
+    if let Some(age_source) = matches.get_one::<AgeSource>("age-source") {
+        config.age_source = Some(*age_source);
+    }
+    if let Some(min_atime_age) = matches.get_one::<i64>("min-atime-age") {
+        config.min_atime_age = Some(*min_atime_age);
+    }
+    if let Some(days) = matches.get_one::<i64>("repo-inactive-days") {
+        config.repo_inactive_days = Some(*days);
+    }
+    if let Some(keep) = matches.get_one::<usize>("keep-latest") {
+        config.keep_latest = Some(*keep);
+    }
+    if matches.contains_id("follow-symlinks-traversal") {
+        config.follow_symlinks_traversal = Some(matches.get_flag("follow-symlinks-traversal"));
+    }
+    if matches.contains_id("count-symlinked-sizes") {
+        config.count_symlinked_sizes = Some(matches.get_flag("count-symlinked-sizes"));
+    }
+    if matches.contains_id("one-file-system") {
+        config.one_file_system = Some(matches.get_flag("one-file-system"));
+    }
+    if matches.contains_id("count-hardlinks") {
+        config.count_hardlinks = Some(matches.get_flag("count-hardlinks"));
+    }
+    if let Some(size_mode) = matches.get_one::<SizeMode>("size-mode") {
+        config.size_mode = Some(*size_mode);
+    }
+    if matches.contains_id("estimate") {
+        config.estimate = Some(matches.get_flag("estimate"));
+    }
+    if matches.contains_id("reclaimable") {
+        config.reclaimable = Some(matches.get_flag("reclaimable"));
+    }
+    if matches.contains_id("respect-ignore") {
+        config.respect_ignore = Some(matches.get_flag("respect-ignore"));
+    }
+    if matches.get_flag("no-prune") {
+        config.prune = Some(false);
+    }
+    if matches.contains_id("require-manifest") {
+        config.require_manifest = Some(matches.get_flag("require-manifest"));
+    }
+    if let Some(pairs) = matches.get_many::<String>("manifest") {
+        let manifests = config.manifests.get_or_insert_with(HashMap::new);
+        for pair in pairs {
+            let (target, marker) = parse_manifest(pair)?;
+            manifests.entry(target).or_default().push(marker);
+        }
+    }
+    if let Some(pairs) = matches.get_many::<String>("action") {
+        let actions = config.actions.get_or_insert_with(HashMap::new);
+        for pair in pairs {
+            let (target, action) = pair.split_once('=')
+                .ok_or_else(|| format!("{} Invalid --action '{}', expected TARGET=ACTION", CROSS, pair))?;
+            let action = action.parse().map_err(|e| format!("{} {}", CROSS, e))?;
+            actions.insert(target.to_string(), action);
+        }
     }
     if matches.contains_id("delete") {
         config.delete = Some(matches.get_flag("delete"));
     }
+    // A purge list names what to delete
+    if purge_list.is_some() {
+        config.delete = Some(true);
+    }
     if matches.contains_id("yes") {
         config.yes = Some(matches.get_flag("yes"));
     }
@@ -830,14 +3761,130 @@ fn main() -> Result<(), String> {
     if matches.contains_id("use-trash") {
         config.use_trash = Some(matches.get_flag("use-trash"));
     }
+    if matches.get_flag("quarantine") {
+        config.quarantine = Some(true);
+    }
+    if let Some(quarantine_dir) = matches.get_one::<String>("quarantine-dir") {
+        config.quarantine_dir = Some(quarantine_dir.clone());
+    }
     if matches.contains_id("backup") {
         config.backup = Some(matches.get_flag("backup"));
     }
     if matches.contains_id("archive") {
         config.archive = Some(matches.get_flag("archive"));
     }
-    if let Some(backup_dir) = matches.get_one::<String>("backup-dir") {
-        config.backup_dir = Some(backup_dir.clone());
+    if let Some(format) = matches.get_one::<archive::ArchiveFormat>("archive-format") {
+        config.archive_format = Some(*format);
+    }
+    if let Some(level) = matches.get_one::<i32>("compression-level") {
+        config.compression_level = Some(*level);
+    }
+    if matches.get_flag("archive-manifest") {
+        config.archive_manifest = Some(true);
+    }
+    if matches.get_flag("archive-top-dir") {
+        config.archive_top_dir = Some(true);
+    }
+    if let Some(size) = matches.get_one::<String>("archive-split-size") {
+        config.archive_split_size = Some(size.clone());
+    }
+    if let Some(patterns) = matches.get_many::<String>("backup-exclude") {
+        config.backup_exclude = Some(patterns.cloned().collect());
+    }
+    if let Some(rules) = matches.get_many::<String>("s3-storage-class") {
+        config.s3_storage_class = Some(rules.cloned().collect());
+    }
+    if let Some(template) = matches.get_one::<String>("backup-name-template") {
+        config.backup_name_template = Some(template.clone());
+    }
+    if let Some(preserve) = matches.get_one::<preserve::Preserve>("backup-preserve") {
+        config.backup_preserve = Some(*preserve);
+    }
+    if let Some(strategy) = matches.get_one::<backup::BackupStrategy>("backup-strategy") {
+        config.backup_strategy = Some(*strategy);
+    }
+    if let Some(backup_dirs) = matches.get_many::<String>("backup-dir") {
+        let backup_dirs: Vec<String> = backup_dirs.cloned().collect();
+        config.backup_dir = backup_dirs.first().cloned();
+        config.backup_dirs = (backup_dirs.len() > 1).then_some(backup_dirs);
+    }
+    if let Some(backup_policy) = matches.get_one::<backup::BackupPolicy>("backup-policy") {
+        config.backup_policy = Some(*backup_policy);
+    }
+    if let Some(prune_backups) = matches.get_one::<prune::RetentionPolicy>("prune-backups") {
+        config.prune_backups = Some(*prune_backups);
+    }
+    if let Some(free) = matches.get_one::<String>("free") {
+        config.free = Some(free.clone());
+    }
+    if let Some(max_dirs) = matches.get_one::<usize>("max-dirs") {
+        config.max_dirs = Some(*max_dirs);
+    }
+    if let Some(max_total) = matches.get_one::<String>("max-total") {
+        config.max_total = Some(max_total.clone());
+    }
+    if let Some(days) = matches.get_one::<i64>("purge-files-older-than") {
+        config.purge_files_older_than = Some(*days);
+    }
+    if let Some(upload_limit) = matches.get_one::<String>("upload-limit") {
+        config.upload_limit = Some(upload_limit.clone());
+    }
+    if matches.contains_id("readahead") {
+        config.readahead = Some(matches.get_flag("readahead"));
+    }
+    if matches.contains_id("incremental") {
+        config.incremental = Some(matches.get_flag("incremental"));
+    }
+    if let Some(jobs) = matches.get_one::<u16>("jobs") {
+        config.jobs = Some(*jobs as usize);
+    }
+    if let Some(entries) = matches.get_one::<usize>("chunk-above") {
+        config.chunk_above = Some(*entries);
+    }
+    if let Some(verify_sample) = matches.get_one::<f64>("verify-sample") {
+        config.verify_sample = Some(*verify_sample);
+    }
+    if matches.get_flag("verify-archive") {
+        config.verify_archive = Some(true);
+    }
+    if matches.get_flag("skip-space-check") {
+        config.skip_space_check = Some(true);
+    }
+    if let Some(hash_algo) = matches.get_one::<hash::HashAlgo>("hash-algo") {
+        config.hash_algo = Some(*hash_algo);
+    }
+    if let Some(sudo_helper) = matches.get_one::<String>("sudo-helper") {
+        config.sudo_helper = Some(sudo_helper.clone());
+    }
+    if matches.contains_id("ignore-locks") {
+        config.ignore_locks = Some(matches.get_flag("ignore-locks"));
+    }
+    if matches.contains_id("ignore-processes") {
+        config.ignore_processes = Some(matches.get_flag("ignore-processes"));
+    }
+    if matches.contains_id("allow-dangerous-root") {
+        config.allow_dangerous_root = Some(matches.get_flag("allow-dangerous-root"));
+    }
+    if matches.contains_id("allow-outside-root") {
+        config.allow_outside_root = Some(matches.get_flag("allow-outside-root"));
+    }
+    if let Some(journal) = matches.get_one::<String>("journal") {
+        config.journal = Some(journal.clone());
+    }
+    if let Some(secs) = matches.get_one::<u64>("checkpoint-interval") {
+        config.checkpoint_interval = Some(*secs);
+    }
+    if let Some(paths) = matches.get_many::<String>("protect") {
+        config.protected.get_or_insert_with(Vec::new).extend(paths.cloned());
+    }
+    if matches.contains_id("git-safe") {
+        config.git_safe = Some(matches.get_flag("git-safe"));
+    }
+    if matches.contains_id("stop-daemons") {
+        config.stop_daemons = Some(matches.get_flag("stop-daemons"));
+    }
+    if matches.contains_id("elevate") {
+        config.elevate = Some(matches.get_flag("elevate"));
     }
     if matches.contains_id("interactive") {
         config.interactive = Some(matches.get_flag("interactive"));
@@ -848,9 +3895,39 @@ fn main() -> Result<(), String> {
     if let Some(json) = matches.get_one::<String>("json") {
         config.json = Some(json.clone());
     }
+    if let Some(yaml) = matches.get_one::<String>("yaml") {
+        config.yaml = Some(yaml.clone());
+    }
+    if let Some(jsonl) = matches.get_one::<String>("jsonl") {
+        config.jsonl = Some(jsonl.clone());
+    }
     if let Some(csv) = matches.get_one::<String>("csv") {
         config.csv = Some(csv.clone());
     }
+    if let Some(pdf) = matches.get_one::<String>("pdf") {
+        config.pdf = Some(pdf.clone());
+    }
+    if let Some(treemap) = matches.get_one::<String>("treemap") {
+        config.treemap = Some(treemap.clone());
+    }
+    if let Some(stream) = matches.get_one::<String>("stream") {
+        config.stream = Some(stream.clone());
+    }
+    if let Some(history) = matches.get_one::<String>("history") {
+        config.history = Some(history.clone());
+    }
+    if let Some(percent) = matches.get_one::<f64>("growth-alert") {
+        config.growth_alert = Some(*percent);
+    }
+    if let Some(url) = matches.get_one::<String>("growth-webhook") {
+        config.growth_webhook = Some(url.clone());
+    }
+    if let Some(secs) = matches.get_one::<u64>("heartbeat") {
+        config.heartbeat = Some(*secs);
+    }
+    if let Some(url) = matches.get_one::<String>("heartbeat-webhook") {
+        config.heartbeat_webhook = Some(url.clone());
+    }
     if let Some(log_file) = matches.get_one::<String>("log") {
         config.log = Some(log_file.clone());
     }
@@ -860,95 +3937,285 @@ fn main() -> Result<(), String> {
     if matches.contains_id("quiet") {
         config.quiet = Some(matches.get_flag("quiet"));
     }
+    if let Some(output) = matches.get_one::<OutputStyle>("output") {
+        config.output = Some(*output);
+    }
+    if let Some(tag) = matches.get_one::<String>("report-locale") {
+        config.report_locale = Some(tag.clone());
+    }
+    // Probes print their one line and nothing else worth a cron mail
+    let check = matches.get_flag("check");
+    report::init(config.output.unwrap_or_default(), config.verbose.unwrap_or(false), config.quiet.unwrap_or(false) || check);
+    locale::init(config.report_locale.as_deref(), config.output.unwrap_or_default() == OutputStyle::Human)?;
+    protected::init(config.protected.as_deref().unwrap_or_default(), &own_state(&config, matches.get_one::<String>("config")));
 
     // Save config if requested
     if let Some(config_path) = matches.get_one::<String>("save-config") {
         save_config(&config, config_path)?;
-        println!("{} {}", DISK, green().apply_to(format!("Configuration saved to {}", config_path)));
+        report::message(Kind::Saved, format!("Configuration saved to {}", config_path));
     }
+    check_tools(&mut config)?;
 
     // Extract config values with defaults
     let target = config.target.clone().unwrap_or_else(|| vec!["venv".to_string(), ".venv".to_string(), "node_modules".to_string()]);
     let exclude = config.exclude.clone().unwrap_or_default();
     let depth = config.depth;
+    let min_depth = config.min_depth;
     let min_size = config.min_size.map(|mb| (mb * 1024.0 * 1024.0) as u64);
+    let max_size = config.max_size.map(|mb| (mb * 1024.0 * 1024.0) as u64);
+    let min_items = config.min_items;
+    let max_items = config.max_items;
     let min_age = config.min_age;
-    let follow_symlinks = config.follow_symlinks.unwrap_or(false);
+    let max_age = config.max_age;
+    let modified_before = config.modified_before.as_deref().map(units::parse_date).transpose()?;
+    let modified_after = config.modified_after.as_deref().map(units::parse_date).transpose()?;
+    let min_atime_age = config.min_atime_age;
+    let repo_inactive_days = config.repo_inactive_days;
+    let keep_latest = config.keep_latest;
+    let age_source = config.age_source.unwrap_or_default();
+    let owner_uid = resolve_owner(config.owned_by_me.unwrap_or(false), config.owner.as_deref())?;
+    let follow_symlinks_traversal = config.follow_symlinks_traversal.unwrap_or(false);
+    let count_symlinked_sizes = config.count_symlinked_sizes.unwrap_or(false);
+    let one_file_system = config.one_file_system.unwrap_or(false);
+    let count_hardlinks = config.count_hardlinks.unwrap_or(false);
+    let size_mode = config.size_mode.unwrap_or_default();
+    let estimate = config.estimate.unwrap_or(false);
+    let reclaimable = config.reclaimable.unwrap_or(false);
+    let respect_ignore = config.respect_ignore.unwrap_or(false);
+    let prune = config.prune.unwrap_or(true);
+    let manifests = config.require_manifest.unwrap_or(false).then(|| {
+        let mut manifests = default_manifests();
+        manifests.extend(config.manifests.clone().unwrap_or_default());
+        manifests
+    });
     let delete_enabled = config.delete.unwrap_or(false);
     let yes = config.yes.unwrap_or(false);
     let dry_run = config.dry_run.unwrap_or(false);
-    let use_trash = config.use_trash.unwrap_or(true);
+    // Builds without the trash feature fall back to permanent deletion
+    let use_trash = config.use_trash.unwrap_or(cfg!(feature = "trash"));
+    let quarantine = config.quarantine.unwrap_or(false).then(|| quarantine::resolve(config.quarantine_dir.as_deref())).transpose()?;
     let backup = config.backup.unwrap_or(false);
     let archive = config.archive.unwrap_or(false);
-    let backup_dir = config.backup_dir.clone().unwrap_or_else(|| "./backups".to_string());
+    let archive_format = config.archive_format.unwrap_or_default();
+    let compression_level = config.compression_level;
+    let archive_manifest = config.archive_manifest.unwrap_or(false);
+    let archive_top_dir = config.archive_top_dir.unwrap_or(false);
+    let archive_split_size = config.archive_split_size.as_deref().map(units::parse_volume_size).transpose()?;
+    let backup_exclude = config.backup_exclude.clone().unwrap_or_default();
+    let s3_storage_class = config.s3_storage_class.clone().unwrap_or_default();
+    #[cfg(feature = "s3")]
+    storage_class::StorageClasses::new(&s3_storage_class)?;
+    #[cfg(not(feature = "s3"))]
+    if !s3_storage_class.is_empty() {
+        return Err(feature_disabled("s3"));
+    }
+    let backup_name_template = config.backup_name_template.clone();
+    if let Some(template) = &backup_name_template {
+        naming::NameTemplate::parse(template)?;
+    }
+    let backup_preserve = config.backup_preserve.unwrap_or_default();
+    let backup_strategy = config.backup_strategy.unwrap_or_default();
+    if let Some(level) = compression_level {
+        archive_format.check_level(level)?;
+    }
+    let backup_dirs = config.backup_dirs.clone()
+        .or_else(|| config.backup_dir.clone().map(|dir| vec![dir]))
+        .unwrap_or_else(|| vec!["./backups".to_string()]);
+    let backup_policy = config.backup_policy.unwrap_or_default();
+    let upload_limit = config.upload_limit.as_deref().map(units::parse_rate).transpose()?;
+    let readahead = config.readahead.unwrap_or(false);
+    let incremental = config.incremental.unwrap_or(false);
+    let verify_sample = config.verify_sample;
+    let verify_archive = config.verify_archive.unwrap_or(false);
+    let skip_space_check = config.skip_space_check.unwrap_or(false);
+    let hash_algo = config.hash_algo.unwrap_or_default();
+    let jobs = config.jobs.unwrap_or(1);
+    let tunings = mounts::resolve(&config.mounts.clone().unwrap_or_default())?;
+    let free_goal = config.free.as_deref().map(units::parse_size).transpose()?;
+    let max_dirs = config.max_dirs;
+    let max_total = config.max_total.as_deref().map(units::parse_size).transpose()?;
+    let purge_files_older_than = config.purge_files_older_than;
+    // Thinning frees an unknown part of each directory
+    if free_goal.is_some() && purge_files_older_than.is_some() {
+        return Err(format!("{} --free can't be combined with --purge-files-older-than", CROSS));
+    }
+    let sudo_helper = config.sudo_helper.clone();
+    let elevate = config.elevate.unwrap_or(false);
+    let respect_locks = !config.ignore_locks.unwrap_or(false);
+    let check_processes = !config.ignore_processes.unwrap_or(false);
+    let stop_daemons = config.stop_daemons.unwrap_or(false)
+        .then(|| config.daemon_commands.clone().unwrap_or_default());
+    let actions = config.actions.clone().unwrap_or_default();
+    let git_safe = config.git_safe.unwrap_or(false);
+    let journal_path = config.journal.clone();
+    journal::checkpoint_every(config.checkpoint_interval);
+    // Listed paths have no search root to stay inside
+    let root = (paths_from.is_none() && !config.allow_outside_root.unwrap_or(false))
+        .then(|| base_path.to_string());
     let interactive = config.interactive.unwrap_or(false);
     let confirm_phrase = config.confirm_phrase.clone();
-    let json_output = config.json.clone();
-    let csv_output = config.csv.clone();
+    let exports = Exports::of(&config);
     let verbose = config.verbose.unwrap_or(false);
-    let quiet = config.quiet.unwrap_or(false);
+
+    // Refuse up front instead of failing on every directory
+    #[cfg(not(feature = "trash"))]
+    if use_trash || actions.values().any(|a| *a == TargetAction::Trash) {
+        return Err(feature_disabled("trash"));
+    }
+    #[cfg(not(feature = "archive"))]
+    if archive || actions.values().any(|a| *a == TargetAction::ArchiveDelete) {
+        return Err(feature_disabled("archive"));
+    }
+
+    if config.history.is_none() && (config.growth_alert.is_some() || config.growth_webhook.is_some()) {
+        return Err(format!("{} --growth-alert and --growth-webhook need --history FILE to compare against", CROSS));
+    }
+
+    // A slip of the search path shouldn't be able to purge a whole disk or home directory
+    if delete_enabled && !dry_run && paths_from.is_none() && !config.allow_dangerous_root.unwrap_or(false)
+        && let Some(what) = protected::dangerous_root(Path::new(base_path))
+    {
+        return Err(format!("{} {} is {}, pass --allow-dangerous-root to delete there", CROSS, base_path, what));
+    }
+
+    // stdin can't carry both the path list and the confirmation phrase
+    if paths_from.is_some_and(|source| source == "-") && (delete_enabled || dry_run) && !yes {
+        return Err(format!("{} --paths-from - reads stdin, combine it with --yes to delete", CROSS));
+    }
 
     // Show banner and configuration summary
-    if !quiet {
-        println!("\n{} {} v1.0.0", GEAR, bold().apply_to("🧹 dirpurge"));
-        println!("{} {}", MAG, cyan().apply_to(format!("Searching in: {}", base_path)));
-        println!("{} {}", MAG, cyan().apply_to(format!("Targets: {}", target.join(", "))));
-        
-        if !exclude.is_empty() {
-            println!("{} {}", MAG, cyan().apply_to(format!("Excluding: {}", exclude.join(", "))));
-        }
-        
-        if verbose {
-            println!("{} {}", MAG, cyan().apply_to(format!("Depth: {}", depth.map_or("unlimited".to_string(), |d| d.to_string()))));
-            println!("{} {}", MAG, cyan().apply_to(format!("Min size: {}", min_size.map_or("none".to_string(), |s| format!("{:.2} MB", s as f64 / 1024.0 / 1024.0)))));
-            println!("{} {}", MAG, cyan().apply_to(format!("Min age: {}", min_age.map_or("none".to_string(), |a| format!("{} days", a)))));
-            println!("{} {}", MAG, cyan().apply_to(format!("Follow symlinks: {}", follow_symlinks)));
-            println!("{} {}", MAG, cyan().apply_to(format!("Mode: {}", if dry_run { "DRY RUN" } else if delete_enabled { "DELETE" } else { "SCAN ONLY" })));
-        }
+    report::banner("1.0.0");
+    if let Some(source) = paths_from {
+        report::message(Kind::Setting, format!("Reading directories from: {}", source));
+    } else {
+        report::message(Kind::Setting, format!("Searching in: {}", base_path));
+        report::message(Kind::Setting, format!("Targets: {}", target.join(", ")));
+    }
+    
+    if !exclude.is_empty() {
+        report::message(Kind::Setting, format!("Excluding: {}", exclude.join(", ")));
+    }
+    
+    report::detail(Kind::Setting, format!("Depth: {}", depth.map_or("unlimited".to_string(), |d| d.to_string())));
+    report::detail(Kind::Setting, format!("Min depth: {}", min_depth.unwrap_or(0)));
+    report::detail(Kind::Setting, format!("Min size: {}", min_size.map_or("none".to_string(), |s| format!("{:.2} MB", s as f64 / 1024.0 / 1024.0))));
+    report::detail(Kind::Setting, format!("Max size: {}", max_size.map_or("none".to_string(), |s| format!("{:.2} MB", s as f64 / 1024.0 / 1024.0))));
+    report::detail(Kind::Setting, format!("Items: {}..{}", min_items.map_or(String::new(), |n| n.to_string()), max_items.map_or(String::new(), |n| n.to_string())));
+    report::detail(Kind::Setting, format!("Min age: {}", min_age.map_or("none".to_string(), |a| format!("{} days", a))));
+    report::detail(Kind::Setting, format!("Max age: {}", max_age.map_or("none".to_string(), |a| format!("{} days", a))));
+    if let Some(before) = &config.modified_before {
+        report::detail(Kind::Setting, format!("Modified before: {}", before));
+    }
+    if let Some(after) = &config.modified_after {
+        report::detail(Kind::Setting, format!("Modified after: {}", after));
+    }
+    report::detail(Kind::Setting, format!("Age source: {:?}", age_source));
+    if let Some(uid) = owner_uid {
+        report::detail(Kind::Setting, format!("Owner uid: {}", uid));
+    }
+    report::detail(Kind::Setting, format!("Min access age: {}", min_atime_age.map_or("none".to_string(), |a| format!("{} days", a))));
+    if let Some(days) = repo_inactive_days {
+        report::detail(Kind::Setting, format!("Repo inactive for: {} days", days));
     }
+    if let Some(keep) = keep_latest {
+        report::detail(Kind::Setting, format!("Keep latest: {} per parent", keep));
+    }
+    report::detail(Kind::Setting, format!("Follow symlinks: {}", follow_symlinks_traversal));
+    report::detail(Kind::Setting, format!("Count symlinked sizes: {}", count_symlinked_sizes));
+    report::detail(Kind::Setting, format!("One file system: {}", one_file_system));
+    report::detail(Kind::Setting, format!("Size mode: {:?}", size_mode));
+    report::detail(Kind::Setting, format!("Estimate sizes: {}", estimate));
+    report::detail(Kind::Setting, format!("Respect ignore files: {}", respect_ignore));
+    report::detail(Kind::Setting, format!("Prune matches: {}", prune));
+    report::detail(Kind::Setting, format!("Require manifest: {}", manifests.is_some()));
+    report::detail(Kind::Setting, format!("Git safe: {}", git_safe));
+    if !actions.is_empty() {
+        let mut listed: Vec<String> = actions.iter().map(|(t, a)| format!("{}={}", t, a)).collect();
+        listed.sort();
+        report::detail(Kind::Setting, format!("Actions: {}", listed.join(", ")));
+    }
+    report::detail(Kind::Setting, format!("Mode: {}", if dry_run { "DRY RUN" } else if delete_enabled { "DELETE" } else { "SCAN ONLY" }));
 
     // Find matching directories
-    let mut dirs = find_directories(
-        base_path,
-        &target,
-        &exclude,
+    let scan_options = ScanOptions {
+        rules: Rules {
+            target: target.clone(),
+            exclude: exclude.clone(),
+            min_size,
+            max_size,
+            min_items,
+            max_items,
+            min_age,
+            max_age,
+            modified_before,
+            modified_after,
+            min_atime_age,
+            owner_uid,
+            repo_inactive_days,
+        },
         depth,
-        min_size,
-        min_age,
-        follow_symlinks,
+        min_depth,
+        age_source,
+        follow_symlinks_traversal,
+        count_symlinked_sizes,
+        one_file_system,
+        count_hardlinks,
+        size_mode,
+        estimate,
+        reclaimable,
+        mounts: tunings.clone(),
+        respect_ignore,
+        prune,
+        manifests,
+        actions: actions.clone(),
+        keep_latest,
+        reserved: reserved::Reserved::new(&backup_dirs, quarantine::resolve(config.quarantine_dir.as_deref()).ok()),
         verbose,
-    );
+    };
+    let rules = policy::effective(base_path, &scan_options);
+    let mut stream = config.stream.as_deref().map(ResultStream::open).transpose()?;
+    let mut on_found = |info: &DirInfo| {
+        if let Some(stream) = stream.as_mut() {
+            stream.emit(info);
+        }
+    };
+    let mut dirs = match paths_from {
+        Some(source) => directories_from_list(read_path_list(source, purge_list.is_some())?, &scan_options, &mut on_found),
+        None => find_directories(base_path, &scan_options, &mut on_found),
+    };
+    if let Some(keep) = keep_latest {
+        dirs = spare_latest(dirs, keep, &scan_options);
+    }
     
     // Sort directories by size (largest first)
-    dirs.sort_by(|a, b| b.size_bytes.cmp(&a.size_bytes));
+    dirs.sort_by_key(|d| std::cmp::Reverse(d.size_bytes));
+    // Report-only matches are listed and exported but never offered for deletion
+    let (inventory, dirs): (Vec<DirInfo>, Vec<DirInfo>) = dirs.into_iter().partition(|d| d.report_only);
 
-    // Handle when no matching directories are found
-    if dirs.is_empty() {
-        info!("No matching directories found");
-        println!("{} {}", INFO, yellow().apply_to("No matching directories found"));
+    if check {
+        let total_size: u64 = dirs.iter().map(|d| d.size_bytes).sum();
+        report::message(Kind::Info, format!("{} directories to purge, {}", locale::count(dirs.len()), format_mb(total_size, estimate)));
+        ACTION_NEEDED.store(!dirs.is_empty(), Ordering::Relaxed);
         return Ok(());
     }
 
-    // Show found directories
-    if !quiet {
-        println!("\n{} {} matching directories found:", TICK, bold().apply_to(dirs.len()));
-        
-        let total_size: u64 = dirs.iter().map(|d| d.size_bytes).sum();
-        println!("{} Total size: {:.2} MB", INFO, total_size as f64 / 1024.0 / 1024.0);
-        
-        for (i, dir) in dirs.iter().enumerate().take(10) {
-            println!("  {}. {} ({:.2} MB)", 
-                i + 1,
-                dir.path,
-                dir.size_bytes as f64 / 1024.0 / 1024.0
-            );
-        }
-        
-        if dirs.len() > 10 {
-            println!("  ... and {} more", dirs.len() - 10);
+    report::found(&dirs, &inventory, estimate);
+
+    if let Some(history_path) = &config.history {
+        history::check_growth(&inventory, history_path, config.growth_alert, config.growth_webhook.as_deref())?;
+    }
+
+    // Handle when no matching directories are found
+    if dirs.is_empty() {
+        let message = if inventory.is_empty() { "No matching directories found" } else { "No deletable directories found" };
+        info!("{}", message);
+        report::message(Kind::Info, message);
+        if !inventory.is_empty() && exports.any() {
+            export_summary(&[], &inventory, &exports, &[], &rules)?;
         }
+        return Ok(());
     }
-    
+
     // Interactive mode - select directories to delete
     let selected_dirs = if interactive {
         interactive_select_directories(&dirs)
@@ -958,12 +4225,78 @@ fn main() -> Result<(), String> {
     
     // If no directories were selected in interactive mode
     if selected_dirs.is_empty() && interactive {
-        println!("{} No directories selected for deletion", INFO);
+        report::message(Kind::Info, "No directories selected for deletion");
         return Ok(());
     }
     
+    let delete_options = DeleteOptions {
+        dry_run,
+        verbose,
+        use_trash,
+        quarantine,
+        backup,
+        archive,
+        archive_format,
+        compression_level,
+        archive_manifest,
+        archive_top_dir,
+        archive_split_size,
+        backup_exclude,
+        s3_storage_class,
+        backup_name_template,
+        backup_preserve,
+        backup_strategy,
+        backup_dirs,
+        backup_policy,
+        prune_backups: config.prune_backups,
+        upload_limit,
+        readahead,
+        incremental,
+        interactive: false, // Interactive selection already done
+        verify_sample,
+        verify_archive,
+        skip_space_check,
+        hash_algo,
+        sudo_helper,
+        respect_locks,
+        check_processes,
+        stop_daemons,
+        actions,
+        git_safe,
+        root,
+        backed_up: HashMap::new(),
+        copying: HashMap::new(),
+        jobs,
+        chunk_above: config.chunk_above,
+        free_goal,
+        mounts: tunings,
+        max_dirs,
+        max_total,
+        purge_files_older_than,
+        heartbeat: config.heartbeat.map(Duration::from_secs),
+        heartbeat_webhook: config.heartbeat_webhook.clone(),
+    };
+
+    // Save the selection for review instead of acting on it
+    if let Some(plan_path) = matches.get_one::<String>("save-plan") {
+        let selected_dirs = size_exactly(selected_dirs, &scan_options);
+        let mut plan = plan::Plan::new(base_path, &selected_dirs, &delete_options);
+        plan.approval = config.approval.as_ref().and_then(plan::RequiredApproval::of);
+        plan::save_plan(&plan, plan_path)?;
+        info!("Saved plan with {} directories to {}", selected_dirs.len(), plan_path);
+        report::message(Kind::Saved, format!("Plan saved to {}", plan_path));
+        report::detail(Kind::Setting, format!("Confirm token: {}", plan.confirm_token()?));
+        return Ok(());
+    }
+
     // Backup/delete only if requested
     if delete_enabled || dry_run {
+        let (selected_dirs, elevated) = permissions::partition(selected_dirs, delete_options.sudo_helper.as_deref(), elevate);
+        if selected_dirs.is_empty() && elevated.is_empty() {
+            report::message(Kind::Info, "No directories left to delete");
+            return Ok(());
+        }
+
         // Skip confirmation if yes flag is provided
         let confirmed = if yes {
             true
@@ -972,35 +4305,47 @@ fn main() -> Result<(), String> {
         };
         
         if confirmed {
-            let backup_paths = delete_directories(
-                &selected_dirs,
-                dry_run,
-                verbose,
-                use_trash,
-                backup,
-                archive,
-                Some(backup_dir.as_str()),
-                false // Interactive selection already done
-            )?;
+            let selected_dirs = size_exactly(selected_dirs, &scan_options);
+            if !dry_run {
+                journal::begin(&journal::resolve(journal_path.as_deref())?, &plan::Plan::new(base_path, &selected_dirs, &delete_options))?;
+            }
+            let deleted = delete_directories(&selected_dirs, &delete_options);
+            if let Some(history_path) = &config.history {
+                history::record_actions(history_path)?;
+            }
+            let backup_paths = deleted?;
             
             // Export summary if requested
-            if json_output.is_some() || csv_output.is_some() {
+            if exports.any() {
                 export_summary(
                     &selected_dirs,
-                    json_output.as_deref(),
-                    csv_output.as_deref(),
+                    &inventory,
+                    &exports,
                     &backup_paths,
+                    &rules,
                 )?;
             }
+
+            if !elevated.is_empty() {
+                let elevated = size_exactly(elevated, &scan_options);
+                apply_elevated_plan(base_path, &elevated, &delete_options)?;
+            }
         } else {
-            println!("{} {}", INFO, yellow().apply_to("Operation canceled"));
+            report::message(Kind::Info, "Operation canceled");
             return Ok(());
         }
-    } else if !quiet {
-        println!("\n{} {}", 
-            INFO,
-            yellow().apply_to("Use --delete to remove directories or --dry-run to simulate")
-        );
+    } else {
+        // Scan-only runs still record the inventory, and the treemap shows
+        // what a purge would take
+        let treemap = exports.treemap.clone();
+        let exports = Exports { treemap: None, ..exports };
+        if !inventory.is_empty() && exports.any() {
+            export_summary(&[], &inventory, &exports, &[], &rules)?;
+        }
+        if let Some(treemap_file) = treemap {
+            export_treemap(&treemap_file, &dirs, &inventory);
+        }
+        report::message(Kind::Hint, "Use --delete to remove directories or --dry-run to simulate");
     }
 
     Ok(())
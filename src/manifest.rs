@@ -0,0 +1,79 @@
+// The list of files an archive holds, stored as its last entry and optionally
+// beside it, so what was archived can be audited without unpacking it.
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::io::{self, Read};
+
+// Entry name inside archives; restores and searches leave it out
+pub const NAME: &str = ".dirpurge-manifest.json";
+
+// Bump when the layout changes incompatibly; 2 added `prefix`
+const FORMAT: u32 = 2;
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Manifest {
+    #[serde(rename = "v")]
+    pub version: u32,
+    // Absolute path of the archived directory, where unarchive puts it back
+    pub source: String,
+    pub created_at: String,
+    // What every entry is stored under, with --archive-top-dir: the name of
+    // the archived directory. File paths below are relative to it all the same.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prefix: Option<String>,
+    pub files: Vec<ManifestFile>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ManifestFile {
+    // Relative to the archived directory, `/`-separated
+    pub path: String,
+    pub size: u64,
+    // Seconds since the Unix epoch
+    pub mtime: u64,
+    pub sha256: String,
+}
+
+impl Manifest {
+    pub fn new(source: &str) -> Self {
+        Manifest {
+            version: FORMAT,
+            source: source.to_string(),
+            created_at: chrono::Local::now().to_rfc3339(),
+            prefix: None,
+            files: Vec::new(),
+        }
+    }
+
+    pub fn to_json(&self) -> io::Result<Vec<u8>> {
+        serde_json::to_vec_pretty(self).map_err(io::Error::other)
+    }
+}
+
+// Hashes what passes through it, so files are checksummed as they are archived
+pub struct Hashing<R> {
+    inner: R,
+    hasher: Sha256,
+    read: u64,
+}
+
+impl<R: Read> Hashing<R> {
+    pub fn new(inner: R) -> Self {
+        Hashing { inner, hasher: Sha256::new(), read: 0 }
+    }
+
+    // Bytes read and their SHA-256 in hex
+    pub fn finish(self) -> (u64, String) {
+        let digest = self.hasher.finalize();
+        (self.read, digest.iter().map(|b| format!("{:02x}", b)).collect())
+    }
+}
+
+impl<R: Read> Read for Hashing<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.hasher.update(&buf[..n]);
+        self.read += n as u64;
+        Ok(n)
+    }
+}
@@ -0,0 +1,196 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{Condvar, Mutex},
+};
+use log::debug;
+
+use crate::{protected, units, SizeMode, CROSS};
+
+// Settings for everything under one mount point or path prefix, as configured
+// under `mounts`; unset fields fall back to the global ones
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct MountConfig {
+    pub jobs: Option<usize>,
+    pub upload_limit: Option<String>,
+    pub estimate: Option<bool>,
+    pub size_mode: Option<SizeMode>,
+}
+
+// A parsed `mounts` entry
+#[derive(Debug, Clone)]
+pub struct Tuning {
+    pub prefix: PathBuf,
+    pub jobs: Option<usize>,
+    pub upload_limit: Option<u64>,
+    pub estimate: Option<bool>,
+    pub size_mode: Option<SizeMode>,
+}
+
+// Longest prefixes first, so the most specific entry wins
+pub fn resolve(configured: &HashMap<String, MountConfig>) -> Result<Vec<Tuning>, String> {
+    let mut tunings = configured.iter()
+        .map(|(prefix, config)| {
+            if config.jobs == Some(0) {
+                return Err(format!("{} Invalid mounts entry '{}': jobs must be at least 1", CROSS, prefix));
+            }
+            let upload_limit = config.upload_limit.as_deref()
+                .map(units::parse_rate)
+                .transpose()
+                .map_err(|e| format!("{} Invalid mounts entry '{}': {}", CROSS, prefix, e))?;
+            let prefix = protected::expand(prefix);
+            Ok(Tuning {
+                prefix: prefix.canonicalize().unwrap_or(prefix),
+                jobs: config.jobs,
+                upload_limit,
+                estimate: config.estimate,
+                size_mode: config.size_mode,
+            })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    tunings.sort_by_key(|t| std::cmp::Reverse(t.prefix.components().count()));
+    Ok(tunings)
+}
+
+// Index of the entry `path` lives under, judged by where it really is
+pub fn index_for(tunings: &[Tuning], path: &Path) -> Option<usize> {
+    if tunings.is_empty() {
+        return None;
+    }
+    let resolved = path.canonicalize()
+        .or_else(|_| std::env::current_dir().map(|cwd| cwd.join(path)))
+        .unwrap_or_else(|_| path.to_path_buf());
+    let index = tunings.iter().position(|t| resolved.starts_with(&t.prefix));
+    if let Some(index) = index {
+        debug!("{} uses the settings for {}", path.display(), tunings[index].prefix.display());
+    }
+    index
+}
+
+pub fn for_path<'a>(tunings: &'a [Tuning], path: &Path) -> Option<&'a Tuning> {
+    index_for(tunings, path).map(|index| &tunings[index])
+}
+
+// How many directories of each group may be worked on at once
+pub struct Slots {
+    limits: Vec<usize>,
+    busy: Mutex<Vec<usize>>,
+    released: Condvar,
+}
+
+pub struct Slot<'a> {
+    slots: &'a Slots,
+    group: usize,
+}
+
+impl Slots {
+    pub fn new(limits: Vec<usize>) -> Self {
+        let busy = Mutex::new(vec![0; limits.len()]);
+        Slots { limits, busy, released: Condvar::new() }
+    }
+
+    // Wait for a free slot in `group`, held until the returned guard is dropped
+    pub fn acquire(&self, group: usize) -> Slot<'_> {
+        let mut busy = self.busy.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        while busy[group] >= self.limits[group] {
+            busy = self.released.wait(busy).unwrap_or_else(|poisoned| poisoned.into_inner());
+        }
+        busy[group] += 1;
+        Slot { slots: self, group }
+    }
+}
+
+impl Drop for Slot<'_> {
+    fn drop(&mut self) {
+        let mut busy = self.slots.busy.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        busy[self.group] -= 1;
+        self.slots.released.notify_all();
+    }
+}
+
+// The type of filesystem `path` is on, as the OS names it ("ext4", "btrfs",
+// "apfs"), or "unknown". `path` has to exist.
+#[cfg(target_os = "linux")]
+pub fn filesystem(path: &Path) -> String {
+    use std::sync::OnceLock;
+
+    // (mount point, type), longest mount points first
+    static MOUNTED: OnceLock<Vec<(PathBuf, String)>> = OnceLock::new();
+    let mounted = MOUNTED.get_or_init(|| {
+        let table = std::fs::read_to_string("/proc/self/mounts").unwrap_or_default();
+        let mut mounted: Vec<(PathBuf, String)> = table.lines()
+            .filter_map(|line| {
+                let mut fields = line.split(' ').skip(1);
+                Some((PathBuf::from(unescape_mount(fields.next()?)), fields.next()?.to_string()))
+            })
+            .collect();
+        // Later mounts hide earlier ones at the same point, so they stay ahead
+        mounted.reverse();
+        mounted.sort_by_key(|(point, _)| std::cmp::Reverse(point.components().count()));
+        mounted
+    });
+    let Ok(resolved) = path.canonicalize() else {
+        return "unknown".to_string();
+    };
+    mounted.iter()
+        .find(|(point, _)| resolved.starts_with(point))
+        .map_or_else(|| "unknown".to_string(), |(_, kind)| kind.clone())
+}
+
+// Spaces, tabs and backslashes in mount points are octal escapes
+#[cfg(target_os = "linux")]
+fn unescape_mount(field: &str) -> String {
+    field.replace("\\040", " ").replace("\\011", "\t").replace("\\012", "\n").replace("\\134", "\\")
+}
+
+#[cfg(target_os = "macos")]
+pub fn filesystem(path: &Path) -> String {
+    use std::{ffi::{CStr, CString}, os::unix::ffi::OsStrExt};
+
+    let Ok(path) = CString::new(path.as_os_str().as_bytes()) else {
+        return "unknown".to_string();
+    };
+    let mut stats: libc::statfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statfs(path.as_ptr(), &mut stats) } != 0 {
+        return "unknown".to_string();
+    }
+    unsafe { CStr::from_ptr(stats.f_fstypename.as_ptr()) }.to_string_lossy().into_owned()
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+pub fn filesystem(_path: &Path) -> String {
+    "unknown".to_string()
+}
+
+// Bytes that can still be written to the filesystem `path` is on, by this
+// user; None where it can't be told. `path` has to exist.
+#[cfg(unix)]
+pub fn free_space(path: &Path) -> Option<u64> {
+    use std::{ffi::CString, os::unix::ffi::OsStrExt};
+
+    let path = CString::new(path.as_os_str().as_bytes()).ok()?;
+    let mut stats: libc::statvfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statvfs(path.as_ptr(), &mut stats) } != 0 {
+        return None;
+    }
+    Some(stats.f_bavail as u64 * stats.f_frsize as u64)
+}
+
+#[cfg(windows)]
+pub fn free_space(path: &Path) -> Option<u64> {
+    use std::os::windows::ffi::OsStrExt;
+    use windows_sys::Win32::Storage::FileSystem::GetDiskFreeSpaceExW;
+
+    let wide: Vec<u16> = path.as_os_str().encode_wide().chain(Some(0)).collect();
+    let mut available = 0u64;
+    if unsafe { GetDiskFreeSpaceExW(wide.as_ptr(), &mut available, std::ptr::null_mut(), std::ptr::null_mut()) } == 0 {
+        return None;
+    }
+    Some(available)
+}
+
+#[cfg(not(any(unix, windows)))]
+pub fn free_space(_path: &Path) -> Option<u64> {
+    None
+}
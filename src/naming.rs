@@ -0,0 +1,157 @@
+// Where backups go inside a backup directory with --backup-name-template, e.g.
+// `{project}/{name}-{date}-{hash}.{ext}`, so they can be kept per project
+// rather than side by side as `<name>_<timestamp>`. Names taken already get
+// `_2`, `_3`, ... before the extension.
+use chrono::{DateTime, Local};
+use regex::Regex;
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+use crate::CROSS;
+
+const EXT: &str = ".{ext}";
+
+#[derive(Debug, Clone)]
+pub struct NameTemplate {
+    // The template without its `.{ext}`, which archives get either way
+    stem: String,
+}
+
+// One value filled in for a backup of a directory
+fn value(placeholder: &str, dir: &Path, now: &DateTime<Local>) -> String {
+    let name = |path: Option<&Path>| path.and_then(Path::file_name).map(|name| name.to_string_lossy().into_owned());
+    match placeholder {
+        // The project the directory belongs to, the one it sits in
+        "project" => name(dir.parent()).unwrap_or_else(|| "_".to_string()),
+        "name" => name(Some(dir)).unwrap_or_else(|| "_".to_string()),
+        "date" => now.format("%Y%m%d").to_string(),
+        "time" => now.format("%H%M%S").to_string(),
+        // Tells apart directories of the same name in different places
+        "hash" => {
+            let absolute = std::path::absolute(dir).unwrap_or_else(|_| dir.to_path_buf());
+            Sha256::digest(absolute.to_string_lossy().as_bytes()).iter().take(4).map(|b| format!("{:02x}", b)).collect()
+        }
+        _ => unreachable!("placeholders are checked when parsed"),
+    }
+    // Never a separator of its own
+    .replace(['/', '\\'], "_")
+}
+
+// What a placeholder of a backup made at any time matches, for `dir` or for
+// any directory
+fn pattern(placeholder: &str, dir: Option<&Path>) -> String {
+    match (placeholder, dir) {
+        ("date", _) => r"\d{8}".to_string(),
+        ("time", _) => r"\d{6}".to_string(),
+        ("hash", None) => "[0-9a-f]{8}".to_string(),
+        (_, None) => "[^/]+".to_string(),
+        (placeholder, Some(dir)) => regex::escape(&value(placeholder, dir, &Local::now())),
+    }
+}
+
+impl NameTemplate {
+    pub fn parse(template: &str) -> Result<Self, String> {
+        let invalid = |why: String| format!("{} Invalid --backup-name-template '{}': {}", CROSS, template, why);
+        let stem = template.strip_suffix(EXT).unwrap_or(template);
+        let mut rest = stem;
+        while let Some(open) = rest.find(['{', '}']) {
+            if rest[open..].starts_with('}') {
+                return Err(invalid("unmatched '}'".to_string()));
+            }
+            let close = rest[open..].find('}').ok_or_else(|| invalid("unmatched '{'".to_string()))? + open;
+            match &rest[open + 1..close] {
+                "ext" => return Err(invalid("{ext} only goes at the end, as .{ext}".to_string())),
+                "project" | "name" | "date" | "time" | "hash" => {}
+                other => return Err(invalid(format!("unknown placeholder {{{}}}, use {{project}}, {{name}}, {{date}}, {{time}}, {{hash}} or {{ext}}", other))),
+            }
+            rest = &rest[close + 1..];
+        }
+        if stem.starts_with(['/', '\\']) || stem.contains('\\') || stem.contains(':')
+            || stem.split('/').any(|part| part.is_empty() || part == "." || part == "..")
+        {
+            return Err(invalid("names a path outside the backup directory".to_string()));
+        }
+        Ok(NameTemplate { stem: stem.to_string() })
+    }
+
+    // The template's placeholders filled in by `fill`, the text between them by `literal`
+    fn expand(&self, mut fill: impl FnMut(&str) -> String, literal: impl Fn(&str) -> String) -> String {
+        let mut out = String::new();
+        let mut rest = self.stem.as_str();
+        while let Some(open) = rest.find('{') {
+            let close = rest[open..].find('}').expect("braces are checked when parsed") + open;
+            out += &literal(&rest[..open]);
+            out += &fill(&rest[open + 1..close]);
+            rest = &rest[close + 1..];
+        }
+        out + &literal(rest)
+    }
+
+    // Where, relative to the backup directory and before any numbering or
+    // extension, a backup of `dir` made `now` goes
+    pub fn render(&self, dir: &Path, now: &DateTime<Local>) -> PathBuf {
+        PathBuf::from(self.expand(|placeholder| value(placeholder, dir, now), str::to_string))
+    }
+
+    // Matches the paths, relative to the backup directory and with `/`
+    // between parts, of what the template names: the backups of `dir` made
+    // at any time, or of any directory. Captures the date, time and number.
+    fn regex(&self, dir: Option<&Path>) -> Regex {
+        let mut seen = Vec::new();
+        let body = self.expand(
+            |placeholder| {
+                let pattern = pattern(placeholder, dir);
+                // Times are compared by the first of each
+                if matches!(placeholder, "date" | "time") && !seen.contains(&placeholder.to_string()) {
+                    seen.push(placeholder.to_string());
+                    return format!("(?P<{}>{})", placeholder, pattern);
+                }
+                pattern
+            },
+            regex::escape,
+        );
+        Regex::new(&format!(r"^{}(?:_(?P<n>\d+))?(?:\.[a-z0-9.]+)?$", body)).expect("valid regex")
+    }
+
+    // What the template could have named in `root`: only ever at its depth,
+    // and only in its shape, so nothing else kept there is taken for a backup
+    pub fn stored(&self, root: &Path) -> Vec<PathBuf> {
+        let depth = self.stem.matches('/').count() + 1;
+        let shape = self.regex(None);
+        WalkDir::new(root).min_depth(depth).max_depth(depth).into_iter()
+            .filter_map(|e| e.ok())
+            .filter(|entry| entry.path().strip_prefix(root).is_ok_and(|relative| shape.is_match(&relative.to_string_lossy().replace('\\', "/"))))
+            .map(|entry| entry.into_path())
+            .collect()
+    }
+
+    // The newest backup of `dir` in `root` other than `current`, by the time
+    // in its name and then its number
+    pub fn previous(&self, root: &Path, dir: &Path, current: &Path) -> Option<PathBuf> {
+        let own = self.regex(Some(dir));
+        self.stored(root).into_iter()
+            .filter(|backup| backup != current && backup.is_dir())
+            .filter_map(|backup| {
+                let relative = backup.strip_prefix(root).ok()?.to_string_lossy().replace('\\', "/");
+                let captures = own.captures(&relative)?;
+                let part = |name| captures.name(name).map_or(String::new(), |m| m.as_str().to_string());
+                let counter: u64 = captures.name("n").map_or(1, |n| n.as_str().parse().unwrap_or(1));
+                Some(((part("date"), part("time"), counter), backup))
+            })
+            .max_by(|a, b| a.0.cmp(&b.0))
+            .map(|(_, backup)| backup)
+    }
+}
+
+// `stem`, then `stem_2`, `stem_3`, ... with `ext` after, if any
+pub fn numbered(stem: &Path, n: usize, ext: Option<&str>) -> PathBuf {
+    let mut name = stem.as_os_str().to_os_string();
+    if n > 1 {
+        name.push(format!("_{}", n));
+    }
+    if let Some(ext) = ext {
+        name.push(format!(".{}", ext));
+    }
+    PathBuf::from(name)
+}
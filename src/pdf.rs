@@ -0,0 +1,222 @@
+// The run summary as a PDF, for change tickets that take nothing else. The
+// PDF is written by hand: A4 pages, the standard Helvetica fonts and no
+// compression, which every viewer can open.
+use std::{fs, io};
+
+use crate::{format_mb, locale, skipped::SkippedDir, DirInfo};
+
+const WIDTH: f64 = 595.0;
+const HEIGHT: f64 = 842.0;
+const MARGIN: f64 = 50.0;
+const LINE: f64 = 14.0;
+// Bars in the chart of the largest directories
+const TOP: usize = 10;
+
+#[derive(Clone, Copy)]
+enum Font {
+    Regular,
+    Bold,
+}
+
+// Pages of content streams, laid out top to bottom
+struct Document {
+    pages: Vec<Vec<u8>>,
+    y: f64,
+}
+
+impl Document {
+    fn new() -> Self {
+        Document { pages: vec![Vec::new()], y: HEIGHT - MARGIN }
+    }
+
+    fn page(&mut self) -> &mut Vec<u8> {
+        self.pages.last_mut().expect("a page")
+    }
+
+    // Room for `height` more points, starting a new page if it isn't left
+    fn reserve(&mut self, height: f64) {
+        if self.y - height < MARGIN {
+            self.pages.push(Vec::new());
+            self.y = HEIGHT - MARGIN;
+        }
+    }
+
+    fn text(&mut self, x: f64, y: f64, size: f64, font: Font, text: &str) {
+        let name = match font {
+            Font::Regular => "F1",
+            Font::Bold => "F2",
+        };
+        let page = self.page();
+        page.extend_from_slice(format!("BT /{} {} Tf {:.1} {:.1} Td (", name, size, x, y).as_bytes());
+        page.extend(escaped(text));
+        page.extend_from_slice(b") Tj ET\n");
+    }
+
+    fn rect(&mut self, x: f64, y: f64, width: f64, height: f64, gray: f64) {
+        self.page().extend_from_slice(format!("{:.2} g {:.1} {:.1} {:.1} {:.1} re f 0 g\n", gray, x, y, width, height).as_bytes());
+    }
+
+    fn heading(&mut self, text: &str) {
+        self.reserve(LINE * 3.0);
+        self.y -= LINE * 1.5;
+        let y = self.y;
+        self.text(MARGIN, y, 13.0, Font::Bold, text);
+        self.y -= LINE;
+    }
+
+    // One table row; columns start at the given offsets and are cut to fit
+    fn row(&mut self, columns: &[(f64, &str)], font: Font) {
+        self.reserve(LINE);
+        let y = self.y;
+        for (i, (x, text)) in columns.iter().enumerate() {
+            let room = columns.get(i + 1).map_or(WIDTH - MARGIN, |(next, _)| MARGIN + next) - (MARGIN + x) - 6.0;
+            self.text(MARGIN + x, y, 9.0, font, &fitted(text, room, 9.0));
+        }
+        self.y -= LINE;
+    }
+
+    fn finish(self) -> Vec<u8> {
+        let mut out = b"%PDF-1.4\n".to_vec();
+        let mut offsets = Vec::new();
+        let mut object = |out: &mut Vec<u8>, body: &[u8]| {
+            offsets.push(out.len());
+            out.extend_from_slice(format!("{} 0 obj\n", offsets.len()).as_bytes());
+            out.extend_from_slice(body);
+            out.extend_from_slice(b"\nendobj\n");
+        };
+        // 1 catalog, 2 page tree, 3-4 fonts, then a page and its contents each
+        let kids: Vec<String> = (0..self.pages.len()).map(|i| format!("{} 0 R", 5 + i * 2)).collect();
+        object(&mut out, b"<< /Type /Catalog /Pages 2 0 R >>");
+        object(&mut out, format!("<< /Type /Pages /Kids [{}] /Count {} >>", kids.join(" "), kids.len()).as_bytes());
+        object(&mut out, b"<< /Type /Font /Subtype /Type1 /BaseFont /Helvetica /Encoding /WinAnsiEncoding >>");
+        object(&mut out, b"<< /Type /Font /Subtype /Type1 /BaseFont /Helvetica-Bold /Encoding /WinAnsiEncoding >>");
+        for (i, content) in self.pages.iter().enumerate() {
+            object(&mut out, format!(
+                "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {} {}] /Resources << /Font << /F1 3 0 R /F2 4 0 R >> >> /Contents {} 0 R >>",
+                WIDTH, HEIGHT, 6 + i * 2
+            ).as_bytes());
+            let mut stream = format!("<< /Length {} >>\nstream\n", content.len()).into_bytes();
+            stream.extend_from_slice(content);
+            stream.extend_from_slice(b"\nendstream");
+            object(&mut out, &stream);
+        }
+        let xref = out.len();
+        out.extend_from_slice(format!("xref\n0 {}\n0000000000 65535 f \n", offsets.len() + 1).as_bytes());
+        for offset in &offsets {
+            out.extend_from_slice(format!("{:010} 00000 n \n", offset).as_bytes());
+        }
+        out.extend_from_slice(format!("trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{}\n%%EOF\n", offsets.len() + 1, xref).as_bytes());
+        out
+    }
+}
+
+// The WinAnsi byte for `c`, `?` for what the standard fonts can't show
+fn winansi(c: char) -> u8 {
+    match c {
+        ' '..='~' | '\u{a0}'..='\u{ff}' => c as u8,
+        '\u{202f}' => 0xa0,
+        '\u{20ac}' => 0x80,
+        '\u{2026}' => 0x85,
+        '\u{2013}' => 0x96,
+        '\u{2014}' => 0x97,
+        '\u{2019}' => 0x92,
+        _ => b'?',
+    }
+}
+
+fn escaped(text: &str) -> impl Iterator<Item = u8> + '_ {
+    text.chars().map(winansi).flat_map(|b| match b {
+        b'(' | b')' | b'\\' => vec![b'\\', b],
+        b => vec![b],
+    })
+}
+
+// Helvetica averages about half its size per character; paths lose their
+// start rather than their end, which names the directory
+fn fitted(text: &str, room: f64, size: f64) -> String {
+    let fits = (room / (size * 0.52)).max(4.0) as usize;
+    let chars: Vec<char> = text.chars().collect();
+    if chars.len() <= fits {
+        return text.to_string();
+    }
+    let tail: String = chars[chars.len() - (fits - 1)..].iter().collect();
+    format!("\u{2026}{}", tail)
+}
+
+fn days(age: Option<i64>) -> String {
+    age.map_or_else(|| "-".to_string(), |age| format!("{} days", age))
+}
+
+// Summary figures, a chart of the largest directories, then every directory
+// and skip
+pub fn write(path: &str, dirs: &[DirInfo], inventory: &[DirInfo], skipped: &[SkippedDir], backups: &[String], timestamp: &str) -> io::Result<()> {
+    let mut doc = Document::new();
+    let y = doc.y;
+    doc.text(MARGIN, y - 4.0, 18.0, Font::Bold, "dirpurge report");
+    doc.y -= LINE * 1.5;
+    let y = doc.y;
+    doc.text(MARGIN, y, 9.0, Font::Regular, &locale::date(timestamp));
+    doc.y -= LINE / 2.0;
+
+    let total: u64 = dirs.iter().map(|d| d.size_bytes).sum();
+    let estimated = dirs.iter().any(|d| d.estimated);
+    doc.heading("Summary");
+    let figures = [
+        ("Directories", locale::count(dirs.len())),
+        ("Total size", format_mb(total, estimated)),
+        ("Average size", format_mb(if dirs.is_empty() { 0 } else { total / dirs.len() as u64 }, estimated)),
+        ("Oldest", days(dirs.iter().filter_map(|d| d.age_days).max())),
+        ("Newest", days(dirs.iter().filter_map(|d| d.age_days).min())),
+        ("Report-only", locale::count(inventory.len())),
+        ("Skipped", locale::count(skipped.len())),
+        ("Backups", locale::count(backups.len())),
+    ];
+    for (label, value) in &figures {
+        doc.row(&[(0.0, label), (120.0, value)], Font::Regular);
+    }
+
+    let mut largest: Vec<&DirInfo> = dirs.iter().chain(inventory).collect();
+    largest.sort_by_key(|d| std::cmp::Reverse(d.size_bytes));
+    largest.truncate(TOP);
+    if let Some(biggest) = largest.first().map(|d| d.size_bytes.max(1)) {
+        doc.heading(&format!("Largest {}", largest.len()));
+        let (label_width, bar_width) = (200.0, WIDTH - MARGIN * 2.0 - 200.0 - 80.0);
+        for dir in &largest {
+            doc.reserve(LINE * 1.3);
+            let y = doc.y;
+            doc.text(MARGIN, y, 9.0, Font::Regular, &fitted(&dir.path, label_width - 6.0, 9.0));
+            let width = (dir.size_bytes as f64 / biggest as f64 * bar_width).max(1.0);
+            doc.rect(MARGIN + label_width, y - 2.0, width, 10.0, 0.45);
+            doc.text(MARGIN + label_width + width + 4.0, y, 9.0, Font::Regular, &format_mb(dir.size_bytes, dir.estimated));
+            doc.y -= LINE * 1.3;
+        }
+    }
+
+    let columns = [0.0, 290.0, 370.0, 430.0];
+    for (title, list) in [("Directories", dirs), ("Report-only", inventory)] {
+        if list.is_empty() {
+            continue;
+        }
+        doc.heading(title);
+        doc.row(&[(columns[0], "Path"), (columns[1], "Size"), (columns[2], "Age"), (columns[3], "Items")], Font::Bold);
+        for dir in list {
+            let items = dir.item_count.map_or_else(|| "-".to_string(), locale::count);
+            doc.row(&[
+                (columns[0], &dir.path),
+                (columns[1], &format_mb(dir.size_bytes, dir.estimated)),
+                (columns[2], &days(dir.age_days)),
+                (columns[3], &items),
+            ], Font::Regular);
+        }
+    }
+
+    if !skipped.is_empty() {
+        doc.heading("Skipped");
+        doc.row(&[(0.0, "Path"), (290.0, "Reason")], Font::Bold);
+        for skip in skipped {
+            doc.row(&[(0.0, &skip.path), (290.0, &format!("{}: {}", skip.reason, skip.detail))], Font::Regular);
+        }
+    }
+
+    fs::write(path, doc.finish())
+}
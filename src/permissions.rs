@@ -0,0 +1,198 @@
+use std::{collections::hash_map::RandomState, fs, hash::BuildHasher, io::{self, Write}, path::{Path, PathBuf}, process::Command};
+use log::{debug, info, warn};
+
+use crate::{plan::Plan, report::{self, Kind}, skipped::{self, SkipReason}, DirInfo, CROSS};
+
+// Removing a directory needs write and search permission on its parent and on
+// every directory inside it; sticky directories also require owning the entry
+#[cfg(unix)]
+pub fn needs_elevation(path: &Path) -> bool {
+    use std::{collections::HashSet, ffi::CString, os::unix::{ffi::OsStrExt, fs::MetadataExt}};
+    use walkdir::WalkDir;
+
+    let writable = |dir: &Path| {
+        CString::new(dir.as_os_str().as_bytes())
+            .is_ok_and(|c| unsafe { libc::access(c.as_ptr(), libc::W_OK | libc::X_OK) } == 0)
+    };
+    let uid = unsafe { libc::geteuid() };
+
+    let Some(parent) = path.parent() else {
+        return true;
+    };
+    if !writable(parent) {
+        debug!("Parent not writable: {}", parent.display());
+        return true;
+    }
+
+    let is_sticky = |m: &std::fs::Metadata| m.mode() & 0o1000 != 0; // S_ISVTX
+    let mut sticky_dirs = HashSet::new();
+    if parent.metadata().is_ok_and(|m| is_sticky(&m)) {
+        sticky_dirs.insert(parent.to_path_buf());
+    }
+
+    for entry in WalkDir::new(path).into_iter().filter_map(|e| e.ok()) {
+        let Ok(metadata) = entry.metadata() else {
+            continue;
+        };
+        let in_sticky = entry.path().parent().is_some_and(|p| sticky_dirs.contains(p));
+        if uid != 0 && in_sticky && metadata.uid() != uid {
+            debug!("Owned by another user in a sticky directory: {}", entry.path().display());
+            return true;
+        }
+        if entry.file_type().is_dir() {
+            if !writable(entry.path()) {
+                debug!("Not writable: {}", entry.path().display());
+                return true;
+            }
+            if is_sticky(&metadata) {
+                sticky_dirs.insert(entry.path().to_path_buf());
+            }
+        }
+    }
+    false
+}
+
+// Windows reports access problems only when the removal is attempted
+#[cfg(not(unix))]
+pub fn needs_elevation(_path: &Path) -> bool {
+    false
+}
+
+// Split candidates into those removable with the current rights and those that
+// need elevation. With a helper everything is processed now; with `elevate` the
+// second list is handed to an elevated re-run; otherwise it is skipped.
+pub fn partition(dirs: Vec<DirInfo>, sudo_helper: Option<&str>, elevate: bool) -> (Vec<DirInfo>, Vec<DirInfo>) {
+    let (elevated, deletable): (Vec<_>, Vec<_>) = dirs.into_iter()
+        .partition(|d| needs_elevation(Path::new(&d.path)));
+    if elevated.is_empty() {
+        return (deletable, elevated);
+    }
+
+    report::message(Kind::Info, format!(
+        "{} deletable by you, {} require elevation:", deletable.len(), elevated.len()
+    ));
+    report::items(&elevated.iter().map(|dir| dir.path.clone()).collect::<Vec<_>>());
+
+    if let Some(helper) = sudo_helper {
+        report::message(Kind::Warning, format!(
+            "Directories requiring elevation are removed permanently with {}", helper
+        ));
+        return (deletable.into_iter().chain(elevated).collect(), Vec::new());
+    }
+    if elevate {
+        report::message(Kind::Info, "They will be applied from a plan file in an elevated run");
+        return (deletable, elevated);
+    }
+
+    warn!("Skipping {} directories that require elevation", elevated.len());
+    for dir in &elevated {
+        skipped::record(&dir.path, SkipReason::RequiresElevation, "no --sudo-helper or --elevate given");
+    }
+    report::message(Kind::Info, "Skipping them, use --sudo-helper or --elevate to elevate");
+    (deletable, Vec::new())
+}
+
+// Writes `plan` where only this user can reach it, for an elevated run: as a
+// new file in a new directory of their own. A fixed name in the shared temp
+// directory could be claimed first, or its contents swapped, by anyone.
+// Returns the directory, to remove afterwards, and the plan in it.
+pub fn write_private_plan(plan: &Plan) -> Result<(PathBuf, PathBuf), String> {
+    let failed = |e: io::Error| format!("{} Could not write the plan for the elevated run: {}", CROSS, e);
+    let content = serde_json::to_vec_pretty(plan)
+        .map_err(|e| format!("{} Error serializing plan: {}", CROSS, e))?;
+    let random = RandomState::new();
+    let mut attempt = 0u32;
+    let dir = loop {
+        let dir = std::env::temp_dir().join(format!("dirpurge-elevated-{:08x}", random.hash_one(attempt) as u32));
+        let mut builder = fs::DirBuilder::new();
+        #[cfg(unix)]
+        std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o700);
+        match builder.create(&dir) {
+            Ok(()) => break dir,
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists && attempt < 8 => attempt += 1,
+            Err(e) => return Err(failed(e)),
+        }
+    };
+
+    let path = dir.join("plan.json");
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    if let Err(e) = options.open(&path).and_then(|mut file| file.write_all(&content)) {
+        let _ = fs::remove_dir_all(&dir);
+        return Err(failed(e));
+    }
+    Ok((dir, path))
+}
+
+// Re-run `dirpurge apply` on a plan file with elevated rights: pkexec (polkit)
+// on Linux, a UAC prompt on Windows. The elevated process only ever sees the
+// plan, and is confirmed with the token of `plan` rather than --yes, so it
+// refuses the file if it is anything else by the time it reads it.
+pub fn apply_elevated(plan_path: &Path, plan: &Plan, extra_args: &[String]) -> Result<(), String> {
+    let exe = std::env::current_exe()
+        .map_err(|e| format!("{} Cannot locate the dirpurge executable: {}", CROSS, e))?;
+    let plan_path = plan_path.canonicalize()
+        .map_err(|e| format!("{} Cannot resolve plan {}: {}", CROSS, plan_path.display(), e))?;
+    // The elevated run only deletes. Daemon commands come from the plan and
+    // the projects, so the caller runs them unprivileged beforehand.
+    let mut args = vec![
+        "apply".to_string(), plan_path.to_string_lossy().into_owned(), "--confirm-token".to_string(), plan.confirm_token()?,
+        "--skip-daemons".to_string(),
+    ];
+    args.extend_from_slice(extra_args);
+
+    info!("Applying {} with elevated rights", plan_path.display());
+    let status = elevated_command(&exe, &args)?
+        .status()
+        .map_err(|e| format!("{} Failed to start elevated run: {}", CROSS, e))?;
+    if !status.success() {
+        return Err(format!("{} Elevated run failed ({})", CROSS, status));
+    }
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+fn elevated_command(exe: &Path, args: &[String]) -> Result<Command, String> {
+    let mut command = Command::new("pkexec");
+    command.arg(exe).args(args);
+    Ok(command)
+}
+
+#[cfg(windows)]
+fn elevated_command(exe: &Path, args: &[String]) -> Result<Command, String> {
+    let quote = |s: &str| format!("'{}'", s.replace('\'', "''"));
+    let argument_list = args.iter().map(|a| quote(a)).collect::<Vec<_>>().join(",");
+    let script = format!(
+        "$p = Start-Process -FilePath {} -ArgumentList {} -Verb RunAs -Wait -PassThru; exit $p.ExitCode",
+        quote(&exe.to_string_lossy()), argument_list
+    );
+    let mut command = Command::new("powershell");
+    command.args(["-NoProfile", "-NonInteractive", "-Command", &script]);
+    Ok(command)
+}
+
+#[cfg(not(any(target_os = "linux", windows)))]
+fn elevated_command(_exe: &Path, _args: &[String]) -> Result<Command, String> {
+    Err(format!("{} --elevate is not supported on this platform, use --sudo-helper", CROSS))
+}
+
+// Permanently remove a directory through the elevation helper (e.g. `sudo`)
+pub fn elevated_delete(path: &str, helper: &str) -> Result<(), String> {
+    info!("Deleting with {}: {}", helper, path);
+    let mut parts = helper.split_whitespace();
+    let program = parts.next()
+        .ok_or_else(|| format!("{} Empty --sudo-helper command", CROSS))?;
+    let status = Command::new(program)
+        .args(parts)
+        .args(["rm", "-rf", "--", path])
+        .status()
+        .map_err(|e| format!("{} Failed to run {}: {}", CROSS, helper, e))?;
+    if !status.success() {
+        return Err(format!("{} {} failed to delete {} ({})", CROSS, helper, path, status));
+    }
+
+    report::detail(Kind::Deleted, format!("Deleted with {}: {}", helper, path));
+    Ok(())
+}
@@ -0,0 +1,430 @@
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize, Serializer};
+use sha2::{Digest, Sha256};
+use std::{collections::{BTreeMap, HashMap, HashSet}, fs, path::{Path, PathBuf}, time::{Duration, SystemTime, UNIX_EPOCH}};
+use walkdir::WalkDir;
+use log::{debug, info, warn};
+
+use crate::{actions::TargetAction, archive::ArchiveFormat, backup::{BackupPolicy, BackupStrategy}, hash::HashAlgo, preserve::Preserve, prune::RetentionPolicy, skipped::{self, SkipReason}, report::{self, Kind}, DeleteOptions, DirInfo, CROSS};
+
+// Maps in the plan are written in key order, so it digests to the same token
+// in every process
+fn sorted<S: Serializer, V: Serialize>(map: &HashMap<String, V>, serializer: S) -> Result<S::Ok, S::Error> {
+    map.iter().collect::<BTreeMap<_, _>>().serialize(serializer)
+}
+
+fn sorted_opt<S: Serializer, V: Serialize>(map: &Option<HashMap<String, V>>, serializer: S) -> Result<S::Ok, S::Error> {
+    map.as_ref().map(|map| map.iter().collect::<BTreeMap<_, _>>()).serialize(serializer)
+}
+
+// A saved selection of directories, reviewed before being applied
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Plan {
+    pub created_at: String,
+    pub created_at_ms: u64,
+    pub base_path: String,
+    pub use_trash: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub quarantine: Option<PathBuf>,
+    pub backup: bool,
+    pub archive: bool,
+    #[serde(default)]
+    pub archive_format: ArchiveFormat,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub compression_level: Option<i32>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub archive_manifest: bool,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub archive_top_dir: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub archive_split_size: Option<u64>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub backup_exclude: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub s3_storage_class: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub backup_name_template: Option<String>,
+    #[serde(default, skip_serializing_if = "Preserve::is_all")]
+    pub backup_preserve: Preserve,
+    #[serde(default, skip_serializing_if = "BackupStrategy::is_default")]
+    pub backup_strategy: BackupStrategy,
+    pub backup_dirs: Vec<String>,
+    #[serde(default)]
+    pub backup_policy: BackupPolicy,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prune_backups: Option<RetentionPolicy>,
+    #[serde(default)]
+    pub upload_limit: Option<u64>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub readahead: bool,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub incremental: bool,
+    #[serde(default)]
+    pub verify_sample: Option<f64>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub verify_archive: bool,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub skip_space_check: bool,
+    #[serde(default)]
+    pub hash_algo: HashAlgo,
+    #[serde(default, skip_serializing_if = "Option::is_none", serialize_with = "sorted_opt")]
+    pub stop_daemons: Option<HashMap<String, String>>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty", serialize_with = "sorted")]
+    pub actions: HashMap<String, TargetAction>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub git_safe: bool,
+    // Search root the directories must resolve inside, None when unconfined
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub root: Option<String>,
+    // Bytes to free, after which the remaining directories are kept
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub free_goal: Option<u64>,
+    // Most directories and bytes the plan may remove in one run
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_dirs: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_total: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub purge_files_older_than: Option<i64>,
+    pub directories: Vec<DirInfo>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub approval: Option<RequiredApproval>,
+    #[serde(default)]
+    pub signatures: Vec<PlanSignature>,
+}
+
+// An approver's HMAC-SHA256 over the plan contents (excluding signatures)
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PlanSignature {
+    pub signer: String,
+    pub signed_at: String,
+    pub mac: String,
+}
+
+// Plans above either threshold need `required_signatures` distinct approvers;
+// with no thresholds set, every plan needs them
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct ApprovalPolicy {
+    pub required_signatures: usize,
+    pub threshold_mb: Option<f64>,
+    pub threshold_dirs: Option<usize>,
+    // Signer name -> path of that signer's key file
+    #[serde(default)]
+    pub keys: HashMap<String, String>,
+}
+
+// The approval policy in force when the plan was made. It is part of what
+// approvers sign, so `apply` holds the plan to it whatever config it is given.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RequiredApproval {
+    pub required_signatures: usize,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub threshold_mb: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub threshold_dirs: Option<usize>,
+    // Only signatures from these count
+    pub signers: Vec<String>,
+}
+
+impl RequiredApproval {
+    pub fn of(policy: &ApprovalPolicy) -> Option<Self> {
+        let mut signers: Vec<String> = policy.keys.keys().cloned().collect();
+        signers.sort();
+        (policy.required_signatures > 0).then_some(RequiredApproval {
+            required_signatures: policy.required_signatures,
+            threshold_mb: policy.threshold_mb,
+            threshold_dirs: policy.threshold_dirs,
+            signers,
+        })
+    }
+}
+
+impl Plan {
+    pub fn new(base_path: &str, dirs: &[DirInfo], opts: &DeleteOptions) -> Self {
+        let now = chrono::Local::now();
+        Plan {
+            created_at: now.to_rfc3339(),
+            created_at_ms: now.timestamp_millis().max(0) as u64,
+            base_path: base_path.to_string(),
+            use_trash: opts.use_trash,
+            quarantine: opts.quarantine.clone(),
+            backup: opts.backup,
+            archive: opts.archive,
+            archive_format: opts.archive_format,
+            compression_level: opts.compression_level,
+            archive_manifest: opts.archive_manifest,
+            archive_top_dir: opts.archive_top_dir,
+            archive_split_size: opts.archive_split_size,
+            backup_exclude: opts.backup_exclude.clone(),
+            s3_storage_class: opts.s3_storage_class.clone(),
+            backup_name_template: opts.backup_name_template.clone(),
+            backup_preserve: opts.backup_preserve,
+            backup_strategy: opts.backup_strategy,
+            backup_dirs: opts.backup_dirs.clone(),
+            backup_policy: opts.backup_policy,
+            prune_backups: opts.prune_backups,
+            upload_limit: opts.upload_limit,
+            readahead: opts.readahead,
+            incremental: opts.incremental,
+            verify_sample: opts.verify_sample,
+            verify_archive: opts.verify_archive,
+            skip_space_check: opts.skip_space_check,
+            hash_algo: opts.hash_algo,
+            stop_daemons: opts.stop_daemons.clone(),
+            actions: opts.actions.clone(),
+            git_safe: opts.git_safe,
+            root: opts.root.clone(),
+            free_goal: opts.free_goal,
+            max_dirs: opts.max_dirs,
+            max_total: opts.max_total,
+            purge_files_older_than: opts.purge_files_older_than,
+            directories: dirs.to_vec(),
+            approval: None,
+            signatures: Vec::new(),
+        }
+    }
+
+    // The actions that were approved with the plan
+    pub fn delete_options(&self, dry_run: bool, verbose: bool) -> DeleteOptions {
+        DeleteOptions {
+            dry_run,
+            verbose,
+            use_trash: self.use_trash,
+            quarantine: self.quarantine.clone(),
+            backup: self.backup,
+            archive: self.archive,
+            archive_format: self.archive_format,
+            compression_level: self.compression_level,
+            archive_manifest: self.archive_manifest,
+            archive_top_dir: self.archive_top_dir,
+            archive_split_size: self.archive_split_size,
+            backup_exclude: self.backup_exclude.clone(),
+            s3_storage_class: self.s3_storage_class.clone(),
+            backup_name_template: self.backup_name_template.clone(),
+            backup_preserve: self.backup_preserve,
+            backup_strategy: self.backup_strategy,
+            backup_dirs: self.backup_dirs.clone(),
+            backup_policy: self.backup_policy,
+            prune_backups: self.prune_backups,
+            upload_limit: self.upload_limit,
+            readahead: self.readahead,
+            incremental: self.incremental,
+            interactive: false,
+            verify_sample: self.verify_sample,
+            verify_archive: self.verify_archive,
+            skip_space_check: self.skip_space_check,
+            hash_algo: self.hash_algo,
+            sudo_helper: None,
+            respect_locks: true,
+            check_processes: true,
+            stop_daemons: self.stop_daemons.clone(),
+            actions: self.actions.clone(),
+            git_safe: self.git_safe,
+            root: self.root.clone(),
+            backed_up: HashMap::new(),
+            copying: HashMap::new(),
+            jobs: 1,
+            chunk_above: None,
+            free_goal: self.free_goal,
+            mounts: Vec::new(),
+            max_dirs: self.max_dirs,
+            max_total: self.max_total,
+            purge_files_older_than: self.purge_files_older_than,
+            heartbeat: None,
+            heartbeat_webhook: None,
+        }
+    }
+
+    // Saturating, since a hand-edited plan can claim any sizes
+    pub fn total_size_bytes(&self) -> u64 {
+        self.directories.iter().fold(0, |total, d| total.saturating_add(d.size_bytes))
+    }
+
+    // SHA-256 of the plan as it was reviewed, so signatures survive being added
+    fn digest(&self) -> Result<Vec<u8>, String> {
+        let mut unsigned = self.clone();
+        unsigned.signatures.clear();
+        serde_json::to_vec(&unsigned)
+            .map(|bytes| Sha256::digest(bytes).to_vec())
+            .map_err(|e| format!("{} Error serializing plan: {}", CROSS, e))
+    }
+
+    // Stands in for --yes on `apply`, and only for this plan. Signing doesn't change it.
+    pub fn confirm_token(&self) -> Result<String, String> {
+        Ok(to_hex(&self.digest()?[..10]))
+    }
+
+    fn mac(&self, key: &[u8]) -> Result<Hmac<Sha256>, String> {
+        let mut mac = Hmac::<Sha256>::new_from_slice(key)
+            .map_err(|e| format!("{} Invalid signing key: {}", CROSS, e))?;
+        mac.update(&self.digest()?);
+        Ok(mac)
+    }
+
+    // Add (or replace) the signature of `signer`
+    pub fn sign(&mut self, signer: &str, key: &[u8]) -> Result<(), String> {
+        let mac = to_hex(&self.mac(key)?.finalize().into_bytes());
+        self.signatures.retain(|s| s.signer != signer);
+        self.signatures.push(PlanSignature {
+            signer: signer.to_string(),
+            signed_at: chrono::Local::now().to_rfc3339(),
+            mac,
+        });
+        Ok(())
+    }
+
+    fn requires_approval(&self, policy: &ApprovalPolicy) -> bool {
+        if policy.required_signatures == 0 {
+            return false;
+        }
+        if policy.threshold_mb.is_none() && policy.threshold_dirs.is_none() {
+            return true;
+        }
+        let size_mb = self.total_size_bytes() as f64 / 1024.0 / 1024.0;
+        policy.threshold_mb.is_some_and(|mb| size_mb > mb)
+            || policy.threshold_dirs.is_some_and(|n| self.directories.len() > n)
+    }
+}
+
+pub fn read_key(key_path: &str) -> Result<Vec<u8>, String> {
+    fs::read_to_string(key_path)
+        .map(|key| key.trim().as_bytes().to_vec())
+        .map_err(|e| format!("{} Error reading key {}: {}", CROSS, key_path, e))
+}
+
+// Refuse plans that exceed the thresholds without enough valid signatures:
+// those of the policy the plan was made under, and of `policy` from the
+// config given to apply, which holds the signers' keys
+pub fn check_approvals(plan: &Plan, policy: Option<&ApprovalPolicy>) -> Result<(), String> {
+    if let Some(required) = &plan.approval {
+        let keys = policy.map(|policy| policy.keys.iter()
+            .filter(|(signer, _)| required.signers.contains(signer))
+            .map(|(signer, key)| (signer.clone(), key.clone()))
+            .collect())
+            .unwrap_or_default();
+        let recorded = ApprovalPolicy {
+            required_signatures: required.required_signatures,
+            threshold_mb: required.threshold_mb,
+            threshold_dirs: required.threshold_dirs,
+            keys,
+        };
+        if policy.is_none() && plan.requires_approval(&recorded) {
+            return Err(format!(
+                "{} Plan was made under a policy requiring {} approval signature(s); pass the config with the signers' keys (-c) to check them",
+                CROSS, required.required_signatures
+            ));
+        }
+        check_policy(plan, &recorded)?;
+    }
+    policy.map_or(Ok(()), |policy| check_policy(plan, policy))
+}
+
+fn check_policy(plan: &Plan, policy: &ApprovalPolicy) -> Result<(), String> {
+    if !plan.requires_approval(policy) {
+        return Ok(());
+    }
+
+    let mut approvers = HashSet::new();
+    for signature in &plan.signatures {
+        let Some(key_path) = policy.keys.get(&signature.signer) else {
+            warn!("Ignoring signature from unknown signer: {}", signature.signer);
+            continue;
+        };
+        let valid = from_hex(&signature.mac)
+            .is_some_and(|tag| plan.mac(&read_key(key_path).unwrap_or_default())
+                .is_ok_and(|mac| mac.verify_slice(&tag).is_ok()));
+        if valid {
+            debug!("Valid signature from {}", signature.signer);
+            approvers.insert(signature.signer.as_str());
+        } else {
+            warn!("Invalid signature from {}", signature.signer);
+        }
+    }
+
+    if approvers.len() < policy.required_signatures {
+        return Err(format!(
+            "{} Plan requires {} approval signature(s), found {} valid",
+            CROSS, policy.required_signatures, approvers.len()
+        ));
+    }
+
+    info!("Plan approved by: {}", approvers.into_iter().collect::<Vec<_>>().join(", "));
+    Ok(())
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+pub fn save_plan(plan: &Plan, plan_path: &str) -> Result<(), String> {
+    debug!("Saving plan to {}", plan_path);
+    serde_json::to_string_pretty(plan)
+        .map_err(|e| format!("{} Error serializing plan: {}", CROSS, e))
+        .and_then(|content| fs::write(plan_path, content)
+        .map_err(|e| format!("{} Error writing plan: {}", CROSS, e)))
+}
+
+pub fn load_plan(plan_path: &str) -> Result<Plan, String> {
+    debug!("Loading plan from {}", plan_path);
+    fs::read_to_string(plan_path)
+        .map_err(|e| format!("{} Error reading plan: {}", CROSS, e))
+        .and_then(|content| parse_plan(&content))
+}
+
+pub fn parse_plan(content: &str) -> Result<Plan, String> {
+    serde_json::from_str(content)
+        .map_err(|e| format!("{} Error parsing plan: {}", CROSS, e))
+}
+
+// Newest modification time of the directory or anything inside it
+pub fn newest_mtime(path: &Path) -> Option<SystemTime> {
+    WalkDir::new(path)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter_map(|e| e.metadata().ok())
+        .filter_map(|m| m.modified().ok())
+        .max()
+}
+
+// Split the planned directories into those still safe to apply and those
+// that disappeared or were modified after the plan was created
+pub fn revalidate(plan: &Plan, allow_changed: bool) -> Vec<DirInfo> {
+    let created = UNIX_EPOCH + Duration::from_millis(plan.created_at_ms);
+    let mut valid = Vec::new();
+
+    for dir in &plan.directories {
+        let path = Path::new(&dir.path);
+        if !path.is_dir() {
+            warn!("Planned directory no longer exists: {}", dir.path);
+            skipped::record(&dir.path, SkipReason::Changed, "missing since the plan was created");
+            report::message(Kind::Info, format!("Skipping missing directory: {}", dir.path));
+            continue;
+        }
+
+        let changed = newest_mtime(path).is_some_and(|mtime| mtime > created);
+        if changed && !allow_changed {
+            warn!("Directory modified after plan was created: {}", dir.path);
+            skipped::record(&dir.path, SkipReason::Changed, "modified since the plan was created");
+            report::message(Kind::Warning, format!("Skipping directory modified since plan was created: {}", dir.path));
+            continue;
+        }
+
+        if changed {
+            info!("Applying changed directory (allowed): {}", dir.path);
+            report::message(Kind::Warning, format!("Modified since plan, applying anyway: {}", dir.path));
+        }
+        valid.push(dir.clone());
+    }
+
+    valid
+}
@@ -0,0 +1,98 @@
+use serde::Serialize;
+use std::{collections::BTreeMap, time::SystemTime};
+
+use crate::{actions::TargetAction, protected, AgeSource, ScanOptions};
+
+// The rules a run matched and protected directories by, fully resolved, so a
+// summary read months later still shows which policy produced it
+#[derive(Serialize, Debug, Clone)]
+pub struct Policy {
+    pub search_path: String,
+    pub targets: Vec<String>,
+    pub excludes: Vec<String>,
+    pub filters: Filters,
+    // Never deleted from, as resolved at the time
+    pub protected: Vec<String>,
+    // Never deleted themselves, though matches inside them may be
+    pub protected_roots: Vec<String>,
+    // Target -> marker files one of which had to sit next to a match
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub manifests: Option<BTreeMap<String, Vec<String>>>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub actions: BTreeMap<String, TargetAction>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct Filters {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_size_bytes: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_size_bytes: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_items: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_items: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_age_days: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_age_days: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub modified_before: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub modified_after: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_atime_age_days: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub owner_uid: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub repo_inactive_days: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub keep_latest: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_depth: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_depth: Option<usize>,
+    pub age_source: AgeSource,
+    pub respect_ignore: bool,
+    pub one_file_system: bool,
+    pub follow_symlinks: bool,
+}
+
+fn timestamp(time: SystemTime) -> String {
+    chrono::DateTime::<chrono::Local>::from(time).to_rfc3339()
+}
+
+pub fn effective(search_path: &str, opts: &ScanOptions) -> Policy {
+    let rules = &opts.rules;
+    let (protected, protected_roots) = protected::listed();
+    let display = |paths: Vec<std::path::PathBuf>| paths.iter().map(|p| p.to_string_lossy().into_owned()).collect();
+    Policy {
+        search_path: search_path.to_string(),
+        targets: rules.target.clone(),
+        excludes: rules.exclude.clone(),
+        filters: Filters {
+            min_size_bytes: rules.min_size,
+            max_size_bytes: rules.max_size,
+            min_items: rules.min_items,
+            max_items: rules.max_items,
+            min_age_days: rules.min_age,
+            max_age_days: rules.max_age,
+            modified_before: rules.modified_before.map(timestamp),
+            modified_after: rules.modified_after.map(timestamp),
+            min_atime_age_days: rules.min_atime_age,
+            owner_uid: rules.owner_uid,
+            repo_inactive_days: rules.repo_inactive_days,
+            keep_latest: opts.keep_latest,
+            min_depth: opts.min_depth,
+            max_depth: opts.depth,
+            age_source: opts.age_source,
+            respect_ignore: opts.respect_ignore,
+            one_file_system: opts.one_file_system,
+            follow_symlinks: opts.follow_symlinks_traversal,
+        },
+        protected: display(protected),
+        protected_roots: display(protected_roots),
+        manifests: opts.manifests.as_ref().map(|m| m.clone().into_iter().collect()),
+        actions: opts.actions.clone().into_iter().collect(),
+    }
+}
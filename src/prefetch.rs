@@ -0,0 +1,77 @@
+use std::{fs, io, path::Path};
+use log::debug;
+
+// Files hinted ahead of the one being copied. Enough to keep a spinning disk's
+// queue busy without pushing what is being copied out of the page cache.
+pub const LOOKAHEAD: usize = 16;
+
+// Ask the OS to start reading `path` into the cache now, so it is there by the
+// time it gets copied. Only a hint: failures are ignored.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub fn will_need(path: &Path) {
+    use std::os::unix::io::AsRawFd;
+
+    if let Ok(file) = fs::File::open(path) {
+        // The readahead it queues carries on after the file is closed
+        let advised = unsafe { libc::posix_fadvise(file.as_raw_fd(), 0, 0, libc::POSIX_FADV_WILLNEED) };
+        if advised != 0 {
+            debug!("fadvise failed for {}: {}", path.display(), io::Error::from_raw_os_error(advised));
+        }
+    }
+}
+
+#[cfg(target_os = "macos")]
+pub fn will_need(path: &Path) {
+    use std::os::unix::io::AsRawFd;
+
+    let Ok(file) = fs::File::open(path) else {
+        return;
+    };
+    let len = file.metadata().map_or(0, |m| m.len());
+    let advice = libc::radvisory {
+        ra_offset: 0,
+        ra_count: len.min(libc::c_int::MAX as u64) as libc::c_int,
+    };
+    if unsafe { libc::fcntl(file.as_raw_fd(), libc::F_RDADVISE, &advice) } == -1 {
+        debug!("F_RDADVISE failed for {}: {}", path.display(), io::Error::last_os_error());
+    }
+}
+
+// Windows has no advice for a file that isn't open yet; the sequential-scan
+// flag of `open_sequential` makes the cache manager read ahead aggressively
+#[cfg(not(any(target_os = "linux", target_os = "android", target_os = "macos")))]
+pub fn will_need(_path: &Path) {}
+
+// Open a file that is about to be read from start to end
+#[cfg(windows)]
+pub fn open_sequential(path: &Path) -> io::Result<fs::File> {
+    use std::os::windows::fs::OpenOptionsExt;
+    use windows_sys::Win32::Storage::FileSystem::FILE_FLAG_SEQUENTIAL_SCAN;
+
+    fs::OpenOptions::new().read(true).custom_flags(FILE_FLAG_SEQUENTIAL_SCAN).open(path)
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub fn open_sequential(path: &Path) -> io::Result<fs::File> {
+    use std::os::unix::io::AsRawFd;
+
+    let file = fs::File::open(path)?;
+    // Doubles the readahead window for this file
+    unsafe { libc::posix_fadvise(file.as_raw_fd(), 0, 0, libc::POSIX_FADV_SEQUENTIAL) };
+    Ok(file)
+}
+
+#[cfg(not(any(windows, target_os = "linux", target_os = "android")))]
+pub fn open_sequential(path: &Path) -> io::Result<fs::File> {
+    fs::File::open(path)
+}
+
+// Hint `files` from `*hinted` on up to LOOKAHEAD past `current`, the one about
+// to be copied
+pub fn hint_ahead(files: &[&Path], current: usize, hinted: &mut usize) {
+    let until = (current + LOOKAHEAD).min(files.len());
+    while *hinted < until {
+        will_need(files[*hinted]);
+        *hinted += 1;
+    }
+}
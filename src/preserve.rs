@@ -0,0 +1,174 @@
+// What plain backup copies and restores keep of the original besides its
+// contents, chosen with --backup-preserve. Everything by default, so a copy
+// restored is the directory that was removed.
+use serde::{Deserialize, Serialize};
+use std::{fmt, fs, io, path::Path, str::FromStr};
+use log::debug;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(try_from = "String", into = "String")]
+pub struct Preserve {
+    // Permission bits
+    pub mode: bool,
+    // User and group, where the OS lets them be given away
+    pub owner: bool,
+    // Access and modification times
+    pub times: bool,
+    // Symlinks as symlinks; otherwise what they point to is copied
+    pub links: bool,
+}
+
+impl Default for Preserve {
+    fn default() -> Self {
+        Preserve { mode: true, owner: true, times: true, links: true }
+    }
+}
+
+impl Preserve {
+    pub const NONE: Preserve = Preserve { mode: false, owner: false, times: false, links: false };
+
+    pub fn is_all(&self) -> bool {
+        *self == Preserve::default()
+    }
+}
+
+impl FromStr for Preserve {
+    type Err = String;
+
+    // `all`, `none`, or any of mode, owner, times and links separated by commas
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.trim() {
+            "all" => return Ok(Preserve::default()),
+            "none" => return Ok(Preserve::NONE),
+            _ => {}
+        }
+        let mut preserve = Preserve::NONE;
+        for aspect in value.split(',').map(str::trim) {
+            match aspect {
+                "mode" => preserve.mode = true,
+                "owner" => preserve.owner = true,
+                "times" => preserve.times = true,
+                "links" => preserve.links = true,
+                _ => return Err(format!("invalid aspect '{}', expected all, none, or mode, owner, times and/or links separated by commas", aspect)),
+            }
+        }
+        Ok(preserve)
+    }
+}
+
+impl TryFrom<String> for Preserve {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl fmt::Display for Preserve {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let aspects: Vec<&str> = [(self.mode, "mode"), (self.owner, "owner"), (self.times, "times"), (self.links, "links")]
+            .into_iter()
+            .filter_map(|(kept, name)| kept.then_some(name))
+            .collect();
+        if aspects.is_empty() {
+            return write!(f, "none");
+        }
+        write!(f, "{}", aspects.join(","))
+    }
+}
+
+impl From<Preserve> for String {
+    fn from(preserve: Preserve) -> Self {
+        preserve.to_string()
+    }
+}
+
+// Give the copy `dst` what `preserve` keeps of the file or directory `src`
+// was read as. Directories get theirs once their contents are in, so writing
+// those doesn't change the times and a read-only mode doesn't get in the way.
+pub fn apply(src: &fs::Metadata, dst: &Path, preserve: Preserve) -> io::Result<()> {
+    // Before the mode: a change of owner clears setuid and setgid
+    if preserve.owner
+        && let Err(e) = set_owner(src, dst)
+    {
+        debug!("Could not keep the owner of {}: {}", dst.display(), e);
+    }
+    if preserve.mode {
+        fs::set_permissions(dst, src.permissions())?;
+    }
+    if preserve.times {
+        let mut times = fs::FileTimes::new().set_modified(src.modified()?);
+        if let Ok(accessed) = src.accessed() {
+            times = times.set_accessed(accessed);
+        }
+        open_for_times(dst)?.set_times(times)?;
+    }
+    Ok(())
+}
+
+// The link itself is owned; the times of links are left as made
+pub fn apply_to_link(src: &fs::Metadata, dst: &Path, preserve: Preserve) {
+    if preserve.owner
+        && let Err(e) = set_owner(src, dst)
+    {
+        debug!("Could not keep the owner of {}: {}", dst.display(), e);
+    }
+}
+
+#[cfg(unix)]
+fn set_owner(src: &fs::Metadata, dst: &Path) -> io::Result<()> {
+    use std::os::unix::fs::MetadataExt;
+    std::os::unix::fs::lchown(dst, Some(src.uid()), Some(src.gid()))
+}
+
+// Owners on Windows are ACLs, which copies already inherit from where they go
+#[cfg(not(unix))]
+fn set_owner(_src: &fs::Metadata, _dst: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+#[cfg(windows)]
+fn open_for_times(path: &Path) -> io::Result<fs::File> {
+    use std::os::windows::fs::OpenOptionsExt;
+    use windows_sys::Win32::Storage::FileSystem::{FILE_FLAG_BACKUP_SEMANTICS, FILE_WRITE_ATTRIBUTES};
+
+    // Directories only open with backup semantics; read-only files still take
+    // new times through attribute access
+    fs::OpenOptions::new().access_mode(FILE_WRITE_ATTRIBUTES).custom_flags(FILE_FLAG_BACKUP_SEMANTICS).open(path)
+}
+
+#[cfg(not(windows))]
+fn open_for_times(path: &Path) -> io::Result<fs::File> {
+    fs::File::open(path)
+}
+
+// A link at `dst` pointing where `src` does, replacing one an interrupted
+// copy left there
+pub fn copy_symlink(src: &Path, dst: &Path) -> io::Result<()> {
+    let target = fs::read_link(src)?;
+    if dst.symlink_metadata().is_ok() {
+        // Links to directories are directories to Windows
+        fs::remove_file(dst).or_else(|_| fs::remove_dir(dst))?;
+    }
+    make_symlink(src, &target, dst)
+}
+
+#[cfg(unix)]
+fn make_symlink(_src: &Path, target: &Path, dst: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(target, dst)
+}
+
+#[cfg(windows)]
+fn make_symlink(src: &Path, target: &Path, dst: &Path) -> io::Result<()> {
+    // Windows links are made for a file or a directory; dangling ones count as files
+    if fs::metadata(src).is_ok_and(|m| m.is_dir()) {
+        std::os::windows::fs::symlink_dir(target, dst)
+    } else {
+        std::os::windows::fs::symlink_file(target, dst)
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+fn make_symlink(_src: &Path, _target: &Path, _dst: &Path) -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "symlinks are not supported here"))
+}
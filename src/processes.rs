@@ -0,0 +1,70 @@
+use std::path::{Path, PathBuf};
+use log::debug;
+
+// Tools whose long-running processes keep build directories and caches busy
+const BUILD_TOOLS: [&str; 8] = ["cargo", "rustc", "node", "npm", "yarn", "pnpm", "java", "gradle"];
+
+struct Process {
+    pid: u32,
+    name: String,
+    // Working directory and every open file
+    paths: Vec<PathBuf>,
+}
+
+// The processes running when deletion starts and the files they are using
+pub struct ProcessSnapshot {
+    processes: Vec<Process>,
+}
+
+impl ProcessSnapshot {
+    #[cfg(target_os = "linux")]
+    pub fn capture() -> Self {
+        use std::fs;
+
+        let processes = fs::read_dir("/proc")
+            .into_iter()
+            .flatten()
+            .filter_map(|e| e.ok())
+            .filter_map(|e| {
+                let pid: u32 = e.file_name().to_str()?.parse().ok()?;
+                let dir = e.path();
+                let name = fs::read_to_string(dir.join("comm")).ok()?.trim().to_string();
+                let mut paths: Vec<PathBuf> = fs::read_link(dir.join("cwd")).into_iter().collect();
+                paths.extend(fs::read_dir(dir.join("fd"))
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|fd| fd.ok())
+                    .filter_map(|fd| fs::read_link(fd.path()).ok()));
+                Some(Process { pid, name, paths })
+            })
+            .filter(|p| p.pid != std::process::id())
+            .collect();
+        ProcessSnapshot { processes }
+    }
+
+    // Other platforms don't expose working directories and open files cheaply
+    #[cfg(not(target_os = "linux"))]
+    pub fn capture() -> Self {
+        debug!("Process detection is only available on Linux");
+        ProcessSnapshot { processes: Vec::new() }
+    }
+
+    // "name (pid)" of every process working inside `path`, build tools first
+    pub fn users_of(&self, path: &Path) -> Vec<String> {
+        let path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+        let mut users: Vec<&Process> = self.processes.iter()
+            .filter(|p| p.paths.iter().any(|used| used.starts_with(&path)))
+            .collect();
+        users.sort_by_key(|p| !is_build_tool(&p.name));
+        users.iter()
+            .map(|p| {
+                debug!("Process {} ({}) is using {}", p.name, p.pid, path.display());
+                format!("{} ({})", p.name, p.pid)
+            })
+            .collect()
+    }
+}
+
+pub fn is_build_tool(name: &str) -> bool {
+    BUILD_TOOLS.iter().any(|tool| name.starts_with(tool))
+}
@@ -0,0 +1,120 @@
+use std::{env, path::{Path, PathBuf}, sync::OnceLock};
+use log::debug;
+
+// System trees dirpurge never deletes from, whatever a target matches
+#[cfg(unix)]
+const SYSTEM_TREES: &[&str] = &[
+    "/bin", "/boot", "/dev", "/etc", "/lib", "/lib32", "/lib64", "/proc", "/sbin", "/sys", "/usr",
+    "/var/lib", "/System", "/Library", "/Applications",
+];
+#[cfg(windows)]
+const SYSTEM_TREES: &[&str] = &[
+    "C:\\Windows", "C:\\Program Files", "C:\\Program Files (x86)", "C:\\ProgramData",
+];
+#[cfg(not(any(unix, windows)))]
+const SYSTEM_TREES: &[&str] = &[];
+
+// Directories that may hold deletable matches but must never go themselves
+#[cfg(unix)]
+const SYSTEM_ROOTS: &[&str] = &["/", "/home", "/Users", "/root", "/opt", "/var", "/tmp"];
+#[cfg(windows)]
+const SYSTEM_ROOTS: &[&str] = &["C:\\", "C:\\Users"];
+#[cfg(not(any(unix, windows)))]
+const SYSTEM_ROOTS: &[&str] = &[];
+
+#[derive(Debug, Default)]
+struct DenyList {
+    // Protected along with everything inside them
+    trees: Vec<PathBuf>,
+    // Protected themselves; what's inside them may still be purged
+    roots: Vec<PathBuf>,
+    // dirpurge's own journal, history, config and cache, protected as trees:
+    // a broad target must not take the record needed to undo it
+    own: Vec<PathBuf>,
+}
+
+static DENY_LIST: OnceLock<DenyList> = OnceLock::new();
+
+// Symlinks and `..` mustn't slip a path past the list
+fn resolve(path: &Path) -> PathBuf {
+    path.canonicalize().unwrap_or_else(|_| path.to_path_buf())
+}
+
+// `~/` entries are relative to the home directory
+pub fn expand(entry: &str) -> PathBuf {
+    match (entry.strip_prefix("~/"), home_dir()) {
+        (Some(rest), Some(home)) => home.join(rest),
+        _ => PathBuf::from(entry),
+    }
+}
+
+pub fn home_dir() -> Option<PathBuf> {
+    env::var_os("HOME")
+        .or_else(|| env::var_os("USERPROFILE"))
+        .map(PathBuf::from)
+}
+
+// Set up the built-in list plus the user's `protected` entries and the
+// files of dirpurge's own the run uses (`own`); before this only the built-in
+// list applies
+pub fn init(entries: &[String], own: &[PathBuf]) {
+    let _ = DENY_LIST.set(deny_list(entries, own));
+}
+
+// The protected trees, dirpurge's own state among them, and roots in effect
+pub fn listed() -> (Vec<PathBuf>, Vec<PathBuf>) {
+    let list = DENY_LIST.get_or_init(|| deny_list(&[], &[]));
+    (list.trees.iter().chain(&list.own).cloned().collect(), list.roots.clone())
+}
+
+fn deny_list(entries: &[String], own: &[PathBuf]) -> DenyList {
+    let mut trees: Vec<PathBuf> = SYSTEM_TREES.iter().map(|p| resolve(Path::new(p))).collect();
+    trees.extend(entries.iter().map(|entry| resolve(&expand(entry))));
+    let mut roots: Vec<PathBuf> = SYSTEM_ROOTS.iter().map(|p| resolve(Path::new(p))).collect();
+    roots.extend(home_dir().map(|home| resolve(&home)));
+    // Files not written yet are still placed where their directory resolves
+    let own = own.iter().cloned().chain(own_dirs())
+        .map(|path| path.canonicalize().or_else(|_| std::path::absolute(&path)).unwrap_or(path))
+        .collect();
+    DenyList { trees, roots, own }
+}
+
+// Where dirpurge keeps state unless told otherwise. Those not made yet hold
+// nothing to lose, and leave their parents purgeable.
+fn own_dirs() -> Vec<PathBuf> {
+    let home = home_dir();
+    let xdg = |var: &str, default: &str| env::var_os(var).map(PathBuf::from).or_else(|| home.as_ref().map(|home| home.join(default)));
+    let dirs = if cfg!(windows) {
+        vec![env::var_os("LOCALAPPDATA").map(PathBuf::from), env::var_os("APPDATA").map(PathBuf::from)]
+    } else {
+        vec![xdg("XDG_STATE_HOME", ".local/state"), xdg("XDG_CONFIG_HOME", ".config"), xdg("XDG_CACHE_HOME", ".cache")]
+    };
+    dirs.into_iter().flatten().map(|dir| dir.join("dirpurge")).filter(|dir| dir.exists()).collect()
+}
+
+// What deleting `path` would touch that must stay, if anything: a protected
+// tree it is inside of or a protected path inside it, and what that path is
+pub fn check(path: &Path) -> Option<(PathBuf, &'static str)> {
+    let list = DENY_LIST.get_or_init(|| deny_list(&[], &[]));
+    let path = resolve(path);
+    let overlaps = |tree: &&PathBuf| path.starts_with(tree) || tree.starts_with(&path);
+    let hit = list.trees.iter().find(overlaps).map(|tree| (tree.clone(), "protected path"))
+        .or_else(|| list.own.iter().find(overlaps).map(|own| (own.clone(), "dirpurge's own state")))
+        .or_else(|| list.roots.iter().find(|root| root.starts_with(&path)).map(|root| (root.clone(), "protected path")));
+    if let Some((hit, _)) = &hit {
+        debug!("{} is protected by {}", path.display(), hit.display());
+    }
+    hit
+}
+
+// What makes `path` too broad a place to purge from, if anything
+pub fn dangerous_root(path: &Path) -> Option<&'static str> {
+    let path = resolve(path);
+    if path.parent().is_none() {
+        return Some(if cfg!(windows) { "a drive root" } else { "the filesystem root" });
+    }
+    if home_dir().is_some_and(|home| resolve(&home) == path) {
+        return Some("your home directory");
+    }
+    None
+}
@@ -0,0 +1,180 @@
+// Keeping backup directories from growing forever: after a run, the oldest
+// copies and archives beyond --prune-backups' limits are removed.
+use serde::{Deserialize, Serialize};
+use std::{fmt, fs, path::{Path, PathBuf}, str::FromStr, time::SystemTime};
+use walkdir::WalkDir;
+use log::{debug, warn};
+
+use crate::{archive::{self, ArchiveFormat}, backend, format_mb, locale, naming::NameTemplate, report::{self, Kind}, thin, units};
+
+// What to keep; a backup goes once it breaks any of the limits set
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(try_from = "String", into = "String")]
+pub struct RetentionPolicy {
+    pub days: Option<u64>,
+    // Total bytes of the newest backups kept
+    pub size: Option<u64>,
+    pub count: Option<usize>,
+}
+
+impl FromStr for RetentionPolicy {
+    type Err = String;
+
+    // `days=30,size=10GB,count=20`, any of them
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid prune policy '{}', expected days=N, size=SIZE and/or count=N separated by commas", value);
+        let mut policy = RetentionPolicy::default();
+        for limit in value.split(',').map(str::trim) {
+            match limit.split_once('=').ok_or_else(invalid)? {
+                ("days", days) => policy.days = Some(days.parse().map_err(|_| invalid())?),
+                ("size", size) => policy.size = Some(units::parse_size(size)?),
+                ("count", count) => policy.count = Some(count.parse().map_err(|_| invalid())?),
+                _ => return Err(invalid()),
+            }
+        }
+        Ok(policy)
+    }
+}
+
+impl TryFrom<String> for RetentionPolicy {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl fmt::Display for RetentionPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let limits: Vec<String> = [
+            self.days.map(|days| format!("days={}", days)),
+            self.size.map(|size| format!("size={}", size)),
+            self.count.map(|count| format!("count={}", count)),
+        ].into_iter().flatten().collect();
+        write!(f, "{}", limits.join(","))
+    }
+}
+
+impl From<RetentionPolicy> for String {
+    fn from(policy: RetentionPolicy) -> Self {
+        policy.to_string()
+    }
+}
+
+struct Backup {
+    path: PathBuf,
+    modified: SystemTime,
+    bytes: u64,
+}
+
+// Copies and archives at the top of a backup directory, or where the
+// template puts them; other files are not dirpurge's and are left alone
+fn backups(root: &Path, template: Option<&NameTemplate>) -> Vec<Backup> {
+    let paths = match template {
+        Some(template) => template.stored(root),
+        None => match fs::read_dir(root) {
+            Ok(entries) => entries.filter_map(|e| e.ok()).map(|entry| entry.path()).collect(),
+            Err(_) => {
+                warn!("Backup directory not readable: {}", root.display());
+                return Vec::new();
+            }
+        },
+    };
+    paths.into_iter()
+        .filter_map(|path| {
+            let metadata = fs::symlink_metadata(&path).ok()?;
+            let bytes = if metadata.is_dir() {
+                WalkDir::new(&path).into_iter()
+                    .filter_map(|e| e.ok())
+                    .filter(|e| e.file_type().is_file())
+                    .filter_map(|e| e.metadata().ok())
+                    .map(|m| m.len())
+                    .sum()
+            } else if metadata.is_file() && ArchiveFormat::of(&path).is_some() {
+                archive::files(&path).iter().filter_map(|volume| volume.metadata().ok()).map(|m| m.len()).sum()
+            } else {
+                return None;
+            };
+            Some(Backup { path, modified: metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH), bytes })
+        })
+        .collect()
+}
+
+fn same(a: &Path, b: &Path) -> bool {
+    a == b || fs::canonicalize(a).ok().is_some_and(|a| fs::canonicalize(b).is_ok_and(|b| a == b))
+}
+
+// Newest first, what breaks `policy`. The copies just made always stay, though
+// they count towards the limits.
+fn expired(mut backups: Vec<Backup>, policy: &RetentionPolicy, made: &[PathBuf]) -> Vec<Backup> {
+    backups.sort_by_key(|backup| std::cmp::Reverse(backup.modified));
+    let oldest = policy.days.map(|days| thin::cutoff(days.min(i64::MAX as u64) as i64));
+    let (mut kept, mut kept_bytes) = (0, 0u64);
+    backups.into_iter()
+        .filter(|backup| {
+            let fits = oldest.is_none_or(|oldest| backup.modified >= oldest)
+                && policy.count.is_none_or(|count| kept < count)
+                && policy.size.is_none_or(|size| kept_bytes + backup.bytes <= size);
+            let keep = fits || made.iter().any(|copy| same(copy, &backup.path));
+            if keep {
+                kept += 1;
+                kept_bytes += backup.bytes;
+            }
+            !keep
+        })
+        .collect()
+}
+
+fn remove(backup: &Backup) -> std::io::Result<()> {
+    if backup.path.is_dir() {
+        return fs::remove_dir_all(&backup.path);
+    }
+    for volume in archive::files(&backup.path) {
+        fs::remove_file(volume)?;
+    }
+    // The manifest --archive-manifest wrote beside it
+    let beside = PathBuf::from(format!("{}.manifest.json", backup.path.display()));
+    match fs::remove_file(beside) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+// Prune every local backup directory and report what went. Failures are
+// warned about; they never fail the run that made the backups.
+pub fn prune(backup_dirs: &[String], template: Option<&NameTemplate>, policy: &RetentionPolicy, made: &[String], dry_run: bool) {
+    let made: Vec<PathBuf> = made.iter().map(PathBuf::from).collect();
+    for backup_dir in backup_dirs {
+        let Some(root) = backend::local_root(backup_dir) else {
+            warn!("Pruning remote backups is not supported: {}", backup_dir);
+            continue;
+        };
+        let expired = expired(backups(&root, template), policy, &made);
+        if expired.is_empty() {
+            debug!("Nothing in {} breaks the prune policy {}", backup_dir, policy);
+            continue;
+        }
+        let mut pruned = Vec::new();
+        for backup in &expired {
+            if !dry_run && let Err(e) = remove(backup) {
+                report::message(Kind::Warning, format!("Could not prune {}: {}", backup.path.display(), e));
+                continue;
+            }
+            // The template's directories it was the last backup in
+            if !dry_run {
+                for dir in backup.path.ancestors().skip(1).take_while(|dir| *dir != root && dir.starts_with(&root)) {
+                    if fs::remove_dir(dir).is_err() {
+                        break;
+                    }
+                }
+            }
+            pruned.push(backup);
+        }
+        let bytes = pruned.iter().map(|backup| backup.bytes).sum();
+        let verb = if dry_run { "[Dry Run] Would prune" } else { "Pruned" };
+        report::message(if dry_run { Kind::DryRun } else { Kind::Info }, format!(
+            "{} {} backups ({}) from {}", verb, locale::count(pruned.len()), format_mb(bytes, false), backup_dir
+        ));
+        report::items(&pruned.iter().map(|backup| backup.path.display().to_string()).collect::<Vec<_>>());
+    }
+}
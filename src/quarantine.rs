@@ -0,0 +1,126 @@
+// Directories put aside with --quarantine instead of being deleted, for a
+// grace period that doesn't rely on the desktop trash. Each gets an entry of
+// its own in the quarantine directory, `<date>-<time>-<hash>/<name>`, beside a
+// note of where it came from. `dirpurge quarantine purge --older-than 14d`
+// removes them for good; until then `dirpurge restore` brings one back.
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{fs, io, path::{Path, PathBuf}, time::{Duration, SystemTime}};
+use log::{debug, info, warn};
+
+use crate::{backup, format_mb, journal, locale, naming, report::{self, Kind}, throttle::RateLimiter, CROSS};
+
+// The note in each entry; only entries with one are ever purged
+const NOTE: &str = ".dirpurge-quarantine.json";
+
+#[derive(Serialize, Deserialize)]
+struct Note {
+    path: String,
+    quarantined_at: String,
+    size_bytes: u64,
+}
+
+pub fn resolve(configured: Option<&str>) -> Result<PathBuf, String> {
+    let dir = configured.map(PathBuf::from)
+        .or_else(|| journal::state_dir().map(|dir| dir.join("quarantine")))
+        .ok_or_else(|| format!("{} No home directory for the quarantine, pass --quarantine-dir DIR", CROSS))?;
+    // Recorded as a copy, so it has to hold from wherever a restore is run
+    Ok(std::path::absolute(&dir).unwrap_or(dir))
+}
+
+// Moves `path` into a new entry in `root`. Returns where it went.
+pub fn quarantine(path: &Path, root: &Path, size_bytes: u64) -> Result<PathBuf, String> {
+    let failed = |e: io::Error| format!("{} Quarantine failed: {}", CROSS, e);
+    fs::create_dir_all(root).map_err(failed)?;
+    let now = Local::now();
+    let absolute = std::path::absolute(path).map_err(failed)?;
+    let hash: String = Sha256::digest(absolute.to_string_lossy().as_bytes()).iter().take(4).map(|b| format!("{:02x}", b)).collect();
+    let stem = root.join(format!("{}-{}", now.format("%Y%m%d-%H%M%S"), hash));
+    let note = Note { path: absolute.to_string_lossy().into_owned(), quarantined_at: now.to_rfc3339(), size_bytes };
+    let note = serde_json::to_vec_pretty(&note).map_err(|e| failed(io::Error::other(e)))?;
+    // Claimed by making it, so runs at the same time never share one
+    let mut n = 1;
+    let entry = loop {
+        let entry = naming::numbered(&stem, n, None);
+        match fs::create_dir(&entry) {
+            Ok(()) => break entry,
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => n += 1,
+            Err(e) => return Err(failed(e)),
+        }
+    };
+
+    let dest = entry.join(absolute.file_name().unwrap_or("dir".as_ref()));
+    let moved = fs::write(entry.join(NOTE), note).and_then(|_| move_dir(&absolute, &dest));
+    if let Err(e) = moved {
+        // Kept if the copy is whole and only removing the original failed
+        if !dest.exists() {
+            let _ = fs::remove_dir_all(&entry);
+        }
+        return Err(failed(e));
+    }
+    info!("Quarantined {} in {}", absolute.display(), dest.display());
+    Ok(dest)
+}
+
+// A rename on one filesystem; across filesystems a copy, then the original removed
+fn move_dir(from: &Path, to: &Path) -> io::Result<()> {
+    match fs::rename(from, to) {
+        Err(e) if e.kind() == io::ErrorKind::CrossesDevices => {
+            debug!("{} is on another filesystem than the quarantine, copying it", from.display());
+            let copied = backup::copy_dir_recursive(from, to, &mut RateLimiter::new(None), backup::CopyMode::default(), &mut |_, _| {});
+            if let Err(e) = copied {
+                let _ = fs::remove_dir_all(to);
+                return Err(e);
+            }
+            fs::remove_dir_all(from)
+        }
+        result => result,
+    }
+}
+
+fn read_note(entry: &Path) -> Option<(Note, SystemTime)> {
+    let note: Note = serde_json::from_slice(&fs::read(entry.join(NOTE)).ok()?).ok()?;
+    let at = DateTime::parse_from_rfc3339(&note.quarantined_at).ok()?.into();
+    Some((note, at))
+}
+
+// Removes the entries in `root` quarantined longer than `older_than` ago
+pub fn purge(root: &Path, older_than: Duration, dry_run: bool) -> Result<(), String> {
+    let mut entries: Vec<PathBuf> = match fs::read_dir(root) {
+        Ok(entries) => entries.filter_map(|e| e.ok()).map(|e| e.path()).collect(),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+        Err(e) => return Err(format!("{} Could not read the quarantine {}: {}", CROSS, root.display(), e)),
+    };
+    entries.sort();
+    let cutoff = SystemTime::now().checked_sub(older_than).unwrap_or(SystemTime::UNIX_EPOCH);
+
+    let (mut purged, mut bytes, mut failed) = (Vec::new(), 0, 0);
+    for entry in entries {
+        let Some((note, at)) = read_note(&entry) else {
+            debug!("Not a quarantine entry, left alone: {}", entry.display());
+            continue;
+        };
+        if at >= cutoff {
+            continue;
+        }
+        if !dry_run && let Err(e) = fs::remove_dir_all(&entry) {
+            warn!("Could not purge {}: {}", entry.display(), e);
+            report::message(Kind::Warning, format!("Could not purge {} ({}): {}", entry.display(), note.path, e));
+            failed += 1;
+            continue;
+        }
+        bytes += note.size_bytes;
+        purged.push(format!("{} (quarantined {})", note.path, note.quarantined_at.get(..10).unwrap_or(&note.quarantined_at)));
+    }
+
+    let verb = if dry_run { "[Dry Run] Would purge" } else { "Purged" };
+    report::message(if dry_run { Kind::DryRun } else { Kind::Deleted }, format!(
+        "{} {} quarantined directories ({}) from {}", verb, locale::count(purged.len()), format_mb(bytes, false), root.display()
+    ));
+    report::items(&purged);
+    if failed > 0 {
+        return Err(format!("{} {} quarantined directories could not be purged", CROSS, locale::count(failed)));
+    }
+    Ok(())
+}
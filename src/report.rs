@@ -0,0 +1,267 @@
+use console::Style;
+use indicatif::ProgressDrawTarget;
+use serde::{Deserialize, Serialize};
+use std::sync::OnceLock;
+
+use crate::{locale, disk_total, DirInfo, CROSS, DISK, GEAR, INFO, MAG, TICK, TRASH, WARN, bold, cyan, format_mb, green, progress, red, yellow};
+
+// How console output is rendered, picked once at startup
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum OutputStyle {
+    // Emoji and colours
+    #[default]
+    Human,
+    // Bare text for logs and CI
+    Plain,
+    // One JSON object per line
+    JsonEvents,
+}
+
+// What a line of output is about
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Kind {
+    // Configuration and progress notes
+    Setting,
+    Info,
+    // An external step starting, e.g. stopping a daemon
+    Step,
+    Success,
+    Saved,
+    Trashed,
+    Deleted,
+    Warning,
+    DryRun,
+    Error,
+    // Pointers on what to do next, dropped by --quiet
+    Hint,
+}
+
+impl Kind {
+    fn icon(self) -> String {
+        match self {
+            Kind::Setting => MAG.to_string(),
+            Kind::Info | Kind::Hint => INFO.to_string(),
+            Kind::Step => GEAR.to_string(),
+            Kind::Success => TICK.to_string(),
+            Kind::Saved => DISK.to_string(),
+            Kind::Trashed => TRASH.to_string(),
+            Kind::Warning | Kind::DryRun => yellow().apply_to(WARN).to_string(),
+            Kind::Deleted | Kind::Error => CROSS.to_string(),
+        }
+    }
+
+    fn style(self) -> Style {
+        match self {
+            Kind::Setting | Kind::Step | Kind::DryRun => cyan(),
+            Kind::Info | Kind::Hint | Kind::Warning => yellow(),
+            Kind::Success | Kind::Saved | Kind::Trashed => green(),
+            Kind::Deleted | Kind::Error => red(),
+        }
+    }
+}
+
+// Renders everything dirpurge tells the user outside of prompts and progress bars
+pub trait Reporter: Send + Sync {
+    fn banner(&self, version: &str);
+    fn message(&self, kind: Kind, text: &str);
+    // The scan's candidates, largest first, and the report-only matches
+    fn found(&self, dirs: &[DirInfo], inventory: &[DirInfo], estimated: bool);
+    // A list belonging to the message before it
+    fn items(&self, items: &[String]);
+}
+
+struct Human;
+
+impl Reporter for Human {
+    fn banner(&self, version: &str) {
+        println!("\n{} {} v{}", GEAR, bold().apply_to("🧹 dirpurge"), version);
+    }
+
+    fn message(&self, kind: Kind, text: &str) {
+        if kind == Kind::Error {
+            eprintln!("{} {}", kind.icon(), kind.style().apply_to(text));
+        } else {
+            println!("{} {}", kind.icon(), kind.style().apply_to(text));
+        }
+    }
+
+    fn found(&self, dirs: &[DirInfo], inventory: &[DirInfo], estimated: bool) {
+        if !dirs.is_empty() {
+            println!("\n{} {} matching directories found:", TICK, bold().apply_to(locale::count(dirs.len())));
+            let total_size: u64 = dirs.iter().map(|d| d.size_bytes).sum();
+            println!("{} Total size: {}{}{}", INFO, format_mb(total_size, estimated), if estimated { " (estimated)" } else { "" }, on_disk(dirs));
+            for (i, dir) in dirs.iter().enumerate().take(10) {
+                println!("  {}. {} ({})", i + 1, dir.path, dir.size_label());
+            }
+            if dirs.len() > 10 {
+                println!("  ... and {} more", locale::count(dirs.len() - 10));
+            }
+        }
+        if !inventory.is_empty() {
+            let total_size: u64 = inventory.iter().map(|d| d.size_bytes).sum();
+            println!("\n{} {} report-only directories ({}):", INFO, bold().apply_to(locale::count(inventory.len())), format_mb(total_size, estimated));
+            for dir in inventory.iter().take(10) {
+                println!("  - {} ({})", dir.path, dir.size_label());
+            }
+            if inventory.len() > 10 {
+                println!("  ... and {} more", locale::count(inventory.len() - 10));
+            }
+        }
+    }
+
+    fn items(&self, items: &[String]) {
+        for item in items {
+            println!("  {}", cyan().apply_to(item));
+        }
+    }
+}
+
+// ", N MB on disk" when compression makes that less than the total
+fn on_disk(dirs: &[DirInfo]) -> String {
+    disk_total(dirs).map_or_else(String::new, |disk| format!(", {} on disk", format_mb(disk, false)))
+}
+
+// No emoji, no colours and complete listings
+struct Plain;
+
+impl Reporter for Plain {
+    fn banner(&self, version: &str) {
+        println!("dirpurge v{}", version);
+    }
+
+    fn message(&self, kind: Kind, text: &str) {
+        match kind {
+            Kind::Error => eprintln!("error: {}", text),
+            Kind::Warning => println!("warning: {}", text),
+            _ => println!("{}", text),
+        }
+    }
+
+    fn found(&self, dirs: &[DirInfo], inventory: &[DirInfo], estimated: bool) {
+        let total_size: u64 = dirs.iter().map(|d| d.size_bytes).sum();
+        println!("{} matching directories found, {} total{}", locale::count(dirs.len()), format_mb(total_size, estimated), on_disk(dirs));
+        for dir in dirs {
+            println!("{}\t{}", dir.path, dir.size_label());
+        }
+        for dir in inventory {
+            println!("{}\t{}\treport-only", dir.path, dir.size_label());
+        }
+    }
+
+    fn items(&self, items: &[String]) {
+        for item in items {
+            println!("  {}", item);
+        }
+    }
+}
+
+// Machine-readable event stream on stdout
+struct JsonEvents;
+
+impl JsonEvents {
+    fn emit(&self, event: serde_json::Value) {
+        println!("{}", event);
+    }
+}
+
+impl Reporter for JsonEvents {
+    fn banner(&self, version: &str) {
+        self.emit(serde_json::json!({ "event": "start", "version": version }));
+    }
+
+    fn message(&self, kind: Kind, text: &str) {
+        self.emit(serde_json::json!({ "event": "message", "kind": kind, "text": text }));
+    }
+
+    fn found(&self, dirs: &[DirInfo], inventory: &[DirInfo], estimated: bool) {
+        self.emit(serde_json::json!({
+            "event": "found",
+            "directories": dirs,
+            "inventory": inventory,
+            "total_size_bytes": dirs.iter().map(|d| d.size_bytes).sum::<u64>(),
+            "total_disk_bytes": disk_total(dirs),
+            "estimated": estimated,
+        }));
+    }
+
+    fn items(&self, items: &[String]) {
+        self.emit(serde_json::json!({ "event": "items", "items": items }));
+    }
+}
+
+// Only what needs attention: results, warnings and errors
+struct Quiet(Box<dyn Reporter>);
+
+impl Reporter for Quiet {
+    fn banner(&self, _version: &str) {}
+
+    fn message(&self, kind: Kind, text: &str) {
+        if !matches!(kind, Kind::Setting | Kind::Hint) {
+            self.0.message(kind, text);
+        }
+    }
+
+    fn found(&self, _dirs: &[DirInfo], _inventory: &[DirInfo], _estimated: bool) {}
+
+    fn items(&self, items: &[String]) {
+        self.0.items(items);
+    }
+}
+
+struct Output {
+    reporter: Box<dyn Reporter>,
+    style: OutputStyle,
+    verbose: bool,
+}
+
+static OUTPUT: OnceLock<Output> = OnceLock::new();
+
+// Pick the renderer for the rest of the run; output before this uses the human one
+pub fn init(style: OutputStyle, verbose: bool, quiet: bool) {
+    let reporter: Box<dyn Reporter> = match style {
+        OutputStyle::Human => Box::new(Human),
+        OutputStyle::Plain => Box::new(Plain),
+        OutputStyle::JsonEvents => Box::new(JsonEvents),
+    };
+    let reporter = if quiet { Box::new(Quiet(reporter)) } else { reporter };
+    // Progress bars would garble anything but the human renderer
+    if style != OutputStyle::Human {
+        progress().set_draw_target(ProgressDrawTarget::hidden());
+    }
+    let _ = OUTPUT.set(Output { reporter, style, verbose });
+}
+
+fn output() -> &'static Output {
+    OUTPUT.get_or_init(|| Output { reporter: Box::new(Human), style: OutputStyle::Human, verbose: false })
+}
+
+// Whether spinners and progress bars should be drawn
+pub fn animated() -> bool {
+    output().style == OutputStyle::Human
+}
+
+pub fn banner(version: &str) {
+    output().reporter.banner(version);
+}
+
+pub fn message(kind: Kind, text: impl AsRef<str>) {
+    output().reporter.message(kind, text.as_ref());
+}
+
+// Only shown with --verbose
+pub fn detail(kind: Kind, text: impl AsRef<str>) {
+    if output().verbose {
+        message(kind, text);
+    }
+}
+
+pub fn found(dirs: &[DirInfo], inventory: &[DirInfo], estimated: bool) {
+    output().reporter.found(dirs, inventory, estimated);
+}
+
+pub fn items(items: &[String]) {
+    output().reporter.items(items);
+}
+
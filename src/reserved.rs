@@ -0,0 +1,81 @@
+// Places dirpurge itself keeps what it removed: the local backup
+// destinations, the quarantine and the trash. Scans never descend into them, or the copies
+// kept there would be found and offered for deletion in turn.
+use std::path::{Path, PathBuf};
+
+use crate::{backend, deleting};
+
+#[derive(Debug, Clone, Default)]
+pub struct Reserved {
+    // Resolved location and what it is, for the note when one is passed over
+    locations: Vec<(PathBuf, &'static str)>,
+}
+
+impl Reserved {
+    pub fn new(backup_dirs: &[String], quarantine: Option<PathBuf>) -> Self {
+        let mut locations: Vec<(PathBuf, &'static str)> = backup_dirs.iter()
+            .filter_map(|uri| backend::local_root(uri))
+            .map(|dir| (dir, "backup directory"))
+            .collect();
+        locations.extend(quarantine.map(|dir| (dir, "quarantine")));
+        locations.extend(home_trash().into_iter().map(|dir| (dir, "trash")));
+        for (location, _) in &mut locations {
+            *location = resolve(location);
+        }
+        Reserved { locations }
+    }
+
+    // Those of the locations outside `base`, the resolved scan root: one
+    // scanned on purpose is scanned
+    pub fn around(&self, base: &Path) -> Reserved {
+        let locations = self.locations.iter().filter(|(location, _)| !base.starts_with(location)).cloned().collect();
+        Reserved { locations }
+    }
+
+    // What the directory `path` is, if it is one of the locations or a
+    // trash of its own volume. `path` is resolved, or built on a resolved root.
+    pub fn kind_of(&self, path: &Path) -> Option<&'static str> {
+        if let Some(name) = path.file_name().and_then(|name| name.to_str()) {
+            if is_volume_trash(name) {
+                return Some("trash");
+            }
+            if deleting::is_set_aside(name) {
+                return Some("directory being removed");
+            }
+        }
+        self.locations.iter().find(|(location, _)| location == path).map(|(_, kind)| *kind)
+    }
+}
+
+fn resolve(path: &Path) -> PathBuf {
+    path.canonicalize()
+        .or_else(|_| std::path::absolute(path))
+        .unwrap_or_else(|_| path.to_path_buf())
+}
+
+// Where the desktop trash keeps files on the home volume
+#[cfg(target_os = "macos")]
+pub fn home_trash() -> Vec<PathBuf> {
+    crate::protected::home_dir().map(|home| home.join(".Trash")).into_iter().collect()
+}
+
+#[cfg(all(unix, not(target_os = "macos")))]
+pub fn home_trash() -> Vec<PathBuf> {
+    let data = std::env::var_os("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .or_else(|| crate::protected::home_dir().map(|home| home.join(".local/share")));
+    data.map(|data| data.join("Trash")).into_iter().collect()
+}
+
+// The recycle bin lives at the top of each volume, found by name
+#[cfg(not(unix))]
+pub fn home_trash() -> Vec<PathBuf> {
+    Vec::new()
+}
+
+// Trash kept at the top of other volumes: `.Trash-<uid>` (and `.Trash`
+// holding one per user) on Linux, `.Trashes` on macOS, `$RECYCLE.BIN` on Windows
+fn is_volume_trash(name: &str) -> bool {
+    let numbered = name.strip_prefix(".Trash-").is_some_and(|uid| !uid.is_empty() && uid.bytes().all(|b| b.is_ascii_digit()));
+    numbered || name == ".Trash" || name == ".Trashes" || name.eq_ignore_ascii_case("$RECYCLE.BIN")
+}
@@ -0,0 +1,105 @@
+use std::{fs, path::{Path, PathBuf}};
+use log::{debug, info};
+
+use crate::{archive, backend, backup, journal::{self, Event, Removed}, report::{self, Kind}, throttle::RateLimiter, CROSS};
+
+// Bring back a directory removed by an earlier run: from a backup copy or
+// archive if one was made, otherwise from the trash. Returns what it was
+// restored from.
+pub fn restore(removed: &Removed, dest: &Path, dry_run: bool) -> Result<String, String> {
+    if dest.symlink_metadata().is_ok() {
+        return Err(format!("{} {} already exists, pass --to DIR to restore elsewhere", CROSS, dest.display()));
+    }
+
+    if let Some(copy) = removed.copies.iter()
+        .filter_map(|copy| backend::local_root(copy))
+        .find(|copy| copy.exists())
+    {
+        if dry_run {
+            report::message(Kind::DryRun, format!("[Dry Run] Would restore {} from {}", dest.display(), copy.display()));
+            return Ok(copy.to_string_lossy().into_owned());
+        }
+        restore_copy(&copy, dest)?;
+        return Ok(copy.to_string_lossy().into_owned());
+    }
+    if !removed.copies.is_empty() {
+        debug!("No local copy of {} left among {:?}", removed.path.display(), removed.copies);
+    }
+
+    if removed.trashed {
+        if dry_run {
+            report::message(Kind::DryRun, format!("[Dry Run] Would restore {} from the trash", dest.display()));
+            return Ok("trash".to_string());
+        }
+        restore_from_trash(&removed.path)?;
+        if dest != removed.path {
+            fs::rename(&removed.path, dest)
+                .map_err(|e| format!("{} Restored to {} but could not move it to {}: {}", CROSS, removed.path.display(), dest.display(), e))?;
+        }
+        return Ok("trash".to_string());
+    }
+
+    if removed.copies.is_empty() {
+        Err(format!("{} {} was deleted permanently without a backup, nothing to restore it from", CROSS, removed.path.display()))
+    } else {
+        Err(format!("{} None of the copies of {} can be found: {}", CROSS, removed.path.display(), removed.copies.join(", ")))
+    }
+}
+
+// Copies are directories, archives are zip files. A failed restore is
+// removed again rather than left half-done in place.
+fn restore_copy(copy: &Path, dest: &Path) -> Result<(), String> {
+    let restored = if copy.is_dir() {
+        backup::copy_dir_recursive(copy, dest, &mut RateLimiter::new(None), backup::CopyMode::default(), &mut |_, _| {})
+            .map_err(|e| format!("{} Failed to copy {} back: {}", CROSS, copy.display(), e))
+    } else {
+        archive::extract(copy, dest)
+            .map(|files| info!("Extracted {} files from {}", files, copy.display()))
+    };
+    if restored.is_err() {
+        let _ = fs::remove_dir_all(dest);
+    }
+    restored
+}
+
+#[cfg(all(feature = "trash", any(
+    target_os = "windows",
+    all(unix, not(target_os = "macos"), not(target_os = "ios"), not(target_os = "android"))
+)))]
+pub fn restore_from_trash(original: &Path) -> Result<(), String> {
+    let items = trash::os_limited::list()
+        .map_err(|e| format!("{} Could not list the trash: {}", CROSS, e))?;
+    // The latest one, if the same path was trashed more than once
+    let item = items.into_iter()
+        .filter(|item| item.original_path() == original)
+        .max_by_key(|item| item.time_deleted)
+        .ok_or_else(|| format!("{} {} is no longer in the trash", CROSS, original.display()))?;
+    trash::os_limited::restore_all([item])
+        .map_err(|e| format!("{} Could not restore {} from the trash: {}", CROSS, original.display(), e))
+}
+
+#[cfg(not(all(feature = "trash", any(
+    target_os = "windows",
+    all(unix, not(target_os = "macos"), not(target_os = "ios"), not(target_os = "android"))
+))))]
+pub fn restore_from_trash(original: &Path) -> Result<(), String> {
+    Err(format!("{} Restoring from the trash isn't supported in this build; restore {} from your file manager", CROSS, original.display()))
+}
+
+// Record a restore in the journal of the run that removed it
+pub fn record(journal_path: &Path, removed: &Removed, from: &str, to: &Path) -> Result<(), String> {
+    journal::attach(journal_path, &removed.run)?;
+    journal::record(Event::Restored {
+        path: removed.path.to_string_lossy().into_owned(),
+        from: from.to_string(),
+        to: to.to_string_lossy().into_owned(),
+    });
+    Ok(())
+}
+
+// The journalled form of a path given on the command line
+pub fn absolute(path: &Path) -> Result<PathBuf, String> {
+    let cwd = std::env::current_dir()
+        .map_err(|e| format!("{} Cannot determine the current directory: {}", CROSS, e))?;
+    Ok(journal::normalize(&cwd.join(path)))
+}
@@ -0,0 +1,351 @@
+// Backups to S3 and S3-compatible stores (MinIO, R2, ...) with
+// `--backup-dir s3://bucket/prefix`. Archives are streamed up in parts as
+// they are written, so no more than one part is ever held locally. Requests
+// are signed with AWS Signature V4 and sent with curl, which brings TLS and
+// proxy support.
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+use std::{collections::hash_map::RandomState, env, fs, hash::BuildHasher, io::{self, Write}, path::PathBuf, process::{Command, Stdio}, sync::atomic::{AtomicUsize, Ordering}, thread, time::Duration};
+use log::{debug, warn};
+
+use crate::{archive, backend::{BackupBackend, BackupOptions}, naming, throttle::{RateLimiter, Throttled}, CROSS};
+
+// S3 takes at least 5 MiB for every part but the last
+const PART_SIZE: usize = 8 << 20;
+const ATTEMPTS: u32 = 3;
+
+struct Credentials {
+    access_key: String,
+    secret_key: String,
+    session_token: Option<String>,
+}
+
+pub struct S3Backend {
+    bucket: String,
+    prefix: String,
+    // Scheme and host, e.g. https://s3.eu-west-1.amazonaws.com
+    endpoint: String,
+    region: String,
+    credentials: Credentials,
+}
+
+fn var(name: &str) -> Option<String> {
+    env::var(name).ok().filter(|value| !value.is_empty())
+}
+
+impl S3Backend {
+    // `bucket/prefix` from an s3:// URI; credentials, region and endpoint
+    // come from the usual AWS_* variables
+    pub fn open(location: &str) -> Result<Self, String> {
+        let (bucket, prefix) = location.split_once('/').unwrap_or((location, ""));
+        if bucket.is_empty() {
+            return Err(format!("{} s3://{} names no bucket", CROSS, location));
+        }
+        let (Some(access_key), Some(secret_key)) = (var("AWS_ACCESS_KEY_ID"), var("AWS_SECRET_ACCESS_KEY")) else {
+            return Err(format!("{} s3:// destinations need AWS_ACCESS_KEY_ID and AWS_SECRET_ACCESS_KEY", CROSS));
+        };
+        let region = var("AWS_REGION").or_else(|| var("AWS_DEFAULT_REGION")).unwrap_or_else(|| "us-east-1".to_string());
+        let endpoint = var("AWS_ENDPOINT_URL_S3").or_else(|| var("AWS_ENDPOINT_URL"))
+            .unwrap_or_else(|| format!("https://s3.{}.amazonaws.com", region));
+        Ok(S3Backend {
+            bucket: bucket.to_string(),
+            prefix: prefix.trim_matches('/').to_string(),
+            endpoint: endpoint.trim_end_matches('/').to_string(),
+            region,
+            credentials: Credentials { access_key, secret_key, session_token: var("AWS_SESSION_TOKEN") },
+        })
+    }
+
+    fn key(&self, name: &str) -> String {
+        if self.prefix.is_empty() { name.to_string() } else { format!("{}/{}", self.prefix, name) }
+    }
+
+    // So a failed backup doesn't leave parts behind to be billed for
+    fn abort(&self, key: &str, id: &str) {
+        if let Err(e) = self.send("DELETE", key, &[("uploadId", id)], &[], &[]) {
+            warn!("Could not abort the upload of {}: {}", key, e);
+        }
+    }
+
+    // One signed request, retried with backoff on failures and 5xx replies.
+    // `headers` go out signed along with the ones every request has.
+    fn send(&self, method: &str, key: &str, query: &[(&str, &str)], headers: &[(&'static str, String)], body: &[u8]) -> Result<Response, String> {
+        let mut attempt = 1;
+        loop {
+            match self.attempt(method, key, query, headers, body) {
+                Ok(response) if response.status < 500 => return response.ok(method, key),
+                Ok(response) if attempt >= ATTEMPTS => return response.ok(method, key),
+                Err(e) if attempt >= ATTEMPTS => return Err(e),
+                Ok(response) => warn!("{} {} got {}, retrying", method, key, response.status),
+                Err(e) => warn!("{} {} failed, retrying: {}", method, key, e),
+            }
+            thread::sleep(Duration::from_millis(500 << attempt));
+            attempt += 1;
+        }
+    }
+
+    fn attempt(&self, method: &str, key: &str, query: &[(&str, &str)], headers: &[(&'static str, String)], body: &[u8]) -> Result<Response, String> {
+        let path = format!("/{}/{}", encode(&self.bucket), key.split('/').map(encode).collect::<Vec<_>>().join("/"));
+        let mut query: Vec<(String, String)> = query.iter().map(|(k, v)| (encode(k), encode(v))).collect();
+        query.sort();
+        let query = query.iter().map(|(k, v)| format!("{}={}", k, v)).collect::<Vec<_>>().join("&");
+        let url = if query.is_empty() { format!("{}{}", self.endpoint, path) } else { format!("{}{}?{}", self.endpoint, path, query) };
+
+        let headers = HeaderFile::new(&self.signed_headers(method, &path, &query, headers, body))?;
+        let mut child = Command::new("curl")
+            .args(["--silent", "--show-error", "--include", "-X", method, "--data-binary", "@-", "-H", "Expect:"])
+            .arg("-H").arg(format!("@{}", headers.0.display()))
+            .arg(&url)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| format!("{} s3:// destinations need curl on the PATH: {}", CROSS, e))?;
+        let mut stdin = child.stdin.take().expect("piped stdin");
+        // Written from another thread, so a large part can't deadlock against the reply
+        let body = body.to_vec();
+        let writer = thread::spawn(move || stdin.write_all(&body));
+        let output = child.wait_with_output().map_err(|e| format!("{} curl failed: {}", CROSS, e))?;
+        let _ = writer.join();
+        if !output.status.success() {
+            return Err(format!("{} {} {} failed: {}", CROSS, method, url, String::from_utf8_lossy(&output.stderr).trim()));
+        }
+        Response::parse(&output.stdout).ok_or_else(|| format!("{} {} {} got no HTTP reply", CROSS, method, url))
+    }
+
+    // The headers of an AWS Signature V4 request, Authorization last
+    fn signed_headers(&self, method: &str, path: &str, query: &str, extra: &[(&'static str, String)], body: &[u8]) -> Vec<(&'static str, String)> {
+        let now = chrono::Utc::now();
+        let (amz_date, date) = (now.format("%Y%m%dT%H%M%SZ").to_string(), now.format("%Y%m%d").to_string());
+        let host = self.endpoint.split_once("://").map_or(self.endpoint.as_str(), |(_, host)| host).to_string();
+        let payload = hex(&Sha256::digest(body));
+        let mut headers = vec![
+            ("host", host),
+            ("x-amz-content-sha256", payload.clone()),
+            ("x-amz-date", amz_date.clone()),
+        ];
+        if let Some(token) = &self.credentials.session_token {
+            headers.push(("x-amz-security-token", token.clone()));
+        }
+        headers.extend_from_slice(extra);
+        // SigV4 signs headers in order of name
+        headers.sort_by_key(|(name, _)| *name);
+        let signed = headers.iter().map(|(name, _)| *name).collect::<Vec<_>>().join(";");
+        let canonical = format!(
+            "{}\n{}\n{}\n{}\n{}\n{}",
+            method, path, query,
+            headers.iter().map(|(name, value)| format!("{}:{}\n", name, value.trim())).collect::<String>(),
+            signed, payload
+        );
+        let scope = format!("{}/{}/s3/aws4_request", date, self.region);
+        let to_sign = format!("AWS4-HMAC-SHA256\n{}\n{}\n{}", amz_date, scope, hex(&Sha256::digest(canonical.as_bytes())));
+        let key = [date.as_str(), &self.region, "s3", "aws4_request"].iter()
+            .fold(format!("AWS4{}", self.credentials.secret_key).into_bytes(), |key, part| hmac(&key, part.as_bytes()));
+        headers.push(("authorization", format!(
+            "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+            self.credentials.access_key, scope, signed, hex(&hmac(&key, to_sign.as_bytes()))
+        )));
+        // curl sets Host itself
+        headers.retain(|(name, _)| *name != "host");
+        headers
+    }
+}
+
+// The signed headers, handed to curl as `-H @file` in a new file only this
+// user can read: on curl's command line anyone on the host could read the
+// credentials in them from ps. Removed once the request is done.
+struct HeaderFile(PathBuf);
+
+impl HeaderFile {
+    fn new(headers: &[(&str, String)]) -> Result<Self, String> {
+        let content: String = headers.iter().map(|(name, value)| format!("{}: {}\n", name, value)).collect();
+        let random = RandomState::new();
+        let mut attempt = 0u32;
+        loop {
+            let path = env::temp_dir().join(format!("dirpurge-s3-{:08x}.headers", random.hash_one(attempt) as u32));
+            let mut options = fs::OpenOptions::new();
+            options.write(true).create_new(true);
+            #[cfg(unix)]
+            std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+            match options.open(&path) {
+                Ok(mut file) => {
+                    // Removed on drop even if the write fails
+                    let headers = HeaderFile(path);
+                    file.write_all(content.as_bytes())
+                        .map_err(|e| format!("{} Could not write request headers to {}: {}", CROSS, headers.0.display(), e))?;
+                    return Ok(headers);
+                }
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists && attempt < 8 => attempt += 1,
+                Err(e) => return Err(format!("{} Could not write request headers to {}: {}", CROSS, path.display(), e)),
+            }
+        }
+    }
+}
+
+impl Drop for HeaderFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.0);
+    }
+}
+
+fn hmac(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC takes keys of any length");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+// Percent-encoding as SigV4 wants it: everything but unreserved characters
+fn encode(value: &str) -> String {
+    value.bytes().map(|b| match b {
+        b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => (b as char).to_string(),
+        _ => format!("%{:02X}", b),
+    }).collect()
+}
+
+struct Response {
+    status: u16,
+    headers: String,
+    body: String,
+}
+
+impl Response {
+    // `curl --include` output: status line and headers, a blank line, the body
+    fn parse(output: &[u8]) -> Option<Self> {
+        let text = String::from_utf8_lossy(output);
+        let (headers, body) = text.split_once("\r\n\r\n").unwrap_or((&text, ""));
+        let status = headers.split_whitespace().nth(1)?.parse().ok()?;
+        Some(Response { status, headers: headers.to_string(), body: body.to_string() })
+    }
+
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers.lines()
+            .filter_map(|line| line.split_once(':'))
+            .find(|(key, _)| key.trim().eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.trim())
+    }
+
+    // S3 reports some failures in the body of a 200
+    fn ok(self, method: &str, key: &str) -> Result<Self, String> {
+        if (200..300).contains(&self.status) && !self.body.contains("<Error>") {
+            return Ok(self);
+        }
+        Err(format!("{} {} {} failed with {}: {}", CROSS, method, key, self.status, tag(&self.body, "Message").unwrap_or(self.body.trim())))
+    }
+}
+
+// The text of the first <name> element, enough for S3's small XML replies
+fn tag<'a>(xml: &'a str, name: &str) -> Option<&'a str> {
+    let start = xml.find(&format!("<{}>", name))? + name.len() + 2;
+    let end = xml[start..].find(&format!("</{}>", name))?;
+    Some(&xml[start..start + end])
+}
+
+// A multipart upload, written to like a file. Parts go up as they fill; the
+// object only appears once `finish` completes the upload.
+struct Upload<'a> {
+    backend: &'a S3Backend,
+    key: String,
+    id: String,
+    part: Vec<u8>,
+    etags: Vec<String>,
+}
+
+impl<'a> Upload<'a> {
+    // The object's storage class is set once, when the upload starts
+    fn start(backend: &'a S3Backend, key: String, storage_class: Option<&str>) -> Result<Self, String> {
+        let headers: Vec<_> = storage_class.map(|class| ("x-amz-storage-class", class.to_string())).into_iter().collect();
+        let response = backend.send("POST", &key, &[("uploads", "")], &headers, &[])?;
+        let id = tag(&response.body, "UploadId")
+            .ok_or_else(|| format!("{} Starting the upload of {} returned no upload id", CROSS, key))?
+            .to_string();
+        debug!("Started multipart upload {} of {}", id, key);
+        Ok(Upload { backend, key, id, part: Vec::with_capacity(PART_SIZE), etags: Vec::new() })
+    }
+
+    fn upload_part(&mut self) -> Result<(), String> {
+        let number = (self.etags.len() + 1).to_string();
+        let response = self.backend.send("PUT", &self.key, &[("partNumber", &number), ("uploadId", &self.id)], &[], &self.part)?;
+        let etag = response.header("etag")
+            .ok_or_else(|| format!("{} Part {} of {} was stored without an ETag", CROSS, number, self.key))?;
+        self.etags.push(etag.to_string());
+        self.part.clear();
+        Ok(())
+    }
+
+    fn finish(mut self) -> Result<(), String> {
+        if !self.part.is_empty() || self.etags.is_empty() {
+            self.upload_part()?;
+        }
+        let parts: String = self.etags.iter().enumerate()
+            .map(|(i, etag)| format!("<Part><PartNumber>{}</PartNumber><ETag>{}</ETag></Part>", i + 1, etag))
+            .collect();
+        let body = format!("<CompleteMultipartUpload>{}</CompleteMultipartUpload>", parts);
+        self.backend.send("POST", &self.key, &[("uploadId", &self.id)], &[], body.as_bytes())?;
+        Ok(())
+    }
+
+}
+
+impl Write for Upload<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let take = buf.len().min(PART_SIZE - self.part.len());
+        self.part.extend_from_slice(&buf[..take]);
+        if self.part.len() == PART_SIZE {
+            self.upload_part().map_err(io::Error::other)?;
+        }
+        Ok(take)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// Numbers archives of directories with the same name made in the same second
+static STORED: AtomicUsize = AtomicUsize::new(0);
+
+impl BackupBackend for S3Backend {
+    fn describe(&self) -> String {
+        format!("s3://{}/{}", self.bucket, self.prefix)
+    }
+
+    fn store(&self, path: &str, opts: &BackupOptions) -> Result<String, String> {
+        if !opts.archive || !opts.archive_format.is_tar() {
+            return Err(format!("{} s3:// destinations take streamed archives: use --archive with --archive-format tar.gz or tar.zst", CROSS));
+        }
+        let name = std::path::Path::new(path).file_name()
+            .ok_or_else(|| format!("{} Invalid directory name", CROSS))?
+            .to_string_lossy()
+            .into_owned();
+        let now = chrono::Local::now();
+        let stem = match &opts.name_template {
+            Some(template) => template.render(std::path::Path::new(path), &now),
+            None => std::path::PathBuf::from(format!("{}_{}", name, now.format("%Y%m%d_%H%M%S"))),
+        };
+        let name = naming::numbered(&stem, STORED.fetch_add(1, Ordering::SeqCst) + 1, Some(opts.archive_format.extension()))
+            .to_string_lossy()
+            .replace('\\', "/");
+        let key = self.key(&name);
+
+        let upload = Upload::start(self, key.clone(), opts.storage_class.as_deref())?;
+        let id = upload.id.clone();
+        let mut limiter = RateLimiter::new(opts.upload_limit);
+        let manifest = archive::stream(path, Throttled::new(upload, &mut limiter), opts)
+            .and_then(|(upload, manifest)| upload.into_inner().finish().map(|_| manifest))
+            .inspect_err(|_| self.abort(&key, &id))?;
+        // Manifests are small and read to find things, so stay in the bucket's default class
+        if opts.archive_manifest {
+            self.send("PUT", &format!("{}.manifest.json", key), &[], &[], &manifest.to_json().map_err(|e| e.to_string())?)?;
+        }
+        Ok(format!("s3://{}/{}", self.bucket, key))
+    }
+
+    // Nothing to read back locally, so runs asked to verify copies are refused
+    fn local_copy(&self, _stored: &str) -> Option<std::path::PathBuf> {
+        None
+    }
+}
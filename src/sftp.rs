@@ -0,0 +1,214 @@
+// Backups to another machine over SSH with `--backup-dir sftp://[user@]host[:port]/path`.
+// Transfers go through the OpenSSH `sftp` client, so keys, the agent and
+// ~/.ssh/config apply as they do for a shell. Uploads are made under a
+// `.part` name and renamed once complete; a dropped connection is retried
+// with `reput`, which continues from what already arrived.
+use std::{env, fs, io::Write, path::{Path, PathBuf}, process::{Command, Stdio}, sync::atomic::{AtomicUsize, Ordering}, thread, time::Duration};
+use log::{debug, warn};
+
+use crate::{archive, backend::{BackupBackend, BackupOptions}, backup::{self, CopyMode}, naming, throttle::RateLimiter, CROSS};
+
+const ATTEMPTS: u32 = 3;
+
+pub struct SftpBackend {
+    // `user@host` or `host`, as ssh takes it
+    host: String,
+    port: Option<u16>,
+    // Absolute, or relative to the remote home when written `/~/path`
+    path: String,
+}
+
+impl SftpBackend {
+    // `[user@]host[:port]/path` from an sftp:// URI
+    pub fn open(location: &str) -> Result<Self, String> {
+        let (authority, path) = location.split_once('/').unwrap_or((location, ""));
+        let (host, port) = match authority.rsplit_once(':') {
+            Some((host, port)) => {
+                let port = port.parse().map_err(|_| format!("{} Invalid port in sftp://{}", CROSS, location))?;
+                (host, Some(port))
+            }
+            None => (authority, None),
+        };
+        if host.is_empty() || host.ends_with('@') {
+            return Err(format!("{} sftp://{} names no host", CROSS, location));
+        }
+        let path = match path.strip_prefix("~/").or_else(|| (path == "~").then_some("")) {
+            Some(home) => home.trim_end_matches('/').to_string(),
+            None => format!("/{}", path.trim_end_matches('/')),
+        };
+        Ok(SftpBackend { host: host.to_string(), port, path })
+    }
+
+    fn remote(&self, name: &str) -> String {
+        match self.path.as_str() {
+            "" => name.to_string(),
+            "/" => format!("/{}", name),
+            path => format!("{}/{}", path, name),
+        }
+    }
+
+    // What `store` returns for what it put at `name`
+    fn uri(&self, name: &str) -> String {
+        format!("{}/{}", self.describe().trim_end_matches('/'), name)
+    }
+
+    // Run sftp commands in one session; any failing command fails the batch
+    fn batch(&self, commands: &str, upload_limit: Option<u64>) -> Result<(), String> {
+        let mut sftp = Command::new("sftp");
+        sftp.args(["-b", "-", "-q", "-o", "BatchMode=yes"]);
+        if let Some(port) = self.port {
+            sftp.arg("-P").arg(port.to_string());
+        }
+        // sftp limits in Kbit/s
+        if let Some(limit) = upload_limit {
+            sftp.arg("-l").arg((limit * 8 / 1000).max(1).to_string());
+        }
+        debug!("sftp {}: {}", self.host, commands.trim_end().replace('\n', "; "));
+        let mut child = sftp.arg(&self.host)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| format!("{} sftp:// destinations need the OpenSSH sftp client on the PATH: {}", CROSS, e))?;
+        child.stdin.take().expect("piped stdin").write_all(commands.as_bytes())
+            .map_err(|e| format!("{} sftp failed: {}", CROSS, e))?;
+        let output = child.wait_with_output().map_err(|e| format!("{} sftp failed: {}", CROSS, e))?;
+        if !output.status.success() {
+            return Err(format!("{} sftp to {} failed: {}", CROSS, self.host, String::from_utf8_lossy(&output.stderr).trim()));
+        }
+        Ok(())
+    }
+
+    // `local` to `remote` by way of `<remote>.part`, retried from where the
+    // last attempt stopped. `flags` go to put and reput.
+    fn upload(&self, local: &Path, remote: &str, flags: &str, upload_limit: Option<u64>) -> Result<(), String> {
+        let part = format!("{}.part", remote);
+        let mut attempt = 1;
+        loop {
+            let put = if attempt == 1 { "put" } else { "reput" };
+            let commands = format!(
+                "{}{} {} {}\nrename {} {}\n",
+                put, flags, quote(&local.to_string_lossy()), quote(&part), quote(&part), quote(remote)
+            );
+            match self.batch(&(self.mkdirs(remote) + &commands), upload_limit) {
+                Ok(()) => return Ok(()),
+                Err(e) if attempt >= ATTEMPTS => return Err(e),
+                Err(e) => warn!("Upload of {} to {} failed, resuming: {}", local.display(), self.host, e),
+            }
+            thread::sleep(Duration::from_millis(500 << attempt));
+            attempt += 1;
+        }
+    }
+
+    // Creates the directories `remote` goes in; `-` lets those already there pass
+    fn mkdirs(&self, remote: &str) -> String {
+        let absolute = remote.starts_with('/');
+        let parent = remote.rsplit_once('/').map_or("", |(parent, _)| parent);
+        let mut dir = String::new();
+        let mut commands = String::new();
+        for part in parent.split('/').filter(|part| !part.is_empty()) {
+            if absolute || !dir.is_empty() {
+                dir.push('/');
+            }
+            dir.push_str(part);
+            commands += &format!("-mkdir {}\n", quote(&dir));
+        }
+        commands
+    }
+}
+
+// A batch-file argument: sftp splits on spaces and takes backslash escapes
+// inside double quotes
+fn quote(arg: &str) -> String {
+    format!("\"{}\"", arg.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+// Where copies and archives wait to be uploaded
+fn staging() -> PathBuf {
+    env::temp_dir().join(format!("dirpurge-sftp-{}", std::process::id()))
+}
+
+// The directories from `staged`'s up to and including `staging` that are
+// empty now; each only goes once the last backup staged in it has
+fn remove_empty(staged: &Path, staging: &Path) {
+    for dir in staged.ancestors().skip(1).take_while(|dir| dir.starts_with(staging)) {
+        if fs::remove_dir(dir).is_err() {
+            break;
+        }
+    }
+}
+
+// Numbers copies of directories with the same name made in the same second
+static STORED: AtomicUsize = AtomicUsize::new(0);
+
+impl BackupBackend for SftpBackend {
+    fn describe(&self) -> String {
+        let port = self.port.map_or(String::new(), |port| format!(":{}", port));
+        match self.path.strip_prefix('/') {
+            Some(path) => format!("sftp://{}{}/{}", self.host, port, path),
+            None => format!("sftp://{}{}/~/{}", self.host, port, self.path),
+        }
+    }
+
+    fn store(&self, path: &str, opts: &BackupOptions) -> Result<String, String> {
+        let name = Path::new(path).file_name()
+            .ok_or_else(|| format!("{} Invalid directory name", CROSS))?
+            .to_string_lossy()
+            .into_owned();
+        if !opts.archive {
+            let now = chrono::Local::now();
+            let stem = match &opts.name_template {
+                Some(template) => template.render(Path::new(path), &now),
+                None => PathBuf::from(format!("{}_{}", name, now.format("%Y%m%d_%H%M%S"))),
+            };
+            let name = naming::numbered(&stem, STORED.fetch_add(1, Ordering::SeqCst) + 1, None).to_string_lossy().replace('\\', "/");
+            let remote = self.remote(&name);
+            // -p keeps modes and times together; owners and links stay behind
+            let flags = if opts.preserve.mode || opts.preserve.times { " -r -p" } else { " -r" };
+            if opts.exclude.is_empty() {
+                self.upload(Path::new(path), &remote, flags, opts.upload_limit)?;
+                return Ok(self.uri(&name));
+            }
+            // `put -r` takes everything, so what is kept is copied aside first
+            let staged = staging().join(&name);
+            let mode = CopyMode { exclude: Some((Path::new(path), &opts.exclude)), preserve: opts.preserve, ..CopyMode::default() };
+            let uploaded = backup::copy_dir_recursive(Path::new(path), &staged, &mut RateLimiter::new(None), mode, &mut |_, _| {})
+                .map_err(|e| format!("{} Failed to stage {} for upload: {}", CROSS, path, e))
+                .and_then(|_| self.upload(&staged, &remote, flags, opts.upload_limit));
+            if let Err(e) = fs::remove_dir_all(&staged) {
+                warn!("Could not remove staged copy {}: {}", staged.display(), e);
+            }
+            remove_empty(&staged, &staging());
+            return uploaded.map(|_| self.uri(&name));
+        }
+
+        // Archives are made locally first: reput needs a file to continue from
+        let staging = staging();
+        let staged = archive::create(path, &staging.to_string_lossy(), &BackupOptions { upload_limit: None, ..opts.clone() })?;
+        let staged = Path::new(&staged);
+        // Where it is under the staging directory is where it goes remotely
+        let remote_name = |file: &Path| file.strip_prefix(&staging).unwrap_or(file).to_string_lossy().replace('\\', "/");
+        let archive_name = remote_name(staged);
+        let manifest = opts.archive_manifest.then(|| PathBuf::from(format!("{}.manifest.json", staged.display())));
+        // Each volume of a split archive under its own name
+        let volumes = archive::files(staged);
+        let uploaded = volumes.iter()
+            .try_for_each(|volume| self.upload(volume, &self.remote(&remote_name(volume)), "", opts.upload_limit))
+            .and_then(|_| match &manifest {
+                Some(manifest) => self.upload(manifest, &self.remote(&format!("{}.manifest.json", archive_name)), "", None),
+                None => Ok(()),
+            });
+        for file in volumes.into_iter().chain(manifest) {
+            if let Err(e) = fs::remove_file(&file) {
+                warn!("Could not remove staged archive {}: {}", file.display(), e);
+            }
+        }
+        remove_empty(staged, &staging);
+        uploaded.map(|_| self.uri(&archive_name))
+    }
+
+    // Nothing to read back locally, so runs asked to verify copies are refused
+    fn local_copy(&self, _stored: &str) -> Option<PathBuf> {
+        None
+    }
+}
@@ -0,0 +1,91 @@
+use serde::Serialize;
+use std::{fmt, sync::Mutex};
+use log::{debug, info};
+
+// Why a candidate was found but left alone
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum SkipReason {
+    // Matched by name but rejected by an age, size or other filter
+    Filtered,
+    // Declined in interactive mode
+    UserDeclined,
+    // Not removable with the current rights
+    RequiresElevation,
+    // Missing or modified since the plan was created
+    Changed,
+    // Another tool still held a lock inside it after the retry
+    Locked,
+    // A running process works in it or has files in it open
+    InUse,
+    // On the protected-paths deny list
+    Protected,
+    // Its repository has uncommitted or unpushed work (--git-safe)
+    ActiveRepo,
+    // Its target's action is report-only
+    ReportOnly,
+    // Resolves through a symlink to outside the search path
+    OutsideRoot,
+    // Kept because the --free goal was already met
+    GoalMet,
+    // Would have taken the run past --max-dirs or --max-total
+    Capped,
+    // Among the --keep-latest newest matches of its parent
+    KeptLatest,
+    // Deletion or backup failed
+    Error,
+}
+
+impl fmt::Display for SkipReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            SkipReason::Filtered => "filtered",
+            SkipReason::UserDeclined => "user-declined",
+            SkipReason::RequiresElevation => "requires-elevation",
+            SkipReason::Changed => "changed",
+            SkipReason::Locked => "locked",
+            SkipReason::InUse => "in-use",
+            SkipReason::Protected => "protected",
+            SkipReason::ActiveRepo => "active-repo",
+            SkipReason::ReportOnly => "report-only",
+            SkipReason::OutsideRoot => "outside-root",
+            SkipReason::GoalMet => "goal-met",
+            SkipReason::Capped => "capped",
+            SkipReason::KeptLatest => "kept-latest",
+            SkipReason::Error => "error",
+        })
+    }
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct SkippedDir {
+    pub path: String,
+    pub reason: SkipReason,
+    pub detail: String,
+}
+
+// Every skip of the run, for the exports; collected globally because skips
+// happen all the way from the scan to the deletion loop
+static SKIPPED: Mutex<Vec<SkippedDir>> = Mutex::new(Vec::new());
+
+pub fn record(path: &str, reason: SkipReason, detail: impl Into<String>) {
+    let detail = detail.into();
+    // Filters reject most of a large scan, keep those out of the default log
+    if reason == SkipReason::Filtered {
+        debug!("Skipped {} ({}): {}", path, reason, detail);
+    } else {
+        info!("Skipped {} ({}): {}", path, reason, detail);
+    }
+    if let Ok(mut skipped) = SKIPPED.lock() {
+        skipped.push(SkippedDir { path: path.to_string(), reason, detail });
+    }
+}
+
+// Whether a deletion or backup failed at some point in the run
+pub fn any_failed() -> bool {
+    SKIPPED.lock().is_ok_and(|skipped| skipped.iter().any(|s| s.reason == SkipReason::Error))
+}
+
+pub fn all() -> Vec<SkippedDir> {
+    SKIPPED.lock().map(|skipped| skipped.clone()).unwrap_or_default()
+}
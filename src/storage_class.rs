@@ -0,0 +1,77 @@
+// The S3 storage class each archive is uploaded in, with --s3-storage-class:
+// purge archives are rarely read again, so they belong in the cheaper tiers.
+// A rule is `CLASS` for every archive, `TARGET=CLASS` for the directories of
+// one target or `>SIZE=CLASS` for directories over a size. A directory's
+// target wins, then the largest size it is over, then the plain class; with
+// none of them, the bucket's default applies.
+use std::path::Path;
+
+use crate::units;
+
+// What S3 takes in x-amz-storage-class; S3-compatible stores take some of them
+const CLASSES: [&str; 9] = [
+    "STANDARD", "REDUCED_REDUNDANCY", "STANDARD_IA", "ONEZONE_IA", "INTELLIGENT_TIERING",
+    "GLACIER", "GLACIER_IR", "DEEP_ARCHIVE", "EXPRESS_ONEZONE",
+];
+
+enum Rule {
+    Every(String),
+    Target(String, String),
+    Over(u64, String),
+}
+
+fn class(value: &str) -> Result<String, String> {
+    let class = value.trim().to_ascii_uppercase();
+    if CLASSES.contains(&class.as_str()) {
+        return Ok(class);
+    }
+    Err(format!("unknown storage class '{}', expected one of {}", value, CLASSES.join(", ")))
+}
+
+fn parse_rule(value: &str) -> Result<Rule, String> {
+    match value.rsplit_once('=') {
+        None => class(value).map(Rule::Every),
+        Some((size, to)) if size.starts_with('>') => Ok(Rule::Over(units::parse_size(&size[1..])?, class(to)?)),
+        Some(("", _)) => Err(format!("invalid storage class rule '{}', expected CLASS, TARGET=CLASS or >SIZE=CLASS", value)),
+        Some((target, to)) => Ok(Rule::Target(target.to_string(), class(to)?)),
+    }
+}
+
+// For the argument parser
+pub fn check_rule(value: &str) -> Result<String, String> {
+    parse_rule(value).map(|_| value.to_string())
+}
+
+#[derive(Default)]
+pub struct StorageClasses(Vec<Rule>);
+
+impl StorageClasses {
+    pub fn new(rules: &[String]) -> Result<Self, String> {
+        rules.iter().map(|rule| parse_rule(rule)).collect::<Result<_, _>>()
+            .map(StorageClasses)
+            .map_err(|e| format!("{} {}", crate::CROSS, e))
+    }
+
+    // The class for the directory at `path` of `size_bytes`. Targets match
+    // as they do for --action: the whole name, else the longest part of it.
+    pub fn for_dir(&self, path: &str, size_bytes: u64) -> Option<String> {
+        let name = Path::new(path).file_name().map(|name| name.to_string_lossy()).unwrap_or_default();
+        let target = self.0.iter()
+            .filter_map(|rule| match rule {
+                Rule::Target(target, class) if name.contains(target.as_str()) => Some((target, class)),
+                _ => None,
+            })
+            .max_by_key(|(target, _)| (**target == name, target.len()));
+        let over = self.0.iter()
+            .filter_map(|rule| match rule {
+                Rule::Over(size, class) if size_bytes > *size => Some((size, class)),
+                _ => None,
+            })
+            .max_by_key(|(size, _)| **size);
+        let every = self.0.iter().rev().find_map(|rule| match rule {
+            Rule::Every(class) => Some(class),
+            _ => None,
+        });
+        target.map(|(_, class)| class).or(over.map(|(_, class)| class)).or(every).cloned()
+    }
+}
@@ -0,0 +1,278 @@
+// Reading and writing POSIX tar streams, the container of .tar.gz archives.
+// Entries are ustar, with a pax header in front where a path, link target or
+// size doesn't fit.
+use std::io::{self, Read, Write};
+
+const BLOCK: usize = 512;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Kind {
+    File,
+    Dir,
+    Symlink(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct Entry {
+    // Relative, `/`-separated; directories without the trailing slash
+    pub name: String,
+    pub kind: Kind,
+    pub size: u64,
+    pub mode: u32,
+    pub mtime: u64,
+    pub uid: u64,
+    pub gid: u64,
+}
+
+pub struct Writer<W: Write> {
+    out: W,
+}
+
+impl<W: Write> Writer<W> {
+    pub fn new(out: W) -> Self {
+        Writer { out }
+    }
+
+    // Add an entry; files are followed by exactly `entry.size` bytes of `data`
+    pub fn append(&mut self, entry: &Entry, data: Option<&mut dyn Read>) -> io::Result<()> {
+        let name = match entry.kind {
+            Kind::Dir => format!("{}/", entry.name),
+            _ => entry.name.clone(),
+        };
+        let link = match &entry.kind {
+            Kind::Symlink(target) => target.as_str(),
+            _ => "",
+        };
+        let size = if entry.kind == Kind::File { entry.size } else { 0 };
+
+        let mut pax = Vec::new();
+        if name.len() > 100 {
+            pax_record(&mut pax, "path", &name);
+        }
+        if link.len() > 100 {
+            pax_record(&mut pax, "linkpath", link);
+        }
+        if size > MAX_OCTAL_SIZE {
+            pax_record(&mut pax, "size", &size.to_string());
+        }
+        if !pax.is_empty() {
+            let mut header = header(&truncated(&format!("PaxHeaders/{}", entry.name), 100), b'x', pax.len() as u64, 0o644, entry.mtime, 0, 0, "");
+            checksum(&mut header);
+            self.out.write_all(&header)?;
+            self.out.write_all(&pax)?;
+            self.pad(pax.len() as u64)?;
+        }
+
+        let typeflag = match entry.kind {
+            Kind::File => b'0',
+            Kind::Dir => b'5',
+            Kind::Symlink(_) => b'2',
+        };
+        let mut header = header(&truncated(&name, 100), typeflag, size.min(MAX_OCTAL_SIZE), entry.mode, entry.mtime, entry.uid, entry.gid, &truncated(link, 100));
+        checksum(&mut header);
+        self.out.write_all(&header)?;
+
+        if let Some(data) = data.filter(|_| size > 0) {
+            let copied = io::copy(&mut data.take(size), &mut self.out)?;
+            if copied != size {
+                return Err(io::Error::new(io::ErrorKind::UnexpectedEof, format!("{} changed size while being archived", entry.name)));
+            }
+            self.pad(size)?;
+        }
+        Ok(())
+    }
+
+    fn pad(&mut self, len: u64) -> io::Result<()> {
+        let rest = (BLOCK - (len % BLOCK as u64) as usize) % BLOCK;
+        self.out.write_all(&[0; BLOCK][..rest])
+    }
+
+    // Write the end-of-archive marker and hand back the stream
+    pub fn finish(mut self) -> io::Result<W> {
+        self.out.write_all(&[0; BLOCK * 2])?;
+        Ok(self.out)
+    }
+}
+
+// 11 octal digits
+const MAX_OCTAL_SIZE: u64 = 0o77777777777;
+
+fn truncated(value: &str, len: usize) -> String {
+    let mut end = value.len().min(len);
+    while !value.is_char_boundary(end) {
+        end -= 1;
+    }
+    value[..end].to_string()
+}
+
+#[allow(clippy::too_many_arguments)]
+fn header(name: &str, typeflag: u8, size: u64, mode: u32, mtime: u64, uid: u64, gid: u64, link: &str) -> [u8; BLOCK] {
+    let mut block = [0u8; BLOCK];
+    block[..name.len()].copy_from_slice(name.as_bytes());
+    octal(&mut block[100..108], (mode & 0o7777) as u64);
+    octal(&mut block[108..116], uid);
+    octal(&mut block[116..124], gid);
+    octal(&mut block[124..136], size);
+    octal(&mut block[136..148], mtime);
+    block[156] = typeflag;
+    block[157..157 + link.len()].copy_from_slice(link.as_bytes());
+    block[257..263].copy_from_slice(b"ustar\0");
+    block[263..265].copy_from_slice(b"00");
+    block
+}
+
+// Zero-padded octal, NUL-terminated; values too large for the field are left 0
+fn octal(field: &mut [u8], value: u64) {
+    let digits = format!("{:0width$o}", value, width = field.len() - 1);
+    if digits.len() < field.len() {
+        field[..digits.len()].copy_from_slice(digits.as_bytes());
+    }
+}
+
+fn checksum(block: &mut [u8; BLOCK]) {
+    block[148..156].fill(b' ');
+    let sum: u32 = block.iter().map(|&b| b as u32).sum();
+    block[148..155].copy_from_slice(format!("{:06o}\0", sum).as_bytes());
+}
+
+// `<length> <key>=<value>\n`, the length counting itself
+fn pax_record(out: &mut Vec<u8>, key: &str, value: &str) {
+    let rest = key.len() + value.len() + 3;
+    let mut len = rest + 1;
+    while len != rest + len.to_string().len() {
+        len = rest + len.to_string().len();
+    }
+    out.extend_from_slice(format!("{} {}={}\n", len, key, value).as_bytes());
+}
+
+fn parse_pax(data: &[u8]) -> Vec<(String, String)> {
+    let mut records = Vec::new();
+    let mut rest = data;
+    while let Some(space) = rest.iter().position(|&b| b == b' ') {
+        let Some(len) = std::str::from_utf8(&rest[..space]).ok().and_then(|len| len.parse::<usize>().ok()) else {
+            break;
+        };
+        if len <= space || len > rest.len() {
+            break;
+        }
+        let record = String::from_utf8_lossy(&rest[space + 1..len]);
+        if let Some((key, value)) = record.trim_end_matches('\n').split_once('=') {
+            records.push((key.to_string(), value.to_string()));
+        }
+        rest = &rest[len..];
+    }
+    records
+}
+
+fn text(field: &[u8]) -> String {
+    let end = field.iter().position(|&b| b == 0).unwrap_or(field.len());
+    String::from_utf8_lossy(&field[..end]).into_owned()
+}
+
+// Octal, or big-endian binary when the top bit is set (GNU tar for large values)
+fn number(field: &[u8]) -> io::Result<u64> {
+    if field.first().is_some_and(|&b| b & 0x80 != 0) {
+        return Ok(field[1..].iter().fold(0u64, |n, &b| (n << 8) | b as u64));
+    }
+    let digits = text(field);
+    let digits = digits.trim_matches(|c: char| c == ' ' || c == '\0');
+    if digits.is_empty() {
+        return Ok(0);
+    }
+    u64::from_str_radix(digits, 8).map_err(|_| invalid(format!("bad number '{}' in tar header", digits)))
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn skip(input: &mut impl Read, len: u64) -> io::Result<()> {
+    let skipped = io::copy(&mut input.take(len), &mut io::sink())?;
+    if skipped != len {
+        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "tar stream ends mid-entry"));
+    }
+    Ok(())
+}
+
+fn padding(len: u64) -> u64 {
+    (BLOCK as u64 - len % BLOCK as u64) % BLOCK as u64
+}
+
+// Call `visit` with every entry of the stream and, for files, a reader over
+// its contents. Hard links, devices and other special entries are passed over.
+pub fn each_entry(mut input: impl Read, mut visit: impl FnMut(&Entry, &mut dyn Read) -> io::Result<()>) -> io::Result<()> {
+    let mut extended: Vec<(String, String)> = Vec::new();
+    let mut long_name: Option<String> = None;
+    loop {
+        let mut block = [0u8; BLOCK];
+        input.read_exact(&mut block)?;
+        if block.iter().all(|&b| b == 0) {
+            return Ok(());
+        }
+        let stored: u32 = block[148..156].iter().map(|&b| b as u32).sum::<u32>();
+        let sum: u32 = block.iter().map(|&b| b as u32).sum::<u32>() - stored + 8 * b' ' as u32;
+        if number(&block[148..156])? != sum as u64 {
+            return Err(invalid("tar header checksum mismatch".to_string()));
+        }
+
+        let mut size = number(&block[124..136])?;
+        let typeflag = block[156];
+        if matches!(typeflag, b'x' | b'L') {
+            let mut data = vec![0; size as usize];
+            input.read_exact(&mut data)?;
+            skip(&mut input, padding(size))?;
+            if typeflag == b'x' {
+                extended = parse_pax(&data);
+            } else {
+                long_name = Some(text(&data));
+            }
+            continue;
+        }
+        if typeflag == b'g' {
+            skip(&mut input, size + padding(size))?;
+            continue;
+        }
+
+        let prefix = text(&block[345..500]);
+        let mut name = long_name.take().unwrap_or_else(|| match prefix.is_empty() {
+            true => text(&block[..100]),
+            false => format!("{}/{}", prefix, text(&block[..100])),
+        });
+        let mut link = text(&block[157..257]);
+        for (key, value) in extended.drain(..) {
+            match key.as_str() {
+                "path" => name = value,
+                "linkpath" => link = value,
+                "size" => size = value.parse().map_err(|_| invalid(format!("bad pax size '{}'", value)))?,
+                _ => {}
+            }
+        }
+
+        let kind = match typeflag {
+            b'0' | b'\0' | b'7' if name.ends_with('/') => Some(Kind::Dir),
+            b'0' | b'\0' | b'7' => Some(Kind::File),
+            b'5' => Some(Kind::Dir),
+            b'2' => Some(Kind::Symlink(link)),
+            _ => None,
+        };
+        let data_len = if matches!(kind, Some(Kind::File)) || kind.is_none() { size } else { 0 };
+        if let Some(kind) = kind {
+            let entry = Entry {
+                name: name.trim_end_matches('/').to_string(),
+                kind,
+                size: data_len,
+                mode: number(&block[100..108])? as u32,
+                mtime: number(&block[136..148])?,
+                uid: number(&block[108..116])?,
+                gid: number(&block[116..124])?,
+            };
+            let mut data = (&mut input).take(data_len);
+            visit(&entry, &mut data)?;
+            // Whatever the visitor left unread
+            io::copy(&mut data, &mut io::sink())?;
+        } else {
+            skip(&mut input, data_len)?;
+        }
+        skip(&mut input, padding(data_len))?;
+    }
+}
@@ -0,0 +1,70 @@
+use std::{collections::HashSet, fs, path::{Path, PathBuf}, time::{Duration, SystemTime}};
+use walkdir::WalkDir;
+use log::debug;
+
+use crate::{move_to_trash, CROSS};
+
+// What thinning a directory removed, or would have
+#[derive(Debug, Default)]
+pub struct Thinned {
+    pub files: usize,
+    pub bytes: u64,
+    pub dirs: usize,
+}
+
+pub fn cutoff(days: i64) -> SystemTime {
+    SystemTime::now()
+        .checked_sub(Duration::from_secs(days.max(0) as u64 * 86_400))
+        .unwrap_or(SystemTime::UNIX_EPOCH)
+}
+
+// Remove the files under `path` last modified before `cutoff`, then the
+// subdirectories that leaves empty. `path` itself and directories that were
+// already empty are kept. Links are removed, never followed.
+pub fn thin_directory(path: &Path, cutoff: SystemTime, use_trash: bool, dry_run: bool) -> Result<Thinned, String> {
+    let mut thinned = Thinned::default();
+    // Directories something was removed from
+    let mut emptied: HashSet<PathBuf> = HashSet::new();
+
+    for entry in WalkDir::new(path).contents_first(true) {
+        let entry = entry.map_err(|e| format!("{} Failed to read {}: {}", CROSS, path.display(), e))?;
+        let entry_path = entry.path();
+
+        if entry.file_type().is_dir() {
+            if entry.depth() == 0 || dry_run || !emptied.contains(entry_path) {
+                continue;
+            }
+            if fs::read_dir(entry_path).is_ok_and(|mut rest| rest.next().is_none()) {
+                fs::remove_dir(entry_path)
+                    .map_err(|e| format!("{} Failed to remove {}: {}", CROSS, entry_path.display(), e))?;
+                thinned.dirs += 1;
+                if let Some(parent) = entry_path.parent() {
+                    emptied.insert(parent.to_path_buf());
+                }
+            }
+            continue;
+        }
+
+        let metadata = entry.metadata()
+            .map_err(|e| format!("{} Failed to read {}: {}", CROSS, entry_path.display(), e))?;
+        if metadata.modified().is_ok_and(|modified| modified >= cutoff) {
+            continue;
+        }
+        if !dry_run {
+            if use_trash {
+                move_to_trash(&entry_path.to_string_lossy())
+                    .map_err(|e| format!("{} Trash failed for {}: {}", CROSS, entry_path.display(), e))?;
+            } else {
+                fs::remove_file(entry_path)
+                    .map_err(|e| format!("{} Failed to remove {}: {}", CROSS, entry_path.display(), e))?;
+            }
+        }
+        debug!("Removed stale file {}", entry_path.display());
+        thinned.files += 1;
+        thinned.bytes += metadata.len();
+        if let Some(parent) = entry_path.parent() {
+            emptied.insert(parent.to_path_buf());
+        }
+    }
+    Ok(thinned)
+}
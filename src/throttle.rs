@@ -0,0 +1,67 @@
+use std::{io::{self, Seek, SeekFrom, Write}, thread, time::{Duration, Instant}};
+
+// Keeps the average throughput of everything written through it under a limit
+pub struct RateLimiter {
+    bytes_per_sec: Option<u64>,
+    start: Instant,
+    transferred: u64,
+}
+
+impl RateLimiter {
+    pub fn new(bytes_per_sec: Option<u64>) -> Self {
+        RateLimiter {
+            bytes_per_sec,
+            start: Instant::now(),
+            transferred: 0,
+        }
+    }
+
+    pub fn is_limited(&self) -> bool {
+        self.bytes_per_sec.is_some()
+    }
+
+    fn consume(&mut self, bytes: usize) {
+        let Some(limit) = self.bytes_per_sec else {
+            return;
+        };
+        self.transferred += bytes as u64;
+        let due = Duration::from_secs_f64(self.transferred as f64 / limit as f64);
+        if let Some(ahead) = due.checked_sub(self.start.elapsed()) {
+            thread::sleep(ahead);
+        }
+    }
+}
+
+pub struct Throttled<'a, W> {
+    inner: W,
+    limiter: &'a mut RateLimiter,
+}
+
+impl<'a, W> Throttled<'a, W> {
+    pub fn new(inner: W, limiter: &'a mut RateLimiter) -> Self {
+        Throttled { inner, limiter }
+    }
+
+    #[cfg_attr(not(feature = "archive"), allow(dead_code))]
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+impl<W: Write> Write for Throttled<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.limiter.consume(written);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<W: Seek> Seek for Throttled<'_, W> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.inner.seek(pos)
+    }
+}
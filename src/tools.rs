@@ -0,0 +1,96 @@
+// Programs some features call out to rather than build in. Runs look for the
+// ones they need on the PATH before touching anything: features that can do
+// without are turned off with one warning naming everything missing, the
+// rest refuse to start, so nothing gives out part way through a purge.
+use std::{collections::HashMap, env, path::PathBuf, sync::Mutex};
+use log::warn;
+
+use crate::report::{self, Kind};
+
+pub struct Tool {
+    pub program: &'static str,
+    pub used_for: &'static str,
+}
+
+pub const TOOLS: &[Tool] = &[
+    Tool { program: "git", used_for: "--git-safe and --repo-inactive-days" },
+    Tool { program: "curl", used_for: "--growth-webhook, --heartbeat-webhook and s3:// backups" },
+    Tool { program: "sftp", used_for: "sftp:// backups" },
+    Tool { program: "gradle", used_for: "stopping Gradle daemons with --stop-daemons" },
+    Tool { program: "sbt", used_for: "stopping sbt servers with --stop-daemons" },
+    #[cfg(target_os = "linux")]
+    Tool { program: "pkexec", used_for: "apply --elevate" },
+    #[cfg(windows)]
+    Tool { program: "powershell", used_for: "apply --elevate" },
+];
+
+// Looked up once each, however many directories ask
+static FOUND: Mutex<Option<HashMap<String, Option<PathBuf>>>> = Mutex::new(None);
+
+// Where `program` would be run from: a path as given, or the first match on the PATH
+pub fn find(program: &str) -> Option<PathBuf> {
+    let mut found = FOUND.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    found.get_or_insert_with(HashMap::new)
+        .entry(program.to_string())
+        .or_insert_with(|| search(program))
+        .clone()
+}
+
+pub fn available(program: &str) -> bool {
+    find(program).is_some()
+}
+
+fn search(program: &str) -> Option<PathBuf> {
+    let given = PathBuf::from(program);
+    if given.components().count() > 1 {
+        return runnable(given);
+    }
+    env::split_paths(&env::var_os("PATH")?).find_map(|dir| runnable(dir.join(program)))
+}
+
+#[cfg(unix)]
+fn runnable(path: PathBuf) -> Option<PathBuf> {
+    use std::os::unix::fs::PermissionsExt;
+    path.metadata().ok().filter(|m| m.is_file() && m.permissions().mode() & 0o111 != 0).map(|_| path)
+}
+
+// Programs are found by any of PATHEXT's extensions
+#[cfg(not(unix))]
+fn runnable(path: PathBuf) -> Option<PathBuf> {
+    if path.is_file() {
+        return Some(path);
+    }
+    let extensions = env::var("PATHEXT").unwrap_or_else(|_| ".COM;.EXE;.BAT;.CMD".to_string());
+    extensions.split(';')
+        .map(|ext| PathBuf::from(format!("{}{}", path.display(), ext)))
+        .find(|candidate| candidate.is_file())
+}
+
+// What a run turned off for want of a tool, reported together once it knows
+#[derive(Default)]
+pub struct Missing(Vec<(String, String)>);
+
+impl Missing {
+    // `program` is needed for `what`; true if it is there
+    pub fn check(&mut self, program: &str, what: &str) -> bool {
+        if available(program) {
+            return true;
+        }
+        match self.0.iter_mut().find(|(missing, _)| missing == program) {
+            Some((_, turned_off)) => *turned_off += &format!(", {}", what),
+            None => self.0.push((program.to_string(), what.to_string())),
+        }
+        false
+    }
+
+    // One warning for everything turned off
+    pub fn report(&self) {
+        if self.0.is_empty() {
+            return;
+        }
+        let programs: Vec<&str> = self.0.iter().map(|(program, _)| program.as_str()).collect();
+        warn!("Not found on the PATH: {}", programs.join(", "));
+        report::message(Kind::Warning, format!("Not found on the PATH, so turned off for this run: {}", programs.join(", ")));
+        report::items(&self.0.iter().map(|(program, what)| format!("{}: {}", program, what)).collect::<Vec<_>>());
+    }
+}
@@ -0,0 +1,89 @@
+// The other half of --use-trash: `dirpurge trash list` shows what runs moved
+// to the OS trash and is still there, `dirpurge trash restore` puts one back.
+// Only what the journal records dirpurge trashing is listed or restored, never
+// the rest of the trash. The trash can be read on Linux and Windows.
+use std::path::{Path, PathBuf};
+
+use crate::{journal::{self, Removed}, locale, report::{self, Kind}, restore, CROSS};
+
+// A directory in the trash: where it came from, when it was trashed and how
+// big it is, in bytes or, for directories, entries
+struct Item {
+    original: PathBuf,
+    trashed_at: String,
+    size: Option<String>,
+}
+
+// What the journal at `journal_path` records moving to the trash
+fn trashed_by_runs(journal_path: &Path) -> Result<Vec<Removed>, String> {
+    let entries = journal::read(journal_path)?;
+    Ok(journal::removals(&entries, None).into_iter().filter(|removed| removed.trashed).collect())
+}
+
+pub fn list(journal_path: &Path) -> Result<(), String> {
+    let trashed = trashed_by_runs(journal_path)?;
+    let mut items: Vec<Item> = items()?.into_iter()
+        .filter(|item| trashed.iter().any(|removed| removed.path == item.original))
+        .collect();
+    if items.is_empty() {
+        report::message(Kind::Info, format!("Nothing {} records moving to the trash is still there", journal_path.display()));
+        return Ok(());
+    }
+    items.sort_by(|a, b| b.trashed_at.cmp(&a.trashed_at));
+    report::message(Kind::Setting, format!("{} directories dirpurge moved to the trash:", locale::count(items.len())));
+    report::items(&items.iter().map(|item| {
+        let size = item.size.as_ref().map_or(String::new(), |size| format!("{}, ", size));
+        format!("{} ({}trashed {})", item.original.display(), size, locale::date(&item.trashed_at))
+    }).collect::<Vec<_>>());
+    report::message(Kind::Hint, "Run `dirpurge trash restore PATH` to put one back");
+    Ok(())
+}
+
+// Puts the latest trashing of `path` back, or at `to`
+pub fn restore(journal_path: &Path, path: &Path, to: Option<&Path>, dry_run: bool) -> Result<(), String> {
+    let entries = journal::read(journal_path)?;
+    let removed = journal::removals(&entries, None).into_iter()
+        .rfind(|removed| removed.trashed && removed.path == path)
+        .ok_or_else(|| format!("{} The journal {} has no record of moving {} to the trash", CROSS, journal_path.display(), path.display()))?;
+    let dest = to.unwrap_or(path);
+    if dest.symlink_metadata().is_ok() {
+        return Err(format!("{} {} already exists, pass --to DIR to restore elsewhere", CROSS, dest.display()));
+    }
+    if dry_run {
+        report::message(Kind::DryRun, format!("[Dry Run] Would restore {} from the trash", dest.display()));
+        return Ok(());
+    }
+    restore::restore_from_trash(path)?;
+    if dest != path {
+        std::fs::rename(path, dest)
+            .map_err(|e| format!("{} Restored to {} but could not move it to {}: {}", CROSS, path.display(), dest.display(), e))?;
+    }
+    restore::record(journal_path, &removed, "trash", dest)?;
+    report::message(Kind::Success, format!("Restored {} from the trash", dest.display()));
+    Ok(())
+}
+
+#[cfg(all(feature = "trash", any(
+    target_os = "windows",
+    all(unix, not(target_os = "macos"), not(target_os = "ios"), not(target_os = "android"))
+)))]
+fn items() -> Result<Vec<Item>, String> {
+    let items = trash::os_limited::list()
+        .map_err(|e| format!("{} Could not list the trash: {}", CROSS, e))?;
+    Ok(items.into_iter().map(|item| Item {
+        original: item.original_path(),
+        trashed_at: chrono::DateTime::from_timestamp(item.time_deleted, 0).map_or_else(String::new, |at| at.to_rfc3339()),
+        size: trash::os_limited::metadata(&item).ok().map(|metadata| match metadata.size {
+            trash::TrashItemSize::Bytes(bytes) => crate::format_mb(bytes, false),
+            trash::TrashItemSize::Entries(entries) => format!("{} entries", locale::count(entries)),
+        }),
+    }).collect())
+}
+
+#[cfg(not(all(feature = "trash", any(
+    target_os = "windows",
+    all(unix, not(target_os = "macos"), not(target_os = "ios"), not(target_os = "android"))
+))))]
+fn items() -> Result<Vec<Item>, String> {
+    Err(format!("{} Reading the trash isn't supported in this build; look for dirpurge's directories in your file manager", CROSS))
+}
@@ -0,0 +1,157 @@
+// The matches as an SVG treemap: one block per target type, sized by the
+// bytes under it, tiled with its directories labelled by project.
+use std::{collections::HashMap, path::Path};
+
+use crate::{format_mb, locale, DirInfo};
+
+const WIDTH: f64 = 1200.0;
+const HEIGHT: f64 = 800.0;
+const HEADER: f64 = 32.0;
+// Above each group's tiles, when the group is tall enough for it
+const GROUP_LABEL: f64 = 18.0;
+const PALETTE: [&str; 8] = ["#4e79a7", "#f28e2b", "#e15759", "#76b7b2", "#59a14f", "#edc948", "#b07aa1", "#9c755f"];
+
+#[derive(Debug, Clone, Copy)]
+struct Rect {
+    x: f64,
+    y: f64,
+    w: f64,
+    h: f64,
+}
+
+// The worst aspect ratio of a row of areas laid along `side`
+fn worst(row: &[f64], side: f64) -> f64 {
+    let sum: f64 = row.iter().sum();
+    let max = row.iter().cloned().fold(f64::MIN, f64::max);
+    let min = row.iter().cloned().fold(f64::MAX, f64::min);
+    let (sum2, side2) = (sum * sum, side * side);
+    (side2 * max / sum2).max(sum2 / (side2 * min))
+}
+
+// Squarified layout (Bruls, Huizing and van Wijk) of sizes sorted largest
+// first: rows along the shorter side, grown while their tiles get squarer
+fn squarify(sizes: &[f64], mut rect: Rect) -> Vec<Rect> {
+    let total: f64 = sizes.iter().sum();
+    let scale = rect.w * rect.h / total;
+    let areas: Vec<f64> = sizes.iter().map(|size| size * scale).collect();
+    let mut tiles = Vec::with_capacity(areas.len());
+    let mut start = 0;
+    while start < areas.len() {
+        let side = rect.w.min(rect.h);
+        let mut end = start + 1;
+        while end < areas.len() && worst(&areas[start..=end], side) <= worst(&areas[start..end], side) {
+            end += 1;
+        }
+        let row = &areas[start..end];
+        let thickness = row.iter().sum::<f64>() / side;
+        let mut offset = 0.0;
+        for area in row {
+            let length = area / thickness;
+            tiles.push(if rect.w >= rect.h {
+                Rect { x: rect.x, y: rect.y + offset, w: thickness, h: length }
+            } else {
+                Rect { x: rect.x + offset, y: rect.y, w: length, h: thickness }
+            });
+            offset += length;
+        }
+        if rect.w >= rect.h {
+            rect.x += thickness;
+            rect.w -= thickness;
+        } else {
+            rect.y += thickness;
+            rect.h -= thickness;
+        }
+        start = end;
+    }
+    tiles
+}
+
+fn escaped(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+fn name(path: &Path) -> String {
+    path.file_name().map_or_else(|| path.to_string_lossy().into_owned(), |name| name.to_string_lossy().into_owned())
+}
+
+// `text` if it fits `width` at 12px, roughly 7px a character
+fn label(text: &str, width: f64) -> Option<&str> {
+    (text.chars().count() as f64 * 7.0 <= width - 8.0).then_some(text)
+}
+
+fn tile(svg: &mut String, rect: Rect, fill: &str, opacity: f64, tooltip: &str, lines: &[&str]) {
+    svg.push_str(&format!(
+        "<g><title>{}</title><rect x=\"{:.1}\" y=\"{:.1}\" width=\"{:.1}\" height=\"{:.1}\" fill=\"{}\" fill-opacity=\"{}\" stroke=\"#fff\"/>",
+        escaped(tooltip), rect.x, rect.y, rect.w, rect.h, fill, opacity
+    ));
+    for (i, line) in lines.iter().enumerate() {
+        let baseline = rect.y + 16.0 + i as f64 * 14.0;
+        if baseline + 4.0 > rect.y + rect.h {
+            break;
+        }
+        if let Some(text) = label(line, rect.w) {
+            svg.push_str(&format!("<text x=\"{:.1}\" y=\"{:.1}\" font-size=\"12\" fill=\"#fff\">{}</text>", rect.x + 4.0, baseline, escaped(text)));
+        }
+    }
+    svg.push_str("</g>\n");
+}
+
+pub fn svg(dirs: &[DirInfo], inventory: &[DirInfo]) -> String {
+    // Target type (the directory's own name) -> its directories, largest first
+    let mut groups: HashMap<String, Vec<&DirInfo>> = HashMap::new();
+    for dir in dirs.iter().chain(inventory) {
+        groups.entry(name(Path::new(&dir.path))).or_default().push(dir);
+    }
+    let mut groups: Vec<(String, Vec<&DirInfo>, u64)> = groups.into_iter()
+        .map(|(target, mut dirs)| {
+            dirs.sort_by_key(|d| std::cmp::Reverse(d.size_bytes));
+            let bytes = dirs.iter().map(|d| d.size_bytes).sum();
+            (target, dirs, bytes)
+        })
+        .collect();
+    groups.sort_by(|a, b| b.2.cmp(&a.2).then_with(|| a.0.cmp(&b.0)));
+
+    let total: u64 = groups.iter().map(|g| g.2).sum();
+    let count = dirs.len() + inventory.len();
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{w}\" height=\"{h}\" viewBox=\"0 0 {w} {h}\" font-family=\"sans-serif\">\n\
+         <rect width=\"{w}\" height=\"{h}\" fill=\"#f4f4f4\"/>\n\
+         <text x=\"8\" y=\"22\" font-size=\"16\" font-weight=\"bold\">dirpurge: {} directories, {}</text>\n",
+        locale::count(count), format_mb(total, dirs.iter().chain(inventory).any(|d| d.estimated)), w = WIDTH, h = HEIGHT
+    );
+    if groups.is_empty() {
+        svg.push_str("</svg>\n");
+        return svg;
+    }
+
+    // Empty directories still get a sliver
+    let weight = |bytes: u64| bytes.max(1) as f64;
+    let canvas = Rect { x: 0.0, y: HEADER, w: WIDTH, h: HEIGHT - HEADER };
+    let sizes: Vec<f64> = groups.iter().map(|g| weight(g.2)).collect();
+    for (i, ((target, dirs, bytes), area)) in groups.iter().zip(squarify(&sizes, canvas)).enumerate() {
+        let fill = PALETTE[i % PALETTE.len()];
+        let labelled = area.h > GROUP_LABEL * 2.0 && label(target, area.w).is_some();
+        let inner = if labelled {
+            let heading = format!("{} · {}", target, format_mb(*bytes, false));
+            svg.push_str(&format!(
+                "<rect x=\"{:.1}\" y=\"{:.1}\" width=\"{:.1}\" height=\"{:.1}\" fill=\"{}\" stroke=\"#fff\"/><text x=\"{:.1}\" y=\"{:.1}\" font-size=\"12\" font-weight=\"bold\" fill=\"#fff\">{}</text>\n",
+                area.x, area.y, area.w, GROUP_LABEL, fill, area.x + 4.0, area.y + 13.0,
+                escaped(label(&heading, area.w).unwrap_or(target))
+            ));
+            Rect { x: area.x, y: area.y + GROUP_LABEL, w: area.w, h: area.h - GROUP_LABEL }
+        } else {
+            area
+        };
+        let sizes: Vec<f64> = dirs.iter().map(|d| weight(d.size_bytes)).collect();
+        for (dir, rect) in dirs.iter().zip(squarify(&sizes, inner)) {
+            let path = Path::new(&dir.path);
+            let project = path.parent().map(name).filter(|p| !p.is_empty() && p != ".").unwrap_or_else(|| dir.path.clone());
+            let size = format_mb(dir.size_bytes, dir.estimated);
+            // Report-only directories are paler: shown, never deleted
+            let opacity = if dir.report_only { 0.5 } else { 0.85 };
+            tile(&mut svg, rect, fill, opacity, &format!("{} — {}", dir.path, size), &[&project, &size]);
+        }
+    }
+    svg.push_str("</svg>\n");
+    svg
+}
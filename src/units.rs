@@ -0,0 +1,72 @@
+use std::time::{Duration, SystemTime};
+
+// Parses sizes such as "500", "64KB", "10MB", "1.5GiB" (binary multiples, like the MB shown in reports)
+pub fn parse_size(value: &str) -> Result<u64, String> {
+    let trimmed = value.trim();
+    let split = trimmed
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(trimmed.len());
+    let (number, unit) = trimmed.split_at(split);
+
+    let number: f64 = number.parse()
+        .map_err(|_| format!("invalid size '{}'", value))?;
+    let multiplier: u64 = match unit.trim().to_ascii_uppercase().as_str() {
+        "" | "B" => 1,
+        "K" | "KB" | "KIB" => 1 << 10,
+        "M" | "MB" | "MIB" => 1 << 20,
+        "G" | "GB" | "GIB" => 1 << 30,
+        "T" | "TB" | "TIB" => 1 << 40,
+        _ => return Err(format!("invalid size unit in '{}', expected B, KB, MB, GB or TB", value)),
+    };
+
+    Ok((number * multiplier as f64) as u64)
+}
+
+// Parses transfer rates such as "10MB/s" or "512KB"
+pub fn parse_rate(value: &str) -> Result<u64, String> {
+    let size = parse_size(value.trim().trim_end_matches("/s"))?;
+    if size == 0 {
+        return Err(format!("invalid rate '{}', must be greater than zero", value));
+    }
+    Ok(size)
+}
+
+// Parses archive volume sizes, which have to hold something
+pub fn parse_volume_size(value: &str) -> Result<u64, String> {
+    let size = parse_size(value)?;
+    if size == 0 {
+        return Err(format!("invalid volume size '{}', must be greater than zero", value));
+    }
+    Ok(size)
+}
+
+// Parses ages such as "90m", "12h", "14d" or "2w"
+pub fn parse_age(value: &str) -> Result<Duration, String> {
+    let trimmed = value.trim();
+    let split = trimmed.find(|c: char| !c.is_ascii_digit()).unwrap_or(trimmed.len());
+    let (number, unit) = trimmed.split_at(split);
+    let invalid = || format!("invalid age '{}', expected a number of minutes, hours, days or weeks, e.g. 14d", value);
+    let number: u64 = number.parse().map_err(|_| invalid())?;
+    let seconds: u64 = match unit.trim().to_ascii_lowercase().as_str() {
+        "m" | "min" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        "w" => 7 * 24 * 60 * 60,
+        _ => return Err(invalid()),
+    };
+    Ok(Duration::from_secs(number.saturating_mul(seconds)))
+}
+
+// Parses cutoff dates given as RFC 3339 or as YYYY-MM-DD (local midnight)
+pub fn parse_date(value: &str) -> Result<SystemTime, String> {
+    let value = value.trim();
+    if let Ok(date) = chrono::DateTime::parse_from_rfc3339(value) {
+        return Ok(date.into());
+    }
+    chrono::NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .ok()
+        .and_then(|date| date.and_hms_opt(0, 0, 0))
+        .and_then(|midnight| midnight.and_local_timezone(chrono::Local).earliest())
+        .map(SystemTime::from)
+        .ok_or_else(|| format!("invalid date '{}', expected YYYY-MM-DD or RFC 3339", value))
+}
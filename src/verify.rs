@@ -0,0 +1,103 @@
+use std::{collections::HashMap, io, path::{Path, PathBuf}, thread, time::{SystemTime, UNIX_EPOCH}};
+use walkdir::WalkDir;
+use log::{debug, info};
+
+use crate::{archive, backend::BackupOptions, hash::{self, HashAlgo}, CROSS};
+
+// Accepts "5%", "5" or "0.5%" and returns the percentage
+pub fn parse_percent(value: &str) -> Result<f64, String> {
+    let number = value.trim().trim_end_matches('%');
+    match number.parse::<f64>() {
+        Ok(pct) if pct > 0.0 && pct <= 100.0 => Ok(pct),
+        _ => Err(format!("invalid percentage '{}', expected a value in (0, 100]", value)),
+    }
+}
+
+// Hash `files` a share per thread, returning the hashes in the order given
+fn hash_all(algo: HashAlgo, files: &[PathBuf]) -> Vec<io::Result<Vec<u8>>> {
+    let share = files.len().div_ceil(hash::workers()).max(1);
+    thread::scope(|scope| {
+        let workers: Vec<_> = files.chunks(share)
+            .map(|part| scope.spawn(move || part.iter().map(|file| hash::hash_file(algo, file)).collect::<Vec<_>>()))
+            .collect();
+        workers.into_iter().flat_map(|worker| worker.join().expect("hashing thread panicked")).collect()
+    })
+}
+
+// Small xorshift PRNG, good enough to pick which files to spot-check
+fn sample<T>(mut items: Vec<T>, count: usize) -> Vec<T> {
+    let mut state = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos() as u64)
+        .unwrap_or(0x9e37_79b9_7f4a_7c15)
+        | 1;
+    let count = count.min(items.len());
+    for i in 0..count {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        let j = i + (state % (items.len() - i) as u64) as usize;
+        items.swap(i, j);
+    }
+    items.truncate(count);
+    items
+}
+
+// Hash a random sample of the source files and compare them with their copies
+// in the plain backup directory or archive `opts` made, passing over what
+// they excluded. Returns how many files were checked.
+pub fn verify_backup_sample(source: &Path, backup: &Path, percent: f64, algo: HashAlgo, opts: &BackupOptions) -> Result<usize, String> {
+    let files: Vec<PathBuf> = WalkDir::new(source)
+        .into_iter()
+        .filter_entry(|e| !opts.exclude.matches(source, e.path()))
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_file())
+        .filter_map(|e| e.path().strip_prefix(source).ok().map(Path::to_path_buf))
+        .collect();
+
+    let count = ((files.len() as f64 * percent / 100.0).ceil() as usize).min(files.len());
+    let chosen = sample(files, count);
+
+    let originals = hash_all(algo, &chosen.iter().map(|relative| source.join(relative)).collect::<Vec<_>>());
+    let copy_failed = |relative: &Path, e: io::Error| format!("{} Failed to hash backup of {}: {}", CROSS, relative.display(), e);
+    // Archives are read through once, copies can be hashed alongside
+    let copies: Vec<Result<Vec<u8>, String>> = if opts.archive {
+        // Entries are stored under the directory's name with --archive-top-dir
+        let prefix = opts.top_dir.then(|| source.file_name().map(|name| format!("{}/", name.to_string_lossy()))).flatten().unwrap_or_default();
+        let wanted: HashMap<String, usize> = chosen.iter()
+            .enumerate()
+            .map(|(index, relative)| (format!("{}{}", prefix, relative.to_string_lossy().replace('\\', "/")), index))
+            .collect();
+        let mut hashed: Vec<Option<Result<Vec<u8>, String>>> = vec![None; chosen.len()];
+        archive::each_file(backup, |name, entry| {
+            if let Some(&index) = wanted.get(name) {
+                hashed[index] = Some(hash::hash_reader(algo, entry).map_err(|e| copy_failed(&chosen[index], e)));
+            }
+            Ok(())
+        })?;
+        hashed.into_iter()
+            .zip(&chosen)
+            .map(|(hash, relative)| hash.unwrap_or_else(|| Err(format!("{} {} missing from archive", CROSS, relative.display()))))
+            .collect()
+    } else {
+        hash_all(algo, &chosen.iter().map(|relative| backup.join(relative)).collect::<Vec<_>>())
+            .into_iter()
+            .zip(&chosen)
+            .map(|(hashed, relative)| hashed.map_err(|e| copy_failed(relative, e)))
+            .collect()
+    };
+
+    for ((relative, expected), actual) in chosen.iter().zip(originals).zip(copies) {
+        let expected = expected
+            .map_err(|e| format!("{} Failed to hash {}: {}", CROSS, relative.display(), e))?;
+        let actual = actual?;
+
+        if expected != actual {
+            return Err(format!("{} Backup verification failed: {} differs from its backup", CROSS, relative.display()));
+        }
+        debug!("Verified backup of {}", relative.display());
+    }
+
+    info!("Verified {} sampled file(s) of {} with {:?}", chosen.len(), source.display(), algo);
+    Ok(chosen.len())
+}
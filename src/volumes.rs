@@ -0,0 +1,196 @@
+// Archives split into fixed-size volumes with --archive-split-size, so they fit
+// on FAT32 drives and under upload limits: `<archive>.001`, `<archive>.002`,
+// ... which `cat` puts back together. Written through one seekable file that
+// rolls over to the next volume, and read back the same way, so zips and tars
+// need to know nothing about it.
+use std::{fs, io::{self, Read, Seek, SeekFrom, Write}, path::{Path, PathBuf}, sync::{atomic::{AtomicU64, Ordering}, Arc}};
+use log::debug;
+
+// What volumes are named while being written; never mistaken for backups
+pub const PART_SUFFIX: &str = ".part";
+
+// Volume `n`, counting from 1, of the archive `archive`
+pub fn volume(archive: &Path, n: usize) -> PathBuf {
+    PathBuf::from(format!("{}.{:03}", archive.display(), n))
+}
+
+// The archive and number of the volume at `path`, if it is one
+pub fn split(path: &Path) -> Option<(PathBuf, usize)> {
+    let name = path.file_name()?.to_str()?;
+    let (archive, number) = name.rsplit_once('.')?;
+    if number.len() < 3 || !number.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    Some((path.with_file_name(archive), number.parse().ok().filter(|&n| n > 0)?))
+}
+
+// Every file of the archive at `path`: the volumes following a first one, or
+// just `path`
+pub fn all(path: &Path) -> Vec<PathBuf> {
+    match split(path) {
+        Some((archive, 1)) => (1..).map(|n| volume(&archive, n)).take_while(|volume| volume.is_file()).collect(),
+        _ => vec![path.to_path_buf()],
+    }
+}
+
+// An archive being written, in volumes of `size` bytes or as one file. The
+// volumes carry PART_SUFFIX until `commit`, and are removed if it never comes.
+pub struct Writer {
+    archive: PathBuf,
+    size: Option<u64>,
+    // Each volume written so far and the name it gets once committed
+    volumes: Vec<(fs::File, PathBuf)>,
+    pos: u64,
+    // Bytes written, up to the furthest point
+    len: Arc<AtomicU64>,
+    committed: bool,
+}
+
+impl Writer {
+    // `first` is the already claimed partial of the first volume
+    pub fn new(archive: &Path, size: Option<u64>, first: fs::File) -> Self {
+        let name = Writer::final_name(archive, size, 1);
+        Writer { archive: archive.to_path_buf(), size, volumes: vec![(first, name)], pos: 0, len: Arc::default(), committed: false }
+    }
+
+    // Where the first volume is written until committed, for claiming
+    pub fn first_part(archive: &Path, size: Option<u64>) -> PathBuf {
+        part(&Writer::final_name(archive, size, 1))
+    }
+
+    // What volume `n` is named once committed: the archive itself, unsplit
+    fn final_name(archive: &Path, size: Option<u64>, n: usize) -> PathBuf {
+        match size {
+            Some(_) => volume(archive, n),
+            None => archive.to_path_buf(),
+        }
+    }
+
+    // Bytes written so far, readable while the writer is in use
+    pub fn written(&self) -> Arc<AtomicU64> {
+        self.len.clone()
+    }
+
+    // The volume `pos` falls in, positioned there and made if need be, and
+    // the room left in it
+    fn at(&mut self, pos: u64) -> io::Result<(&mut fs::File, u64)> {
+        let (index, offset, room) = match self.size {
+            Some(size) => ((pos / size) as usize, pos % size, size - pos % size),
+            None => (0, pos, u64::MAX),
+        };
+        while self.volumes.len() <= index {
+            let name = Writer::final_name(&self.archive, self.size, self.volumes.len() + 1);
+            let file = fs::OpenOptions::new().read(true).write(true).create_new(true).open(part(&name))?;
+            debug!("Starting archive volume {}", name.display());
+            self.volumes.push((file, name));
+        }
+        let file = &mut self.volumes[index].0;
+        file.seek(SeekFrom::Start(offset))?;
+        Ok((file, room))
+    }
+
+    // Sync every volume and give each its final name. Returns the first.
+    pub fn commit(mut self) -> io::Result<PathBuf> {
+        for (file, name) in &self.volumes {
+            file.sync_all()?;
+            fs::rename(part(name), name)?;
+        }
+        self.committed = true;
+        Ok(self.volumes[0].1.clone())
+    }
+}
+
+fn part(name: &Path) -> PathBuf {
+    PathBuf::from(format!("{}{}", name.display(), PART_SUFFIX))
+}
+
+impl Write for Writer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let pos = self.pos;
+        let (file, room) = self.at(pos)?;
+        let written = file.write(&buf[..buf.len().min(room.min(usize::MAX as u64) as usize)])?;
+        self.pos += written as u64;
+        self.len.fetch_max(self.pos, Ordering::Relaxed);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.volumes.iter_mut().try_for_each(|(file, _)| file.flush())
+    }
+}
+
+impl Seek for Writer {
+    fn seek(&mut self, to: SeekFrom) -> io::Result<u64> {
+        self.pos = seek_to(to, self.pos, self.len.load(Ordering::Relaxed))?;
+        Ok(self.pos)
+    }
+}
+
+impl Drop for Writer {
+    fn drop(&mut self) {
+        if !self.committed {
+            for (_, name) in &self.volumes {
+                debug!("Removing partial archive: {}", part(name).display());
+                let _ = fs::remove_file(part(name));
+            }
+        }
+    }
+}
+
+fn seek_to(to: SeekFrom, pos: u64, len: u64) -> io::Result<u64> {
+    let target = match to {
+        SeekFrom::Start(offset) => Some(offset),
+        SeekFrom::Current(delta) => pos.checked_add_signed(delta),
+        SeekFrom::End(delta) => len.checked_add_signed(delta),
+    };
+    target.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "seek before the start of the archive"))
+}
+
+// The volumes of an archive read back as one file
+pub struct Reader {
+    // Each volume and where in the whole it starts
+    volumes: Vec<(fs::File, u64)>,
+    len: u64,
+    pos: u64,
+}
+
+impl Reader {
+    // The archive whose only or first volume is at `path`
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let mut volumes = Vec::new();
+        let mut len = 0;
+        for volume in all(path) {
+            let file = fs::File::open(&volume)?;
+            let size = file.metadata()?.len();
+            volumes.push((file, len));
+            len += size;
+        }
+        if volumes.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::NotFound, format!("{} not found", path.display())));
+        }
+        Ok(Reader { volumes, len, pos: 0 })
+    }
+}
+
+impl Read for Reader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pos >= self.len || buf.is_empty() {
+            return Ok(0);
+        }
+        let index = self.volumes.partition_point(|(_, start)| *start <= self.pos) - 1;
+        let end = self.volumes.get(index + 1).map_or(self.len, |(_, start)| *start);
+        let (file, start) = &mut self.volumes[index];
+        file.seek(SeekFrom::Start(self.pos - *start))?;
+        let wanted = buf.len().min((end - self.pos).min(usize::MAX as u64) as usize);
+        let read = file.read(&mut buf[..wanted])?;
+        self.pos += read as u64;
+        Ok(read)
+    }
+}
+
+impl Seek for Reader {
+    fn seek(&mut self, to: SeekFrom) -> io::Result<u64> {
+        self.pos = seek_to(to, self.pos, self.len)?;
+        Ok(self.pos)
+    }
+}
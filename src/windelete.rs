@@ -0,0 +1,83 @@
+// Removing files on Windows through handles. With POSIX semantics (Windows 10
+// 1607 and later, on NTFS) a file's name goes the moment it is marked, so its
+// directory can follow straight away even while a scanner still holds the
+// file open, and read-only files need no attribute change first. Batches are
+// spread over a few threads, as the per-file cost is mostly spent waiting on
+// the filesystem filters. Where POSIX semantics are refused, files are
+// marked delete-on-close instead.
+use std::{fs, io, os::windows::{fs::OpenOptionsExt, io::AsRawHandle}, path::Path, sync::atomic::{AtomicBool, Ordering}, thread};
+use windows_sys::Win32::Storage::FileSystem::{
+    SetFileInformationByHandle, DELETE, FILE_FLAG_BACKUP_SEMANTICS, FILE_FLAG_OPEN_REPARSE_POINT, FILE_SHARE_DELETE, FILE_SHARE_READ, FILE_SHARE_WRITE,
+};
+
+// FILE_INFO_BY_HANDLE_CLASS values
+const FILE_DISPOSITION_INFO: i32 = 4;
+const FILE_DISPOSITION_INFO_EX: i32 = 21;
+const FLAG_DELETE: u32 = 0x1;
+const FLAG_POSIX_SEMANTICS: u32 = 0x2;
+const FLAG_IGNORE_READONLY_ATTRIBUTE: u32 = 0x10;
+// What older Windows and non-NTFS volumes answer FILE_DISPOSITION_INFO_EX with
+const ERROR_NOT_SUPPORTED: i32 = 50;
+const ERROR_INVALID_PARAMETER: i32 = 87;
+
+// Fewer than this go one at a time on the calling thread
+const PARALLEL_ABOVE: usize = 64;
+const MAX_THREADS: usize = 8;
+
+#[repr(C)]
+struct Disposition {
+    delete_file: u8,
+}
+
+#[repr(C)]
+struct DispositionEx {
+    flags: u32,
+}
+
+// Set once a volume has refused POSIX semantics, so they aren't asked for again
+static NO_POSIX_DELETE: AtomicBool = AtomicBool::new(false);
+
+fn set_info<T>(file: &fs::File, class: i32, info: &T) -> io::Result<()> {
+    let set = unsafe { SetFileInformationByHandle(file.as_raw_handle(), class, (info as *const T).cast(), size_of::<T>() as u32) };
+    if set == 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+// Remove the file, empty directory or link at `path`
+pub fn delete(path: &Path) -> io::Result<()> {
+    let file = fs::OpenOptions::new()
+        .access_mode(DELETE)
+        .share_mode(FILE_SHARE_READ | FILE_SHARE_WRITE | FILE_SHARE_DELETE)
+        // Links and junctions themselves, and directories as well as files
+        .custom_flags(FILE_FLAG_OPEN_REPARSE_POINT | FILE_FLAG_BACKUP_SEMANTICS)
+        .open(path)?;
+    if !NO_POSIX_DELETE.load(Ordering::Relaxed) {
+        let info = DispositionEx { flags: FLAG_DELETE | FLAG_POSIX_SEMANTICS | FLAG_IGNORE_READONLY_ATTRIBUTE };
+        match set_info(&file, FILE_DISPOSITION_INFO_EX, &info) {
+            Ok(()) => return Ok(()),
+            Err(e) if matches!(e.raw_os_error(), Some(ERROR_INVALID_PARAMETER | ERROR_NOT_SUPPORTED)) => {
+                NO_POSIX_DELETE.store(true, Ordering::Relaxed);
+            }
+            Err(e) => return Err(e),
+        }
+    }
+    // Gone when `file` is closed, on the way out
+    set_info(&file, FILE_DISPOSITION_INFO, &Disposition { delete_file: 1 })
+}
+
+// `delete` each of `files`, a share per thread; results in the order given
+pub fn delete_all(files: &[&Path]) -> Vec<io::Result<()>> {
+    let threads = thread::available_parallelism().map_or(1, |n| n.get()).min(MAX_THREADS);
+    if files.len() < PARALLEL_ABOVE || threads == 1 {
+        return files.iter().map(|file| delete(file)).collect();
+    }
+    let share = files.len().div_ceil(threads);
+    thread::scope(|scope| {
+        let workers: Vec<_> = files.chunks(share)
+            .map(|part| scope.spawn(move || part.iter().map(|file| delete(file)).collect::<Vec<_>>()))
+            .collect();
+        workers.into_iter().flat_map(|worker| worker.join().expect("deleting thread panicked")).collect()
+    })
+}
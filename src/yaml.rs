@@ -0,0 +1,84 @@
+// The run summary as YAML, for --yaml. Written from the same JSON value as
+// --json, in block style: strings are left plain where YAML reads them back
+// as the same string and double-quoted, with JSON's escapes, where not.
+use serde_json::Value;
+
+pub fn to_string(value: &Value) -> String {
+    match value {
+        Value::Object(map) if !map.is_empty() => block(value, 0),
+        Value::Array(items) if !items.is_empty() => block(value, 0),
+        scalar => format!("{}\n", inline(scalar)),
+    }
+}
+
+// A non-empty object or array, one line per entry at `indent` spaces
+fn block(value: &Value, indent: usize) -> String {
+    let pad = " ".repeat(indent);
+    let mut out = String::new();
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map {
+                out += &format!("{}{}:", pad, string(key));
+                if nested(value) {
+                    out += "\n";
+                    out += &block(value, indent + 2);
+                } else {
+                    out += &format!(" {}\n", inline(value));
+                }
+            }
+        }
+        Value::Array(items) => {
+            for item in items {
+                if nested(item) {
+                    // The item's first line goes on the dash's
+                    let inner = block(item, indent + 2);
+                    out += &format!("{}- {}", pad, &inner[indent + 2..]);
+                } else {
+                    out += &format!("{}- {}\n", pad, inline(item));
+                }
+            }
+        }
+        _ => unreachable!("only containers are written as blocks"),
+    }
+    out
+}
+
+fn nested(value: &Value) -> bool {
+    match value {
+        Value::Object(map) => !map.is_empty(),
+        Value::Array(items) => !items.is_empty(),
+        _ => false,
+    }
+}
+
+fn inline(value: &Value) -> String {
+    match value {
+        Value::Null => "null".to_string(),
+        Value::Bool(b) => b.to_string(),
+        Value::Number(n) => n.to_string(),
+        Value::String(s) => string(s),
+        Value::Object(_) => "{}".to_string(),
+        Value::Array(_) => "[]".to_string(),
+    }
+}
+
+fn string(s: &str) -> String {
+    if plain(s) { s.to_string() } else { Value::String(s.to_string()).to_string() }
+}
+
+// Whether `s` reads back as itself unquoted: nothing YAML takes for another
+// type, an indicator or a comment
+fn plain(s: &str) -> bool {
+    let Some(first) = s.chars().next() else {
+        return false;
+    };
+    let special = matches!(s.to_ascii_lowercase().as_str(), "null" | "~" | "true" | "false" | "yes" | "no" | "on" | "off" | "y" | "n" | ".inf" | "-.inf" | ".nan");
+    let numeric = s.parse::<f64>().is_ok() || s.starts_with("0x") || s.starts_with("0o");
+    !special && !numeric
+        && !"-?:,[]{}#&*!|>'\"%@`".contains(first)
+        // Nor anything some parsers would read as a date or number
+        && !first.is_ascii_digit()
+        && !first.is_whitespace() && !s.ends_with(char::is_whitespace)
+        && !s.contains(": ") && !s.contains(" #") && !s.ends_with(':')
+        && !s.chars().any(|c| c.is_control())
+}
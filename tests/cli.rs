@@ -0,0 +1,1638 @@
+// End-to-end runs of the dirpurge binary against temporary trees, checking
+// what it reports and what is left on disk afterwards.
+
+mod common;
+
+use common::{Fixture, assert_golden, paths};
+
+#[test]
+fn scan_reports_matches_and_leaves_them_alone() {
+    let fx = Fixture::new("scan");
+    fx.file("app/node_modules/pkg/index.js", 100)
+        .file("api/target/debug/app", 200)
+        .file("app/src/main.js", 10);
+
+    let run = fx.run(&[".", "--output", "json-events"]);
+    run.assert_success();
+    let found = run.event("found");
+    assert_eq!(paths(&found, "directories"), ["./api/target", "./app/node_modules"]);
+    assert_eq!(found["total_size_bytes"], 300);
+    assert!(fx.exists("app/node_modules/pkg/index.js"));
+    assert!(fx.exists("api/target/debug/app"));
+}
+
+#[test]
+fn delete_removes_matches_and_nothing_else() {
+    let fx = Fixture::new("delete");
+    fx.file("app/node_modules/pkg/index.js", 100)
+        .file("app/src/main.js", 10)
+        .file("app/package.json", 10)
+        .file("py/.venv/lib/site.py", 50);
+
+    let (_, summary) = fx.run_json(&[".", "--delete", "--yes", "--output", "plain"]);
+    assert_eq!(paths(&summary, "directories"), ["./app/node_modules", "./py/.venv"]);
+    assert!(!fx.exists("app/node_modules"));
+    assert!(!fx.exists("py/.venv"));
+    assert!(fx.exists("app/src/main.js"));
+    assert!(fx.exists("app/package.json"));
+    assert!(fx.exists("py"));
+}
+
+#[test]
+fn dry_run_changes_nothing() {
+    let fx = Fixture::new("dry-run");
+    fx.file("app/node_modules/pkg/index.js", 100);
+
+    let (_, summary) = fx.run_json(&[".", "--delete", "--dry-run", "--yes", "--output", "plain"]);
+    assert_eq!(summary["count"], 1);
+    assert!(fx.exists("app/node_modules/pkg/index.js"));
+}
+
+#[test]
+fn yaml_and_json_lines_exports_carry_every_directory() {
+    let fx = Fixture::new("yaml-jsonl");
+    fx.file("app/node_modules/pkg/index.js", 100).file("odd: name/target/out", 10);
+    let (yaml, jsonl) = (fx.path("summary.yaml"), fx.path("summary.jsonl"));
+
+    fx.run(&[".", "--delete", "--dry-run", "--yes", "--yaml", yaml.to_str().unwrap(), "--jsonl", jsonl.to_str().unwrap(), "--output", "plain"]).assert_success();
+    let yaml = std::fs::read_to_string(yaml).unwrap();
+    assert!(yaml.contains("count: 2\n"), "{}", yaml);
+    assert!(yaml.contains("  - age_days: 0\n") && yaml.contains("    path: ./app/node_modules\n"), "{}", yaml);
+    // Quoted where a plain string would read as something else
+    assert!(yaml.contains("    path: \"./odd: name/target\"\n"), "{}", yaml);
+
+    let lines: Vec<serde_json::Value> = std::fs::read_to_string(jsonl).unwrap().lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+    assert_eq!(lines.len(), 2);
+    assert_eq!(lines[0]["path"], "./app/node_modules");
+    assert_eq!(lines[0]["size_bytes"], 100);
+    assert!(lines.iter().all(|line| line["status"] == "processed" && line["timestamp"].is_string()));
+}
+
+#[test]
+fn check_exits_with_4_only_when_something_matches() {
+    let fx = Fixture::new("check");
+    fx.file("app/node_modules/pkg/index.js", 100).file("api/target/debug/app", 200);
+
+    let run = fx.run(&[".", "--check", "--output", "plain"]);
+    assert_eq!(run.0.status.code(), Some(4));
+    assert_eq!(run.stdout().trim(), "2 directories to purge, 0.00 MB");
+    assert!(fx.exists("app/node_modules/pkg/index.js"));
+
+    let run = fx.run(&[".", "--check", "--target", "build", "--output", "plain"]);
+    assert_eq!(run.0.status.code(), Some(0));
+    assert_eq!(run.stdout().trim(), "0 directories to purge, 0.00 MB");
+}
+
+#[test]
+fn nested_matches_are_pruned_to_the_outermost() {
+    let fx = Fixture::new("nested");
+    fx.file("app/node_modules/a/node_modules/b/node_modules/c/index.js", 10)
+        .file("app/node_modules/a/index.js", 10);
+
+    let run = fx.run(&[".", "--output", "json-events"]);
+    run.assert_success();
+    assert_eq!(paths(&run.event("found"), "directories"), ["./app/node_modules"]);
+
+    let (_, summary) = fx.run_json(&[".", "--delete", "--yes", "--output", "plain"]);
+    assert_eq!(summary["count"], 1);
+    assert!(!fx.exists("app/node_modules"));
+    assert!(fx.exists("app"));
+}
+
+#[test]
+fn filters_and_excludes_shape_the_summary() {
+    let fx = Fixture::new("golden");
+    fx.file("app/node_modules/pkg/index.js", 3000)
+        .file("lib/target/release/lib.rlib", 2000)
+        .file("py/.venv/bin/python", 1000)
+        .file("tiny/build/out.o", 10)
+        .file("vendor/node_modules/dep/index.js", 5000)
+        .file("keep/src/main.rs", 10);
+
+    let (_, summary) = fx.run_json(&[
+        ".", "--exclude", "vendor", "--min-size", "0.0005", "--delete", "--dry-run", "--yes", "--output", "plain",
+    ]);
+    assert_golden("filters_and_excludes", summary);
+    assert!(fx.exists("vendor/node_modules/dep/index.js"));
+}
+
+#[test]
+fn huge_directories_are_counted_and_removed() {
+    let fx = Fixture::new("huge");
+    for project in 0..20 {
+        fx.files(format!("p{}/node_modules/pkg", project), 500);
+    }
+    fx.files("p0/src", 5);
+
+    let (_, summary) = fx.run_json(&[".", "--delete", "--yes", "--output", "plain"]);
+    assert_eq!(summary["count"], 20);
+    for dir in summary["directories"].as_array().unwrap() {
+        // The directory itself, its package and the package's files
+        assert_eq!(dir["item_count"], 502, "{}", dir);
+        assert_eq!(dir["size_bytes"], 500, "{}", dir);
+    }
+    for project in 0..20 {
+        assert!(!fx.exists(format!("p{}/node_modules", project)));
+    }
+    assert!(fx.exists("p0/src/4"));
+}
+
+#[test]
+fn parallel_deletion_backs_up_every_directory_separately() {
+    let fx = Fixture::new("jobs");
+    for project in 0..12 {
+        fx.file(format!("p{}/node_modules/pkg/index.js", project), 10 + project);
+    }
+    let backups = fx.state_dir().join("backups");
+    let backups_arg = backups.to_str().unwrap();
+
+    let (_, summary) = fx.run_json(&[".", "--jobs", "4", "--backup", "--backup-dir", backups_arg, "--delete", "--yes", "--output", "plain"]);
+    assert_eq!(summary["count"], 12);
+    for project in 0..12 {
+        assert!(!fx.exists(format!("p{}/node_modules", project)));
+    }
+    // Same-named directories backed up at the same moment each get their own copy
+    let mut sizes: Vec<usize> = std::fs::read_dir(&backups)
+        .unwrap()
+        .map(|copy| std::fs::read(copy.unwrap().path().join("pkg/index.js")).unwrap().len())
+        .collect();
+    sizes.sort();
+    assert_eq!(sizes, (10..22).collect::<Vec<_>>());
+}
+
+#[test]
+fn readahead_backups_copy_every_file() {
+    let fx = Fixture::new("readahead");
+    for file in 0..40 {
+        fx.file(format!("app/node_modules/pkg/f{}.js", file), 100 + file);
+    }
+    let backups = fx.state_dir().join("backups");
+    let backups_arg = backups.to_str().unwrap();
+
+    fx.run_json(&[".", "--readahead", "--upload-limit", "100MB/s", "--backup", "--backup-dir", backups_arg, "--delete", "--yes", "--output", "plain"]);
+    assert!(!fx.exists("app/node_modules"));
+    let copy = std::fs::read_dir(&backups).unwrap().next().unwrap().unwrap().path();
+    for file in 0..40 {
+        assert_eq!(std::fs::read(copy.join(format!("pkg/f{}.js", file))).unwrap().len(), 100 + file);
+    }
+}
+
+#[test]
+fn long_runs_report_a_heartbeat() {
+    let fx = Fixture::new("heartbeat");
+    fx.file("app/node_modules/pkg/big.bin", 300 * 1024).file("lib/node_modules/pkg/index.js", 10);
+    let backups = fx.state_dir().join("backups");
+
+    // Throttled to about three seconds of copying
+    let run = fx.run(&[
+        ".", "--backup", "--backup-dir", backups.to_str().unwrap(), "--upload-limit", "100KB/s", "--heartbeat", "1",
+        "--delete", "--yes", "--output", "json-events",
+    ]);
+    run.assert_success();
+    assert!(!fx.exists("app/node_modules"));
+    let beats: Vec<_> = run.events().into_iter()
+        .filter(|event| event["event"] == "message" && event["text"].as_str().unwrap().starts_with("Still running after"))
+        .collect();
+    assert!(!beats.is_empty(), "{}", run.stdout());
+    assert!(beats[0]["text"].as_str().unwrap().contains("of 2 directories"), "{}", beats[0]);
+
+    let run = fx.run(&[".", "--heartbeat", "0"]);
+    assert!(!run.0.status.success());
+}
+
+#[test]
+fn missing_tools_turn_features_off_with_one_warning() {
+    let fx = Fixture::new("missing-tools");
+    fx.file("app/node_modules/index.js", 10).dir("empty-path");
+    let path = fx.path("empty-path");
+    let path = path.to_str().unwrap();
+
+    let run = fx.run_with_env(&["app", "--heartbeat", "60", "--heartbeat-webhook", "http://127.0.0.1:9/beat", "--git-safe", "--delete", "--yes", "--output", "plain"], &[("PATH", path)]);
+    run.assert_success();
+    let stdout = run.stdout();
+    let warnings: Vec<&str> = stdout.lines().filter(|line| line.contains("Not found on the PATH")).collect();
+    assert_eq!(warnings.len(), 1, "{}", stdout);
+    assert!(warnings[0].contains("curl, git"), "{}", warnings[0]);
+    assert!(stdout.contains("curl: --heartbeat-webhook"), "{}", stdout);
+    // Outside a repository, --git-safe has nothing to keep
+    assert!(!fx.exists("app/node_modules"));
+
+    // What can't go without its tool stops the run before anything goes
+    fx.file("app/node_modules/index.js", 10);
+    let run = fx.run_with_env(&["app", "--backup", "--backup-dir", "sftp://host/backups", "--delete", "--yes"], &[("PATH", path)]);
+    assert_ne!(run.0.status.code(), Some(0));
+    assert!(run.stderr().contains("sftp:// destinations need the OpenSSH sftp client"), "{}", run.stderr());
+    assert!(fx.exists("app/node_modules"));
+
+    let doctor = fx.run_with_env(&["doctor"], &[("PATH", path)]);
+    doctor.assert_success();
+    assert!(doctor.stdout().contains("git: not found"), "{}", doctor.stdout());
+}
+
+#[test]
+fn doctor_reports_readiness_and_fails_on_problems() {
+    let fx = Fixture::new("doctor");
+    fx.dir("app");
+    let backups = fx.state_dir().join("backups");
+
+    let run = fx.run(&["doctor", "app", "--backup-dir", backups.to_str().unwrap()]);
+    run.assert_success();
+    let stdout = run.stdout();
+    for section in ["Trash", "Terminal", "Backup destinations", "Filesystem of app", "Optional tools", "Ready"] {
+        assert!(stdout.contains(section), "{}: {}", section, stdout);
+    }
+    assert!(stdout.contains("writable") && stdout.contains("Reflinks:"), "{}", stdout);
+    // The probe leaves nothing behind
+    assert!(std::fs::read_dir(fx.root()).unwrap().all(|e| !e.unwrap().file_name().to_string_lossy().starts_with(".dirpurge-doctor")));
+
+    std::fs::write(fx.path("bad.json"), r#"{"archive_split_size": "lots"}"#).unwrap();
+    let run = fx.run(&["doctor", "app", "--config", fx.path("bad.json").to_str().unwrap()]);
+    assert_ne!(run.0.status.code(), Some(0));
+    assert!(run.stderr().contains("lots"), "{}", run.stderr());
+    assert!(run.stderr().contains("1 problem(s) found"), "{}", run.stderr());
+}
+
+#[cfg(unix)]
+#[test]
+fn incremental_backups_link_unchanged_files() {
+    use std::os::unix::fs::MetadataExt;
+    let fx = Fixture::new("incremental");
+    let backups = fx.state_dir().join("backups");
+    let backups_arg = backups.to_str().unwrap();
+    let installed = std::time::SystemTime::now() - std::time::Duration::from_secs(3600);
+    let install = |changed: usize| {
+        fx.file("app/node_modules/pkg/same.js", 300).file("app/node_modules/pkg/changed.js", changed);
+        for file in ["same.js", "changed.js"] {
+            std::fs::File::open(fx.path("app/node_modules/pkg").join(file)).unwrap().set_modified(installed).unwrap();
+        }
+    };
+
+    install(100);
+    fx.run_json(&[".", "--incremental", "--backup", "--backup-dir", backups_arg, "--delete", "--yes", "--output", "plain"]);
+    install(200);
+    fx.run_json(&[".", "--incremental", "--backup", "--backup-dir", backups_arg, "--delete", "--yes", "--output", "plain"]);
+
+    let first = backups.join("node_modules");
+    let second = std::fs::read_dir(&backups).unwrap()
+        .map(|copy| copy.unwrap().path())
+        .find(|copy| *copy != first)
+        .unwrap();
+    let inode = |copy: &std::path::Path, file: &str| std::fs::metadata(copy.join("pkg").join(file)).unwrap().ino();
+    assert_eq!(inode(&first, "same.js"), inode(&second, "same.js"));
+    assert_ne!(inode(&first, "changed.js"), inode(&second, "changed.js"));
+    assert_eq!(std::fs::read(second.join("pkg/changed.js")).unwrap().len(), 200);
+}
+
+#[test]
+fn reflink_backups_clone_or_keep_the_directory() {
+    let fx = Fixture::new("reflink");
+    fx.file("auto/node_modules/pkg/index.js", 500).file("strict/node_modules/pkg/index.js", 700);
+    let backups = fx.state_dir().join("backups");
+    let backups_arg = backups.to_str().unwrap();
+
+    // Cloned where the filesystem can, copied where it can't
+    fx.run(&["auto", "--backup-strategy", "auto", "--backup", "--backup-dir", backups_arg, "--delete", "--yes", "--output", "plain"]).assert_success();
+    assert!(!fx.exists("auto/node_modules"));
+    assert_eq!(std::fs::read(backups.join("node_modules/pkg/index.js")).unwrap().len(), 500);
+
+    // Never copied: either cloned, or the backup fails and nothing is deleted
+    let run = fx.run(&["strict", "--backup-strategy", "reflink", "--backup", "--backup-dir", backups_arg, "--delete", "--yes", "--output", "plain"]);
+    if fx.exists("strict/node_modules") {
+        assert!(run.stderr().contains("by reflink"), "{}", run.stderr());
+    } else {
+        run.assert_success();
+    }
+}
+
+#[test]
+fn respect_ignore_reads_gits_own_exclude_files() {
+    let fx = Fixture::new("excludes");
+    let init = std::process::Command::new("git").args(["init", "-q"]).arg(fx.path("repo")).status();
+    if !init.is_ok_and(|status| status.success()) {
+        eprintln!("git is not installed; skipping");
+        return;
+    }
+    fx.file("repo/app/node_modules/index.js", 10)
+        .file("repo/web/node_modules/index.js", 10)
+        .file("repo/api/target/out", 10)
+        .file("repo/lib/target/out", 10);
+    std::fs::write(fx.path("ignore"), "target/\n").unwrap();
+    std::fs::write(fx.path("gitconfig"), format!("[core]\n\texcludesFile = {}\n", fx.path("ignore").display())).unwrap();
+    std::fs::write(fx.path("repo/.git/info/exclude"), "web/\n").unwrap();
+    std::fs::write(fx.path("repo/.gitignore"), "!lib/target/\n").unwrap();
+    let config = fx.path("gitconfig");
+    let vars = [("GIT_CONFIG_GLOBAL", config.to_str().unwrap()), ("GIT_CONFIG_NOSYSTEM", "1")];
+
+    // The repository's .gitignore still overrides both
+    let run = fx.run_with_env(&["repo", "--respect-ignore", "--output", "json-events"], &vars);
+    assert_eq!(paths(&run.event("found"), "directories"), ["repo/app/node_modules", "repo/lib/target"]);
+}
+
+#[test]
+fn scans_pass_over_backups_and_trash_under_the_root() {
+    let fx = Fixture::new("reserved");
+    fx.file("app/node_modules/pkg/index.js", 100)
+        .file("web/node_modules/pkg/index.js", 100)
+        .file(".Trash-1000/files/old/node_modules/pkg/index.js", 100);
+
+    // Into the default ./backups, inside the scanned tree
+    fx.run(&["app", "--backup", "--delete", "--yes", "--output", "plain"]).assert_success();
+    assert!(fx.exists("backups/node_modules/pkg/index.js"));
+
+    let run = fx.run(&[".", "-t", "node_modules", "--output", "json-events"]);
+    assert_eq!(paths(&run.event("found"), "directories"), ["./web/node_modules"]);
+    let notes = fx.run(&[".", "-t", "node_modules", "--verbose", "--output", "plain"]).stdout();
+    assert!(notes.contains("Not scanning the backup directory: ./backups"), "{}", notes);
+    assert!(notes.contains("Not scanning the trash: ./.Trash-1000"), "{}", notes);
+
+    // However deep the matches have to be
+    let run = fx.run(&[".", "-t", "node_modules", "--min-depth", "2", "--output", "json-events"]);
+    assert_eq!(paths(&run.event("found"), "directories"), ["./web/node_modules"]);
+
+    // Scanned when asked for by name
+    let run = fx.run(&["./backups", "-t", "node_modules", "--output", "json-events"]);
+    assert_eq!(paths(&run.event("found"), "directories"), ["./backups/node_modules"]);
+}
+
+#[test]
+fn stats_actions_compare_recorded_removals() {
+    let fx = Fixture::new("stats-actions");
+    fx.files("deleted/node_modules/pkg", 20).files("copied/node_modules/pkg", 20);
+    std::fs::create_dir_all(fx.state_dir()).unwrap();
+    let history = fx.state_dir().join("history.json");
+    let history = history.to_str().unwrap();
+    let backups = fx.state_dir().join("backups");
+
+    assert!(!fx.run(&["stats", "actions", "--history", history]).0.status.success());
+    fx.run(&["deleted", "--history", history, "--delete", "--yes", "--output", "plain"]).assert_success();
+    fx.run(&["copied", "--history", history, "--backup", "--backup-dir", backups.to_str().unwrap(), "--delete", "--yes", "--output", "plain"]).assert_success();
+    // Dry runs remove nothing, so they time nothing
+    fx.files("dry/node_modules/pkg", 5);
+    fx.run(&["dry", "--history", history, "--delete", "--dry-run", "--yes", "--output", "plain"]).assert_success();
+
+    let run = fx.run(&["stats", "actions", "--history", history]);
+    run.assert_success();
+    let stats = run.stdout();
+    assert!(stats.contains("from 2 removal(s)"), "{}", stats);
+    assert!(stats.lines().any(|line| line.contains("delete: ") && line.contains("1 dir(s)")), "{}", stats);
+    assert!(stats.lines().any(|line| line.contains("backup+delete: ")), "{}", stats);
+}
+
+#[test]
+fn backup_exclude_leaves_matches_out_of_copies_and_archives() {
+    let fx = Fixture::new("backup-exclude");
+    for project in ["copied", "archived"] {
+        fx.file(format!("{}/target/keep.rs", project), 100)
+            .file(format!("{}/target/obj/main.o", project), 100)
+            .file(format!("{}/target/.git/HEAD", project), 10);
+    }
+    let backups = fx.state_dir().join("backups");
+    let backups_arg = backups.to_str().unwrap();
+    let exclude = ["--backup-exclude", "*.o", "--backup-exclude", ".git/"];
+
+    // Verification only samples what was kept
+    let (run, _) = fx.run_json(&[&["copied", "--backup", "--backup-dir", backups_arg, "--verify-sample", "100%"][..], &exclude, &["--delete", "--yes", "--output", "plain"]].concat());
+    run.assert_success();
+    let copy = backups.join("target");
+    assert!(copy.join("keep.rs").exists());
+    assert!(copy.join("obj").is_dir() && !copy.join("obj/main.o").exists());
+    assert!(!copy.join(".git").exists());
+
+    let (run, _) = fx.run_json(&[&["archived", "--archive", "--archive-format", "tar.gz", "--backup-dir", backups_arg, "--verify-archive"][..], &exclude, &["--delete", "--yes", "--output", "plain"]].concat());
+    run.assert_success();
+    assert!(!fx.exists("archived/target"));
+    let found = fx.run(&["backups", "find", "**", "--backup-dir", backups_arg]).stdout();
+    let archived: Vec<&str> = found.lines().filter(|line| line.contains(".tar.gz")).collect();
+    assert!(archived.iter().any(|line| line.contains("keep.rs")), "{}", found);
+    assert!(!archived.iter().any(|line| line.contains("main.o") || line.contains(".git")), "{}", found);
+}
+
+#[test]
+fn backups_verify_with_either_hash() {
+    for algo in ["blake3", "sha256"] {
+        let fx = Fixture::new("hash-algo");
+        fx.files("app/node_modules/pkg", 30).file("app/node_modules/big.bin", 5000);
+        let backups = fx.state_dir().join("backups");
+
+        let (run, summary) = fx.run_json(&[".", "--backup", "--backup-dir", backups.to_str().unwrap(), "--verify-sample", "100%", "--hash-algo", algo, "--delete", "--yes", "--output", "plain"]);
+        assert_eq!(summary["count"], 1, "{}: {}", algo, run.stderr());
+        assert!(!fx.exists("app/node_modules"));
+    }
+}
+
+#[test]
+fn stale_files_are_thinned_out_of_kept_directories() {
+    let fx = Fixture::new("thin");
+    fx.file("app/node_modules/old/pkg/a.js", 100)
+        .file("app/node_modules/mixed/old.js", 100)
+        .file("app/node_modules/mixed/new.js", 100)
+        .dir("app/node_modules/empty");
+    let long_ago = std::time::SystemTime::now() - std::time::Duration::from_secs(90 * 86_400);
+    for old in ["app/node_modules/old/pkg/a.js", "app/node_modules/mixed/old.js"] {
+        std::fs::File::options().write(true).open(fx.path(old)).unwrap().set_modified(long_ago).unwrap();
+    }
+
+    fx.run_json(&[".", "--purge-files-older-than", "30", "--delete", "--yes", "--output", "plain"]);
+    assert!(fx.exists("app/node_modules/mixed/new.js"));
+    assert!(!fx.exists("app/node_modules/mixed/old.js"));
+    // Emptied by the purge, so pruned; already empty, so kept
+    assert!(!fx.exists("app/node_modules/old"));
+    assert!(fx.exists("app/node_modules/empty"));
+}
+
+#[test]
+fn keep_latest_spares_the_newest_per_parent() {
+    let fx = Fixture::new("keep-latest");
+    let now = std::time::SystemTime::now();
+    for (version, days_old) in [(1, 30), (2, 20), (3, 10)] {
+        let file = format!("proj/build-{}/out.o", version);
+        fx.file(&file, 10);
+        let when = now - std::time::Duration::from_secs(days_old * 86_400);
+        for path in [fx.path(&file), fx.path(format!("proj/build-{}", version))] {
+            std::fs::File::open(path).unwrap().set_modified(when).unwrap();
+        }
+    }
+    fx.file("other/build/out.o", 10);
+
+    let (_, summary) = fx.run_json(&[".", "--keep-latest", "2", "--delete", "--yes", "--output", "plain"]);
+    assert_eq!(paths(&summary, "directories"), ["./proj/build-1"]);
+    assert!(fx.exists("proj/build-2") && fx.exists("proj/build-3") && fx.exists("other/build"));
+    assert!(!fx.exists("proj/build-1"));
+    let kept: Vec<_> = summary["skipped"].as_array().unwrap().iter().filter(|s| s["reason"] == "kept-latest").collect();
+    assert_eq!(kept.len(), 3);
+}
+
+// A sparse file stands in for compression: both leave less allocated than the length
+#[cfg(unix)]
+#[test]
+fn disk_usage_below_the_apparent_size_is_reported() {
+    let fx = Fixture::new("sparse");
+    fx.file("app/node_modules/small.js", 10).file("app/target/small", 10);
+    std::fs::File::create(fx.path("app/node_modules/sparse.bin")).unwrap().set_len(64 << 20).unwrap();
+
+    let (_, summary) = fx.run_json(&[".", "--delete", "--dry-run", "--yes", "--output", "plain"]);
+    let dirs = summary["directories"].as_array().unwrap();
+    let sparse = dirs.iter().find(|d| d["path"] == "./app/node_modules").unwrap();
+    assert_eq!(sparse["size_bytes"], (64 << 20) + 10);
+    assert!(sparse["disk_bytes"].as_u64().unwrap() < 1 << 20, "{}", sparse);
+    let dense = dirs.iter().find(|d| d["path"] == "./app/target").unwrap();
+    assert!(dense.get("disk_bytes").is_none(), "{}", dense);
+    assert_eq!(summary["total_disk_bytes"], sparse["disk_bytes"].as_u64().unwrap() + 10);
+}
+
+#[test]
+fn free_goal_deletes_the_largest_until_met() {
+    let fx = Fixture::new("free");
+    fx.file("a/node_modules/big", 3000).file("b/node_modules/mid", 2000).file("c/node_modules/small", 1000);
+
+    let (run, summary) = fx.run_json(&[".", "--free", "4000", "--delete", "--yes", "--output", "plain"]);
+    assert!(!fx.exists("a/node_modules") && !fx.exists("b/node_modules"));
+    assert!(fx.exists("c/node_modules/small"));
+    assert_eq!(paths(&summary, "skipped"), ["./c/node_modules"]);
+    assert_eq!(summary["skipped"][0]["reason"], "goal-met");
+    assert!(run.stdout().contains("Kept 1 directories"), "{}", run.stdout());
+}
+
+#[test]
+fn caps_bound_what_one_run_removes() {
+    let fx = Fixture::new("caps");
+    fx.file("a/node_modules/big", 3000).file("b/node_modules/mid", 2000).file("c/node_modules/small", 1000);
+
+    // Too big to fit is skipped, smaller ones after it still can
+    let (_, summary) = fx.run_json(&[".", "--max-total", "4500", "--delete", "--dry-run", "--yes", "--output", "plain"]);
+    assert_eq!(paths(&summary, "skipped"), ["./b/node_modules"]);
+    assert_eq!(summary["skipped"][0]["reason"], "capped");
+
+    let (run, summary) = fx.run_json(&[".", "--max-dirs", "2", "--delete", "--yes", "--output", "plain"]);
+    assert_eq!(paths(&summary, "skipped"), ["./c/node_modules"]);
+    assert!(summary["skipped"][0]["detail"].as_str().unwrap().contains("skipped due to cap"));
+    assert!(run.stdout().contains("1 directories skipped due to cap"), "{}", run.stdout());
+    assert!(!fx.exists("a/node_modules") && !fx.exists("b/node_modules"));
+    assert!(fx.exists("c/node_modules/small"));
+}
+
+#[test]
+fn mount_settings_apply_to_directories_under_them() {
+    let fx = Fixture::new("mounts");
+    fx.file("nas/app/node_modules/index.js", 10).file("local/app/node_modules/index.js", 10);
+    let config = serde_json::json!({ "mounts": { fx.path("nas").to_str().unwrap(): { "estimate": true, "jobs": 2 } } });
+    std::fs::write(fx.path("settings.json"), config.to_string()).unwrap();
+
+    let run = fx.run(&[".", "--config", "settings.json", "--output", "json-events"]);
+    run.assert_success();
+    let found = run.event("found");
+    let estimated: Vec<&str> = found["directories"].as_array().unwrap().iter()
+        .filter(|dir| dir["estimated"] == true)
+        .map(|dir| dir["path"].as_str().unwrap())
+        .collect();
+    assert_eq!(estimated, ["./nas/app/node_modules"]);
+
+    fx.run(&[".", "--config", "settings.json", "--delete", "--yes", "--output", "plain"]).assert_success();
+    assert!(!fx.exists("nas/app/node_modules") && !fx.exists("local/app/node_modules"));
+}
+
+#[cfg(unix)]
+#[test]
+fn symlink_loops_do_not_hang_the_scan() {
+    let fx = Fixture::new("loop");
+    fx.file("app/node_modules/index.js", 10)
+        .symlink("..", "app/sub/up")
+        .symlink("../..", "app/node_modules/escape");
+
+    let run = fx.run(&[".", "--follow-symlinks-traversal", "--output", "json-events"]);
+    run.assert_success();
+    assert_eq!(paths(&run.event("found"), "directories"), ["./app/node_modules"]);
+}
+
+#[cfg(unix)]
+#[test]
+fn matches_resolving_outside_the_tree_are_refused() {
+    let outside = Fixture::new("outside");
+    outside.file("elsewhere/node_modules/data.js", 10);
+    let fx = Fixture::new("escape");
+    fx.file("app/node_modules/index.js", 10)
+        .symlink(outside.path("elsewhere"), "linked");
+
+    let (_, summary) = fx.run_json(&[".", "--follow-symlinks-traversal", "--delete", "--yes", "--output", "plain"]);
+    assert!(outside.exists("elsewhere/node_modules/data.js"));
+    assert!(!fx.exists("app/node_modules"));
+    assert_eq!(paths(&summary, "skipped"), ["./linked/node_modules"]);
+    assert_eq!(summary["skipped"][0]["reason"], "outside-root");
+
+    fx.run_json(&[".", "--follow-symlinks-traversal", "--allow-outside-root", "--delete", "--yes", "--output", "plain"]);
+    assert!(!outside.exists("elsewhere/node_modules"));
+}
+
+#[test]
+fn dirpurge_never_deletes_its_own_state() {
+    let fx = Fixture::new("own-state");
+    fx.file("app/node_modules/index.js", 10)
+        .file("tools/.cache/undo/notes.txt", 10)
+        .file("settings/notes.txt", 10);
+    let config = fx.path("settings/config.json");
+    std::fs::write(&config, r#"{"journal": "tools/.cache/undo/journal.jsonl"}"#).unwrap();
+
+    let (_, summary) = fx.run_json(&[
+        ".", "-c", config.to_str().unwrap(), "--target", "node_modules", "--target", ".cache", "--target", "settings",
+        "--delete", "--yes", "--output", "plain",
+    ]);
+    assert!(!fx.exists("app/node_modules"));
+    assert!(fx.exists("tools/.cache/undo/journal.jsonl"));
+    assert!(fx.exists("settings/config.json"));
+    assert_eq!(paths(&summary, "skipped"), ["./settings", "./tools/.cache"]);
+    assert!(summary["skipped"].as_array().unwrap().iter().all(|s| s["reason"] == "protected"), "{}", summary["skipped"]);
+
+    // A config that doesn't load can't be protecting anything, so nothing goes ahead
+    fx.file("web/node_modules/index.js", 10);
+    std::fs::write(&config, r#"{"protected": ["web"],}"#).unwrap();
+    let run = fx.run(&["web", "-c", config.to_str().unwrap(), "--delete", "--yes", "--output", "plain"]);
+    assert!(!run.0.status.success());
+    assert!(run.stderr().contains("Error parsing config"), "{}", run.stderr());
+    assert!(fx.exists("web/node_modules"));
+}
+
+// Linux filesystems accept arbitrary bytes in names, unlike APFS or NTFS
+#[cfg(target_os = "linux")]
+#[test]
+fn non_utf8_names_are_reported_without_harming_neighbours() {
+    use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+
+    let fx = Fixture::new("non-utf8");
+    let odd = OsStr::from_bytes(b"caf\xe9");
+    fx.file(fx.path(odd).join("node_modules/index.js"), 10)
+        .file(fx.path(odd).join("src/main.js"), 10)
+        .file("plain/node_modules/index.js", 10);
+
+    let run = fx.run(&[".", "--output", "json-events"]);
+    run.assert_success();
+    assert_eq!(paths(&run.event("found"), "directories"), ["./caf\u{fffd}/node_modules", "./plain/node_modules"]);
+
+    fx.run(&[".", "--delete", "--yes", "--output", "plain"]).assert_success();
+    assert!(!fx.exists("plain/node_modules"));
+    assert!(fx.path(odd).join("src/main.js").exists());
+}
+
+#[test]
+fn resume_finishes_an_interrupted_run() {
+    let fx = Fixture::new("resume");
+    fx.file("a/node_modules/index.js", 200).file("b/target/out", 100);
+    fx.run(&[".", "--delete", "--yes", "--output", "plain"]).assert_success();
+    assert!(!fx.exists("a/node_modules") && !fx.exists("b/target"));
+
+    // Cut the journal off after the first deletion, as a crash would, and put
+    // the second directory back
+    let journal = fx.state_dir().join("dirpurge/journal.jsonl");
+    let content = std::fs::read_to_string(&journal).unwrap();
+    let lines: Vec<&str> = content.lines().collect();
+    let first_done = lines.iter().position(|line| line.contains(r#""event":"done""#)).unwrap();
+    assert!(lines[first_done].contains("./a/node_modules"), "largest first: {}", lines[first_done]);
+    std::fs::write(&journal, lines[..=first_done].join("\n") + "\n").unwrap();
+    fx.file("b/target/out", 100);
+
+    let run = fx.run(&["resume", "--yes"]);
+    run.assert_success();
+    assert!(run.stdout().contains("1 of 2 directories left"), "{}", run.stdout());
+    assert!(!fx.exists("b/target"));
+    assert!(fx.exists("b"));
+
+    let run = fx.run(&["resume", "--yes"]);
+    run.assert_success();
+    assert!(run.stdout().contains("No interrupted run"), "{}", run.stdout());
+}
+
+#[test]
+fn interrupted_removals_leave_only_a_set_aside_sibling_for_resume() {
+    let fx = Fixture::new("resume-aside");
+    fx.file("a/node_modules/index.js", 200).file("b/target/out", 100);
+    fx.run(&[".", "--delete", "--yes", "--output", "plain"]).assert_success();
+    let leftovers = |dir: &str| std::fs::read_dir(fx.path(dir)).unwrap()
+        .filter(|e| e.as_ref().unwrap().file_name().to_string_lossy().starts_with(".dirpurge-deleting-"))
+        .count();
+    assert_eq!(leftovers("a") + leftovers("b"), 0);
+
+    // Crash part way through removing b/target, after it was set aside
+    let journal = fx.state_dir().join("dirpurge/journal.jsonl");
+    let content = std::fs::read_to_string(&journal).unwrap();
+    let lines: Vec<&str> = content.lines().collect();
+    let deleting = lines.iter().position(|line| line.contains(r#""event":"deleting""#) && line.contains("./b/target")).unwrap();
+    std::fs::write(&journal, lines[..=deleting].join("\n") + "\n").unwrap();
+    fx.file("b/.dirpurge-deleting-0000beef/node_modules/x.js", 100);
+
+    // Never taken for a match while it waits
+    let run = fx.run(&[".", "--output", "plain"]);
+    assert!(!run.stdout().contains(".dirpurge-deleting-"), "{}", run.stdout());
+
+    let run = fx.run(&["resume", "--yes", "--verbose"]);
+    run.assert_success();
+    assert!(run.stdout().contains("Already gone: ./b/target"), "{}", run.stdout());
+    assert_eq!(leftovers("b"), 0);
+}
+
+#[test]
+fn resume_carries_on_with_a_copy_cut_off_part_way() {
+    let fx = Fixture::new("resume-copy");
+    fx.files("app/node_modules/pkg", 5);
+    let backups = fx.state_dir().join("backups");
+    let args = [".", "--backup", "--backup-dir", backups.to_str().unwrap(), "--checkpoint-interval", "0", "--delete", "--yes", "--output", "plain"];
+    fx.run(&args).assert_success();
+
+    // Crash after a checkpoint: the copy is missing a file and the source is all there
+    let journal = fx.state_dir().join("dirpurge/journal.jsonl");
+    let content = std::fs::read_to_string(&journal).unwrap();
+    let lines: Vec<&str> = content.lines().collect();
+    assert!(lines.iter().any(|line| line.contains(r#""event":"copying""#)), "{}", content);
+    let checkpoint = lines.iter().position(|line| line.contains(r#""event":"checkpoint""#)).unwrap();
+    std::fs::write(&journal, lines[..=checkpoint].join("\n") + "\n").unwrap();
+    fx.files("app/node_modules/pkg", 5);
+    std::fs::remove_file(backups.join("node_modules/pkg/4")).unwrap();
+
+    let run = fx.run(&["resume", "--yes", "--verbose"]);
+    run.assert_success();
+    assert!(run.stdout().contains("1 of 1 directories left, 1 part-copied"), "{}", run.stdout());
+    assert!(run.stdout().contains("Continuing the copy of ./app/node_modules"), "{}", run.stdout());
+    assert!(!fx.exists("app/node_modules"));
+    // Finished in place rather than copied again beside it
+    assert_eq!(std::fs::read_dir(&backups).unwrap().count(), 1);
+    assert_eq!(std::fs::read_dir(backups.join("node_modules/pkg")).unwrap().count(), 5);
+}
+
+#[test]
+fn large_directories_are_removed_in_batches() {
+    let fx = Fixture::new("chunked");
+    fx.files("app/node_modules/a", 40).files("app/node_modules/b/c", 40).file("lib/target/out", 10);
+
+    let run = fx.run(&[".", "-t", "node_modules", "-t", "target", "--chunk-above", "20", "--delete", "--yes", "--verbose", "--output", "plain"]);
+    run.assert_success();
+    let stdout = run.stdout();
+    assert!(stdout.contains("Permanently deleted: ./app/node_modules (83 entries, in batches)"), "{}", stdout);
+    assert!(stdout.lines().any(|line| line.ends_with("Permanently deleted: ./lib/target")), "{}", stdout);
+    assert!(!fx.exists("app/node_modules") && !fx.exists("lib/target"));
+}
+
+#[test]
+fn batches_split_across_nested_directories_remove_everything() {
+    let fx = Fixture::new("chunked-nested");
+    // More than the first batch, so directories and their files land in different ones
+    for dir in ["a", "a/b", "a/b/c", "d"] {
+        fx.files(format!("app/node_modules/{}", dir), 700);
+    }
+
+    let run = fx.run(&[".", "-t", "node_modules", "--chunk-above", "100", "--delete", "--yes", "--verbose", "--output", "plain"]);
+    run.assert_success();
+    assert!(run.stdout().contains("(2804 entries, in batches)"), "{}", run.stdout());
+    assert!(!fx.exists("app/node_modules"));
+}
+
+#[test]
+fn restore_brings_back_a_backed_up_directory() {
+    let fx = Fixture::new("restore");
+    fx.file("app/node_modules/pkg/index.js", 100).file("lib/target/out", 50);
+    let backups = fx.state_dir().join("backups");
+    let backups = backups.to_str().unwrap();
+    fx.run(&[".", "--backup", "--backup-dir", backups, "--delete", "--yes", "--output", "plain"]).assert_success();
+    assert!(!fx.exists("app/node_modules"));
+
+    fx.run(&["restore", "app/node_modules"]).assert_success();
+    assert_eq!(std::fs::read(fx.path("app/node_modules/pkg/index.js")).unwrap().len(), 100);
+    assert!(!fx.exists("lib/target"));
+
+    // Never over the top of what is there now
+    let run = fx.run(&["restore", "app/node_modules"]);
+    assert!(!run.0.status.success());
+    assert!(run.stderr().contains("already exists"), "{}", run.stderr());
+
+    fx.run(&["restore", "lib/target", "--to", "restored"]).assert_success();
+    assert!(fx.exists("restored/out"));
+}
+
+#[cfg(all(unix, not(target_os = "macos")))]
+#[test]
+fn trashed_directories_are_listed_and_restored() {
+    let fx = Fixture::new("trash");
+    fx.file("app/node_modules/pkg/index.js", 100).file("other/junk.txt", 10);
+    let data = fx.state_dir().join("data");
+    let env = [("XDG_DATA_HOME", data.to_str().unwrap())];
+
+    fx.run_with_env(&["app", "--use-trash", "--delete", "--yes", "--output", "plain"], &env).assert_success();
+    assert!(!fx.exists("app/node_modules"));
+    // Only what dirpurge trashed is ever shown
+    let listed = fx.run_with_env(&["trash", "list"], &env).stdout();
+    assert!(listed.contains("1 directories dirpurge moved to the trash"), "{}", listed);
+    assert!(listed.contains(&fx.path("app/node_modules").display().to_string()), "{}", listed);
+
+    let run = fx.run_with_env(&["trash", "restore", "other"], &env);
+    assert!(!run.0.status.success());
+    assert!(run.stderr().contains("no record of moving"), "{}", run.stderr());
+
+    fx.run_with_env(&["trash", "restore", "app/node_modules", "--to", "restored"], &env).assert_success();
+    assert_eq!(std::fs::read(fx.path("restored/pkg/index.js")).unwrap().len(), 100);
+    let listed = fx.run_with_env(&["trash", "list"], &env).stdout();
+    assert!(listed.contains("Nothing"), "{}", listed);
+}
+
+#[test]
+fn quarantined_directories_are_restorable_until_purged() {
+    let fx = Fixture::new("quarantine");
+    fx.file("app/node_modules/pkg/index.js", 100);
+    let quarantine = fx.state_dir().join("quarantine");
+    let quarantine_arg = quarantine.to_str().unwrap();
+    let entries = || std::fs::read_dir(&quarantine).map_or(0, |entries| entries.count());
+
+    fx.run(&["app", "--quarantine", "--quarantine-dir", quarantine_arg, "--delete", "--yes", "--output", "plain"]).assert_success();
+    assert!(!fx.exists("app/node_modules"));
+    assert_eq!(entries(), 1);
+
+    // Within its grace period it stays, and can be brought back
+    let purged = fx.run(&["quarantine", "purge", "--older-than", "14d", "--quarantine-dir", quarantine_arg]);
+    purged.assert_success();
+    assert!(purged.stdout().contains("Purged 0 quarantined directories"), "{}", purged.stdout());
+    fx.run(&["restore", "app/node_modules"]).assert_success();
+    assert_eq!(std::fs::read(fx.path("app/node_modules/pkg/index.js")).unwrap().len(), 100);
+
+    fx.run(&["app", "--quarantine", "--quarantine-dir", quarantine_arg, "--delete", "--yes", "--output", "plain"]).assert_success();
+    fx.run(&["quarantine", "purge", "--older-than", "0m", "--quarantine-dir", quarantine_arg, "--dry-run"]).assert_success();
+    assert_eq!(entries(), 2);
+    let purged = fx.run(&["quarantine", "purge", "--older-than", "0m", "--quarantine-dir", quarantine_arg]);
+    purged.assert_success();
+    assert!(purged.stdout().contains("Purged 2 quarantined directories"), "{}", purged.stdout());
+    assert_eq!(entries(), 0);
+}
+
+#[cfg(unix)]
+#[test]
+fn backups_and_restores_keep_modes_times_and_symlinks() {
+    use std::os::unix::fs::PermissionsExt;
+    let fx = Fixture::new("preserve");
+    let then = std::time::SystemTime::now() - std::time::Duration::from_secs(86_400);
+    for project in ["kept", "plain"] {
+        fx.file(format!("{}/target/bin/tool", project), 100).symlink("bin/tool", format!("{}/target/current", project));
+        let tool = fx.path(format!("{}/target/bin/tool", project));
+        std::fs::set_permissions(&tool, std::fs::Permissions::from_mode(0o750)).unwrap();
+        for path in [tool, fx.path(format!("{}/target/bin", project))] {
+            std::fs::File::open(path).unwrap().set_modified(then).unwrap();
+        }
+    }
+    let backups = fx.state_dir().join("backups");
+    let backups = backups.to_str().unwrap();
+    let mode = |path: std::path::PathBuf| std::fs::metadata(path).unwrap().permissions().mode() & 0o777;
+    let modified = |path: std::path::PathBuf| std::fs::metadata(path).unwrap().modified().unwrap();
+
+    fx.run(&["kept", "--backup", "--backup-dir", backups, "--delete", "--yes", "--output", "plain"]).assert_success();
+    fx.run(&["restore", "kept/target"]).assert_success();
+    assert_eq!(mode(fx.path("kept/target/bin/tool")), 0o750);
+    assert_eq!(modified(fx.path("kept/target/bin/tool")), then);
+    assert_eq!(modified(fx.path("kept/target/bin")), then);
+    assert_eq!(std::fs::read_link(fx.path("kept/target/current")).unwrap(), std::path::Path::new("bin/tool"));
+
+    // Links followed, times and modes left to the copy
+    fx.run(&["plain", "--backup", "--backup-preserve", "none", "--backup-dir", backups, "--delete", "--yes", "--output", "plain"]).assert_success();
+    fx.run(&["restore", "plain/target", "--to", "restored"]).assert_success();
+    assert!(!fx.path("restored/current").symlink_metadata().unwrap().file_type().is_symlink());
+    assert_eq!(std::fs::read(fx.path("restored/current")).unwrap().len(), 100);
+    assert!(modified(fx.path("restored/bin/tool")) > then);
+}
+
+#[test]
+fn purge_path_removes_one_named_directory_with_a_backup() {
+    let fx = Fixture::new("purge-path");
+    fx.file("old-build/out/app.bin", 100).file("keep/notes.txt", 10);
+    let backups = fx.state_dir().join("backups");
+    let backups = backups.to_str().unwrap();
+
+    // Not a target name and no filters, yet it is purged and can be restored
+    let (_, summary) = fx.run_json(&["purge-path", "old-build", "--backup", "--backup-dir", backups, "--yes"]);
+    assert_eq!(paths(&summary, "directories"), ["old-build"]);
+    assert!(!fx.exists("old-build"));
+    assert!(fx.exists("keep/notes.txt"));
+    fx.run(&["restore", "old-build"]).assert_success();
+    assert!(fx.exists("old-build/out/app.bin"));
+
+    let run = fx.run(&["purge-path", "keep", "--protect", "keep", "--yes"]);
+    assert!(!run.0.status.success());
+    assert!(run.stderr().contains("Refusing"), "{}", run.stderr());
+    assert!(fx.exists("keep/notes.txt"));
+}
+
+// Removed for good as in a scan, unless --use-trash is given
+#[cfg(all(unix, not(target_os = "macos")))]
+#[test]
+fn purge_path_uses_the_trash_only_when_asked() {
+    let fx = Fixture::new("purge-path-trash");
+    fx.file("old-build/app.bin", 100).file("old-cache/blob", 100);
+    let data = fx.state_dir().join("data");
+    let env = [("XDG_DATA_HOME", data.to_str().unwrap())];
+    let trashed = |name: &str| data.join("Trash/files").join(name).exists();
+
+    fx.run_with_env(&["purge-path", "old-build", "--yes"], &env).assert_success();
+    assert!(!fx.exists("old-build"));
+    assert!(!trashed("old-build"));
+
+    fx.run_with_env(&["purge-path", "old-cache", "--use-trash", "--yes"], &env).assert_success();
+    assert!(!fx.exists("old-cache"));
+    assert!(trashed("old-cache"));
+}
+
+#[test]
+fn restore_reads_journals_of_other_versions() {
+    let fx = Fixture::new("journal-format");
+    fx.file("app/node_modules/index.js", 100);
+    let backups = fx.state_dir().join("backups");
+    fx.run(&[".", "--backup", "--backup-dir", backups.to_str().unwrap(), "--delete", "--yes", "--output", "plain"]).assert_success();
+
+    // Unstamp the lines as an older dirpurge wrote them, then add one from the future
+    let journal = fx.state_dir().join("dirpurge/journal.jsonl");
+    let content = std::fs::read_to_string(&journal).unwrap();
+    assert!(content.lines().all(|line| line.starts_with(r#"{"v":2,"#)), "{}", content);
+    let legacy = content.replace(r#"{"v":2,"#, "{");
+    std::fs::write(&journal, legacy + r#"{"v":99,"run":"x","timestamp":"t","event":"teleported"}"# + "\n").unwrap();
+
+    let run = fx.run(&["restore", "app/node_modules"]);
+    run.assert_success();
+    assert!(run.stderr().contains("newer dirpurge"), "{}", run.stderr());
+    assert!(fx.exists("app/node_modules/index.js"));
+}
+
+#[test]
+fn restore_reads_journals_written_by_the_first_format() {
+    // Two runs journaled by releases before the stamp, the first from before
+    // runs recorded where they started
+    let fx = Fixture::new("journal-v1");
+    let root = fx.root().to_str().unwrap();
+    let legacy = std::fs::read_to_string(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/golden/journal-v1.jsonl")).unwrap();
+    let journal = fx.state_dir().join("dirpurge/journal.jsonl");
+    std::fs::create_dir_all(journal.parent().unwrap()).unwrap();
+    std::fs::write(&journal, legacy.replace("{root}", root)).unwrap();
+    fx.file("backups/node_modules/index.js", 4).file("backups/node_modules_20261014_121318/index.js", 4);
+
+    fx.run(&["restore", "app/node_modules"]).assert_success();
+    fx.run(&["restore", "web/node_modules"]).assert_success();
+    assert!(fx.exists("app/node_modules/index.js"));
+    assert!(fx.exists("web/node_modules/index.js"));
+}
+
+#[cfg(unix)]
+#[test]
+fn tar_archives_keep_modes_and_links_through_a_restore() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let fx = Fixture::new("tar-gz");
+    fx.file("app/node_modules/pkg/index.js", 100)
+        .file("app/node_modules/.bin/run", 20)
+        .symlink("../pkg/index.js", "app/node_modules/.bin/index")
+        .file(format!("app/node_modules/{}/deep.js", "d".repeat(120)), 30);
+    std::fs::set_permissions(fx.path("app/node_modules/.bin/run"), std::fs::Permissions::from_mode(0o750)).unwrap();
+    let backups = fx.state_dir().join("backups");
+
+    fx.run(&[".", "--archive", "--archive-format", "tar.gz", "--backup-dir", backups.to_str().unwrap(), "--verify-sample", "100%", "--delete", "--yes", "--output", "plain"]).assert_success();
+    assert!(!fx.exists("app/node_modules"));
+    let archive = std::fs::read_dir(&backups).unwrap().next().unwrap().unwrap().path();
+    assert!(archive.to_string_lossy().ends_with(".tar.gz"), "{}", archive.display());
+
+    fx.run(&["restore", "app/node_modules"]).assert_success();
+    assert_eq!(std::fs::read(fx.path("app/node_modules/pkg/index.js")).unwrap().len(), 100);
+    assert_eq!(std::fs::metadata(fx.path("app/node_modules/.bin/run")).unwrap().permissions().mode() & 0o777, 0o750);
+    assert_eq!(std::fs::read_link(fx.path("app/node_modules/.bin/index")).unwrap(), std::path::Path::new("../pkg/index.js"));
+    assert!(fx.exists(format!("app/node_modules/{}/deep.js", "d".repeat(120))));
+}
+
+#[test]
+fn restore_refuses_permanent_deletions() {
+    let fx = Fixture::new("restore-none");
+    fx.file("app/node_modules/index.js", 10);
+    fx.run(&[".", "--delete", "--yes", "--output", "plain"]).assert_success();
+
+    let run = fx.run(&["restore", "app/node_modules"]);
+    assert!(!run.0.status.success());
+    assert!(run.stderr().contains("without a backup"), "{}", run.stderr());
+    let run = fx.run(&["restore", "app/never-there"]);
+    assert!(run.stderr().contains("no record"), "{}", run.stderr());
+}
+
+#[test]
+fn zstd_archives_verify_and_restore_at_any_level() {
+    let fx = Fixture::new("tar-zst");
+    fx.file("app/target/debug/app", 5000).file("app/Cargo.toml", 10);
+    let backups = fx.state_dir().join("backups");
+    let backups = backups.to_str().unwrap();
+
+    let run = fx.run(&[".", "-t", "target", "--archive", "--archive-format", "tar.zst", "--compression-level", "23", "--backup-dir", backups, "--delete", "--yes"]);
+    assert!(!run.0.status.success());
+    assert!(run.stderr().contains("1-22"), "{}", run.stderr());
+    assert!(fx.exists("app/target/debug/app"));
+
+    fx.run(&[".", "-t", "target", "--archive", "--archive-format", "tar.zst", "--compression-level", "19", "--backup-dir", backups, "--verify-sample", "100%", "--delete", "--yes", "--output", "plain"]).assert_success();
+    let archive = std::fs::read_dir(backups).unwrap().next().unwrap().unwrap().path();
+    assert!(archive.to_string_lossy().ends_with(".tar.zst"), "{}", archive.display());
+
+    fx.run(&["restore", "app/target"]).assert_success();
+    assert_eq!(std::fs::read(fx.path("app/target/debug/app")).unwrap().len(), 5000);
+}
+
+#[test]
+fn purge_list_deletes_listed_directories_that_pass_the_filters() {
+    let fx = Fixture::new("purge-list");
+    fx.file("a/cache/blob", 2 * 1024 * 1024).file("b/cache/blob", 10).file("c/cache/blob", 2 * 1024 * 1024);
+    std::fs::write(fx.path("list.txt"), "# from the nightly sweep\na/cache\n\nb/cache\n#c/cache\n").unwrap();
+
+    let (_, summary) = fx.run_json(&["--purge-list", "list.txt", "--min-size", "1", "--yes", "--output", "plain"]);
+    assert_eq!(paths(&summary, "directories"), ["a/cache"]);
+    assert!(!fx.exists("a/cache"));
+    // Too small to pass the sanity check, and commented out
+    assert!(fx.exists("b/cache/blob"));
+    assert!(fx.exists("c/cache/blob"));
+}
+
+#[test]
+fn verified_archives_are_read_back_in_full() {
+    let fx = Fixture::new("verify-archive");
+    fx.files("app/node_modules/pkg", 40).file("app/node_modules/big.bin", 300_000);
+    let backups = fx.state_dir().join("backups");
+
+    fx.run(&[".", "--archive", "--archive-format", "tar.gz", "--verify-archive", "--backup-dir", backups.to_str().unwrap(), "--delete", "--yes", "--output", "plain"]).assert_success();
+    assert!(!fx.exists("app/node_modules"));
+    fx.run(&["restore", "app/node_modules"]).assert_success();
+    assert_eq!(std::fs::read_dir(fx.path("app/node_modules/pkg")).unwrap().count(), 40);
+}
+
+#[test]
+fn confirm_tokens_only_apply_the_plan_they_were_issued_for() {
+    let fx = Fixture::new("confirm-token");
+    fx.file("a/node_modules/index.js", 10).file("b/node_modules/index.js", 10);
+    fx.run(&["a", "--save-plan", "a.json"]).assert_success();
+    fx.run(&["b", "--save-plan", "b.json"]).assert_success();
+    let token = |plan: &str| {
+        let run = fx.run(&["plan", "token", plan]);
+        run.assert_success();
+        run.stdout().trim().to_string()
+    };
+    let (a, b) = (token("a.json"), token("b.json"));
+    assert_ne!(a, b);
+
+    let run = fx.run(&["apply", "b.json", "--confirm-token", &a]);
+    assert!(!run.0.status.success());
+    assert!(run.stderr().contains("doesn't match"), "{}", run.stderr());
+    assert!(fx.exists("b/node_modules"));
+
+    fx.run(&["apply", "b.json", "--confirm-token", &b]).assert_success();
+    assert!(!fx.exists("b/node_modules"));
+    assert!(fx.exists("a/node_modules"));
+
+    // The same in every process, whatever order the plan's maps come in
+    let mut args = vec!["a", "--save-plan", "actions.json"];
+    for action in ["node_modules=delete", "target=report-only", "dist=trash", ".venv=delete"] {
+        args.extend(["--action", action]);
+    }
+    fx.run(&args).assert_success();
+    let first = token("actions.json");
+    for _ in 0..4 {
+        assert_eq!(token("actions.json"), first);
+    }
+}
+
+#[test]
+fn plans_keep_the_approval_policy_they_were_made_under() {
+    let fx = Fixture::new("approval");
+    fx.file("app/node_modules/index.js", 10).file("alice.key", 16).file("mallory.key", 16);
+    let config = |required: usize, signers: &[&str]| {
+        let keys: Vec<String> = signers.iter().map(|signer| format!(r#""{}": "{}""#, signer, fx.path(format!("{}.key", signer)).display())).collect();
+        let approval = format!(r#"{{"required_signatures": {}, "keys": {{{}}}}}"#, required, keys.join(", "));
+        std::fs::write(fx.path("config.json"), format!(r#"{{"approval": {}}}"#, approval)).unwrap();
+    };
+    config(1, &["alice"]);
+    fx.run(&["app", "-c", "config.json", "--save-plan", "plan.json"]).assert_success();
+
+    let run = fx.run(&["apply", "plan.json", "--yes"]);
+    assert!(!run.0.status.success());
+    assert!(run.stderr().contains("made under a policy requiring 1 approval"), "{}", run.stderr());
+    // Nor does a laxer config at apply time, or a signer added to it since
+    config(0, &["alice", "mallory"]);
+    fx.run(&["plan", "sign", "plan.json", "--key", "mallory.key", "--signer", "mallory"]).assert_success();
+    let run = fx.run(&["apply", "plan.json", "-c", "config.json", "--yes"]);
+    assert!(run.stderr().contains("requires 1 approval signature(s), found 0 valid"), "{}", run.stderr());
+    assert!(fx.exists("app/node_modules"));
+
+    fx.run(&["plan", "sign", "plan.json", "--key", "alice.key", "--signer", "alice"]).assert_success();
+    fx.run(&["apply", "plan.json", "-c", "config.json", "--yes"]).assert_success();
+    assert!(!fx.exists("app/node_modules"));
+}
+
+// What elevated runs are given, so they never run the plan's daemon commands
+#[cfg(unix)]
+#[test]
+fn apply_skips_daemon_commands_when_asked() {
+    let fx = Fixture::new("skip-daemons");
+    fx.file("app/node_modules/index.js", 10).file("web/node_modules/index.js", 10);
+    std::fs::write(fx.path("config.json"), r#"{"daemon_commands": {"node_modules": "touch stopped"}}"#).unwrap();
+    fx.run(&["app", "-c", "config.json", "--stop-daemons", "--save-plan", "app.json"]).assert_success();
+    fx.run(&["web", "-c", "config.json", "--stop-daemons", "--save-plan", "web.json"]).assert_success();
+
+    fx.run(&["apply", "app.json", "--skip-daemons", "--yes"]).assert_success();
+    assert!(!fx.exists("app/node_modules") && !fx.exists("app/stopped"));
+    fx.run(&["apply", "web.json", "--yes"]).assert_success();
+    assert!(!fx.exists("web/node_modules") && fx.exists("web/stopped"));
+}
+
+#[test]
+fn archives_carry_a_manifest_of_their_files() {
+    let fx = Fixture::new("manifest");
+    fx.file("app/node_modules/pkg/index.js", 100).file("app/node_modules/README", 0);
+    let backups = fx.state_dir().join("backups");
+
+    fx.run(&[".", "--archive", "--archive-format", "tar.gz", "--archive-manifest", "--backup-dir", backups.to_str().unwrap(), "--delete", "--yes", "--output", "plain"]).assert_success();
+    let beside = std::fs::read_dir(&backups).unwrap()
+        .map(|e| e.unwrap().path())
+        .find(|p| p.to_string_lossy().ends_with(".tar.gz.manifest.json"))
+        .expect("manifest beside the archive");
+    let manifest: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(beside).unwrap()).unwrap();
+    let files = manifest["files"].as_array().unwrap();
+    assert_eq!(paths(&manifest, "files"), ["README", "pkg/index.js"]);
+    let index = files.iter().find(|f| f["path"] == "pkg/index.js").unwrap();
+    assert_eq!(index["size"], 100);
+    assert_eq!(index["sha256"].as_str().unwrap().len(), 64);
+
+    fx.run(&["restore", "app/node_modules"]).assert_success();
+    assert!(fx.exists("app/node_modules/pkg/index.js"));
+    assert!(!fx.exists("app/node_modules/.dirpurge-manifest.json"));
+}
+
+#[test]
+fn failures_are_summed_up_after_the_rest_is_removed() {
+    let fx = Fixture::new("failures");
+    fx.file("app/node_modules/index.js", 10).file("lib/target/out", 10).file("blocker", 1);
+    let json = fx.path("summary.json");
+
+    // Backups can't be written under a file, so only node_modules fails
+    let run = fx.run(&[".", "-t", "node_modules", "-t", "target", "--action", "node_modules=backup+delete", "--backup-dir", "blocker/backups", "--delete", "--yes", "--output", "plain", "--json", json.to_str().unwrap()]);
+    assert_eq!(run.0.status.code(), Some(2), "{}", run.stderr());
+    assert!(!fx.exists("lib/target"));
+    assert!(fx.exists("app/node_modules/index.js"));
+    let output = run.stdout() + &run.stderr();
+    assert!(output.contains("1 directories could not be removed"), "{}", output);
+    assert!(output.contains("Backup failed"), "{}", output);
+
+    let summary: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(json).unwrap()).unwrap();
+    let failed: Vec<_> = summary["skipped"].as_array().unwrap().iter().filter(|s| s["reason"] == "error").collect();
+    assert_eq!(failed.len(), 1);
+    assert_eq!(failed[0]["path"], "./app/node_modules");
+}
+
+#[test]
+fn reports_format_numbers_for_the_requested_locale() {
+    let fx = Fixture::new("locale");
+    fx.file("app/node_modules/big", 1_572_864);
+
+    let run = fx.run(&[".", "--output", "plain"]);
+    run.assert_success();
+    assert!(run.stdout().contains("1.50 MB"), "{}", run.stdout());
+
+    let run = fx.run(&[".", "--output", "plain", "--report-locale", "de_DE.UTF-8"]);
+    run.assert_success();
+    assert!(run.stdout().contains("1,50 MB"), "{}", run.stdout());
+
+    let run = fx.run(&[".", "--report-locale", "xx-YY"]);
+    assert!(!run.0.status.success());
+    assert!(run.stderr().contains("Unknown report locale 'xx-YY'"), "{}", run.stderr());
+}
+
+#[test]
+fn archives_hold_paths_relative_to_the_directory_or_under_its_name() {
+    for format in ["zip", "tar.gz"] {
+        for top_dir in [false, true] {
+            let fx = Fixture::new("archive-paths");
+            fx.file("app/node_modules/pkg/lib/index.js", 100).file("app/node_modules/top.js", 10);
+            let backups = fx.state_dir().join("backups");
+            let backups_arg = backups.to_str().unwrap();
+            let top_dir_arg: &[&str] = if top_dir { &["--archive-top-dir"] } else { &[] };
+
+            let run = fx.run(&[&["app", "--archive", "--archive-format", format, "--verify-archive", "--backup-dir", backups_arg][..], top_dir_arg, &["--delete", "--yes", "--output", "plain"]].concat());
+            run.assert_success();
+            assert!(!fx.exists("app/node_modules"), "{} {}", format, top_dir);
+
+            // Listed relative to the directory either way
+            let found = fx.run(&["backups", "find", "pkg/lib/index.js", "--backup-dir", backups_arg]).stdout();
+            assert!(found.contains(" pkg/lib/index.js"), "{} {}: {}", format, top_dir, found);
+
+            fx.run(&["restore", "app/node_modules"]).assert_success();
+            assert_eq!(std::fs::read(fx.path("app/node_modules/pkg/lib/index.js")).unwrap().len(), 100);
+            assert_eq!(std::fs::read(fx.path("app/node_modules/top.js")).unwrap().len(), 10);
+            assert!(!fx.exists("app/node_modules/node_modules"));
+        }
+    }
+}
+
+#[test]
+fn split_archives_are_written_in_volumes_and_restored_from_them() {
+    for format in ["zip", "tar.gz"] {
+        let fx = Fixture::new("archive-split");
+        fx.file("app/node_modules/pkg/index.js", 10);
+        // Noise, so the archive doesn't compress into one volume
+        let mut state = 1u64;
+        let noise: Vec<u8> = (0..20_000).map(|_| { state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407); (state >> 56) as u8 }).collect();
+        std::fs::write(fx.path("app/node_modules/pkg/blob.bin"), &noise).unwrap();
+        let backups = fx.state_dir().join("backups");
+        let backups_arg = backups.to_str().unwrap();
+
+        fx.run(&["app", "--archive", "--archive-format", format, "--archive-split-size", "4KB", "--verify-archive", "--backup-dir", backups_arg, "--delete", "--yes", "--output", "plain"]).assert_success();
+        let mut names: Vec<String> = std::fs::read_dir(&backups).unwrap().map(|e| e.unwrap().file_name().to_string_lossy().into_owned()).collect();
+        names.sort();
+        assert!(names.len() >= 5, "{}: {:?}", format, names);
+        assert!(names[0].ends_with(&format!(".{}.001", format)) && names[1].ends_with(".002"), "{}: {:?}", format, names);
+        assert!(names.iter().all(|name| !name.ends_with(".part")), "{}: {:?}", format, names);
+
+        let found = fx.run(&["backups", "find", "pkg/blob.bin", "--backup-dir", backups_arg]).stdout();
+        assert!(found.contains(" pkg/blob.bin"), "{}: {}", format, found);
+        fx.run(&["restore", "app/node_modules"]).assert_success();
+        assert_eq!(std::fs::read(fx.path("app/node_modules/pkg/blob.bin")).unwrap(), noise);
+    }
+}
+
+// A sparse file reads as far more than the destination can hold
+#[test]
+fn backups_fail_fast_without_room_at_the_destination() {
+    let fx = Fixture::new("backup-space");
+    fx.file("app/node_modules/pkg/index.js", 10);
+    std::fs::File::create(fx.path("app/node_modules/pkg/huge.bin")).unwrap().set_len(8 << 40).unwrap();
+    let backups = fx.state_dir().join("backups");
+    let backups_arg = backups.to_str().unwrap();
+
+    let run = fx.run(&["app", "--backup", "--backup-strategy", "copy", "--backup-dir", backups_arg, "--delete", "--yes", "--output", "plain"]);
+    assert_ne!(run.0.status.code(), Some(0));
+    assert!(run.stderr().contains("Not enough space for backups in"), "{}", run.stderr());
+    assert!(fx.exists("app/node_modules/pkg/huge.bin"));
+    assert!(std::fs::read_dir(&backups).map_or(true, |mut entries| entries.next().is_none()));
+}
+
+// Past the 65,535 entries a plain zip directory can count
+#[test]
+fn zip_archives_hold_more_entries_than_plain_zip_allows() {
+    let fx = Fixture::new("zip64-entries");
+    fx.files("app/node_modules/pkg", 65_600);
+    let backups = fx.state_dir().join("backups");
+    let backups_arg = backups.to_str().unwrap();
+
+    fx.run(&["app", "--archive", "--backup-dir", backups_arg, "--delete", "--yes", "--output", "plain"]).assert_success();
+    assert!(!fx.exists("app/node_modules"));
+    let found = fx.run(&["backups", "find", "pkg/65599", "--backup-dir", backups_arg]).stdout();
+    assert!(found.contains(" pkg/65599"), "{}", found);
+
+    fx.run(&["restore", "app/node_modules"]).assert_success();
+    assert_eq!(std::fs::read_dir(fx.path("app/node_modules/pkg")).unwrap().count(), 65_600);
+}
+
+// Sizes past 4 GiB; too slow to write and read back without optimizations
+#[cfg(unix)]
+#[test]
+#[ignore = "archives 4.3 GB, run with `cargo test --release -- --ignored`"]
+fn zip_archives_hold_files_over_4_gib() {
+    let fx = Fixture::new("zip64-size");
+    fx.file("app/node_modules/small.js", 10);
+    let big = std::fs::File::create(fx.path("app/node_modules/big.bin")).unwrap();
+    // Sparse, so it takes no room on the fixture's disk
+    big.set_len((4u64 << 30) + (300 << 20)).unwrap();
+    drop(big);
+    let backups = fx.state_dir().join("backups");
+
+    fx.run(&["app", "--archive", "--verify-archive", "--backup-dir", backups.to_str().unwrap(), "--delete", "--yes", "--output", "plain"]).assert_success();
+    assert!(!fx.exists("app/node_modules"));
+    fx.run(&["restore", "app/node_modules"]).assert_success();
+    assert_eq!(std::fs::metadata(fx.path("app/node_modules/big.bin")).unwrap().len(), (4u64 << 30) + (300 << 20));
+    assert!(fx.exists("app/node_modules/small.js"));
+}
+
+#[cfg(unix)]
+#[test]
+fn zip_archives_keep_file_modes() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let fx = Fixture::new("zip-modes");
+    fx.file("app/node_modules/.bin/tool", 10).file("app/node_modules/pkg/secret.json", 10).file("app/node_modules/pkg/index.js", 100);
+    let set_mode = |rel: &str, mode: u32| std::fs::set_permissions(fx.path(rel), std::fs::Permissions::from_mode(mode)).unwrap();
+    set_mode("app/node_modules/.bin/tool", 0o750);
+    set_mode("app/node_modules/pkg/secret.json", 0o600);
+    set_mode("app/node_modules/pkg/index.js", 0o644);
+    let backups = fx.state_dir().join("backups");
+
+    fx.run(&[".", "--archive", "--archive-format", "zip", "--backup-dir", backups.to_str().unwrap(), "--delete", "--yes", "--output", "plain"]).assert_success();
+    fx.run(&["restore", "app/node_modules"]).assert_success();
+    let mode = |rel: &str| std::fs::metadata(fx.path(rel)).unwrap().permissions().mode() & 0o777;
+    assert_eq!(mode("app/node_modules/.bin/tool"), 0o750);
+    assert_eq!(mode("app/node_modules/pkg/secret.json"), 0o600);
+    assert_eq!(mode("app/node_modules/pkg/index.js"), 0o644);
+}
+
+#[test]
+fn unarchive_puts_archives_back_where_they_came_from() {
+    let fx = Fixture::new("unarchive");
+    fx.file("app/node_modules/.bin/tool", 10).file("app/node_modules/pkg/index.js", 100);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(fx.path("app/node_modules/.bin/tool"), std::fs::Permissions::from_mode(0o750)).unwrap();
+        std::fs::set_permissions(fx.path("app/node_modules/pkg"), std::fs::Permissions::from_mode(0o555)).unwrap();
+    }
+    let backups = fx.state_dir().join("backups");
+
+    fx.run(&[".", "--archive", "--archive-format", "tar.gz", "--backup-dir", backups.to_str().unwrap(), "--delete", "--yes", "--output", "plain"]).assert_success();
+    assert!(!fx.exists("app/node_modules"));
+    let archive = std::fs::read_dir(&backups).unwrap().next().unwrap().unwrap().path();
+    let archive = archive.to_str().unwrap();
+
+    fx.run(&["unarchive", archive]).assert_success();
+    assert_eq!(std::fs::metadata(fx.path("app/node_modules/pkg/index.js")).unwrap().len(), 100);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = |rel: &str| std::fs::metadata(fx.path(rel)).unwrap().permissions().mode() & 0o777;
+        assert_eq!(mode("app/node_modules/.bin/tool"), 0o750);
+        assert_eq!(mode("app/node_modules/pkg"), 0o555);
+        std::fs::set_permissions(fx.path("app/node_modules/pkg"), std::fs::Permissions::from_mode(0o755)).unwrap();
+    }
+
+    let run = fx.run(&["unarchive", archive]);
+    assert!(!run.0.status.success());
+    assert!(run.stderr().contains("already exists"), "{}", run.stderr());
+    fx.run(&["unarchive", archive, "--to", "copy"]).assert_success();
+    assert!(fx.exists("copy/pkg/index.js"));
+    assert!(!fx.exists("copy/.dirpurge-manifest.json"));
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(fx.path("copy/pkg"), std::fs::Permissions::from_mode(0o755)).unwrap();
+    }
+}
+
+// A link stored ahead of a file by the same name mustn't let the file be
+// written through it, to wherever it points
+#[cfg(unix)]
+#[test]
+fn unarchive_refuses_files_stored_over_links() {
+    let fx = Fixture::new("unarchive-link");
+    fx.file("victim", 6).dir("staging");
+    let script = format!(
+        "ln -s {} a && tar -cf evil.tar a && rm a && echo pwned > a && tar -rf evil.tar a && gzip evil.tar",
+        fx.path("victim").display()
+    );
+    let made = std::process::Command::new("sh").arg("-c").arg(&script).current_dir(fx.path("staging")).status();
+    if !made.is_ok_and(|status| status.success()) {
+        eprintln!("tar and gzip are not installed; skipping");
+        return;
+    }
+
+    let run = fx.run(&["unarchive", "staging/evil.tar.gz", "--to", "out"]);
+    assert!(!run.0.status.success());
+    assert!(run.stderr().contains("written through a link"), "{}", run.stderr());
+    assert_eq!(std::fs::read(fx.path("victim")).unwrap(), b"xxxxxx");
+    assert!(!fx.exists("out"));
+}
+
+#[test]
+fn backup_name_templates_organize_backups_per_project() {
+    let fx = Fixture::new("name-template");
+    fx.file("app/node_modules/pkg/index.js", 10).file("web/node_modules/lib.js", 10).file("api/target/out.o", 10);
+    let backups = fx.state_dir().join("backups");
+    let backups_arg = backups.to_str().unwrap();
+    let template = "{project}/{name}-{date}-{hash}.{ext}";
+    // Not a backup, though it sits where one could
+    fx.file(backups.join("app/notes.txt"), 10);
+
+    fx.run(&[".", "-t", "node_modules", "--archive", "--backup-dir", backups_arg, "--backup-name-template", template, "--delete", "--yes", "--output", "plain"]).assert_success();
+    fx.run(&["api", "-t", "target", "--backup", "--backup-dir", backups_arg, "--backup-name-template", template, "--delete", "--yes", "--output", "plain"]).assert_success();
+    let named = regex::Regex::new(r"^node_modules-\d{8}-[0-9a-f]{8}\.zip$").unwrap();
+    for project in ["app", "web"] {
+        let names: Vec<String> = std::fs::read_dir(backups.join(project)).unwrap().map(|e| e.unwrap().file_name().to_string_lossy().into_owned()).filter(|name| name != "notes.txt").collect();
+        assert!(names.len() == 1 && named.is_match(&names[0]), "{}: {:?}", project, names);
+    }
+    // Copies go without the extension
+    let copies: Vec<_> = std::fs::read_dir(backups.join("api")).unwrap().map(|e| e.unwrap().path()).collect();
+    assert!(copies.len() == 1 && copies[0].join("out.o").is_file(), "{:?}", copies);
+
+    let found = fx.run(&["backups", "find", "pkg/index.js", "--backup-dir", backups_arg, "--backup-name-template", template]).stdout();
+    assert!(found.contains(" pkg/index.js"), "{}", found);
+    fx.run(&["restore", "app/node_modules"]).assert_success();
+    assert!(fx.exists("app/node_modules/pkg/index.js"));
+
+    // Only the newest is kept, and the notes stay
+    fx.file("app/node_modules/more.js", 10);
+    fx.run(&["app", "--archive", "--backup-dir", backups_arg, "--backup-name-template", template, "--prune-backups", "count=1", "--delete", "--yes", "--output", "plain"]).assert_success();
+    assert_eq!(std::fs::read_dir(backups.join("app")).unwrap().count(), 2);
+    assert!(backups.join("app/notes.txt").exists());
+
+    let run = fx.run(&["app", "--archive", "--backup-name-template", "../{name}.{ext}"]);
+    assert_ne!(run.0.status.code(), Some(0));
+    let run = fx.run(&["app", "--archive", "--backup-name-template", "{user}/{name}"]);
+    assert!(String::from_utf8_lossy(&run.0.stderr).contains("unknown placeholder {user}"));
+}
+
+#[test]
+fn prune_backups_removes_old_backups_after_the_run() {
+    let fx = Fixture::new("prune-backups");
+    let backups = fx.state_dir().join("backups");
+    let backups_arg = backups.to_str().unwrap();
+    let backup = |name: &str| backups.join(name);
+    fx.file("app/node_modules/index.js", 10)
+        .file(backup("old_20200101_000000.zip"), 100)
+        .file(backup("old_20200101_000000.zip.manifest.json"), 10)
+        .file(backup("recent_20200101_000000.zip"), 100)
+        .file(backup("notes.txt"), 10);
+    let age = |name: &str, days: u64| {
+        let when = std::time::SystemTime::now() - std::time::Duration::from_secs(days * 86_400);
+        std::fs::File::options().write(true).open(backup(name)).unwrap().set_modified(when).unwrap();
+    };
+    age("old_20200101_000000.zip", 40);
+    age("recent_20200101_000000.zip", 10);
+    age("notes.txt", 40);
+
+    let purge = |policy: &str, dry_run: bool| {
+        let mut args = vec![".", "--backup", "--backup-dir", backups_arg, "--prune-backups", policy, "--delete", "--yes", "--output", "plain"];
+        if dry_run {
+            args.push("--dry-run");
+        }
+        let run = fx.run(&args);
+        run.assert_success();
+        run.stdout()
+    };
+    let output = purge("days=30", true);
+    assert!(output.contains("Would prune 1 backups"), "{}", output);
+    assert!(backup("old_20200101_000000.zip").exists());
+
+    let output = purge("days=30", false);
+    assert!(output.contains("Pruned 1 backups"), "{}", output);
+    assert!(!backup("old_20200101_000000.zip").exists());
+    assert!(!backup("old_20200101_000000.zip.manifest.json").exists());
+    assert!(backup("recent_20200101_000000.zip").exists());
+    assert!(backup("notes.txt").exists());
+
+    // The copy just made stays, whatever the limits
+    fx.file("lib/node_modules/index.js", 10);
+    purge("count=0", false);
+    assert!(!backup("recent_20200101_000000.zip").exists());
+    assert!(!backup("node_modules").exists());
+    assert_eq!(std::fs::read_dir(&backups).unwrap().count(), 2);
+}
+
+#[test]
+fn pdf_reports_list_the_directories_removed() {
+    let fx = Fixture::new("pdf");
+    fx.file("app/node_modules/index.js", 2048).file("lib/node_modules/(odd).js", 10);
+    let pdf = fx.path("report.pdf");
+
+    fx.run(&[".", "--delete", "--yes", "--pdf", pdf.to_str().unwrap(), "--output", "plain"]).assert_success();
+    let pdf = std::fs::read(pdf).unwrap();
+    let text = String::from_utf8_lossy(&pdf);
+    assert!(text.starts_with("%PDF-1.4"));
+    assert!(text.trim_end().ends_with("%%EOF"));
+    assert!(text.contains("(dirpurge report)"));
+    assert!(text.contains("(./app/node_modules)"));
+    // The cross-reference table points at the objects
+    let xref: usize = text.rsplit("startxref").next().unwrap().split_whitespace().next().unwrap().parse().unwrap();
+    assert!(pdf[xref..].starts_with(b"xref"));
+}
+
+#[test]
+fn treemaps_group_matches_by_target() {
+    let fx = Fixture::new("treemap");
+    fx.file("web/node_modules/index.js", 4096).file("api/target/out", 1024).file("a&b/node_modules/x", 10);
+    let svg = fx.path("map.svg");
+
+    // Drawn before anything is deleted, to make the case for it
+    fx.run(&[".", "-t", "node_modules", "-t", "target", "--treemap", svg.to_str().unwrap(), "--output", "plain"]).assert_success();
+    let svg = std::fs::read_to_string(svg).unwrap();
+    assert!(svg.starts_with("<svg"), "{}", svg);
+    assert!(svg.contains("dirpurge: 3 directories"), "{}", svg);
+    assert!(svg.contains(">node_modules ·"), "{}", svg);
+    assert!(svg.contains("<title>./api/target"), "{}", svg);
+    assert!(svg.contains("./a&amp;b/node_modules"), "{}", svg);
+    assert_eq!(svg.matches("<g>").count(), 3);
+    assert!(fx.exists("web/node_modules"));
+}
+
+// A stand-in for S3 that records every request; the first part upload fails
+// so the retry is exercised
+// Request line, headers and body of each request, in order
+#[cfg(feature = "s3")]
+type Requests = std::sync::Arc<std::sync::Mutex<Vec<(String, String, Vec<u8>)>>>;
+
+#[cfg(feature = "s3")]
+fn fake_s3() -> (String, Requests) {
+    use std::io::{BufRead, BufReader, Read, Write};
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let endpoint = format!("http://{}", listener.local_addr().unwrap());
+    let requests = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+    let seen = requests.clone();
+    std::thread::spawn(move || {
+        for (n, stream) in listener.incoming().enumerate() {
+            let mut stream = BufReader::new(stream.unwrap());
+            let (mut line, mut headers) = (String::new(), String::new());
+            stream.read_line(&mut line).unwrap();
+            let mut length = 0;
+            loop {
+                let mut header = String::new();
+                stream.read_line(&mut header).unwrap();
+                if header.trim().is_empty() {
+                    break;
+                }
+                if let Some(value) = header.to_ascii_lowercase().strip_prefix("content-length:") {
+                    length = value.trim().parse().unwrap();
+                }
+                headers.push_str(&header);
+            }
+            let mut body = vec![0; length];
+            stream.read_exact(&mut body).unwrap();
+            let target = line.split_whitespace().take(2).collect::<Vec<_>>().join(" ");
+            let (status, reply) = if target.ends_with("?uploads=") {
+                ("200 OK", "<InitiateMultipartUploadResult><UploadId>upload-1</UploadId></InitiateMultipartUploadResult>".to_string())
+            } else if target.contains("partNumber=") && n == 1 {
+                ("500 Internal Server Error", "<Error><Message>try again</Message></Error>".to_string())
+            } else {
+                ("200 OK", String::new())
+            };
+            let reply = format!("HTTP/1.1 {}\r\nETag: \"part-{}\"\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}", status, n, reply.len(), reply);
+            stream.get_mut().write_all(reply.as_bytes()).unwrap();
+            seen.lock().unwrap().push((target, headers, body));
+        }
+    });
+    (endpoint, requests)
+}
+
+#[cfg(feature = "s3")]
+#[test]
+fn s3_backups_are_uploaded_in_parts_before_deleting() {
+    if std::process::Command::new("curl").arg("--version").output().is_err() {
+        eprintln!("curl is not installed; skipping");
+        return;
+    }
+    let fx = Fixture::new("s3");
+    fx.file("app/node_modules/index.js", 4096);
+    let (endpoint, requests) = fake_s3();
+    let staging = fx.state_dir();
+    let vars = [
+        ("AWS_ENDPOINT_URL", endpoint.as_str()),
+        ("AWS_ACCESS_KEY_ID", "AKIDEXAMPLE"),
+        ("AWS_SECRET_ACCESS_KEY", "secret"),
+        ("AWS_SESSION_TOKEN", "session-token"),
+        ("AWS_REGION", "eu-west-1"),
+        ("TMPDIR", staging.to_str().unwrap()),
+    ];
+
+    // Zip needs a seekable file, so it is refused and nothing is deleted
+    let run = fx.run_with_env(&[".", "--backup", "--backup-dir", "s3://bucket/ci", "--archive", "--delete", "--yes", "--output", "plain"], &vars);
+    let output = run.stdout() + &run.stderr();
+    assert!(output.contains("streamed archives"), "{}", output);
+    assert!(fx.exists("app/node_modules"));
+    let run = fx.run_with_env(&[".", "--archive", "--backup-dir", "s3://bucket/ci", "--s3-storage-class", "GLACIAL", "--delete", "--yes"], &vars);
+    assert!(run.stderr().contains("unknown storage class 'GLACIAL'"), "{}", run.stderr());
+
+    // The directory's target outranks the size it is over, which outranks the plain class
+    let run = fx.run_with_env(&[
+        ".", "--backup", "--backup-dir", "s3://bucket/ci", "--archive", "--archive-format", "tar.gz",
+        "--s3-storage-class", "GLACIER_IR", "--s3-storage-class", ">1KB=STANDARD_IA", "--s3-storage-class", "node_modules=deep_archive",
+        "--delete", "--yes", "--output", "plain",
+    ], &vars);
+    run.assert_success();
+    assert!(!fx.exists("app/node_modules"));
+
+    let requests = requests.lock().unwrap();
+    let targets: Vec<&str> = requests.iter().map(|(target, _, _)| target.as_str()).collect();
+    assert_eq!(targets.len(), 4, "{:?}", targets);
+    assert!(targets[0].starts_with("POST /bucket/ci/node_modules_") && targets[0].ends_with(".tar.gz?uploads="), "{:?}", targets);
+    assert!(targets[1].contains("?partNumber=1&uploadId=upload-1"), "{:?}", targets);
+    assert_eq!(targets[1], targets[2], "the failed part is sent again");
+    assert!(targets[3].starts_with("POST") && targets[3].ends_with("?uploadId=upload-1"), "{:?}", targets);
+    // The storage class is given, and signed, only when the upload starts
+    assert!(requests[0].1.contains("x-amz-storage-class: DEEP_ARCHIVE"), "{}", requests[0].1);
+    assert!(requests[0].1.contains("SignedHeaders=host;x-amz-content-sha256;x-amz-date;x-amz-security-token;x-amz-storage-class,"), "{}", requests[0].1);
+    assert!(requests[1..].iter().all(|(_, headers, _)| !headers.contains("x-amz-storage-class")));
+    assert!(requests[2].1.contains("AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/"), "{}", requests[2].1);
+    assert!(requests[2].1.contains("/eu-west-1/s3/aws4_request"), "{}", requests[2].1);
+    // Signed, and handed to curl in a file that is gone afterwards
+    assert!(requests[2].1.contains("x-amz-security-token: session-token"), "{}", requests[2].1);
+    assert!(requests[2].1.contains("SignedHeaders=host;x-amz-content-sha256;x-amz-date;x-amz-security-token,"), "{}", requests[2].1);
+    assert!(!staging.read_dir().unwrap().any(|e| e.unwrap().file_name().to_string_lossy().starts_with("dirpurge-s3")));
+    // The part is the whole gzipped tar, and the completion names its ETag
+    assert_eq!(&requests[2].2[..2], &[0x1f, 0x8b]);
+    let complete = String::from_utf8_lossy(&requests[3].2);
+    assert!(complete.contains("<PartNumber>1</PartNumber><ETag>\"part-2\"</ETag>"), "{}", complete);
+}
+
+// Stands in for OpenSSH sftp, running the batch on stdin against the local
+// disk. The first put of a file only gets half of it across, as a dropped
+// connection would.
+#[cfg(all(unix, feature = "sftp"))]
+const FAKE_SFTP: &str = r#"#!/bin/sh
+while IFS= read -r line; do
+    echo "$line" >> "$FAKE_SFTP_LOG"
+    eval "set -- $line"
+    command=$1
+    shift
+    case "$command" in
+        -mkdir) mkdir "$1" 2>/dev/null ;;
+        put|reput)
+            recursive=
+            while [ "${1#-}" != "$1" ]; do
+                [ "$1" = -r ] && recursive=1
+                shift
+            done
+            if [ -n "$recursive" ]; then
+                mkdir -p "$2" && cp -Rp "$1/." "$2/" || exit 1
+            elif [ "$command" = put ] && [ ! -e "$FAKE_SFTP_LOG.cut" ]; then
+                : > "$FAKE_SFTP_LOG.cut"
+                head -c $(($(wc -c < "$1") / 2)) "$1" > "$2"
+                exit 1
+            else
+                have=0
+                [ -e "$2" ] && have=$(wc -c < "$2")
+                tail -c +$((have + 1)) "$1" >> "$2"
+            fi ;;
+        rename) mv "$1" "$2" || exit 1 ;;
+        *) exit 1 ;;
+    esac
+done
+"#;
+
+#[cfg(all(unix, feature = "sftp"))]
+#[test]
+fn sftp_uploads_resume_after_a_dropped_connection() {
+    use std::os::unix::fs::PermissionsExt;
+    let fx = Fixture::new("sftp");
+    fx.file("app/node_modules/index.js", 64 * 1024).file("lib/target/out", 100);
+    fx.file("bin/sftp", 0);
+    let fake = fx.path("bin/sftp");
+    std::fs::write(&fake, FAKE_SFTP).unwrap();
+    std::fs::set_permissions(&fake, std::fs::Permissions::from_mode(0o755)).unwrap();
+    let remote = fx.state_dir().join("nas/backups");
+    let uri = format!("sftp://backup@nas{}", remote.display());
+    let log = fx.path("sftp.log");
+    let path = format!("{}:{}", fx.path("bin").display(), std::env::var("PATH").unwrap_or_default());
+    let staging = fx.state_dir();
+    let vars = [("PATH", path.as_str()), ("FAKE_SFTP_LOG", log.to_str().unwrap()), ("TMPDIR", staging.to_str().unwrap())];
+
+    // Copies there can't be read back, so verifying them is refused up front
+    let run = fx.run_with_env(&[
+        "app", "--archive", "--archive-format", "tar.gz", "--verify-archive", "--backup-dir", &uri, "--delete", "--yes", "--output", "plain",
+    ], &vars);
+    assert!(!run.0.status.success());
+    assert!(run.stderr().contains("can't be read back to verify"), "{}", run.stderr());
+    assert!(fx.exists("app/node_modules") && !log.exists());
+
+    fx.run_with_env(&[
+        "app", "--archive", "--archive-format", "tar.gz", "--backup-dir", &uri, "--delete", "--yes", "--output", "plain",
+    ], &vars).assert_success();
+    assert!(!fx.exists("app/node_modules"));
+    let log_text = std::fs::read_to_string(&log).unwrap();
+    assert!(log_text.contains("reput"), "{}", log_text);
+    let stored: Vec<String> = std::fs::read_dir(&remote).unwrap().map(|e| e.unwrap().file_name().to_string_lossy().into_owned()).collect();
+    assert!(stored.len() == 1 && stored[0].ends_with(".tar.gz"), "{:?}", stored);
+    // Whole once resumed: what was cut off is readable again
+    let run = fx.run(&["backups", "find", "index.js", "--backup-dir", remote.to_str().unwrap()]);
+    run.assert_success();
+    assert!(run.stdout().contains("index.js"), "{}", run.stdout());
+    // Nothing left behind in the staging area
+    assert!(!staging.read_dir().unwrap().any(|e| e.unwrap().file_name().to_string_lossy().starts_with("dirpurge-sftp")));
+
+    // Plain copies go up as directories
+    fx.run_with_env(&["lib", "--backup", "--backup-dir", &uri, "--delete", "--yes", "--output", "plain"], &vars).assert_success();
+    assert!(std::fs::read_to_string(&log).unwrap().contains("put -r -p "));
+    let copy = std::fs::read_dir(&remote).unwrap().map(|e| e.unwrap().path()).find(|p| p.is_dir()).unwrap();
+    assert_eq!(std::fs::read(copy.join("out")).unwrap().len(), 100);
+    assert!(!fx.exists("lib/target"));
+}
@@ -0,0 +1,226 @@
+// Shared harness for the end-to-end tests: throwaway directory trees and a
+// way to run the built binary against them.
+
+#![allow(dead_code)]
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::{Command, Output, Stdio},
+    sync::atomic::{AtomicUsize, Ordering},
+    thread,
+    time::{Duration, Instant},
+};
+
+static NEXT: AtomicUsize = AtomicUsize::new(0);
+
+// Long enough for a slow CI machine, short enough to catch a traversal loop
+const TIMEOUT: Duration = Duration::from_secs(60);
+
+// A temporary tree, removed again when dropped
+pub struct Fixture {
+    root: PathBuf,
+}
+
+impl Fixture {
+    pub fn new(name: &str) -> Self {
+        let root = std::env::temp_dir().join(format!(
+            "dirpurge-test-{}-{}-{}",
+            std::process::id(),
+            NEXT.fetch_add(1, Ordering::SeqCst),
+            name
+        ));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).expect("create fixture root");
+        Fixture { root }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    pub fn path(&self, rel: impl AsRef<Path>) -> PathBuf {
+        self.root.join(rel)
+    }
+
+    pub fn dir(&self, rel: impl AsRef<Path>) -> &Self {
+        fs::create_dir_all(self.path(rel)).expect("create fixture dir");
+        self
+    }
+
+    // A file of `len` bytes, creating its parents
+    pub fn file(&self, rel: impl AsRef<Path>, len: usize) -> &Self {
+        let path = self.path(rel);
+        fs::create_dir_all(path.parent().unwrap()).expect("create fixture dir");
+        fs::write(&path, vec![b'x'; len]).expect("write fixture file");
+        self
+    }
+
+    // `count` small files named 0..count inside `rel`
+    pub fn files(&self, rel: impl AsRef<Path>, count: usize) -> &Self {
+        let dir = self.path(rel);
+        fs::create_dir_all(&dir).expect("create fixture dir");
+        for i in 0..count {
+            fs::write(dir.join(i.to_string()), b"x").expect("write fixture file");
+        }
+        self
+    }
+
+    #[cfg(unix)]
+    pub fn symlink(&self, target: impl AsRef<Path>, rel: impl AsRef<Path>) -> &Self {
+        let link = self.path(rel);
+        fs::create_dir_all(link.parent().unwrap()).expect("create fixture dir");
+        std::os::unix::fs::symlink(target, link).expect("create fixture symlink");
+        self
+    }
+
+    // Stands in for the user's state directory, outside the tree being purged
+    pub fn state_dir(&self) -> PathBuf {
+        self.root.with_extension("state")
+    }
+
+    pub fn exists(&self, rel: impl AsRef<Path>) -> bool {
+        self.path(rel).symlink_metadata().is_ok()
+    }
+
+    // Run dirpurge from the fixture root with no terminal attached
+    pub fn run(&self, args: &[&str]) -> Run {
+        self.run_with_env(args, &[])
+    }
+
+    // `run` with extra environment variables set
+    pub fn run_with_env(&self, args: &[&str], vars: &[(&str, &str)]) -> Run {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_dirpurge"))
+            .args(args)
+            .envs(vars.iter().copied())
+            .current_dir(&self.root)
+            .env("NO_COLOR", "1")
+            // Reports follow the locale; tests that care pass --report-locale
+            .env("LC_ALL", "C")
+            // Keep the journal out of the real state directory
+            .env("XDG_STATE_HOME", self.state_dir())
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .expect("spawn dirpurge");
+
+        let started = Instant::now();
+        while child.try_wait().expect("wait for dirpurge").is_none() {
+            if started.elapsed() > TIMEOUT {
+                let _ = child.kill();
+                panic!("dirpurge {:?} did not finish within {:?}", args, TIMEOUT);
+            }
+            thread::sleep(Duration::from_millis(20));
+        }
+        Run(child.wait_with_output().expect("collect dirpurge output"))
+    }
+
+    // Run with `--json` into the fixture and return the parsed summary
+    pub fn run_json(&self, args: &[&str]) -> (Run, serde_json::Value) {
+        let json = self.path("summary.json");
+        let _ = fs::remove_file(&json);
+        let mut args = args.to_vec();
+        let json_arg = json.to_str().unwrap().to_string();
+        args.extend(["--json", &json_arg]);
+        let run = self.run(&args);
+        run.assert_success();
+        let summary = fs::read_to_string(&json)
+            .unwrap_or_else(|e| panic!("no JSON summary ({}):\n{}", e, run.stderr()));
+        let _ = fs::remove_file(&json);
+        (run, serde_json::from_str(&summary).expect("parse JSON summary"))
+    }
+}
+
+impl Drop for Fixture {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.root);
+        let _ = fs::remove_dir_all(self.state_dir());
+    }
+}
+
+pub struct Run(pub Output);
+
+impl Run {
+    pub fn stdout(&self) -> String {
+        String::from_utf8_lossy(&self.0.stdout).into_owned()
+    }
+
+    pub fn stderr(&self) -> String {
+        String::from_utf8_lossy(&self.0.stderr).into_owned()
+    }
+
+    pub fn assert_success(&self) {
+        assert!(
+            self.0.status.success(),
+            "dirpurge failed with {}\nstdout:\n{}\nstderr:\n{}",
+            self.0.status,
+            self.stdout(),
+            self.stderr()
+        );
+    }
+
+    // Every line of `--output json-events`, parsed
+    pub fn events(&self) -> Vec<serde_json::Value> {
+        self.stdout()
+            .lines()
+            .filter(|line| line.starts_with('{'))
+            .map(|line| serde_json::from_str(line).unwrap_or_else(|e| panic!("bad event {}: {}", line, e)))
+            .collect()
+    }
+
+    pub fn event(&self, name: &str) -> serde_json::Value {
+        self.events()
+            .into_iter()
+            .find(|event| event["event"] == name)
+            .unwrap_or_else(|| panic!("no {} event in:\n{}", name, self.stdout()))
+    }
+}
+
+// The paths listed under `key` in a summary or event
+pub fn paths(value: &serde_json::Value, key: &str) -> Vec<String> {
+    let mut paths: Vec<String> = value[key]
+        .as_array()
+        .unwrap_or_else(|| panic!("no {} in {}", key, value))
+        .iter()
+        .map(|dir| dir["path"].as_str().unwrap().to_string())
+        .collect();
+    paths.sort();
+    paths
+}
+
+// Compare against tests/golden/<name>.json, ignoring fields that change
+// between runs. UPDATE_GOLDEN=1 rewrites the file instead.
+pub fn assert_golden(name: &str, mut actual: serde_json::Value) {
+    normalize(&mut actual);
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden").join(format!("{}.json", name));
+    let rendered = serde_json::to_string_pretty(&actual).unwrap() + "\n";
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        fs::write(&path, rendered).expect("write golden file");
+        return;
+    }
+    let expected = fs::read_to_string(&path)
+        .unwrap_or_else(|e| panic!("missing golden file {} ({}), run with UPDATE_GOLDEN=1", path.display(), e));
+    assert_eq!(expected, rendered, "output differs from {}", path.display());
+}
+
+fn normalize(value: &mut serde_json::Value) {
+    match value {
+        serde_json::Value::Object(map) => {
+            for key in ["timestamp", "recorded_at"] {
+                if map.contains_key(key) {
+                    map.insert(key.to_string(), "<time>".into());
+                }
+            }
+            // Resolved against the machine running the tests
+            for key in ["protected", "protected_roots"] {
+                if map.contains_key(key) {
+                    map.insert(key.to_string(), "<paths>".into());
+                }
+            }
+            map.values_mut().for_each(normalize);
+        }
+        serde_json::Value::Array(items) => items.iter_mut().for_each(normalize),
+        _ => {}
+    }
+}
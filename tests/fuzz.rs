@@ -0,0 +1,33 @@
+// The fuzz targets compile src/main.rs in directly, so fuzz/Cargo.toml has to
+// carry the same features and dependencies as the crate. It sits outside the
+// workspace and only builds on nightly, so nothing else notices when it drifts.
+
+use std::fs;
+use std::path::Path;
+
+// The lines of a manifest's `[name]` table, without comments and blank lines
+fn table(manifest: &str, name: &str) -> Vec<String> {
+    let header = format!("[{}]", name);
+    manifest.lines()
+        .skip_while(|line| line.trim() != header)
+        .skip(1)
+        .take_while(|line| !line.starts_with('['))
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(String::from)
+        .collect()
+}
+
+#[test]
+fn fuzz_manifest_mirrors_the_crate() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let crate_manifest = fs::read_to_string(root.join("Cargo.toml")).unwrap();
+    let fuzz_manifest = fs::read_to_string(root.join("fuzz/Cargo.toml")).unwrap();
+    for name in ["features", "dependencies", "target.'cfg(unix)'.dependencies", "target.'cfg(windows)'.dependencies"] {
+        let fuzz: Vec<String> = table(&fuzz_manifest, name).into_iter()
+            .filter(|line| !line.starts_with("libfuzzer-sys "))
+            .map(|line| line.replace("\"../dirpurge-core\"", "\"dirpurge-core\""))
+            .collect();
+        assert_eq!(fuzz, table(&crate_manifest, name), "[{}] in fuzz/Cargo.toml", name);
+    }
+}